@@ -0,0 +1,35 @@
+/* C API of git-credential-keepassxc, built with the cdylib feature */
+#ifndef GIT_CREDENTIAL_KEEPASSXC_H
+#define GIT_CREDENTIAL_KEEPASSXC_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define KPXC_OK 0
+#define KPXC_NOT_FOUND 1
+#define KPXC_ERROR -1
+
+typedef struct KpxcSession KpxcSession;
+
+/* Connects to KeePassXC if it's running. Returns NULL on errors, and always in builds with
+   strict-caller. Sessions are bound to the thread that opened them. */
+KpxcSession *kpxc_session_open(const char *config_path);
+void kpxc_session_close(KpxcSession *session);
+
+/* Looks up the login get would return. username may be NULL. Free the returned strings with
+   kpxc_string_free(). */
+int kpxc_get_login(const KpxcSession *session, const char *url, const char *username,
+                   char **out_username, char **out_password);
+int kpxc_store_login(const KpxcSession *session, const char *url, const char *username,
+                     const char *password);
+
+void kpxc_string_free(char *s);
+/* Message of the last error on the current thread, valid until the next error, or NULL */
+const char *kpxc_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,83 @@
+/// Prompts `askpass` knows how to answer
+#[derive(Debug, PartialEq)]
+pub enum Prompt {
+    /// SSH asking for the passphrase of a private key
+    KeyPassphrase { key_path: String },
+    /// Git asking for the username for a URL
+    Username { url: String },
+    /// Git asking for the password for a URL, which includes the username if known
+    Password { url: String },
+}
+
+/// Parses prompts such as `Enter passphrase for key '/home/me/.ssh/id_ed25519': ` (ssh) and
+/// `Enter passphrase for /home/me/.ssh/id_ed25519 (me@host): ` (ssh-add), as well as
+/// `Username for 'https://example.com': ` and `Password for 'https://me@example.com': ` (Git)
+pub fn parse_prompt(prompt: &str) -> Option<Prompt> {
+    let prompt = prompt.trim().trim_end_matches(':').trim_end();
+    if let Some(url) = prompt.strip_prefix("Username for ") {
+        return Some(Prompt::Username {
+            url: url.trim_matches('\'').to_owned(),
+        });
+    }
+    if let Some(url) = prompt.strip_prefix("Password for ") {
+        return Some(Prompt::Password {
+            url: url.trim_matches('\'').to_owned(),
+        });
+    }
+    if let Some(key) = prompt.strip_prefix("Enter passphrase for ") {
+        let key = key.strip_prefix("key ").unwrap_or(key);
+        let key_path = if key.starts_with('\'') {
+            key.trim_matches('\'')
+        } else if key.ends_with(')') {
+            // ssh-add appends the comment of the key
+            key.rfind(" (").map(|idx| &key[..idx]).unwrap_or(key)
+        } else {
+            key
+        };
+        return Some(Prompt::KeyPassphrase {
+            key_path: key_path.to_owned(),
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_ssh_passphrase_prompts() {
+        let expected = Some(Prompt::KeyPassphrase {
+            key_path: "/home/me/.ssh/id_ed25519".to_owned(),
+        });
+        assert_eq!(
+            parse_prompt("Enter passphrase for key '/home/me/.ssh/id_ed25519': "),
+            expected
+        );
+        assert_eq!(
+            parse_prompt("Enter passphrase for /home/me/.ssh/id_ed25519: "),
+            expected
+        );
+        assert_eq!(
+            parse_prompt("Enter passphrase for /home/me/.ssh/id_ed25519 (me@host): "),
+            expected
+        );
+        assert!(parse_prompt("Are you sure you want to continue connecting?").is_none());
+    }
+
+    #[test]
+    fn test_01_git_prompts() {
+        assert_eq!(
+            parse_prompt("Username for 'https://example.com': "),
+            Some(Prompt::Username {
+                url: "https://example.com".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_prompt("Password for 'https://me@example.com': "),
+            Some(Prompt::Password {
+                url: "https://me@example.com".to_owned()
+            })
+        );
+    }
+}
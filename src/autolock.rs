@@ -0,0 +1,46 @@
+use crate::utils::{read_state_file, write_state_file};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Time of the last access to the databases, persisted next to the configuration file so that
+/// timers started by earlier accesses leave the databases unlocked for later ones
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct AutoLockState {
+    #[serde(default)]
+    last_access: u64,
+}
+
+impl AutoLockState {
+    pub fn read_from<T: AsRef<Path>>(state_path: T) -> Result<Self> {
+        read_state_file(state_path)
+    }
+
+    pub fn write_to<T: AsRef<Path>>(&self, state_path: T) -> Result<()> {
+        write_state_file(self, state_path)
+    }
+
+    pub fn record_access(&mut self, now: u64) {
+        self.last_access = now;
+    }
+
+    /// Whether the databases have been left alone for `after` seconds
+    pub fn is_due(&self, after: u64, now: u64) -> bool {
+        self.last_access + after <= now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_later_access_postpones_lock() {
+        let mut state = AutoLockState::default();
+        state.record_access(1000);
+        assert!(!state.is_due(60, 1030));
+        state.record_access(1030);
+        assert!(!state.is_due(60, 1060));
+        assert!(state.is_due(60, 1090));
+    }
+}
@@ -0,0 +1,56 @@
+use serde::Serialize;
+
+/// Output of AWS `credential_process`
+/// https://docs.aws.amazon.com/cli/latest/userguide/cli-configure-sourcing-external.html
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct ProcessCredentials {
+    version: u8,
+    access_key_id: String,
+    secret_access_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_token: Option<String>,
+    /// ISO 8601 timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expiration: Option<String>,
+}
+
+impl ProcessCredentials {
+    pub fn new<T: Into<String>>(
+        access_key_id: T,
+        secret_access_key: T,
+        session_token: Option<T>,
+        expiration: Option<T>,
+    ) -> Self {
+        Self {
+            version: 1,
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            session_token: session_token.map(|v| v.into()),
+            expiration: expiration.map(|v| v.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_process_credentials_json() {
+        assert_eq!(
+            serde_json::to_string(&ProcessCredentials::new("foo", "bar", None, None)).unwrap(),
+            r#"{"Version":1,"AccessKeyId":"foo","SecretAccessKey":"bar"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&ProcessCredentials::new(
+                "foo",
+                "bar",
+                Some("baz"),
+                Some("2020-01-01T00:00:00Z")
+            ))
+            .unwrap(),
+            r#"{"Version":1,"AccessKeyId":"foo","SecretAccessKey":"bar","SessionToken":"baz","Expiration":"2020-01-01T00:00:00Z"}"#
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+/// First line the credential provider writes, telling Cargo the supported protocol versions
+pub const HELLO: &str = r#"{"v":[1]}"#;
+
+/// Request of the Cargo credential provider protocol
+/// https://doc.rust-lang.org/cargo/reference/credential-provider-protocol.html
+#[derive(Deserialize)]
+pub struct CargoRequest {
+    pub v: u32,
+    pub registry: CargoRegistry,
+    pub kind: String,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CargoRegistry {
+    #[serde(rename = "index-url")]
+    pub index_url: String,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl CargoRegistry {
+    /// URL of the registry index without the protocol prefix, e.g. `https://example.com/index/`
+    /// from `sparse+https://example.com/index/`
+    pub fn url(&self) -> &str {
+        self.index_url
+            .strip_prefix("sparse+")
+            .or_else(|| self.index_url.strip_prefix("git+"))
+            .unwrap_or(&self.index_url)
+    }
+
+    /// Username of the entry holding the token, as Cargo only deals with tokens
+    pub fn username(&self) -> &str {
+        self.name.as_deref().unwrap_or("cargo")
+    }
+}
+
+#[derive(Serialize)]
+pub enum CargoResponse {
+    Ok(CargoSuccess),
+    Err(CargoError),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum CargoSuccess {
+    Get {
+        token: String,
+        cache: String,
+        operation_independent: bool,
+    },
+    Login,
+    Logout,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum CargoError {
+    NotFound,
+    OperationNotSupported,
+    Other { message: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_request_and_response() {
+        let request: CargoRequest = serde_json::from_str(
+            r#"{"v":1,"registry":{"index-url":"sparse+https://example.com/index/","name":"example"},"kind":"get","operation":"read","args":[]}"#,
+        )
+        .unwrap();
+        assert_eq!(request.kind, "get");
+        assert_eq!(request.registry.url(), "https://example.com/index/");
+        assert_eq!(request.registry.username(), "example");
+        let response = CargoResponse::Ok(CargoSuccess::Get {
+            token: "foo".to_owned(),
+            cache: "session".to_owned(),
+            operation_independent: true,
+        });
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"Ok":{"kind":"get","token":"foo","cache":"session","operation_independent":true}}"#
+        );
+        let response = CargoResponse::Err(CargoError::NotFound);
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"Err":{"kind":"not-found"}}"#
+        );
+    }
+}
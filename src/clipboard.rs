@@ -0,0 +1,122 @@
+//! System clipboard access through the usual command line tools, as there's no portable API
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Commands copying standard input to the clipboard and printing it, with their arguments
+struct ClipboardTool {
+    copy: &'static [&'static str],
+    paste: &'static [&'static str],
+}
+
+#[cfg(target_os = "macos")]
+fn clipboard_tools() -> Vec<ClipboardTool> {
+    vec![ClipboardTool {
+        copy: &["pbcopy"],
+        paste: &["pbpaste"],
+    }]
+}
+
+#[cfg(windows)]
+fn clipboard_tools() -> Vec<ClipboardTool> {
+    vec![ClipboardTool {
+        copy: &["clip.exe"],
+        paste: &["powershell.exe", "-NoProfile", "-Command", "Get-Clipboard"],
+    }]
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn clipboard_tools() -> Vec<ClipboardTool> {
+    let mut tools = Vec::new();
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push(ClipboardTool {
+            copy: &["wl-copy"],
+            paste: &["wl-paste", "--no-newline"],
+        });
+    }
+    tools.push(ClipboardTool {
+        copy: &["xclip", "-selection", "clipboard"],
+        paste: &["xclip", "-selection", "clipboard", "-o"],
+    });
+    tools.push(ClipboardTool {
+        copy: &["xsel", "--clipboard", "--input"],
+        paste: &["xsel", "--clipboard", "--output"],
+    });
+    tools
+}
+
+/// Runs a command with `input` on its standard input. Its output isn't captured, as e.g. xclip
+/// keeps running in background to serve the clipboard and would hold the pipe open.
+fn run_with_input(command: &[&str], input: &str) -> std::io::Result<bool> {
+    let mut child = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(input.as_bytes())?;
+    Ok(child.wait()?.success())
+}
+
+/// Copies text to the clipboard with the first tool that works
+pub fn copy(text: &str) -> Result<()> {
+    for tool in clipboard_tools() {
+        match run_with_input(tool.copy, text) {
+            Ok(true) => return Ok(()),
+            Ok(false) => continue,
+            // not installed
+            Err(_) => continue,
+        }
+    }
+    Err(anyhow!(
+        "Failed to copy to clipboard, install e.g. wl-clipboard (Wayland) or xclip (X11)"
+    ))
+}
+
+/// Text in the clipboard
+pub fn paste() -> Result<String> {
+    for tool in clipboard_tools() {
+        if let Ok(output) = Command::new(tool.paste[0])
+            .args(&tool.paste[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+        {
+            if output.status.success() {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+            }
+        }
+    }
+    Err(anyhow!("Failed to read clipboard"))
+}
+
+/// Lowercase hex SHA-256 digest of clipboard content, to check whether it has changed without
+/// keeping the content itself around
+pub fn digest(text: &str) -> String {
+    // e.g. Get-Clipboard appends a line break
+    Sha256::digest(text.trim_end_matches(&['\r', '\n'][..]).as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Empties the clipboard if it still contains text of the digest
+pub fn clear_if_unchanged(digest_of_copied: &str) -> Result<bool> {
+    if digest(&paste()?) != digest_of_copied {
+        return Ok(false);
+    }
+    copy("")?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_digest() {
+        assert_eq!(digest("foo\r\n"), digest("foo"));
+        assert_ne!(digest("foo"), digest("bar"));
+    }
+}
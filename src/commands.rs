@@ -0,0 +1,3997 @@
+//! The subcommands of git-credential-keepassxc, from parsing the command line to printing the
+//! result, on top of the protocol client, configuration handling and [`crate::requests`]
+use crate::audit::AuditRecord;
+use crate::autolock::AutoLockState;
+use crate::cargo::{CargoError, CargoRequest, CargoResponse, CargoSuccess};
+use crate::cli::UnlockOptions;
+use crate::config::{self, Caller, Config, ConfigLock, Database};
+use crate::daemon::{
+    CacheStatus, DaemonError, DaemonMetrics, DaemonRequest, DaemonResponse, ResponseCache,
+};
+use crate::deadline::{self, DeadlineExceededError};
+use crate::docker::{DockerCredential, DockerServers};
+use crate::git::{self, GitCredentialMessage};
+use crate::import::{self, ImportedLogin, KeychainHelper};
+use crate::interrupt::{self, InterruptedError};
+use crate::keepassxc::{errors::*, messages::*, Group};
+use crate::logging::{timed, JsonDrain, TraceTarget};
+use crate::ownership::OwnedEntries;
+#[cfg(unix)]
+use crate::peer;
+use crate::requests::*;
+use crate::session::{retry_on_lost_session, start_session};
+use crate::transport::ConnectionLostError;
+use crate::utils::{self, *};
+use crate::{askpass, aws, cargo, clipboard, daemon, docker};
+use crate::{debug, error, info, warn, LOGGER};
+use crate::{kube, manpage, memlock, npm, output, pinentry, progress};
+use anyhow::{anyhow, Result};
+use clap::{App, ArgMatches};
+use crypto_box::PublicKey;
+use slog::{Drain, Level, Logger};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use sysinfo::{ProcessExt, System, SystemExt};
+
+type LogDrain =
+    Box<dyn slog::SendSyncRefUnwindSafeDrain<Ok = (), Err = slog::Never> + std::panic::UnwindSafe>;
+
+/// Whether `--json` is given, so that errors are printed as JSON too
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+/// Whether `--quiet` is given, so that progress messages aren't printed
+static QUIET_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Creates the directory of profile configuration files for `configure`, accessible by the current
+/// user only
+fn create_profiles_dir(profile_path: &Path) -> Result<()> {
+    let profiles_dir = match profile_path.parent() {
+        Some(dir) if !dir.exists() => dir,
+        _ => return Ok(()),
+    };
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(profiles_dir)?;
+    info!(
+        "Created profile directory {}",
+        profiles_dir.to_string_lossy()
+    );
+    Ok(())
+}
+
+fn handle_secondary_encryption(config_file: &mut Config) -> Result<()> {
+    // the tokens must already be plugged in then
+    if !is_interactive() || !io::stdin().is_terminal() {
+        if config_file.get_encryption_key().is_err() {
+            warn!("Failed to extract encryption key from existing profiles, using a new one");
+        }
+        return Ok(());
+    }
+    println!("There are existing encryption profile(s). If you'd like to reuse an existing encryption key, plug in the corresponding (hardware) token.");
+    print!("Press Enter to continue... ");
+    std::io::stdout().flush()?;
+    std::io::stdin().read_line(&mut String::new())?;
+    if config_file.get_encryption_key().is_err() {
+        warn!("Failed to extract encryption key from existing profiles");
+        println!("Failed to extract the encryption key! Continue to configure a new (hardware) token using a DIFFERENT encryption key.")
+    }
+    println!("Now make sure you've plugged in the (hardware) token you'd like to use.");
+    print!("Press Enter to continue... ");
+    std::io::stdout().flush()?;
+    std::io::stdin().read_line(&mut String::new())?;
+    Ok(())
+}
+
+fn configure<T: AsRef<Path>>(config_path: T, args: &ArgMatches) -> Result<()> {
+    let _lock = ConfigLock::acquire(&config_path)?;
+    // read existing or create new config
+    let mut config_file = if let Ok(config_file) = Config::read_from(&config_path) {
+        verify_caller(&config_file, config_path.as_ref())?;
+        config_file
+    } else {
+        Config::new()
+    };
+
+    // before associating, so that e.g. a missing YubiKey doesn't leave an unused association
+    // behind in KeePassXC
+    let encryption = args
+        .subcommand_matches("configure")
+        .filter(|m| m.is_present("encrypt"))
+        .map(|m| m.value_of("encrypt").unwrap_or_default());
+    if let Some(encryption) = encryption {
+        if encryption.is_empty() && config_file.count_encryptions() == 0 {
+            return Err(anyhow!(
+                "No encryption profile to reuse, pass one, e.g. --encrypt challenge-response"
+            ));
+        }
+        if config_file.count_encryptions() > 0 && !encryption.is_empty() {
+            handle_secondary_encryption(&mut config_file)?;
+        }
+        // this will error if an existing encryption profile has already been configured for the
+        // underlying hardware/etc
+        // in this case user should decrypt the configuration first
+        config_file.add_encryption(encryption)?;
+    }
+
+    // start session
+    let (client_id, session_seckey, _) = start_session()?;
+    let session_pubkey = session_seckey.public_key();
+
+    let configure_args = args
+        .subcommand_matches("configure")
+        .ok_or_else(|| anyhow!("Failed to get subcommand arguments"))?;
+    if !configure_args.is_present("multiple") {
+        let database_json = configure_open_database(
+            &config_path,
+            &mut config_file,
+            configure_args,
+            encryption,
+            &client_id,
+            &session_pubkey,
+        )?;
+        if is_json(args) {
+            println!(
+                "{}",
+                output::json_document(serde_json::json!({ "database": database_json }))
+            );
+        }
+        return Ok(());
+    }
+
+    // KeePassXC only talks to the active database, so the user switches between them
+    let mut configured_hashes = HashSet::new();
+    let mut databases_json = Vec::new();
+    loop {
+        let gh_resp = GetDatabaseHashRequest::new().send(&client_id, true)?;
+        let hash = gh_resp
+            .hash
+            .ok_or_else(|| anyhow!("Failed to get the hash of the active database"))?;
+        if configured_hashes.contains(&hash) {
+            warn!("The active database has been configured already in this run");
+        } else {
+            databases_json.push(configure_open_database(
+                &config_path,
+                &mut config_file,
+                configure_args,
+                encryption,
+                &client_id,
+                &session_pubkey,
+            )?);
+            configured_hashes.insert(hash);
+        }
+        if !confirm_on_terminal(
+            "Switch to another open database in KeePassXC to configure it too, continue?",
+        )? {
+            break;
+        }
+    }
+
+    if is_json(args) {
+        println!(
+            "{}",
+            output::json_document(serde_json::json!({ "databases": databases_json }))
+        );
+    }
+    Ok(())
+}
+
+/// Associates the active database, or updates its profile if it's associated already, and saves
+/// the configuration
+fn configure_open_database<T: AsRef<Path>>(
+    config_path: T,
+    config_file: &mut Config,
+    configure_args: &ArgMatches,
+    encryption: Option<&str>,
+    client_id: &str,
+    session_pubkey: &PublicKey,
+) -> Result<serde_json::Value> {
+    let existing = if configure_args.is_present("force-new") {
+        None
+    } else {
+        find_associated_database(config_file, client_id)
+    };
+    let group_name = configure_args
+        .value_of("group")
+        .expect("Group name not specified (there's a default one though, bug?)");
+    let create_group = |client_id: &str| resolve_group_path(client_id, group_name);
+    let (mut database, existing_index) = match existing {
+        Some((index, mut database)) => {
+            info!(
+                "Database {} is associated already, updating its profile instead (use --force-new to associate again)",
+                database.id
+            );
+            // keep the group unless another one is given
+            if configure_args.occurrences_of("group") > 0 {
+                let group = create_group(client_id)?;
+                database.group = group.name;
+                database.group_uuid = group.uuid;
+            }
+            (database, Some(index))
+        }
+        None => {
+            // generate permanent client key for future authentication
+            let id_seckey = generate_secret_key();
+            let id_pubkey = id_seckey.public_key();
+
+            let aso_req = AssociateRequest::new(session_pubkey, &id_pubkey);
+            let aso_resp = aso_req.send(client_id, false)?;
+            let database_id = aso_resp.id.ok_or_else(|| anyhow!("Association failed"))?;
+            let group = create_group(client_id)?;
+            (Database::new(database_id, id_seckey, group), None)
+        }
+    };
+    if let Some(extra_groups) = configure_args.values_of("extra-group") {
+        database.extra_groups = extra_groups
+            .map(|group_path| Ok(resolve_group_path(client_id, group_path)?.name))
+            .collect::<Result<_>>()?;
+    }
+    if let Some(alias) = configure_args.value_of("alias") {
+        database.alias = Some(alias.to_owned());
+    }
+    database.hash = GetDatabaseHashRequest::new().send(client_id, false)?.hash;
+
+    // save new config
+    info!(
+        "Saving configuration to {}",
+        config_path.as_ref().to_string_lossy()
+    );
+    let encrypted = match existing_index {
+        Some(index) => encryption.is_some() || index >= config_file.count_plain_text_databases(),
+        None => encryption.is_some(),
+    };
+    let database_json = serde_json::json!({
+        "id": database.id,
+        "alias": database.alias,
+        "group": database.group,
+        "extra_groups": database.extra_groups,
+        "encrypted": encrypted,
+        "existing": existing_index.is_some(),
+    });
+    match existing_index {
+        Some(index) => config_file.replace_database(index, database, encrypted)?,
+        None => config_file.add_database(database, encrypted)?,
+    }
+    config_file.write_to(&config_path)?;
+    Ok(database_json)
+}
+
+/// Looks up a group by its `/`-separated path, creating the missing groups along it, and returns
+/// it with the whole path as its name
+fn resolve_group_path(client_id: &str, group_path: &str) -> Result<Group> {
+    let group_path = group_path
+        .split('/')
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    if group_path.is_empty() {
+        return Err(anyhow!("Empty group name"));
+    }
+    match GetDatabaseGroupsRequest::new().send(client_id, false) {
+        Ok(gdg_resp) => {
+            if let Some(group) = Group::find_path(gdg_resp.get_groups(), &group_path) {
+                info!("Group {} exists already", group_path);
+                return Ok(Group::new(group_path, group.uuid.clone()));
+            }
+        }
+        Err(e) => {
+            warn!("Failed to get groups of the database, {}", e);
+        }
+    }
+    // KeePassXC creates the missing ones along the path, or returns the existing group
+    let cng_req = CreateNewGroupRequest::new(group_path.as_str());
+    let cng_resp = cng_req.send(client_id, false)?;
+    Ok(Group::new(group_path, cng_resp.uuid))
+}
+
+/// Index as in `Config::list_databases()` and profile of the database open in KeePassXC, if it's
+/// associated already, so that `configure` doesn't associate it again
+fn find_associated_database(config: &Config, client_id: &str) -> Option<(usize, Database)> {
+    let databases = config.list_databases().unwrap_or_else(|e| {
+        // plain text ones come first, so indices stay the same
+        warn!(
+            "Failed to decrypt database profiles, only checking plain text ones, {}",
+            e
+        );
+        config
+            .get_plain_text_databases()
+            .iter()
+            .map(|database| (database.clone(), false))
+            .collect()
+    });
+    databases
+        .into_iter()
+        .map(|(database, _)| database)
+        .enumerate()
+        .find(|(_, database)| {
+            test_associate(database, client_id, false) == Association::Authenticated
+        })
+}
+
+/// Associates an existing database profile again and updates its ID (and key) in place
+fn reassociate<T: AsRef<Path>>(config_path: T, args: &ArgMatches) -> Result<()> {
+    let _lock = ConfigLock::acquire(&config_path)?;
+    let mut config_file = Config::read_from(&config_path)?;
+    verify_caller(&config_file, config_path.as_ref())?;
+    let reassociate_args = args
+        .subcommand_matches("reassociate")
+        .ok_or_else(|| anyhow!("Failed to get subcommand arguments"))?;
+    let databases = config_file.list_databases()?;
+    let (client_id, session_seckey, _) = start_session()?;
+
+    let (index, (mut database, encrypted)) = match reassociate_args.value_of("DATABASE") {
+        Some(id_or_alias) => databases
+            .into_iter()
+            .enumerate()
+            .find(|(_, (database, _))| {
+                database.id == id_or_alias || database.alias.as_deref() == Some(id_or_alias)
+            })
+            .ok_or_else(|| anyhow!("No database profile {}", id_or_alias))?,
+        None => {
+            let mut rejected: Vec<_> = databases
+                .into_iter()
+                .enumerate()
+                .filter(|(_, (database, _))| {
+                    test_associate(database, &client_id, false) == Association::Rejected
+                })
+                .collect();
+            match rejected.len() {
+                0 => {
+                    warn!("No database profile is rejected by KeePassXC, give one to associate again anyway (or unlock its database if it's locked)");
+                    return Ok(());
+                }
+                1 => rejected.remove(0),
+                _ => {
+                    let names: Vec<_> = rejected
+                        .iter()
+                        .map(|(_, (database, _))| database.alias.as_deref().unwrap_or(&database.id))
+                        .collect();
+                    return Err(anyhow!(
+                        "{} database profiles are rejected by KeePassXC, give one of {}",
+                        rejected.len(),
+                        names.join(", ")
+                    ));
+                }
+            }
+        }
+    };
+
+    let id_pubkey = if reassociate_args.is_present("new-key") {
+        let id_seckey = generate_secret_key();
+        let id_pubkey = id_seckey.public_key();
+        database.key = base64::encode(id_seckey.to_bytes());
+        database.pkey = base64::encode(id_pubkey.as_bytes());
+        id_pubkey
+    } else {
+        to_public_key(&database.pkey)?
+    };
+    info!(
+        "Associating database profile {} again, make sure its database is the one open in KeePassXC",
+        database.id
+    );
+    let aso_req = AssociateRequest::new(&session_seckey.public_key(), &id_pubkey);
+    let aso_resp = aso_req.send(&client_id, false)?;
+    let database_id = aso_resp.id.ok_or_else(|| anyhow!("Association failed"))?;
+    if database_id != database.id {
+        info!("Database profile {} is now {}", database.id, database_id);
+        let count_rules = config_file.rename_database_in_rules(&database.id, &database_id);
+        info!("{} routing rule(s) updated", count_rules);
+        database.id = database_id;
+    }
+    database.hash = GetDatabaseHashRequest::new().send(&client_id, false)?.hash;
+    config_file.replace_database(index, database, encrypted)?;
+    config_file.write_to(&config_path)
+}
+
+/// Removes database profiles, e.g. of a decommissioned database, whether encrypted or not
+fn forget_database<T: AsRef<Path>>(config_path: T, args: &ArgMatches) -> Result<()> {
+    let _lock = ConfigLock::acquire(&config_path)?;
+    let mut config_file = Config::read_from(&config_path)?;
+    verify_caller(&config_file, config_path.as_ref())?;
+    let id_or_alias = args
+        .subcommand_matches("forget-database")
+        .and_then(|m| m.value_of("DATABASE"))
+        .ok_or_else(|| anyhow!("Must specify database"))?;
+    let matches = |database: &Database| {
+        database.id == id_or_alias || database.alias.as_deref() == Some(id_or_alias)
+    };
+    let forgotten: Vec<_> = config_file
+        .list_databases()?
+        .into_iter()
+        .map(|(database, _)| database)
+        .filter(|database| matches(database))
+        .collect();
+    let count_removed = config_file.remove_databases(|_, database| matches(database))?;
+    if count_removed == 0 {
+        return Err(anyhow!("No matching database profile found"));
+    }
+    info!("{} database profile(s) removed", count_removed);
+    let count_rules_removed: usize = forgotten
+        .iter()
+        .map(|database| config_file.remove_rules_for(database))
+        .sum();
+    if count_rules_removed > 0 {
+        info!("{} routing rule(s) removed", count_rules_removed);
+    }
+    config_file.write_to(config_path)?;
+    if is_json(args) {
+        println!(
+            "{}",
+            output::json_document(serde_json::json!({ "removed": count_removed }))
+        );
+    }
+    Ok(())
+}
+
+fn encrypt<T: AsRef<Path>>(config_path: T, args: &ArgMatches) -> Result<()> {
+    let _lock = ConfigLock::acquire(&config_path)?;
+    let mut config_file = Config::read_from(&config_path)?;
+    verify_caller(&config_file, config_path.as_ref())?;
+
+    let encryption = args
+        .subcommand_matches("encrypt")
+        .and_then(|m| m.value_of("ENCRYPTION_PROFILE"));
+
+    let count_databases_to_encrypt =
+        config_file.count_databases() - config_file.count_encrypted_databases();
+    let count_callers_to_encrypt =
+        config_file.count_callers() - config_file.count_encrypted_callers();
+    if count_databases_to_encrypt == 0
+        && count_callers_to_encrypt == 0
+        && encryption.map(|m| m.is_empty()).unwrap_or_else(|| true)
+    {
+        warn!("Database and callers profiles have already been encrypted");
+        return Ok(());
+    }
+    info!(
+        "{} database profile(s) to encrypt",
+        count_databases_to_encrypt
+    );
+    info!(
+        "{} caller profile(s) to encrypt",
+        count_databases_to_encrypt
+    );
+
+    if let Some(encryption) = encryption {
+        if config_file.count_encryptions() > 0 && !encryption.is_empty() {
+            handle_secondary_encryption(&mut config_file)?;
+        }
+        // this will error if an existing encryption profile has already been configured for the
+        // underlying hardware/etc
+        // in this case user should decrypt the configuration first
+        config_file.add_encryption(encryption)?;
+    }
+
+    let count_databases_encrypted = config_file.encrypt_databases()?;
+    let count_callers_encrypted = config_file.encrypt_callers()?;
+    info!(
+        "{} database profile(s) encrypted",
+        count_databases_encrypted
+    );
+    info!("{} caller profile(s) encrypted", count_callers_encrypted);
+
+    config_file.write_to(config_path)?;
+
+    Ok(())
+}
+
+fn decrypt<T: AsRef<Path>>(config_path: T) -> Result<()> {
+    let _lock = ConfigLock::acquire(&config_path)?;
+    let mut config_file = Config::read_from(&config_path)?;
+    verify_caller(&config_file, config_path.as_ref())?;
+
+    let count_databases_to_decrypt = config_file.count_encrypted_databases();
+    let count_callers_to_decrypt = config_file.count_encrypted_callers();
+    if count_databases_to_decrypt == 0 && count_callers_to_decrypt == 0 {
+        warn!("Database and callers profiles have already been decrypted");
+        return Ok(());
+    }
+    info!(
+        "{} database profile(s) to decrypt",
+        count_databases_to_decrypt
+    );
+    info!("{} caller profile(s) to decrypt", count_callers_to_decrypt);
+
+    config_file.decrypt_databases()?;
+    config_file.decrypt_callers()?;
+    if config_file.count_encrypted_databases() == 0 && config_file.count_encrypted_callers() == 0 {
+        config_file.clear_encryptions();
+    }
+
+    config_file.write_to(config_path)?;
+
+    Ok(())
+}
+
+fn manage_config<T: AsRef<Path>>(config_path: T, args: &ArgMatches) -> Result<()> {
+    let _lock = ConfigLock::acquire(&config_path)?;
+    let mut config_file = Config::read_from(&config_path)?;
+    verify_caller(&config_file, config_path.as_ref())?;
+
+    let subcommand = args.subcommand_matches("config").unwrap();
+    match subcommand.subcommand() {
+        ("dedup", _) => {
+            let count_databases_removed = config_file.dedup_databases()?;
+            let count_callers_removed = config_file.dedup_callers()?;
+            info!(
+                "{} duplicate database profile(s) removed",
+                count_databases_removed
+            );
+            info!(
+                "{} duplicate caller profile(s) removed",
+                count_callers_removed
+            );
+            if count_databases_removed == 0 && count_callers_removed == 0 {
+                warn!("No duplicate profiles found");
+                return Ok(());
+            }
+            config_file.write_to(config_path)
+        }
+        ("edit", _) => {
+            let edit_path = state_file_path(&config_path, "edit.json");
+            let result = edit_config(config_path.as_ref(), config_file, &edit_path);
+            // it may contain decrypted profiles
+            if edit_path.exists() {
+                if let Err(e) = std::fs::remove_file(&edit_path) {
+                    error!("Failed to remove {}, {}", edit_path.to_string_lossy(), e);
+                }
+            }
+            result
+        }
+        ("fix-perms", _) => {
+            let fixed = config::fix_permissions(&config_path)?;
+            if fixed.is_empty() {
+                warn!("Permissions are fine already");
+            }
+            for path in fixed {
+                info!(
+                    "Removed access of other users to {}",
+                    path.to_string_lossy()
+                );
+            }
+            Ok(())
+        }
+        _ => Err(anyhow!("No subcommand selected")),
+    }
+}
+
+/// Runs the editor of the user on a file, through the shell like Git does, as it may have
+/// arguments
+fn run_editor(path: &Path) -> Result<()> {
+    ensure_interactive("Editing")?;
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_owned());
+    let status = if cfg!(windows) {
+        std::process::Command::new("cmd")
+            .arg("/C")
+            .arg(format!("{} \"{}\"", editor, path.to_string_lossy()))
+            .status()
+    } else {
+        std::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", editor))
+            .arg(&editor)
+            .arg(path)
+            .status()
+    }
+    .map_err(|e| anyhow!("Failed to run editor {}, {}", editor, e))?;
+    if !status.success() {
+        return Err(anyhow!("Editor {} failed: {}", editor, status));
+    }
+    Ok(())
+}
+
+/// Lets the user edit a decrypted copy of the configuration at `edit_path`, then encrypts the
+/// profiles that were encrypted again and replaces the configuration with it if it's valid
+fn edit_config(config_path: &Path, mut config_file: Config, edit_path: &Path) -> Result<()> {
+    // so that e.g. YubiKey is only asked once
+    Config::keep_encryption_keys(None);
+    let encrypt_databases = config_file.count_encrypted_databases() > 0;
+    let encrypt_callers = config_file.count_encrypted_callers() > 0;
+    config_file.decrypt_databases()?;
+    config_file.decrypt_callers()?;
+    if config_file.count_encrypted_databases() > 0 || config_file.count_encrypted_callers() > 0 {
+        return Err(anyhow!(
+            "Failed to decrypt all profiles, which can't be edited then"
+        ));
+    }
+    config_file.write_to(edit_path)?;
+    let mut edited_config = loop {
+        run_editor(edit_path)?;
+        let result = Config::read_from(edit_path).and_then(|config| {
+            config.validate()?;
+            Ok(config)
+        });
+        match result {
+            Ok(config) => break config,
+            Err(e) => {
+                error!("{}, Caused by: {}", e, e.root_cause());
+                if !confirm_on_terminal("Invalid configuration, edit again?")? {
+                    return Err(anyhow!("Invalid configuration, changes discarded"));
+                }
+            }
+        }
+    };
+    if encrypt_databases && edited_config.count_databases() > 0 {
+        edited_config.encrypt_databases()?;
+    }
+    if encrypt_callers && edited_config.count_callers() > 0 {
+        edited_config.encrypt_callers()?;
+    }
+    // renaming replaces the configuration atomically
+    edited_config.write_to(edit_path)?;
+    std::fs::rename(edit_path, config_path).map_err(|e| {
+        anyhow!(
+            "Failed to replace configuration {}, {}",
+            config_path.to_string_lossy(),
+            e
+        )
+    })?;
+    info!("Configuration saved to {}", config_path.to_string_lossy());
+    Ok(())
+}
+
+fn caller<T: AsRef<Path>>(config_path: T, args: &ArgMatches) -> Result<()> {
+    let _lock = ConfigLock::acquire(&config_path)?;
+    // read existing or create new config
+    let mut config_file = if let Ok(config_file) = Config::read_from(&config_path) {
+        verify_caller(&config_file, config_path.as_ref())?;
+        config_file
+    } else {
+        Config::new()
+    };
+
+    let json = is_json(args);
+    let subcommand = args.subcommand_matches("caller").unwrap();
+    let (caller, encryption) = match subcommand.subcommand() {
+        ("list", _) if json => {
+            let count_encrypted_callers = config_file.count_encrypted_callers();
+            let mut callers = Vec::new();
+            for (idx, (caller, encrypted)) in config_file.list_callers()?.into_iter().enumerate() {
+                let mut caller_json = serde_json::to_value(caller)?;
+                caller_json["index"] = idx.into();
+                caller_json["encrypted"] = encrypted.into();
+                caller_json["ignored"] = (!encrypted && count_encrypted_callers > 0).into();
+                callers.push(caller_json);
+            }
+            println!(
+                "{}",
+                output::json_document(serde_json::json!({ "callers": callers }))
+            );
+            return Ok(());
+        }
+        ("list", _) => {
+            let count_encrypted_callers = config_file.count_encrypted_callers();
+            for (idx, (caller, encrypted)) in config_file.list_callers()?.iter().enumerate() {
+                let flag = if *encrypted {
+                    " [encrypted]"
+                } else if count_encrypted_callers > 0 {
+                    // see Config::get_callers()
+                    " [ignored]"
+                } else {
+                    ""
+                };
+                println!("{}: {}{}", idx, caller, flag);
+            }
+            return Ok(());
+        }
+        ("remove", Some(remove_args)) => {
+            let target = remove_args
+                .value_of("CALLER")
+                .ok_or_else(|| anyhow!("Must specify caller"))?;
+            let count_removed = if let Ok(index) = usize::from_str(target) {
+                config_file.remove_callers(|idx, _| idx == index)?
+            } else {
+                config_file.remove_callers(|_, caller| caller.path == target)?
+            };
+            if count_removed == 0 {
+                return Err(anyhow!("No matching caller profile found"));
+            }
+            info!("{} caller profile(s) removed", count_removed);
+            config_file.write_to(config_path)?;
+            if json {
+                println!(
+                    "{}",
+                    output::json_document(serde_json::json!({ "removed": count_removed }))
+                );
+            }
+            return Ok(());
+        }
+        ("add", Some(add_args)) => {
+            let path = add_args
+                .value_of("PATH")
+                .ok_or_else(|| anyhow!("Must specify path"))?;
+            if !Path::new(path).is_absolute() {
+                return Err(anyhow!("Caller path must be absolute: {}", path));
+            }
+            // resolve symlinks now, so that they can't be redirected later
+            let path = match Path::new(path).canonicalize() {
+                Ok(canonical_path) => {
+                    let canonical_path = canonical_path.to_string_lossy().into_owned();
+                    if canonical_path != path {
+                        info!("Caller path {} resolved to {}", path, canonical_path);
+                    }
+                    canonical_path
+                }
+                Err(_) => {
+                    warn!("Caller executable {} does not exist", path);
+                    path.to_owned()
+                }
+            };
+            let (device, inode) = if add_args.is_present("pin") {
+                let (device, inode) = get_file_id(&path)?;
+                (Some(device), Some(inode))
+            } else {
+                (None, None)
+            };
+            let caller = Caller {
+                path: path.clone(),
+                uid: if let Some(id) = add_args.value_of("uid") {
+                    Some(u32::from_str(id).map_err(|_| anyhow!("Invalid UID"))?)
+                } else {
+                    None
+                },
+                gid: if let Some(id) = add_args.value_of("gid") {
+                    Some(u32::from_str(id).map_err(|_| anyhow!("Invalid GID"))?)
+                } else {
+                    None
+                },
+                sha256: if add_args.is_present("sha256") {
+                    Some(sha256_file(&path)?)
+                } else {
+                    None
+                },
+                ancestor: add_args.is_present("ancestor"),
+                code_requirement: add_args.value_of("code-requirement").map(str::to_owned),
+                device,
+                inode,
+                urls: add_args
+                    .values_of("url")
+                    .map(|urls| urls.map(str::to_owned).collect())
+                    .unwrap_or_default(),
+                group: add_args.value_of("group").map(str::to_owned),
+            };
+            (caller, add_args.value_of("encrypt"))
+        }
+        ("me", Some(me_args)) => {
+            let system = System::new_all();
+            let (proc, pproc) = get_current_and_parent_process(&system)?;
+            #[cfg(unix)]
+            let (uid, gid) = (Some(proc.uid), Some(proc.gid));
+            #[cfg(windows)]
+            let (uid, gid) = {
+                let _ = proc;
+                (None, None)
+            };
+            let (device, inode) = if me_args.is_present("pin") {
+                let (device, inode) = get_file_id(pproc.exe())?;
+                (Some(device), Some(inode))
+            } else {
+                (None, None)
+            };
+            let caller = Caller {
+                path: pproc.exe().to_string_lossy().into_owned(),
+                uid,
+                gid,
+                sha256: if me_args.is_present("sha256") {
+                    Some(sha256_file(pproc.exe())?)
+                } else {
+                    None
+                },
+                ancestor: false,
+                code_requirement: None,
+                device,
+                inode,
+                urls: me_args
+                    .values_of("url")
+                    .map(|urls| urls.map(str::to_owned).collect())
+                    .unwrap_or_default(),
+                group: me_args.value_of("group").map(str::to_owned),
+            };
+            // keep stdout for the JSON document
+            let mut prompt: Box<dyn Write> = if json {
+                Box::new(io::stderr())
+            } else {
+                Box::new(io::stdout())
+            };
+            writeln!(
+                prompt,
+                "Parent process: {} (PID: {})",
+                caller.path,
+                pproc.pid()
+            )?;
+            #[cfg(unix)]
+            writeln!(prompt, "UID: {}, GID: {}", proc.uid, proc.gid)?;
+            if let Some(ref sha256) = caller.sha256 {
+                writeln!(prompt, "SHA-256: {}", sha256)?;
+            }
+            if let (Some(device), Some(inode)) = (caller.device, caller.inode) {
+                writeln!(prompt, "Device: {}, inode: {}", device, inode)?;
+            }
+            ensure_interactive("Confirming the caller")?;
+            if !io::stdin().is_terminal() {
+                return Err(anyhow!(
+                    "Confirming the caller needs a terminal, use caller add instead"
+                ));
+            }
+            write!(prompt, "Add it as an allowed caller? [y/N] ")?;
+            prompt.flush()?;
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+            if !answer.trim().eq_ignore_ascii_case("y") {
+                info!("Caller profile not added");
+                if json {
+                    println!(
+                        "{}",
+                        output::json_document(serde_json::json!({ "added": false }))
+                    );
+                }
+                return Ok(());
+            }
+            (caller, me_args.value_of("encrypt"))
+        }
+        ("clear", _) => {
+            let count_removed = config_file.count_callers();
+            config_file.clear_callers();
+            config_file.write_to(config_path)?;
+            if json {
+                println!(
+                    "{}",
+                    output::json_document(serde_json::json!({ "removed": count_removed }))
+                );
+            }
+            return Ok(());
+        }
+        _ => return Err(anyhow!("No subcommand selected")),
+    };
+
+    if config_file.get_callers()?.contains(&caller) {
+        warn!("Caller profile for {} already exists", caller.path);
+        if json {
+            println!(
+                "{}",
+                output::json_document(serde_json::json!({ "added": false }))
+            );
+        }
+        return Ok(());
+    }
+    if let Some(encryption) = encryption {
+        // this will error if an existing encryption profile has already been configured for the
+        // underlying hardware/etc
+        // in this case user should decrypt the configuration first
+        config_file.add_encryption(encryption)?;
+    }
+    config_file.add_caller(caller, encryption.is_some())?;
+    config_file.write_to(config_path)?;
+    if json {
+        println!(
+            "{}",
+            output::json_document(serde_json::json!({ "added": true }))
+        );
+    }
+    Ok(())
+}
+
+/// Values of a Git configuration key, empty if it's not set
+fn git_config_values(scope: &str, key: &str) -> Result<Vec<String>> {
+    let output = std::process::Command::new("git")
+        .args(["config", scope, "--get-all", key])
+        .output()
+        .map_err(|e| anyhow!("Failed to run git, {}", e))?;
+    match output.status.code() {
+        Some(0) => Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_owned)
+            .collect()),
+        // the key isn't set
+        Some(1) => Ok(Vec::new()),
+        _ => Err(anyhow!(
+            "git config {} --get-all {} failed: {}",
+            scope,
+            key,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+/// Whether a `credential.helper` value refers to git-credential-keepassxc, with or without
+/// arguments
+fn is_keepassxc_helper(helper: &str) -> bool {
+    helper == "keepassxc" || helper.starts_with("keepassxc ")
+}
+
+fn install(args: &ArgMatches) -> Result<()> {
+    let install_args = args
+        .subcommand_matches("install")
+        .ok_or_else(|| anyhow!("Failed to get subcommand arguments"))?;
+    let scope = if install_args.is_present("system") {
+        "--system"
+    } else if install_args.is_present("local") {
+        "--local"
+    } else {
+        "--global"
+    };
+    let sections: Vec<String> = match install_args.values_of("url") {
+        Some(urls) => urls.map(|url| format!("credential.{}", url)).collect(),
+        None => vec!["credential".to_owned()],
+    };
+    let use_http_path = install_args.is_present("use-http-path");
+    for section in sections {
+        let helper_key = format!("{}.helper", section);
+        let http_path_key = format!("{}.useHttpPath", section);
+        if install_args.is_present("remove") {
+            if git_config_values(scope, &helper_key)?
+                .iter()
+                .any(|helper| is_keepassxc_helper(helper))
+            {
+                command_output(
+                    "git",
+                    &[
+                        "config",
+                        scope,
+                        "--unset-all",
+                        &helper_key,
+                        "^keepassxc( |$)",
+                    ],
+                    None,
+                )?;
+                info!("Removed {}", helper_key);
+            } else {
+                warn!("{} doesn't include git-credential-keepassxc", helper_key);
+            }
+            if use_http_path && !git_config_values(scope, &http_path_key)?.is_empty() {
+                command_output(
+                    "git",
+                    &["config", scope, "--unset-all", &http_path_key],
+                    None,
+                )?;
+                info!("Removed {}", http_path_key);
+            }
+            continue;
+        }
+        if git_config_values(scope, &helper_key)?
+            .iter()
+            .any(|helper| is_keepassxc_helper(helper))
+        {
+            warn!("{} already includes git-credential-keepassxc", helper_key);
+        } else {
+            command_output(
+                "git",
+                &["config", scope, "--add", &helper_key, "keepassxc"],
+                None,
+            )?;
+            info!("Set {} to keepassxc", helper_key);
+        }
+        if use_http_path {
+            command_output("git", &["config", scope, &http_path_key, "true"], None)?;
+            info!("Set {} to true", http_path_key);
+        }
+    }
+    Ok(())
+}
+
+/// Completes --database with the plain text database profiles, since decrypting the others on
+/// every tab is out of the question
+const BASH_DATABASE_COMPLETION: &str = r#"
+_git_credential_keepassxc_databases() {
+    if [[ "${COMP_WORDS[COMP_CWORD-1]}" == "--database" ]]; then
+        COMPREPLY=($(compgen -W "$(git-credential-keepassxc completions --list-databases 2>/dev/null)" -- "${COMP_WORDS[COMP_CWORD]}"))
+        return 0
+    fi
+    _git-credential-keepassxc "$@"
+}
+complete -F _git_credential_keepassxc_databases -o bashdefault -o default git-credential-keepassxc
+"#;
+
+const FISH_DATABASE_COMPLETION: &str = r#"
+complete -c git-credential-keepassxc -l database -x -a "(git-credential-keepassxc completions --list-databases 2>/dev/null)"
+"#;
+
+fn completions<T: AsRef<Path>>(config_path: T, args: &ArgMatches) -> Result<()> {
+    let subcommand_args = args
+        .subcommand_matches("completions")
+        .ok_or_else(|| anyhow!("Failed to get subcommand arguments"))?;
+    if subcommand_args.is_present("list-databases") {
+        let config = Config::read_from(config_path.as_ref())?;
+        for database in config.get_plain_text_databases() {
+            println!("{}", database.id);
+            if let Some(ref alias) = database.alias {
+                println!("{}", alias);
+            }
+        }
+        return Ok(());
+    }
+    let shell = subcommand_args
+        .value_of("SHELL")
+        .ok_or_else(|| anyhow!("No shell given"))?;
+    let shell = clap::Shell::from_str(shell).map_err(|e| anyhow!("{}", e))?;
+    let yaml = clap::load_yaml!("cli.yml");
+    let mut stdout = io::stdout();
+    App::from_yaml(yaml).gen_completions_to(clap::crate_name!(), shell, &mut stdout);
+    match shell {
+        clap::Shell::Bash => stdout.write_all(BASH_DATABASE_COMPLETION.as_bytes())?,
+        clap::Shell::Fish => stdout.write_all(FISH_DATABASE_COMPLETION.as_bytes())?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Times each step of answering `get`, to find out where the time goes
+fn bench<T: AsRef<Path>>(config_path: T, args: &ArgMatches) -> Result<()> {
+    use std::time::Instant;
+
+    let url = args
+        .subcommand_matches("bench")
+        .and_then(|m| m.value_of("url"))
+        .unwrap_or("https://example.com");
+    let report = |step: &str, started: Instant| {
+        println!(
+            "{:<48} {:>10.1} ms",
+            step,
+            started.elapsed().as_secs_f64() * 1000.0
+        );
+    };
+
+    let started = Instant::now();
+    let config = Config::read_from(config_path.as_ref())?;
+    report("Read configuration", started);
+    let mut databases = config.get_databases_in_tier_for(url, false)?;
+    if config.count_encrypted_databases() > 0 {
+        let started = Instant::now();
+        databases.extend(config.get_databases_in_tier_for(url, true)?);
+        report("Decrypt database profiles", started);
+    }
+
+    let started = Instant::now();
+    connect_to_keepassxc()?;
+    report("Connect to KeePassXC", started);
+    let started = Instant::now();
+    let (client_id, _, _) = start_session()?;
+    report("Exchange keys", started);
+
+    for database in &databases {
+        let started = Instant::now();
+        let association = test_associate(database, &client_id, false);
+        report(&format!("Test associate {}", database.id), started);
+        if association != Association::Authenticated {
+            println!("  not associated, skipping");
+            continue;
+        }
+        let started = Instant::now();
+        let entries = request_logins(client_id.as_str(), url, database)?;
+        report(&format!("Get logins from {}", database.id), started);
+        println!("  {} login(s) for {}", entries.len(), url);
+    }
+    Ok(())
+}
+
+/// Copies the logins in the files of git credential-store and .netrc, or the keychain of the OS,
+/// into KeePassXC
+fn import_logins<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let config = Config::read_from(config_path.as_ref())?;
+    let import_args = args
+        .subcommand_matches("import")
+        .ok_or_else(|| anyhow!("No subcommand selected"))?;
+    // global argument, given either before or after the subcommand
+    if config.is_read_only() || args.is_present("read-only") || import_args.is_present("read-only")
+    {
+        return Err(anyhow!("Read-only mode, not importing logins"));
+    }
+    let home_dir = directories_next::BaseDirs::new()
+        .ok_or_else(|| anyhow!("Failed to initialise base_dirs"))?
+        .home_dir()
+        .to_owned();
+    let netrc_name = if cfg!(windows) { "_netrc" } else { ".netrc" };
+    // both default files if no source is given
+    let import_all = !import_args.is_present("git-credentials")
+        && !import_args.is_present("netrc")
+        && !import_args.is_present("keychain");
+    let mut sources: Vec<(PathBuf, bool, import::Parser)> = Vec::new();
+    if import_all || import_args.is_present("git-credentials") {
+        let path = import_args.value_of("git-credentials");
+        sources.push((
+            path.map(PathBuf::from)
+                .unwrap_or_else(|| home_dir.join(".git-credentials")),
+            path.is_none(),
+            import::parse_git_credentials,
+        ));
+    }
+    if import_all || import_args.is_present("netrc") {
+        let path = import_args.value_of("netrc");
+        sources.push((
+            path.map(PathBuf::from)
+                .unwrap_or_else(|| home_dir.join(netrc_name)),
+            path.is_none(),
+            import::parse_netrc,
+        ));
+    }
+
+    let group = import_args.value_of("group");
+    for (path, is_default, parse) in sources {
+        if is_default && !path.exists() {
+            info!("{} doesn't exist, skipping", path.to_string_lossy());
+            continue;
+        }
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Failed to read {}, {}", path.to_string_lossy(), e))?;
+        let logins = parse(&text);
+        let failures = store_imported_logins(&config_path, unlock_options, args, &logins, group);
+        if !QUIET_OUTPUT.load(Ordering::Relaxed) {
+            println!(
+                "Imported {} of {} login(s) from {}",
+                logins.len() - failures,
+                logins.len(),
+                path.to_string_lossy()
+            );
+        }
+        if import_args.is_present("shred") {
+            if failures > 0 {
+                warn!(
+                    "Not shredding {} as some logins failed to import",
+                    path.to_string_lossy()
+                );
+            } else {
+                shred_file(&path)?;
+                if !QUIET_OUTPUT.load(Ordering::Relaxed) {
+                    println!("Shredded {}", path.to_string_lossy());
+                }
+            }
+        }
+    }
+
+    if import_args.is_present("keychain") {
+        let helper = match import_args.value_of("keychain") {
+            Some(name) => KeychainHelper::from_str(name)?,
+            None => KeychainHelper::for_current_os(),
+        };
+        let logins = read_keychain_logins(helper)?;
+        let failures = store_imported_logins(&config_path, unlock_options, args, &logins, group);
+        if !QUIET_OUTPUT.load(Ordering::Relaxed) {
+            println!(
+                "Imported {} of {} login(s) from git-credential-{}",
+                logins.len() - failures,
+                logins.len(),
+                helper.name()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Stores imported logins, returning the number of failures
+fn store_imported_logins<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+    logins: &[ImportedLogin],
+    group: Option<&str>,
+) -> usize {
+    let mut failures = 0;
+    for login in logins {
+        let git_req = GitCredentialMessage {
+            url: Some(login.url.clone()),
+            username: Some(login.username.clone()),
+            password: Some(login.password.clone()),
+            ..Default::default()
+        };
+        let result = store_login_in_group(
+            &config_path,
+            unlock_options,
+            args,
+            &mut git_req.to_string().as_bytes(),
+            group,
+        );
+        if let Err(e) = result {
+            error!(
+                "Failed to import login {} for {}, {}",
+                login.username, login.url, e
+            );
+            failures += 1;
+        }
+    }
+    failures
+}
+
+/// Runs a command and returns its standard output
+fn command_output(program: &str, args: &[&str], input: Option<&str>) -> Result<String> {
+    let mut child = std::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to run {}, {}", program, e))?;
+    if let Some(input) = input {
+        child.stdin.take().unwrap().write_all(input.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads the logins saved by a keychain credential helper. libsecret items can be listed along
+/// with their passwords, while the other keychains only list the logins, so their helpers are
+/// asked for the passwords one by one
+fn read_keychain_logins(helper: KeychainHelper) -> Result<Vec<ImportedLogin>> {
+    let requests = match helper {
+        KeychainHelper::Libsecret => {
+            let output = command_output(
+                "secret-tool",
+                &[
+                    "search",
+                    "--all",
+                    "--unlock",
+                    "xdg:schema",
+                    "org.git.Password",
+                ],
+                None,
+            )?;
+            return Ok(import::parse_secret_tool(&output));
+        }
+        KeychainHelper::Osxkeychain => {
+            import::parse_security_dump(&command_output("security", &["dump-keychain"], None)?)
+        }
+        KeychainHelper::Manager => {
+            import::parse_cmdkey(&command_output("cmdkey", &["/list"], None)?)
+        }
+    };
+    let helper_command = format!("credential-{}", helper.name());
+    let mut logins = Vec::new();
+    for request in requests {
+        let result = command_output("git", &[&helper_command, "get"], Some(&request.to_string()))
+            .and_then(|output| Ok(GitCredentialMessage::from_str(&output)?));
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Failed to read the password of {:?}, {}", request, e);
+                continue;
+            }
+        };
+        let request_url = match request.url {
+            Some(url) => url,
+            None => format!(
+                "{}://{}/{}",
+                request.protocol.unwrap_or_default(),
+                request.host.unwrap_or_default(),
+                request.path.unwrap_or_default()
+            ),
+        };
+        match (response.username.or(request.username), response.password) {
+            (Some(username), Some(password)) => logins.push(ImportedLogin {
+                url: request_url.trim_end_matches('/').to_owned(),
+                username,
+                password,
+            }),
+            _ => {
+                info!("No login returned for {}", request_url);
+            }
+        }
+    }
+    Ok(logins)
+}
+
+/// Deletes logins with the same username as an earlier one for the same URL in the same group,
+/// e.g. created by `store` for slightly different URLs, keeping the one `get` and `store` use
+fn prune_logins<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let prune_args = args
+        .subcommand_matches("prune")
+        .ok_or_else(|| anyhow!("No subcommand selected"))?;
+    let config = Config::read_from(config_path.as_ref())?;
+    let verified_caller = verify_caller(&config, config_path.as_ref())?;
+    ensure_configured(&config, config_path.as_ref())?;
+    let apply = prune_args.is_present("apply");
+    if apply && (config.is_read_only() || args.is_present("read-only")) {
+        return Err(anyhow!("Read-only mode, not deleting logins"));
+    }
+    let include_manual = prune_args.is_present("include-manual");
+    let owned_state_path = state_file_path(&config_path, "owned");
+    let mut owned_entries = OwnedEntries::read_from(&owned_state_path)?;
+    let (client_id, _, _) = start_session()?;
+    let databases: Vec<_> = config
+        .list_databases()?
+        .into_iter()
+        .map(|(database, _)| database)
+        .collect();
+
+    let mut pruned = HashSet::new();
+    for url in prune_args.values_of("URL").into_iter().flatten() {
+        let url = config.rewrite_url(url);
+        verify_caller_url(&verified_caller, &url)?;
+        let login_entries =
+            match get_logins_for(&config, &client_id, &url, unlock_options, false, true) {
+                Ok(login_entries) => login_entries,
+                Err(e) if Failure::of(&e) == Some(Failure::NoLoginFound) => {
+                    info!("No logins found for {}", url);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+        // the first one of each username is the one get returns and store updates
+        let mut kept = HashSet::new();
+        for login_entry in &login_entries {
+            // only the groups new logins are stored in, as anywhere else they were moved there
+            let in_configured_group = databases
+                .iter()
+                .filter(|database| login_entry.database_id.as_ref() == Some(&database.id))
+                .flat_map(Database::group_names)
+                .any(|group| login_entry.group.as_deref() == Some(group));
+            let key = (
+                login_entry.database_id.clone(),
+                login_entry.group.clone(),
+                login_entry.login.clone(),
+            );
+            if !in_configured_group || kept.insert(key) || pruned.contains(&login_entry.uuid) {
+                continue;
+            }
+            if !include_manual && !owned_entries.contains(&login_entry.uuid) {
+                info!("Login {} wasn't created by store, kept", login_entry.name);
+                continue;
+            }
+            pruned.insert(login_entry.uuid.clone());
+            if !apply {
+                println!(
+                    "Would delete {} (username: {}, group: {}, database: {}) for {}",
+                    login_entry.name,
+                    login_entry.login,
+                    login_entry.group.as_deref().unwrap_or("?"),
+                    login_entry.database_id.as_deref().unwrap_or("?"),
+                    url
+                );
+                continue;
+            }
+            let de_resp = DeleteEntryRequest::new(&login_entry.uuid).send(&client_id, false)?;
+            if !de_resp.success.as_ref().map(|s| s.0).unwrap_or(false) {
+                return Err(anyhow!("Failed to delete login {}", login_entry.name));
+            }
+            info!("Login {} deleted", login_entry.name);
+            owned_entries.remove(&login_entry.uuid);
+            owned_entries.write_to(&owned_state_path)?;
+        }
+    }
+    if apply {
+        info!("{} duplicate login(s) deleted", pruned.len());
+    } else if pruned.is_empty() {
+        println!("No duplicate logins found");
+    } else {
+        println!("Run again with --apply to delete them");
+    }
+    Ok(())
+}
+
+/// Looks up the login `get` would return for a URL, for the other credential helper protocols
+/// Prints the logins `get` would choose from, the first one being returned
+fn list_logins<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let list_args = args
+        .subcommand_matches("list")
+        .ok_or_else(|| anyhow!("No subcommand selected"))?;
+    let config = Config::read_from(config_path.as_ref())?;
+    let verified_caller = verify_caller(&config, config_path.as_ref())?;
+    let url = &lookup_url(list_args.value_of("url").unwrap());
+    let username = list_args
+        .value_of("username")
+        .map(str::to_owned)
+        .or_else(|| url_username(url).map(str::to_owned))
+        .or_else(|| {
+            config
+                .get_alias_for(url)
+                .and_then(|alias| alias.username.clone())
+        });
+    let url = config.rewrite_url(url);
+    verify_caller_url(&verified_caller, &url)?;
+    if let Some(deny_rule) = config.get_deny_rule_for(&url) {
+        return Err(anyhow!(
+            "{} is denied by pattern {}",
+            url,
+            deny_rule.pattern
+        ));
+    }
+    ensure_configured(&config, config_path.as_ref())?;
+    let login_entries = matching_logins(&config, args, &url, username.as_deref(), unlock_options)?;
+    if is_json(args) {
+        let logins: Vec<_> = login_entries
+            .iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "title": entry.name,
+                    "username": entry.login,
+                    "group": entry.group,
+                    "uuid": entry.uuid,
+                    "database": entry.database_id,
+                    "expired": entry.expired.as_ref().map(|e| e.0).unwrap_or(false),
+                })
+            })
+            .collect();
+        println!(
+            "{}",
+            output::json_document(serde_json::json!({ "logins": logins }))
+        );
+        return Ok(());
+    }
+    for (idx, entry) in login_entries.iter().enumerate() {
+        println!(
+            "{}: {}{}",
+            idx,
+            entry.name,
+            if idx == 0 { " (returned by get)" } else { "" }
+        );
+        println!("  username: {}", entry.login);
+        println!("  group: {}", entry.group.as_deref().unwrap_or("unknown"));
+        println!("  uuid: {}", entry.uuid);
+        println!(
+            "  database: {}",
+            entry.database_id.as_deref().unwrap_or("unknown")
+        );
+        if entry.expired.as_ref().map(|e| e.0).unwrap_or(false) {
+            println!("  expired");
+        }
+    }
+    if login_entries.is_empty() {
+        warn!("No matching logins found");
+    }
+    Ok(())
+}
+
+/// Shows the database KeePassXC has active, and which database profiles it accepts, i.e. which one
+/// logins would come from
+fn list_databases<T: AsRef<Path>>(config_path: T, args: &ArgMatches) -> Result<()> {
+    let config = Config::read_from(config_path.as_ref())?;
+    verify_caller(&config, config_path.as_ref())?;
+    let profiles = config.list_databases()?;
+    let (client_id, _, _) = start_session()?;
+    // the protocol only tells about the active database, not the other ones open in KeePassXC
+    let active_hash = match GetDatabaseHashRequest::new().send(&client_id, false) {
+        Ok(gh_resp) => gh_resp.hash,
+        Err(e)
+            if e.downcast_ref::<KeePassError>()
+                .map(|keepass_error| keepass_error.is_database_locked())
+                .unwrap_or(false) =>
+        {
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    let mut databases = Vec::with_capacity(profiles.len());
+    for (database, encrypted) in profiles {
+        let association = match test_associate(&database, &client_id, false) {
+            Association::Authenticated => "authenticated",
+            Association::Rejected => "rejected",
+            Association::Locked => "locked",
+            Association::Lost => return Err(ConnectionLostError.into()),
+        };
+        // unknown if either hash is
+        let active = match (&database.hash, &active_hash) {
+            (Some(pinned), Some(active_hash)) => Some(pinned == active_hash),
+            _ => None,
+        };
+        databases.push((database, encrypted, association, active));
+    }
+    if is_json(args) {
+        let databases: Vec<_> = databases
+            .iter()
+            .map(|(database, encrypted, association, active)| {
+                serde_json::json!({
+                    "id": database.id,
+                    "alias": database.alias,
+                    "group": database.group,
+                    "encrypted": encrypted,
+                    "hash": database.hash,
+                    "association": association,
+                    "active": active,
+                })
+            })
+            .collect();
+        let active = serde_json::json!({
+            "hash": active_hash,
+            "locked": active_hash.is_none(),
+        });
+        println!(
+            "{}",
+            output::json_document(serde_json::json!({ "active": active, "databases": databases }))
+        );
+        return Ok(());
+    }
+    match active_hash {
+        Some(ref hash) => println!("Active database: {}", hash),
+        None => println!("Active database: locked"),
+    }
+    for (database, encrypted, association, active) in &databases {
+        println!(
+            "{}{}{}",
+            database.id,
+            database
+                .alias
+                .as_ref()
+                .map(|alias| format!(" ({})", alias))
+                .unwrap_or_default(),
+            if *encrypted { " [encrypted]" } else { "" }
+        );
+        println!("  group: {}", database.group);
+        println!("  association: {}", association);
+        match (database.hash.as_deref(), active) {
+            (Some(hash), Some(true)) => println!("  hash: {} (active)", hash),
+            (Some(hash), Some(false)) => println!("  hash: {} (not active)", hash),
+            (Some(hash), None) => println!("  hash: {}", hash),
+            (None, _) => println!("  hash: unknown, run reassociate to record it"),
+        }
+    }
+    if databases.is_empty() {
+        warn!("No database profiles configured");
+    }
+    Ok(())
+}
+
+const UI_MENU: &str = "\
+1) Databases          5) Add caller
+2) Callers            6) Re-associate a database
+3) Routing rules      7) Test a URL lookup
+4) Recent audit events
+q) Quit";
+
+/// Menu on the terminal to look through the configuration and recent audit events, and to run the
+/// common maintenance subcommands without remembering their arguments
+fn ui<T: AsRef<Path>>(config_path: T, unlock_options: &Option<UnlockOptions>) -> Result<()> {
+    let config = Config::read_from(config_path.as_ref())?;
+    verify_caller(&config, config_path.as_ref())?;
+    // empty answers are errors for prompt_on_terminal()
+    let ask = |prompt: &str| prompt_on_terminal(prompt, false).ok();
+    loop {
+        println!("\n{}", UI_MENU);
+        let choice = match ask("Choice") {
+            Some(choice) => choice,
+            None => continue,
+        };
+        let argv: Vec<String> = match choice.trim() {
+            "1" => vec!["databases".to_owned()],
+            "2" => vec!["caller".to_owned(), "list".to_owned()],
+            "3" => {
+                print_rules(&Config::read_from(config_path.as_ref())?);
+                continue;
+            }
+            "4" => {
+                if let Err(e) = print_audit_events(config_path.as_ref(), 20) {
+                    error!("{}", e);
+                }
+                continue;
+            }
+            "5" => {
+                let path = match ask("Absolute path of the caller executable") {
+                    Some(path) => path,
+                    None => continue,
+                };
+                let mut argv = vec!["caller".to_owned(), "add".to_owned(), path];
+                if let Some(url) = ask("URL pattern it may request credentials for (any if empty)")
+                {
+                    argv.extend(vec!["--url".to_owned(), url]);
+                }
+                argv
+            }
+            "6" => {
+                let mut argv = vec!["reassociate".to_owned()];
+                argv.extend(ask(
+                    "ID or alias of the database (the rejected one if empty)",
+                ));
+                argv
+            }
+            "7" => match ask("URL") {
+                Some(url) => vec![
+                    "get".to_owned(),
+                    "--url".to_owned(),
+                    url,
+                    "--check".to_owned(),
+                ],
+                None => continue,
+            },
+            "q" | "Q" => return Ok(()),
+            other => {
+                println!("Unknown choice {}", other);
+                continue;
+            }
+        };
+        if let Err(e) = run_ui_action(config_path.as_ref(), unlock_options, argv) {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Runs a subcommand chosen in `ui` as if it was given on the command line
+fn run_ui_action(
+    config_path: &Path,
+    unlock_options: &Option<UnlockOptions>,
+    argv: Vec<String>,
+) -> Result<()> {
+    let yaml = clap::load_yaml!("cli.yml");
+    let args = App::from_yaml(yaml)
+        .get_matches_from_safe(std::iter::once(clap::crate_name!().to_owned()).chain(argv))?;
+    match args.subcommand_name() {
+        Some("databases") => list_databases(config_path, &args),
+        Some("caller") => caller(config_path, &args),
+        Some("reassociate") => reassociate(config_path, &args),
+        Some("get") => {
+            let url = args
+                .subcommand_matches("get")
+                .and_then(|m| m.value_of("url"))
+                .map(lookup_url)
+                .unwrap_or_default();
+            match Config::read_from(config_path)?.get_rule_for(&url) {
+                Some(rule) => println!("Routing rule {} applies", rule.pattern),
+                None => println!("No routing rule applies"),
+            }
+            let input = read_request(&args)?;
+            let mut output = Vec::new();
+            get_logins(
+                config_path,
+                unlock_options,
+                &args,
+                &mut input.as_bytes(),
+                &mut output,
+            )?;
+            println!("{} login(s) match", String::from_utf8_lossy(&output).trim());
+            Ok(())
+        }
+        _ => Err(anyhow!("Unsupported action")),
+    }
+}
+
+fn print_rules(config: &Config) {
+    let rules = config.get_rules();
+    if rules.is_empty() {
+        println!("No routing rules, new logins are stored in the first database");
+    }
+    for (idx, rule) in rules.iter().enumerate() {
+        println!(
+            "{}: {} -> {}{}",
+            idx,
+            rule.pattern,
+            rule.database,
+            rule.group
+                .as_ref()
+                .map(|group| format!(", group {}", group))
+                .unwrap_or_default()
+        );
+    }
+}
+
+/// Prints the last `count` records of the audit log, leaving out rotated files
+fn print_audit_events(config_path: &Path, count: usize) -> Result<()> {
+    let config = Config::read_from(config_path)?;
+    let audit_log = config
+        .get_audit_log()
+        .ok_or_else(|| anyhow!("No audit log configured, see audit_log in the README"))?;
+    let content = std::fs::read_to_string(&audit_log.path)
+        .map_err(|e| anyhow!("Failed to read audit log {}, {}", audit_log.path, e))?;
+    let lines: Vec<_> = content.lines().collect();
+    for line in &lines[lines.len().saturating_sub(count)..] {
+        match serde_json::from_str::<AuditRecord>(line) {
+            Ok(record) => println!(
+                "{} {} {} {}{}",
+                record.timestamp,
+                record.operation,
+                record.url.as_deref().unwrap_or("-"),
+                record.outcome,
+                record
+                    .error
+                    .map(|error| format!(" ({})", error))
+                    .unwrap_or_default()
+            ),
+            Err(_) => {
+                warn!("Invalid audit record: {}", line);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Request given by `get --url` and `--username`, or read from standard input, in the Git format
+fn read_request(args: &ArgMatches) -> Result<String> {
+    let get_args = args.subcommand_matches("get");
+    if let Some(url) = get_args.and_then(|m| m.value_of("url")) {
+        let git_req = GitCredentialMessage {
+            url: Some(lookup_url(url)),
+            username: get_args
+                .and_then(|m| m.value_of("username"))
+                .map(str::to_owned),
+            ..Default::default()
+        };
+        return Ok(git_req.to_string());
+    }
+    let input = read_stdin_with_timeout()?;
+    if is_json_stdin(args) {
+        return Ok(GitCredentialMessage::from_json(&input)?.to_string());
+    }
+    Ok(input)
+}
+
+/// How long Git (or another program) may take to write its request
+const STDIN_TIMEOUT_SECS: u64 = 30;
+const STDIN_HELP: &str = "Git writes requests on standard input as key=value lines, e.g. protocol=https and host=example.com, ended by an empty line. To look up a login by hand, use get --url <URL> instead";
+
+/// Reads standard input, giving up after `STDIN_TIMEOUT_SECS` instead of blocking forever when
+/// nothing is written, e.g. when run by hand
+fn read_stdin_with_timeout() -> Result<String> {
+    if io::stdin().is_terminal() && !QUIET_OUTPUT.load(Ordering::Relaxed) {
+        eprintln!(
+            "Reading a credential request from the terminal, end it with Ctrl-D. {}.",
+            STDIN_HELP
+        );
+    }
+    let (sender, receiver) = std::sync::mpsc::channel();
+    // left blocked if it times out, which is fine as the process exits then
+    thread::spawn(move || {
+        let mut input = String::new();
+        let result = io::stdin().read_to_string(&mut input).map(|_| input);
+        let _ = sender.send(result);
+    });
+    match receiver.recv_timeout(Duration::from_secs(STDIN_TIMEOUT_SECS)) {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(anyhow!(
+            "No credential request on standard input after {}s. {}",
+            STDIN_TIMEOUT_SECS,
+            STDIN_HELP
+        )),
+    }
+}
+
+fn lookup_login<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+    url: &str,
+    username: Option<&str>,
+) -> Result<LoginEntry> {
+    let git_req = GitCredentialMessage {
+        url: Some(lookup_url(url)),
+        username: username.map(str::to_owned),
+        ..Default::default()
+    };
+    get_logins(
+        config_path,
+        unlock_options,
+        args,
+        &mut git_req.to_string().as_bytes(),
+        &mut io::sink(),
+    )?
+    .ok_or_else(|| anyhow!("{} is denied", url))
+}
+
+/// Implements the Docker credential helper protocol on top of get, store and erase
+fn docker<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let action = args
+        .subcommand_matches("docker")
+        .and_then(|m| m.subcommand_name())
+        .ok_or_else(|| anyhow!("No subcommand selected"))?;
+    let state_path = state_file_path(&config_path, "docker");
+    let mut docker_servers = DockerServers::read_from(&state_path)?;
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    match action {
+        "get" => {
+            let server_url = input.trim();
+            let result = lookup_login(
+                &config_path,
+                unlock_options,
+                args,
+                &docker::server_url_to_url(server_url),
+                docker_servers.get_username(server_url),
+            );
+            match result {
+                Ok(login) => {
+                    let credential = DockerCredential {
+                        server_url: server_url.to_owned(),
+                        username: login.login.clone(),
+                        secret: login.password.clone(),
+                    };
+                    io::stdout().write_all(serde_json::to_string(&credential)?.as_bytes())?;
+                    Ok(())
+                }
+                Err(e) => {
+                    print!("{}", docker::CREDENTIALS_NOT_FOUND);
+                    Err(e)
+                }
+            }
+        }
+        "store" => {
+            let credential: DockerCredential = serde_json::from_str(&input)?;
+            let git_req = GitCredentialMessage {
+                url: Some(docker::server_url_to_url(&credential.server_url)),
+                username: Some(credential.username.clone()),
+                password: Some(credential.secret),
+                ..Default::default()
+            };
+            store_login(
+                &config_path,
+                unlock_options,
+                args,
+                &mut git_req.to_string().as_bytes(),
+            )?;
+            docker_servers.insert(credential.server_url, credential.username);
+            docker_servers.write_to(&state_path)
+        }
+        "erase" => {
+            let server_url = input.trim();
+            let git_req = GitCredentialMessage {
+                url: Some(docker::server_url_to_url(server_url)),
+                username: docker_servers.get_username(server_url).map(str::to_owned),
+                ..Default::default()
+            };
+            erase_login(
+                &config_path,
+                unlock_options,
+                args,
+                &mut git_req.to_string().as_bytes(),
+                &mut io::sink(),
+            )?;
+            docker_servers.remove(server_url);
+            docker_servers.write_to(&state_path)
+        }
+        "list" => {
+            io::stdout().write_all(serde_json::to_string(docker_servers.servers())?.as_bytes())?;
+            Ok(())
+        }
+        _ => Err(anyhow!("Unrecognised subcommand")),
+    }
+}
+
+/// Implements the Cargo credential provider protocol on top of get, store and erase
+fn cargo_provider<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let mut stdout = io::stdout();
+    writeln!(stdout, "{}", cargo::HELLO)?;
+    stdout.flush()?;
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: CargoRequest = serde_json::from_str(&line)?;
+        if request.v != 1 {
+            return Err(anyhow!("Unsupported Cargo protocol version {}", request.v));
+        }
+        let registry = &request.registry;
+        debug!("Cargo {} request for {:?}", request.kind, registry);
+        let mut git_req = GitCredentialMessage {
+            url: Some(registry.url().to_owned()),
+            username: Some(registry.username().to_owned()),
+            ..Default::default()
+        };
+        let response = match request.kind.as_str() {
+            "get" => {
+                let result = lookup_login(
+                    &config_path,
+                    unlock_options,
+                    args,
+                    registry.url(),
+                    Some(registry.username()),
+                );
+                match result {
+                    Ok(login) => CargoResponse::Ok(CargoSuccess::Get {
+                        token: login.password.clone(),
+                        cache: "session".to_owned(),
+                        operation_independent: true,
+                    }),
+                    Err(e) => {
+                        info!("No token for {}, {}", registry.url(), e);
+                        CargoResponse::Err(CargoError::NotFound)
+                    }
+                }
+            }
+            "login" => match request.token {
+                Some(token) => {
+                    git_req.password = Some(token);
+                    match store_login(
+                        &config_path,
+                        unlock_options,
+                        args,
+                        &mut git_req.to_string().as_bytes(),
+                    ) {
+                        Ok(_) => CargoResponse::Ok(CargoSuccess::Login),
+                        Err(e) => CargoResponse::Err(CargoError::Other {
+                            message: e.to_string(),
+                        }),
+                    }
+                }
+                // stdin is taken by the protocol, so there's no way to prompt for it
+                None => CargoResponse::Err(CargoError::Other {
+                    message: "Token is missing, pass it to cargo login".to_owned(),
+                }),
+            },
+            "logout" => match erase_login(
+                &config_path,
+                unlock_options,
+                args,
+                &mut git_req.to_string().as_bytes(),
+                &mut io::sink(),
+            ) {
+                Ok(_) => CargoResponse::Ok(CargoSuccess::Logout),
+                Err(e) => CargoResponse::Err(CargoError::Other {
+                    message: e.to_string(),
+                }),
+            },
+            _ => CargoResponse::Err(CargoError::OperationNotSupported),
+        };
+        writeln!(stdout, "{}", serde_json::to_string(&response)?)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+/// Prints the auth token of an npm registry in `.npmrc` format
+fn npm_auth<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let npm_args = args
+        .subcommand_matches("npm")
+        .ok_or_else(|| anyhow!("No subcommand selected"))?;
+    let registry_url = npm_args
+        .value_of("REGISTRY")
+        .unwrap_or("https://registry.npmjs.org/");
+    let login = lookup_login(
+        config_path,
+        unlock_options,
+        args,
+        registry_url,
+        npm_args.value_of("username"),
+    )?;
+    println!("{}", npm::npmrc_auth_token(registry_url, &login.password));
+    Ok(())
+}
+
+/// Implements `keyring`-like get/set/del of the password of a username for a service
+fn keyring<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let (action, keyring_args) = match args.subcommand_matches("keyring").map(|m| m.subcommand()) {
+        Some((action, Some(keyring_args))) => (action, keyring_args),
+        _ => return Err(anyhow!("No subcommand selected")),
+    };
+    let service = keyring_args.value_of("SERVICE").unwrap();
+    let username = keyring_args.value_of("USERNAME").unwrap();
+    // KeePassXC needs URLs, so use a made-up scheme for plain service names
+    let url = if service.contains("://") {
+        service.to_owned()
+    } else {
+        format!("keyring://{}", service)
+    };
+    match action {
+        "get" => {
+            let login = lookup_login(config_path, unlock_options, args, &url, Some(username))?;
+            if login.login != username {
+                return Err(anyhow!("No password for {} in {}", username, service));
+            }
+            println!("{}", login.password);
+            Ok(())
+        }
+        "set" => {
+            let mut password = String::new();
+            io::stdin().read_line(&mut password)?;
+            let git_req = GitCredentialMessage {
+                url: Some(url),
+                username: Some(username.to_owned()),
+                password: Some(password.trim_end_matches(&['\r', '\n'][..]).to_owned()),
+                ..Default::default()
+            };
+            store_login(
+                config_path,
+                unlock_options,
+                args,
+                &mut git_req.to_string().as_bytes(),
+            )
+        }
+        "del" => {
+            let git_req = GitCredentialMessage {
+                url: Some(url),
+                username: Some(username.to_owned()),
+                ..Default::default()
+            };
+            erase_login(
+                config_path,
+                unlock_options,
+                args,
+                &mut git_req.to_string().as_bytes(),
+                &mut io::sink(),
+            )
+        }
+        _ => Err(anyhow!("Unrecognised subcommand")),
+    }
+}
+
+/// Answers Mercurial's HTTP authentication prompt with the username and password on separate lines
+fn hg_auth<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let hg_args = args
+        .subcommand_matches("hg")
+        .ok_or_else(|| anyhow!("No subcommand selected"))?;
+    let url = hg_args.value_of("URL").unwrap();
+    if let Some(realm) = hg_args.value_of("realm") {
+        info!("Mercurial requested credential for realm {}", realm);
+    }
+    let login = lookup_login(
+        config_path,
+        unlock_options,
+        args,
+        url,
+        hg_args.value_of("username"),
+    )?;
+    println!("{}", login.login);
+    println!("{}", login.password);
+    Ok(())
+}
+
+/// Answers prompts as `SSH_ASKPASS` or `GIT_ASKPASS`, e.g. passphrases of SSH private keys
+fn askpass<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let prompt = args
+        .subcommand_matches("askpass")
+        .and_then(|m| m.value_of("PROMPT"))
+        .unwrap_or_default();
+    debug!("Prompt: {}", prompt);
+    let answer = match askpass::parse_prompt(prompt) {
+        Some(askpass::Prompt::KeyPassphrase { key_path }) => {
+            let config = Config::read_from(config_path.as_ref())?;
+            let ssh_key = config
+                .get_ssh_key_for(&key_path)
+                .ok_or_else(|| anyhow!("No SSH key configured for {}", key_path))?;
+            let login = lookup_login(
+                &config_path,
+                unlock_options,
+                args,
+                &ssh_key.url,
+                ssh_key.username.as_deref(),
+            )?;
+            login.password.clone()
+        }
+        Some(askpass::Prompt::Username { url }) => {
+            let login = lookup_login(&config_path, unlock_options, args, &url, None)?;
+            login.login.clone()
+        }
+        Some(askpass::Prompt::Password { url }) => {
+            // Git includes the username in the URL
+            let login = lookup_login(&config_path, unlock_options, args, &url, None)?;
+            login.password.clone()
+        }
+        None => return Err(anyhow!("Unrecognised prompt: {}", prompt)),
+    };
+    println!("{}", answer);
+    Ok(())
+}
+
+/// How long a client of the session daemon or `serve` may take to send its request and read the
+/// response, as they're served one by one
+#[cfg(unix)]
+const SOCKET_TIMEOUT_SECS: u64 = 5;
+
+/// Binds a Unix socket in a private directory, replacing a stale socket file, or gives `None` if a
+/// server is still listening on it
+#[cfg(unix)]
+fn bind_unix_socket(socket_path: &Path) -> Result<Option<std::os::unix::net::UnixListener>> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if let Some(socket_dir) = socket_path.parent() {
+        std::fs::create_dir_all(socket_dir)?;
+        std::fs::set_permissions(socket_dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Ok(None);
+        }
+        std::fs::remove_file(socket_path)?;
+    }
+    Ok(Some(UnixListener::bind(socket_path)?))
+}
+
+/// Accepts the next client of a Unix socket server, so that a client that never finishes its
+/// request times out instead of blocking all the others
+#[cfg(unix)]
+fn accept_client(
+    stream: io::Result<std::os::unix::net::UnixStream>,
+) -> Option<std::os::unix::net::UnixStream> {
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => {
+            warn!("Failed to accept connection, {}", e);
+            return None;
+        }
+    };
+    let timeout = Some(Duration::from_secs(SOCKET_TIMEOUT_SECS));
+    let _ = stream.set_read_timeout(timeout);
+    let _ = stream.set_write_timeout(timeout);
+    Some(stream)
+}
+
+/// Holds the KeePassXC session and decrypted keys, serving `get`, `store` and `erase` proxied by
+/// other invocations over a Unix socket
+#[cfg(unix)]
+fn session_daemon<T: AsRef<Path>>(config_path: T) -> Result<()> {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    let config = Config::read_from(config_path.as_ref())?;
+    let policy = config.get_session_daemon().cloned().unwrap_or_default();
+    if config.count_encryptions() > 0 {
+        Config::keep_encryption_keys(policy.key_ttl);
+        config.get_encryption_key()?;
+    }
+    // systemd owns the socket then, and starts the daemon again on the next connection
+    let socket_activated = daemon::systemd_listener()?;
+    start_session()?;
+
+    let socket_path = daemon::socket_path()?;
+    let owns_socket = socket_activated.is_none();
+    let listener = match socket_activated {
+        Some(listener) => {
+            info!("Listening on the socket passed by systemd");
+            listener
+        }
+        None => {
+            let listener = bind_unix_socket(&socket_path)?
+                .ok_or_else(|| anyhow!("Session daemon is already running"))?;
+            info!("Listening on {}", socket_path.to_string_lossy());
+            listener
+        }
+    };
+    let metrics = Arc::new(Mutex::new(DaemonMetrics::default()));
+    if let Some(ref address) = policy.metrics_listen {
+        serve_metrics(address, metrics.clone())?;
+    }
+
+    let mut cache = ResponseCache::new(policy.cache_ttl);
+    // 0 while serving a request
+    let last_active = Arc::new(AtomicU64::new(unix_now()));
+    {
+        let last_active = last_active.clone();
+        let socket_path = socket_path.clone();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(1));
+            let last_active = last_active.load(Ordering::SeqCst);
+            if last_active != 0 && unix_now() >= last_active + policy.idle_timeout {
+                info!("Idle for {} seconds, exiting", policy.idle_timeout);
+                if owns_socket {
+                    let _ = std::fs::remove_file(&socket_path);
+                }
+                std::process::exit(0);
+            }
+        });
+    }
+    let canonical_config_path = std::fs::canonicalize(config_path.as_ref())?;
+    for stream in listener.incoming() {
+        let mut stream = match accept_client(stream) {
+            Some(stream) => stream,
+            None => continue,
+        };
+        last_active.store(0, Ordering::SeqCst);
+        let mut request = String::new();
+        let response = match stream.read_to_string(&mut request) {
+            Ok(_) => serde_json::from_str::<DaemonRequest>(&request)
+                .map_err(anyhow::Error::from)
+                .and_then(|request| {
+                    if std::fs::canonicalize(&request.config)? != canonical_config_path {
+                        return Err(anyhow!("Serving another configuration file"));
+                    }
+                    // callers are verified as the process behind the connection
+                    let peer = peer::peer_credentials(&stream)?;
+                    let _peer = peer::PeerGuard::set(peer);
+                    let mut metrics = metrics.lock().unwrap_or_else(|e| e.into_inner());
+                    Ok(handle_daemon_request(
+                        &config_path,
+                        request,
+                        &mut cache,
+                        &mut metrics,
+                    ))
+                })
+                .unwrap_or_else(|e| DaemonResponse {
+                    error: Some(DaemonError {
+                        message: e.to_string(),
+                        exit_code: 1,
+                    }),
+                    ..Default::default()
+                }),
+            Err(e) => {
+                warn!("Failed to read request, {}", e);
+                last_active.store(unix_now(), Ordering::SeqCst);
+                continue;
+            }
+        };
+        let failed = response.error.is_some();
+        if let Err(e) = stream.write_all(serde_json::to_string(&response)?.as_bytes()) {
+            warn!("Failed to send response, {}", e);
+        }
+        drop(stream);
+        // requests are retried with a new session if KeePassXC has been restarted, so this means
+        // that it's gone for good, e.g. quit
+        if failed
+            && start_session()
+                .and_then(|(client_id, _, _)| GetDatabaseHashRequest::new().send(&client_id, false))
+                .is_err()
+        {
+            warn!("Lost the KeePassXC session, exiting");
+            break;
+        }
+        last_active.store(unix_now(), Ordering::SeqCst);
+    }
+    if owns_socket {
+        std::fs::remove_file(&socket_path)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn session_daemon<T: AsRef<Path>>(_config_path: T) -> Result<()> {
+    Err(anyhow!("Session daemon requires Unix sockets"))
+}
+
+/// Serves the metrics of the session daemon over HTTP in background, on a loopback address only
+/// as they aren't authenticated
+#[cfg(unix)]
+fn serve_metrics(
+    address: &str,
+    metrics: std::sync::Arc<std::sync::Mutex<DaemonMetrics>>,
+) -> Result<()> {
+    use std::net::{SocketAddr, TcpListener};
+
+    let address = SocketAddr::from_str(address)
+        .map_err(|e| anyhow!("Invalid metrics_listen {}, {}", address, e))?;
+    if !address.ip().is_loopback() {
+        return Err(anyhow!(
+            "metrics_listen must be a loopback address, e.g. 127.0.0.1:9817"
+        ));
+    }
+    let listener = TcpListener::bind(address)
+        .map_err(|e| anyhow!("Failed to listen on {} for metrics, {}", address, e))?;
+    info!("Serving metrics on http://{}/metrics", address);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(SOCKET_TIMEOUT_SECS)));
+            let mut request_line = String::new();
+            if io::BufReader::new(&stream)
+                .read_line(&mut request_line)
+                .is_err()
+            {
+                continue;
+            }
+            let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+            let response = if path == "/metrics" {
+                let body = metrics.lock().unwrap_or_else(|e| e.into_inner()).render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_owned()
+            };
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    Ok(())
+}
+
+/// Handles a proxied invocation as if it was run locally
+#[cfg(unix)]
+fn handle_daemon_request<T: AsRef<Path>>(
+    config_path: T,
+    request: DaemonRequest,
+    cache: &mut ResponseCache,
+    metrics: &mut DaemonMetrics,
+) -> DaemonResponse {
+    let started = std::time::Instant::now();
+    let mut subcommand = "unknown".to_owned();
+    let mut output = Vec::new();
+    // the path of the executable doesn't matter
+    let cache_key = request.argv.get(1..).unwrap_or_default();
+    let yaml = clap::load_yaml!("cli.yml");
+    let result = App::from_yaml(yaml)
+        .get_matches_from_safe(&request.argv)
+        .map_err(anyhow::Error::from)
+        .and_then(|args| {
+            let unlock_options = unlock_options_of(&args)?;
+            if let Some(name) = args.subcommand_name() {
+                subcommand = name.to_owned();
+            }
+            // KeePassXC may have restarted since the last request
+            match args.subcommand_name() {
+                Some("get") => {
+                    // cached answers would skip verifying the caller
+                    let cacheable = cache.is_enabled()
+                        && Config::read_from(config_path.as_ref())?.count_callers() == 0;
+                    if cacheable {
+                        if let Some(cached) = cache.get(cache_key, &request.input, unix_now()) {
+                            debug!("Answering from cache");
+                            output.extend_from_slice(cached.as_bytes());
+                            metrics.record_cache(true);
+                            return Ok(());
+                        }
+                        metrics.record_cache(false);
+                    }
+                    let login = retry_on_lost_session(|| {
+                        output.clear();
+                        get_logins(
+                            &config_path,
+                            &unlock_options,
+                            &args,
+                            &mut request.input.as_bytes(),
+                            &mut output,
+                        )
+                    })?;
+                    if cacheable && login.is_some() {
+                        let output = String::from_utf8_lossy(&output).into_owned();
+                        cache.insert(cache_key, &request.input, output, unix_now());
+                    }
+                    Ok(())
+                }
+                Some("store") => {
+                    cache.clear();
+                    retry_on_lost_session(|| {
+                        store_login(
+                            &config_path,
+                            &unlock_options,
+                            &args,
+                            &mut request.input.as_bytes(),
+                        )
+                    })
+                }
+                Some("erase") => {
+                    cache.clear();
+                    retry_on_lost_session(|| {
+                        output.clear();
+                        erase_login(
+                            &config_path,
+                            &unlock_options,
+                            &args,
+                            &mut request.input.as_bytes(),
+                            &mut output,
+                        )
+                    })
+                }
+                Some("cache") => {
+                    let status = CacheStatus {
+                        entries: cache.count(unix_now()),
+                        cache_ttl: cache.ttl(),
+                        encryption_keys: Config::count_kept_encryption_keys(),
+                        key_ttl: Config::read_from(config_path.as_ref())?
+                            .get_session_daemon()
+                            .and_then(|policy| policy.key_ttl),
+                    };
+                    let clear = args
+                        .subcommand_matches("cache")
+                        .and_then(|m| m.subcommand_name())
+                        == Some("clear");
+                    if clear {
+                        cache.clear();
+                        Config::forget_encryption_keys();
+                        info!("Cache cleared");
+                    }
+                    output.extend_from_slice(serde_json::to_string(&status)?.as_bytes());
+                    Ok(())
+                }
+                _ => Err(anyhow!("Unsupported subcommand")),
+            }
+        });
+    if let Err(ref e) = result {
+        if e.downcast_ref::<KeePassError>().is_some() {
+            metrics.record_keepassxc_error();
+        }
+    }
+    metrics.record_request(
+        &subcommand,
+        started.elapsed().as_secs_f64(),
+        result.as_ref().err().map(|e| describe_error(e).0),
+    );
+    DaemonResponse {
+        output: String::from_utf8_lossy(&output).into_owned(),
+        error: result.err().map(|e| DaemonError {
+            message: e.to_string(),
+            exit_code: exit_code_of(&e),
+        }),
+    }
+}
+
+/// Adds the arguments of cli.yml that are set in the environment, unless given on the command line,
+/// to `argv` for the session daemon, which would otherwise fall back to its own environment
+#[cfg(unix)]
+fn forward_env_args(argv: &mut Vec<String>, env_var: impl Fn(&str) -> Option<String>) {
+    let yaml = clap::load_yaml!("cli.yml");
+    for (_, arg) in manpage::entries_of(yaml, "args") {
+        let (long, value) = match (arg["long"].as_str(), arg["env"].as_str().and_then(&env_var)) {
+            (Some(long), Some(value)) => (format!("--{}", long), value),
+            _ => continue,
+        };
+        let short = arg["short"].as_str().map(|short| format!("-{}", short));
+        let given = argv.iter().skip(1).any(|given| {
+            given == &long
+                || given.starts_with(&format!("{}=", long))
+                || short
+                    .as_ref()
+                    .map(|short| given.starts_with(short.as_str()) && !given.starts_with("--"))
+                    .unwrap_or(false)
+        });
+        // before the subcommand, as not all of them are global
+        if !given && !argv.is_empty() {
+            argv.insert(1, format!("{}={}", long, value));
+        }
+    }
+}
+
+/// Proxies an invocation to the session daemon if it's enabled, starting the daemon in background
+/// for the next invocations if it's not running. Returns `None` if the request should be handled
+/// locally
+#[cfg(unix)]
+fn proxy_to_daemon<T: AsRef<Path>>(config_path: T, input: &str) -> Option<DaemonResponse> {
+    use std::os::unix::net::UnixStream;
+    use std::os::unix::process::CommandExt;
+
+    let config = Config::read_from(config_path.as_ref()).ok()?;
+    config.get_session_daemon()?;
+    // access is confirmed on the terminal of the caller, which the daemon doesn't have
+    if config.is_confirm_access() {
+        return None;
+    }
+    let socket_path = daemon::socket_path().ok()?;
+    let mut stream = match UnixStream::connect(&socket_path) {
+        Ok(stream) => stream,
+        Err(_) => {
+            info!("Session daemon is not running, starting it");
+            let result = std::env::current_exe().and_then(|exe| {
+                std::process::Command::new(exe)
+                    .arg("--config")
+                    .arg(config_path.as_ref())
+                    .arg("daemon")
+                    .stdin(std::process::Stdio::null())
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    // don't get killed along with Git by Ctrl-C
+                    .process_group(0)
+                    .spawn()
+            });
+            if let Err(e) = result {
+                warn!("Failed to start session daemon, {}", e);
+            }
+            return None;
+        }
+    };
+    let mut argv: Vec<String> = std::env::args_os()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    forward_env_args(&mut argv, |var| std::env::var(var).ok());
+    let request = DaemonRequest {
+        config: std::fs::canonicalize(config_path.as_ref()).ok()?,
+        argv,
+        input: input.to_owned(),
+    };
+    let result = timed("Session daemon request", || {
+        exchange_with_daemon(&mut stream, &request)
+    });
+    match result {
+        Ok(response) => Some(response),
+        Err(e) => {
+            warn!("Failed to proxy request to session daemon, {}", e);
+            None
+        }
+    }
+}
+
+/// Sends a request to the session daemon and waits for its response
+#[cfg(unix)]
+fn exchange_with_daemon(
+    stream: &mut std::os::unix::net::UnixStream,
+    request: &DaemonRequest,
+) -> Result<DaemonResponse> {
+    serde_json::to_writer(&*stream, request)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(serde_json::from_str::<DaemonResponse>(&response)?)
+}
+
+/// Prints, and with `clear` wipes, what the session daemon keeps in memory
+#[cfg(unix)]
+fn cache<T: AsRef<Path>>(config_path: T, args: &ArgMatches) -> Result<()> {
+    use std::os::unix::net::UnixStream;
+
+    let action = args
+        .subcommand_matches("cache")
+        .and_then(|m| m.subcommand_name())
+        .ok_or_else(|| anyhow!("Must specify status or clear"))?;
+    let json = is_json(args);
+    let mut stream = match UnixStream::connect(daemon::socket_path()?) {
+        Ok(stream) => stream,
+        Err(_) => {
+            // each invocation starts afresh otherwise
+            if json {
+                println!(
+                    "{}",
+                    output::json_document(serde_json::json!({ "running": false }))
+                );
+            } else {
+                println!("Session daemon is not running, nothing is cached");
+            }
+            return Ok(());
+        }
+    };
+    let request = DaemonRequest {
+        config: std::fs::canonicalize(config_path.as_ref())?,
+        argv: vec![
+            clap::crate_name!().to_owned(),
+            "cache".to_owned(),
+            action.to_owned(),
+        ],
+        input: String::new(),
+    };
+    let response = exchange_with_daemon(&mut stream, &request)?;
+    if let Some(error) = response.error {
+        return Err(error.into());
+    }
+    let status: CacheStatus = serde_json::from_str(&response.output)?;
+    let cleared = action == "clear";
+    if json {
+        let mut status_json = serde_json::to_value(&status)?;
+        status_json["running"] = true.into();
+        status_json["cleared"] = cleared.into();
+        println!("{}", output::json_document(status_json));
+    } else if cleared {
+        println!(
+            "Cleared {} answer(s) and {} encryption key(s)",
+            status.entries, status.encryption_keys
+        );
+    } else {
+        if status.cache_ttl > 0 {
+            println!(
+                "Answers: {}, kept for {}s",
+                status.entries, status.cache_ttl
+            );
+        } else {
+            println!("Answers: not cached, cache_ttl is not set");
+        }
+        match status.key_ttl {
+            Some(key_ttl) => println!(
+                "Encryption keys: {}, kept for {}s",
+                status.encryption_keys, key_ttl
+            ),
+            None => println!(
+                "Encryption keys: {}, kept while the daemon runs",
+                status.encryption_keys
+            ),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn cache<T: AsRef<Path>>(_config_path: T, _args: &ArgMatches) -> Result<()> {
+    Err(anyhow!("Session daemon requires Unix sockets"))
+}
+
+/// Serves requests of `git credential-cache` on a Unix socket, in place of
+/// `git credential-cache--daemon`
+#[cfg(unix)]
+fn serve<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let socket_path = match args
+        .subcommand_matches("serve")
+        .and_then(|m| m.value_of("listen"))
+    {
+        Some(path) => PathBuf::from(path),
+        None => directories_next::BaseDirs::new()
+            .ok_or_else(|| anyhow!("Failed to initialise base_dirs"))?
+            .cache_dir()
+            .join("git/credential/socket"),
+    };
+    let listener = bind_unix_socket(&socket_path)?.ok_or_else(|| {
+        anyhow!(
+            "Another server is already listening on {}",
+            socket_path.to_string_lossy()
+        )
+    })?;
+    info!("Listening on {}", socket_path.to_string_lossy());
+    for stream in listener.incoming() {
+        let mut stream = match accept_client(stream) {
+            Some(stream) => stream,
+            None => continue,
+        };
+        // credential-cache shuts down its writing end after sending the request
+        let mut request = String::new();
+        if let Err(e) = stream.read_to_string(&mut request) {
+            warn!("Failed to read request, {}", e);
+            continue;
+        }
+        let mut action = "";
+        let mut lines = request.lines().peekable();
+        while let Some(line) = lines.peek() {
+            if let Some(value) = line.strip_prefix("action=") {
+                action = value;
+            } else if !line.starts_with("timeout=") {
+                break;
+            }
+            lines.next();
+        }
+        let credential: String = lines.map(|line| format!("{}\n", line)).collect();
+        debug!("credential-cache {} request", action);
+        // callers are verified as the process behind the connection
+        let peer = match peer::peer_credentials(&stream) {
+            Ok(peer) => peer,
+            Err(e) => {
+                warn!("Failed to handle {} request, {}", action, e);
+                continue;
+            }
+        };
+        let _peer = peer::PeerGuard::set(peer);
+        // nothing is written to the stream unless the request succeeds, so it can be retried
+        let result = match action {
+            "get" => retry_on_lost_session(|| {
+                get_logins(
+                    &config_path,
+                    unlock_options,
+                    args,
+                    &mut credential.as_bytes(),
+                    &mut stream,
+                )
+            })
+            .map(|_| ()),
+            "store" => retry_on_lost_session(|| {
+                store_login(
+                    &config_path,
+                    unlock_options,
+                    args,
+                    &mut credential.as_bytes(),
+                )
+            }),
+            "erase" => retry_on_lost_session(|| {
+                erase_login(
+                    &config_path,
+                    unlock_options,
+                    args,
+                    &mut credential.as_bytes(),
+                    &mut io::sink(),
+                )
+            }),
+            "exit" => break,
+            _ => Err(anyhow!("Unrecognised action {}", action)),
+        };
+        if let Err(e) = result {
+            warn!("Failed to handle {} request, {}", action, e);
+        }
+    }
+    std::fs::remove_file(&socket_path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve<T: AsRef<Path>>(
+    _config_path: T,
+    _unlock_options: &Option<UnlockOptions>,
+    _args: &ArgMatches,
+) -> Result<()> {
+    Err(anyhow!(
+        "Serving credential-cache requests requires Unix sockets"
+    ))
+}
+
+/// Prints the token of a Kubernetes cluster as client-go credential plugins do
+fn kube_credential<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let cluster_name = args
+        .subcommand_matches("kube")
+        .and_then(|m| m.value_of("CLUSTER"))
+        .unwrap();
+    let config = Config::read_from(config_path.as_ref())?;
+    let cluster = config
+        .get_kube_cluster(cluster_name)
+        .ok_or_else(|| anyhow!("No Kubernetes cluster named {} configured", cluster_name))?;
+    let login = lookup_login(
+        &config_path,
+        unlock_options,
+        args,
+        &cluster.url,
+        cluster.username.as_deref(),
+    )?;
+    let exec_credential = kube::ExecCredential::new(login.password.clone());
+    println!("{}", serde_json::to_string(&exec_credential)?);
+    Ok(())
+}
+
+/// Prints a single secret of a login, or the whole login as JSON, for scripts
+fn get_secret<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let secret_args = args
+        .subcommand_matches("get-secret")
+        .ok_or_else(|| anyhow!("No subcommand selected"))?;
+    let login = lookup_login(
+        config_path,
+        unlock_options,
+        args,
+        secret_args.value_of("url").unwrap(),
+        secret_args.value_of("username"),
+    )?;
+    if secret_args.is_present("json") {
+        println!("{}", output::json_document(login_json(&login)));
+        return Ok(());
+    }
+    if let Some(format) = secret_args.value_of("output") {
+        let url = secret_args.value_of("url").unwrap();
+        let (output, extension) = match format {
+            "netrc" => (
+                output::netrc_entry(url, &login.login, &login.password) + "\n",
+                "netrc",
+            ),
+            "shell" => (
+                output::shell_exports(&[
+                    (secret_args.value_of("username-var").unwrap(), &login.login),
+                    (
+                        secret_args.value_of("password-var").unwrap(),
+                        &login.password,
+                    ),
+                ])?,
+                "sh",
+            ),
+            _ => return Err(anyhow!("Unknown output format {}", format)),
+        };
+        if secret_args.is_present("temp-file") {
+            let (path, mut file) = create_private_temp_file(clap::crate_name!(), extension)?;
+            file.write_all(output.as_bytes())?;
+            println!("{}", path.to_string_lossy());
+        } else {
+            print!("{}", output);
+        }
+        return Ok(());
+    }
+    let secret = match secret_args.value_of("field").unwrap_or("password") {
+        "password" => login.password.as_str(),
+        "username" => login.login.as_str(),
+        "title" => login.name.as_str(),
+        name => string_field_of(&login, name)
+            .ok_or_else(|| anyhow!("Login {} has no KPH: {} field", login.name, name))?,
+    };
+    if secret_args.is_present("clip") {
+        let timeout = u64::from_str(secret_args.value_of("clip-timeout").unwrap())
+            .map_err(|e| anyhow!("Invalid clipboard timeout, {}", e))?;
+        clipboard::copy(secret)?;
+        if timeout > 0 {
+            schedule_clipboard_clear(secret, timeout)?;
+        }
+        if !QUIET_OUTPUT.load(Ordering::Relaxed) {
+            eprintln!("Copied to clipboard");
+        }
+        return Ok(());
+    }
+    println!("{}", secret);
+    Ok(())
+}
+
+/// Starts a process in background clearing the clipboard after `timeout` seconds, unless it has
+/// changed since `copied` was copied
+fn schedule_clipboard_clear(copied: &str, timeout: u64) -> Result<()> {
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command
+        .arg("clear-clipboard")
+        .arg("--after")
+        .arg(timeout.to_string())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    // outlive e.g. the terminal
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command
+        .spawn()
+        .map_err(|e| anyhow!("Failed to schedule clearing the clipboard, {}", e))?;
+    // not as an argument, where other users could see it
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(clipboard::digest(copied).as_bytes())?;
+    Ok(())
+}
+
+fn clear_clipboard(args: &ArgMatches) -> Result<()> {
+    let after = args
+        .subcommand_matches("clear-clipboard")
+        .and_then(|m| m.value_of("after"))
+        .ok_or_else(|| anyhow!("Failed to get subcommand arguments"))?;
+    let after = u64::from_str(after).map_err(|e| anyhow!("Invalid delay, {}", e))?;
+    let mut digest = String::new();
+    io::stdin().read_to_string(&mut digest)?;
+    thread::sleep(Duration::from_secs(after));
+    if !clipboard::clear_if_unchanged(digest.trim())? {
+        info!("Clipboard has changed, leaving it alone");
+    }
+    Ok(())
+}
+
+/// Starts a process in background locking the databases after `auto_lock_after` seconds, unless
+/// they're accessed again meanwhile
+fn schedule_auto_lock<T: AsRef<Path>>(config_path: T, args: &ArgMatches) {
+    let after = match Config::read_from(&config_path)
+        .ok()
+        .and_then(|config| config.get_auto_lock_after())
+    {
+        Some(after) => after,
+        None => return,
+    };
+    let result = (|| -> Result<()> {
+        let state_path = state_file_path(&config_path, "autolock");
+        let mut state = AutoLockState::read_from(&state_path)?;
+        state.record_access(unix_now());
+        state.write_to(&state_path)?;
+
+        let mut command = std::process::Command::new(std::env::current_exe()?);
+        command.arg("--config").arg(config_path.as_ref());
+        if let Some(socket) = global_value_of(args, "socket") {
+            command.arg("--socket").arg(socket);
+        }
+        command
+            .arg("lock-database")
+            .arg("--after")
+            .arg(after.to_string())
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null());
+        // outlive Git
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        command.spawn()?;
+        Ok(())
+    })();
+    if let Err(e) = result {
+        warn!("Failed to schedule locking the databases, {}", e);
+    }
+}
+
+fn lock_database<T: AsRef<Path>>(config_path: T, args: &ArgMatches) -> Result<()> {
+    let after = args
+        .subcommand_matches("lock-database")
+        .and_then(|m| m.value_of("after"));
+    if let Some(after) = after {
+        let after = u64::from_str(after).map_err(|e| anyhow!("Invalid delay, {}", e))?;
+        thread::sleep(Duration::from_secs(after));
+        let state = AutoLockState::read_from(state_file_path(&config_path, "autolock"))?;
+        if !state.is_due(after, unix_now()) {
+            info!("Databases have been accessed meanwhile, leaving them to a later timer");
+            return Ok(());
+        }
+    }
+    let (client_id, _, _) = start_session()?;
+    match LockDatabaseRequest::new().send(&client_id, false) {
+        Ok(_) => Ok(()),
+        Err(e)
+            if e.downcast_ref::<KeePassError>()
+                .map(KeePassError::is_database_locked)
+                .unwrap_or(false) =>
+        {
+            info!("Databases are locked");
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Asks KeePassXC to auto-type the login of a URL into the focused window, e.g. from a hotkey
+fn autotype<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let autotype_args = args
+        .subcommand_matches("autotype")
+        .ok_or_else(|| anyhow!("Failed to get subcommand arguments"))?;
+    let config = Config::read_from(config_path.as_ref())?;
+    let verified_caller = verify_caller(&config, config_path.as_ref())?;
+    let url = config.rewrite_url(autotype_args.value_of("URL").unwrap());
+    verify_caller_url(&verified_caller, &url)?;
+    if let Some(deny_rule) = config.get_deny_rule_for(&url) {
+        return Err(anyhow!(
+            "{} is denied by pattern {}",
+            url,
+            deny_rule.pattern
+        ));
+    }
+    ensure_configured(&config, config_path.as_ref())?;
+    let host = url_host(&url).ok_or_else(|| anyhow!("No host in {}", url))?;
+    let (client_id, _, _) = start_session()?;
+    // KeePassXC doesn't check associations for auto-type, so that's done here
+    associated_databases(
+        config.get_databases_for(&url)?,
+        &client_id,
+        unlock_options,
+        config.get_hash_mismatch(),
+    )?;
+    let ra_resp = RequestAutotypeRequest::new(host).send(&client_id, false)?;
+    if !ra_resp.success.as_ref().map(|s| s.0).unwrap_or(false) {
+        return Err(anyhow!("KeePassXC refused to auto-type for {}", url));
+    }
+    Ok(())
+}
+
+/// Runs the `--hook` of `rotate` through the shell with the new password on stdin
+fn run_rotate_hook(command: &str, url: &str, username: &str, password: &str) -> Result<()> {
+    let mut child = if cfg!(windows) {
+        let mut child = std::process::Command::new("cmd");
+        child.arg("/C");
+        child
+    } else {
+        let mut child = std::process::Command::new("sh");
+        child.arg("-c");
+        child
+    };
+    let mut child = child
+        .arg(command)
+        .stdin(std::process::Stdio::piped())
+        // the new password is printed instead if the hook fails, so stdout is kept for it
+        .stdout(std::process::Stdio::null())
+        .env("GIT_CREDENTIAL_KEEPASSXC_URL", url)
+        .env("GIT_CREDENTIAL_KEEPASSXC_USERNAME", username)
+        .spawn()
+        .map_err(|e| anyhow!("Failed to run hook {}, {}", command, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(password.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("Hook {} failed: {}", command, status));
+    }
+    Ok(())
+}
+
+/// Replaces the password of a login with a generated one, printing it or handing it to
+/// a hook, e.g. from a scheduled job
+fn rotate_password<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let rotate_args = args
+        .subcommand_matches("rotate")
+        .ok_or_else(|| anyhow!("Failed to get subcommand arguments"))?;
+    let config = Config::read_from(config_path.as_ref())?;
+    if config.is_read_only() || args.is_present("read-only") {
+        return Err(anyhow!("Read-only mode, not rotating password"));
+    }
+    let url = rotate_args.value_of("url").unwrap();
+    let login = lookup_login(
+        &config_path,
+        unlock_options,
+        args,
+        url,
+        rotate_args.value_of("username"),
+    )?;
+    let (client_id, _, _) = start_session()?;
+    let password = generate_password(&config, url, &client_id)?;
+    let hook = rotate_args.value_of("hook");
+    if let Some(command) = hook {
+        run_rotate_hook(command, url, &login.login, &password)?;
+    }
+    let git_req = GitCredentialMessage {
+        url: Some(url.to_owned()),
+        username: Some(login.login.clone()),
+        password: Some(password.clone()),
+        ..Default::default()
+    };
+    let result = store_login(
+        &config_path,
+        unlock_options,
+        args,
+        &mut git_req.to_string().as_bytes(),
+    );
+    // the password may have been changed on the server already, so it mustn't get lost
+    if hook.is_none() || result.is_err() {
+        println!("{}", password);
+    }
+    result
+}
+
+/// Sends a message as it is, for developers to see what KeePassXC answers
+fn raw_request(args: &ArgMatches) -> Result<()> {
+    let raw_args = args
+        .subcommand_matches("raw")
+        .ok_or_else(|| anyhow!("Failed to get subcommand arguments"))?;
+    let message = match raw_args.value_of("message") {
+        Some(message) => message.to_owned(),
+        None => {
+            let mut message = String::new();
+            io::stdin().read_to_string(&mut message)?;
+            message
+        }
+    };
+    let message: serde_json::Value =
+        serde_json::from_str(&message).map_err(|e| anyhow!("Invalid message, {}", e))?;
+    let (client_id, _, _) = start_session()?;
+    let response = send_raw(&message, client_id, raw_args.is_present("trigger-unlock"))?;
+    println!("{}", serde_json::to_string_pretty(&response)?);
+    Ok(())
+}
+
+/// Prints the password (or username) for a Subversion realm, e.g. for `--password-from-stdin`
+fn svn_credential<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let svn_args = args
+        .subcommand_matches("svn")
+        .ok_or_else(|| anyhow!("No subcommand selected"))?;
+    let realm = svn_args.value_of("REALM").unwrap();
+    let config = Config::read_from(config_path.as_ref())?;
+    let (url, username) = match config.get_svn_realm_for(realm) {
+        Some(svn_realm) => (svn_realm.url.clone(), svn_realm.username.clone()),
+        // realms look like <https://svn.example.com:443> Example Repository
+        None => match (realm.find('<'), realm.find('>')) {
+            (Some(start), Some(end)) if start < end => (realm[start + 1..end].to_owned(), None),
+            _ => return Err(anyhow!("No SVN realm configured for {}", realm)),
+        },
+    };
+    let username = svn_args
+        .value_of("username")
+        .map(str::to_owned)
+        .or(username);
+    let login = lookup_login(
+        &config_path,
+        unlock_options,
+        args,
+        &url,
+        username.as_deref(),
+    )?;
+    if svn_args.is_present("print-username") {
+        println!("{}", login.login);
+    } else {
+        println!("{}", login.password);
+    }
+    Ok(())
+}
+
+/// Answers `GETPIN` of the Assuan pinentry protocol for configured GPG keys, handing the session
+/// over to a real pinentry otherwise
+fn pinentry_session<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let config = Config::read_from(config_path.as_ref())?;
+    let fallback = args
+        .subcommand_matches("pinentry")
+        .and_then(|m| m.value_of("fallback"))
+        .or_else(|| config.get_pinentry_fallback())
+        .map(str::to_owned);
+    let mut stdout = io::stdout();
+    writeln!(stdout, "OK Pleased to meet you")?;
+    stdout.flush()?;
+    // replayed to the real pinentry to set it up the same way
+    let mut history = Vec::new();
+    let mut key_info = String::new();
+    let mut description = String::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let getpin = loop {
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(()),
+        };
+        let (command, argument) = match line.find(' ') {
+            Some(idx) => (&line[..idx], &line[idx + 1..]),
+            None => (line.as_str(), ""),
+        };
+        match command.to_ascii_uppercase().as_str() {
+            "SETKEYINFO" => key_info = argument.to_owned(),
+            "SETDESC" => description = percent_decode(argument),
+            "GETINFO" => match argument {
+                "pid" => writeln!(stdout, "D {}", std::process::id())?,
+                "version" => writeln!(stdout, "D {}", clap::crate_version!())?,
+                "flavor" => writeln!(stdout, "D {}", clap::crate_name!())?,
+                _ => (),
+            },
+            "GETPIN" => {
+                let login = config
+                    .get_gpg_key_for(&key_info, &description)
+                    .and_then(|gpg_key| {
+                        lookup_login(
+                            &config_path,
+                            unlock_options,
+                            args,
+                            &gpg_key.url,
+                            gpg_key.username.as_deref(),
+                        )
+                        .map_err(|e| {
+                            warn!("Failed to look up the passphrase, {}", e);
+                        })
+                        .ok()
+                    });
+                match (login, fallback.as_ref()) {
+                    (Some(login), _) => {
+                        writeln!(stdout, "D {}", pinentry::percent_encode(&login.password))?;
+                    }
+                    (None, Some(_)) => break line,
+                    (None, None) => {
+                        writeln!(stdout, "{}", pinentry::ERR_CANCELED)?;
+                        stdout.flush()?;
+                        continue;
+                    }
+                }
+            }
+            "BYE" => {
+                writeln!(stdout, "OK closing connection")?;
+                return Ok(());
+            }
+            _ => (),
+        }
+        if !command.eq_ignore_ascii_case("GETPIN") && !command.eq_ignore_ascii_case("GETINFO") {
+            history.push(line.clone());
+        }
+        writeln!(stdout, "OK")?;
+        stdout.flush()?;
+    };
+    drop(lines);
+
+    let fallback = fallback.unwrap();
+    info!("Handing over to {}", fallback);
+    let mut child = std::process::Command::new(&fallback)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+    let mut child_stdin = child.stdin.take().unwrap();
+    let mut child_stdout = io::BufReader::new(child.stdout.take().unwrap());
+    let read_response = |child_stdout: &mut io::BufReader<std::process::ChildStdout>| {
+        let mut response = String::new();
+        loop {
+            let mut line = String::new();
+            if child_stdout.read_line(&mut line)? == 0 {
+                return Err(anyhow!("{} exited unexpectedly", fallback));
+            }
+            response.push_str(&line);
+            if line.starts_with("OK") || line.starts_with("ERR") {
+                return Ok(response);
+            }
+        }
+    };
+    // greeting
+    read_response(&mut child_stdout)?;
+    for line in history.iter().chain(std::iter::once(&getpin)) {
+        writeln!(child_stdin, "{}", line)?;
+        child_stdin.flush()?;
+        let response = read_response(&mut child_stdout)?;
+        if line == &getpin {
+            stdout.write_all(response.as_bytes())?;
+            stdout.flush()?;
+        }
+    }
+    // from now on the real pinentry talks to gpg-agent directly
+    std::thread::spawn(move || io::copy(&mut io::stdin(), &mut child_stdin));
+    io::copy(&mut child_stdout, &mut stdout)?;
+    child.wait()?;
+    Ok(())
+}
+
+/// Prints the token for a GitHub host, e.g. for `GH_TOKEN`
+fn gh_token<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let host = args
+        .subcommand_matches("gh")
+        .and_then(|m| m.value_of("HOST"))
+        .unwrap_or(config::GITHUB_HOST);
+    let url = format!("https://{}", host);
+    let login = lookup_login(config_path, unlock_options, args, &url, None)?;
+    println!("{}", login.password);
+    Ok(())
+}
+
+/// Prints the access key of an AWS profile as `credential_process` does
+fn aws_credential<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let profile_name = args
+        .subcommand_matches("aws")
+        .and_then(|m| m.value_of("PROFILE"))
+        .unwrap();
+    let config = Config::read_from(config_path.as_ref())?;
+    let profile = config
+        .get_aws_profile(profile_name)
+        .ok_or_else(|| anyhow!("No AWS profile named {} configured", profile_name))?;
+    let login = lookup_login(
+        &config_path,
+        unlock_options,
+        args,
+        &profile.url,
+        profile.username.as_deref(),
+    )?;
+    let credentials = aws::ProcessCredentials::new(
+        login.login.as_str(),
+        login.password.as_str(),
+        string_field_of(&login, "SessionToken"),
+        string_field_of(&login, "Expiration"),
+    );
+    println!("{}", serde_json::to_string(&credentials)?);
+    Ok(())
+}
+
+/// Prints the logins of Maven/Gradle repositories, so that build tools don't need plaintext
+/// passwords in their configuration
+fn maven_credentials<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+) -> Result<()> {
+    let maven_args = args
+        .subcommand_matches("maven")
+        .ok_or_else(|| anyhow!("No subcommand selected"))?;
+    let config = Config::read_from(config_path.as_ref())?;
+    let mut logins = Vec::new();
+    for id in maven_args.values_of("ID").unwrap() {
+        let server = config
+            .get_maven_server(id)
+            .ok_or_else(|| anyhow!("No Maven server with ID {} configured", id))?;
+        let login = lookup_login(
+            &config_path,
+            unlock_options,
+            args,
+            &server.url,
+            server.username.as_deref(),
+        )?;
+        logins.push((id, login));
+    }
+    let servers: Vec<_> = logins
+        .iter()
+        .map(|(id, login)| (*id, login.login.as_str(), login.password.as_str()))
+        .collect();
+    match maven_args.value_of("format").unwrap() {
+        "gradle" => print!("{}", output::gradle_properties(&servers)),
+        _ => print!("{}", output::maven_servers(&servers)),
+    }
+    Ok(())
+}
+
+/// Warns when running as another user than the one KeePassXC is likely running as, e.g. under
+/// `sudo git`, as the socket (and configuration) of that user is looked for then
+fn warn_if_other_user(config_path: &Path) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let hint = "pass --socket with the KeePassXC socket of your user if this fails";
+        if let Ok(sudo_user) = std::env::var("SUDO_USER") {
+            warn!(
+                "Running under sudo, so the KeePassXC socket of user {} rather than of {} is looked for, {}",
+                unsafe { libc::geteuid() },
+                sudo_user,
+                hint
+            );
+            return;
+        }
+        let euid = unsafe { libc::geteuid() };
+        if let Ok(metadata) = std::fs::metadata(config_path) {
+            if metadata.uid() != euid {
+                warn!(
+                    "Configuration file {} belongs to user {} but running as user {}, so the KeePassXC socket of the wrong user may be used, {}",
+                    config_path.to_string_lossy(),
+                    metadata.uid(),
+                    euid,
+                    hint
+                );
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = config_path;
+}
+
+/// Keeps secrets in memory out of core dumps and away from debuggers of the same user, as far as
+/// each platform allows it
+#[cfg(not(debug_assertions))]
+fn harden_process() -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        prctl::set_dumpable(false)
+            .or_else(|c| Err(anyhow!("Failed to disable dump, code: {}", c)))?;
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let no_core = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &no_core) } != 0 {
+            return Err(anyhow!(
+                "Failed to disable core dumps, {}",
+                io::Error::last_os_error()
+            ));
+        }
+        if unsafe { libc::ptrace(libc::PT_DENY_ATTACH, 0, std::ptr::null_mut(), 0) } != 0 {
+            return Err(anyhow!(
+                "Failed to deny debugger attachment, {}",
+                io::Error::last_os_error()
+            ));
+        }
+    }
+    #[cfg(windows)]
+    {
+        use winapi::um::errhandlingapi::SetErrorMode;
+        use winapi::um::processthreadsapi::SetProcessMitigationPolicy;
+        use winapi::um::winbase::{SEM_FAILCRITICALERRORS, SEM_NOGPFAULTERRORBOX};
+        use winapi::um::winnt::{
+            ProcessExtensionPointDisablePolicy, ProcessImageLoadPolicy,
+            PROCESS_MITIGATION_EXTENSION_POINT_DISABLE_POLICY,
+            PROCESS_MITIGATION_IMAGE_LOAD_POLICY,
+        };
+
+        // no Windows Error Reporting, which would write a dump of crashes
+        unsafe { SetErrorMode(SEM_FAILCRITICALERRORS | SEM_NOGPFAULTERRORBOX) };
+        // no DLLs injected through AppInit_DLLs and the like, or loaded from network shares
+        let mut extension_points = PROCESS_MITIGATION_EXTENSION_POINT_DISABLE_POLICY { Flags: 0 };
+        extension_points.set_DisableExtensionPoints(1);
+        let mut image_load = PROCESS_MITIGATION_IMAGE_LOAD_POLICY { Flags: 0 };
+        image_load.set_NoRemoteImages(1);
+        let policies_set = unsafe {
+            SetProcessMitigationPolicy(
+                ProcessExtensionPointDisablePolicy,
+                &mut extension_points as *mut _ as *mut _,
+                std::mem::size_of_val(&extension_points),
+            ) != 0
+                && SetProcessMitigationPolicy(
+                    ProcessImageLoadPolicy,
+                    &mut image_load as *mut _ as *mut _,
+                    std::mem::size_of_val(&image_load),
+                ) != 0
+        };
+        if !policies_set {
+            return Err(anyhow!(
+                "Failed to set process mitigation policies, {}",
+                io::Error::last_os_error()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Runs the subcommand given on the command line `argv`, including the name of the program, which
+/// also selects the subcommand when invoked as e.g. `docker-credential-keepassxc`
+pub fn run(mut argv: Vec<OsString>) -> Result<()> {
+    #[cfg(not(debug_assertions))]
+    harden_process()?;
+
+    // invoked by Docker as docker-credential-keepassxc <ACTION>
+    let docker_multicall = argv
+        .first()
+        .and_then(|arg0| Path::new(arg0).file_stem())
+        .map(|stem| stem.to_string_lossy().starts_with("docker-credential-"))
+        .unwrap_or(false);
+    if docker_multicall {
+        argv.insert(1, "docker".into());
+    }
+    // invoked as SSH_ASKPASS <PROMPT>, which can't take arguments
+    let askpass_multicall = argv
+        .first()
+        .and_then(|arg0| Path::new(arg0).file_stem())
+        .map(|stem| stem.to_string_lossy().ends_with("-askpass"))
+        .unwrap_or(false);
+    if askpass_multicall {
+        argv.insert(1, "askpass".into());
+    }
+    // set as pinentry-program of gpg-agent
+    let pinentry_multicall = argv
+        .first()
+        .and_then(|arg0| Path::new(arg0).file_stem())
+        .map(|stem| stem.to_string_lossy().starts_with("pinentry-"))
+        .unwrap_or(false);
+    if pinentry_multicall {
+        argv.insert(1, "pinentry".into());
+    }
+    let yaml = clap::load_yaml!("cli.yml");
+    let args = App::from_yaml(yaml)
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .get_matches_from(argv);
+    JSON_OUTPUT.store(is_json(&args), Ordering::Relaxed);
+    JSON_ERRORS.store(
+        is_global_flag_present(&args, "json-errors"),
+        Ordering::Relaxed,
+    );
+    let quiet = is_global_flag_present(&args, "quiet");
+    if is_global_flag_present(&args, "non-interactive") {
+        set_non_interactive();
+    }
+    QUIET_OUTPUT.store(quiet, Ordering::Relaxed);
+
+    let level = if quiet {
+        Level::Error
+    } else {
+        Level::from_usize(std::cmp::min(6, args.occurrences_of("verbose") + 2) as usize)
+            .unwrap_or(Level::Error)
+    };
+    // debugging inside Git's own tracing, e.g. GIT_TRACE=1 git fetch
+    let trace = TraceTarget::from_env();
+    let trace_level = if level.as_usize() >= Level::Debug.as_usize() {
+        level
+    } else {
+        Level::Debug
+    };
+    let level = if trace == Some(TraceTarget::Stderr) && !quiet {
+        trace_level
+    } else {
+        level
+    };
+    let json_log = global_value_of(&args, "log-format") == Some("json");
+    let drain: LogDrain = if json_log {
+        Box::new(JsonDrain::new(io::stderr()).filter_level(level).fuse())
+    } else if io::stderr().is_terminal() {
+        if !quiet {
+            progress::enable();
+        }
+        let decorator = slog_term::TermDecorator::new().stderr().build();
+        let drain = slog_term::FullFormat::new(decorator)
+            .build()
+            .filter_level(level)
+            .fuse();
+        Box::new(std::sync::Mutex::new(drain).fuse())
+    } else {
+        // no colours when e.g. redirected to a file or swallowed by Git
+        let decorator = slog_term::PlainSyncDecorator::new(io::stderr());
+        Box::new(
+            slog_term::FullFormat::new(decorator)
+                .build()
+                .filter_level(level)
+                .fuse(),
+        )
+    };
+    // secrets are redacted from files, which outlive the run
+    let file_drain = |file: std::fs::File, level: Level| -> LogDrain {
+        if json_log {
+            Box::new(JsonDrain::new(file).redacted().filter_level(level).fuse())
+        } else {
+            let decorator = slog_term::PlainSyncDecorator::new(RedactingWriter::new(file));
+            Box::new(
+                slog_term::FullFormat::new(decorator)
+                    .build()
+                    .filter_level(level)
+                    .fuse(),
+            )
+        }
+    };
+    let log_file = global_value_of(&args, "log-file");
+    let drain: LogDrain = if let Some(log_file) = log_file {
+        let mut file_options = std::fs::OpenOptions::new();
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut file_options, 0o600);
+        let file = file_options
+            .create(true)
+            .append(true)
+            .open(log_file)
+            .map_err(|e| anyhow!("Failed to open log file {}, {}", log_file, e))?;
+        Box::new(slog::Duplicate::new(drain, file_drain(file, level)).fuse())
+    } else {
+        drain
+    };
+    // like Git, which only warns about it
+    let (trace_file, trace_error) = match trace.as_ref().map(TraceTarget::open) {
+        Some(Ok(trace_file)) => (trace_file, None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    let drain: LogDrain = if let Some(trace_file) = trace_file {
+        Box::new(slog::Duplicate::new(drain, file_drain(trace_file, trace_level)).fuse())
+    } else {
+        drain
+    };
+    let logger = Logger::root(drain, slog::o!());
+    LOGGER
+        .set(logger)
+        .map_err(|_| anyhow!("Failed to initialise logger"))?;
+    if let (Some(trace), Some(e)) = (&trace, trace_error) {
+        warn!("Failed to open {:?} for tracing, {}", trace, e);
+    }
+    // before anything is printed
+    let subcommand = args.subcommand_name().unwrap_or_default();
+    if is_global_flag_present(&args, "json") && !JSON_SUBCOMMANDS.contains(&subcommand) {
+        return Err(anyhow!("--json is not supported by {}", subcommand));
+    }
+
+    // before starting any thread
+    interrupt::install(|e| {
+        restore_echo();
+        let exit_code = report_error(&e.into());
+        memlock::wipe_all();
+        std::process::exit(exit_code);
+    });
+
+    let timeout = global_value_of(&args, "timeout");
+    // long-running ones would be killed for no reason
+    let long_running = ["daemon", "serve", "clear-clipboard", "lock-database"]
+        .contains(&args.subcommand_name().unwrap_or_default());
+    if let (Some(timeout), false) = (timeout, long_running) {
+        let timeout =
+            u64::from_str(timeout).map_err(|e| anyhow!("Invalid timeout {}, {}", timeout, e))?;
+        deadline::start(timeout, |e| {
+            let exit_code = report_error(&e.into());
+            std::process::exit(exit_code);
+        });
+    }
+
+    #[cfg(all(target_os = "linux", not(debug_assertions)))]
+    {
+        if let Ok(dumpable) = prctl::get_dumpable() {
+            if dumpable {
+                error!("Failed to disable dump");
+            } else {
+                info!("Dump is disabled");
+            }
+        } else {
+            error!("Failed to query dumpable status");
+        }
+    }
+    #[cfg(all(any(target_os = "macos", windows), not(debug_assertions)))]
+    info!("Dump is disabled");
+
+    let config_path = {
+        if let Some(path) = global_value_of(&args, "config") {
+            info!("Configuration file path is set to {} by user", path);
+            PathBuf::from(path)
+        } else {
+            config::default_config_path()?
+        }
+    };
+    let config_path = match global_value_of(&args, "profile") {
+        Some(profile) => {
+            let profile_path = config::profile_config_path(&config_path, profile)?;
+            info!(
+                "Using profile {}, configuration file {}",
+                profile,
+                profile_path.to_string_lossy()
+            );
+            if args.subcommand_name() == Some("configure") {
+                create_profiles_dir(&profile_path)?;
+            }
+            profile_path
+        }
+        None => config_path,
+    };
+    if let Some(path) = global_value_of(&args, "socket") {
+        info!("Socket path is set to {} by user", path);
+        let path = PathBuf::from(path);
+        utils::SOCKET_PATH.with(|s| {
+            s.set(path).expect("Failed to set socket path, bug?");
+        });
+    } else {
+        warn_if_other_user(&config_path);
+    }
+    let unlock_options = unlock_options_of(&args)?;
+
+    let subcommand = args
+        .subcommand_name()
+        .ok_or_else(|| anyhow!("No subcommand selected"))?;
+    debug!("Subcommand: {}", subcommand);
+    let fixing_permissions = args
+        .subcommand_matches("config")
+        .and_then(|m| m.subcommand_name())
+        == Some("fix-perms");
+    // neither needs the configuration
+    let without_config = ["completions", "manpage"].contains(&subcommand);
+    if !fixing_permissions && !without_config {
+        config::check_permissions(&config_path)?;
+    }
+    match subcommand {
+        "configure" => configure(config_path, &args),
+        "reassociate" => reassociate(config_path, &args),
+        "forget-database" => forget_database(config_path, &args),
+        "encrypt" => encrypt(config_path, &args),
+        "decrypt" => decrypt(config_path),
+        "config" => manage_config(config_path, &args),
+        "caller" => caller(config_path, &args),
+        "get" if is_json(&args) => {
+            let input = read_request(&args)?;
+            let mut output = Vec::new();
+            let result = get_logins(
+                &config_path,
+                &unlock_options,
+                &args,
+                &mut input.as_bytes(),
+                &mut output,
+            );
+            record_get(&config_path, &input, &result);
+            let login = result?;
+            if let Some(get_args) = args.subcommand_matches("get") {
+                if get_args.is_present("check") {
+                    let count: usize = String::from_utf8_lossy(&output).trim().parse()?;
+                    println!(
+                        "{}",
+                        output::json_document(serde_json::json!({ "count": count }))
+                    );
+                    return Ok(());
+                }
+            }
+            let login_json = login.as_ref().map(login_json);
+            println!(
+                "{}",
+                output::json_document(serde_json::json!({ "login": login_json }))
+            );
+            Ok(())
+        }
+        "get"
+            if args
+                .subcommand_matches("get")
+                .map(|m| m.is_present("stdin-loop"))
+                .unwrap_or(false) =>
+        {
+            get_logins_batch(
+                config_path,
+                &unlock_options,
+                &args,
+                &mut io::stdin().lock(),
+                &mut io::stdout(),
+            )
+        }
+        "get" | "store" | "erase" => {
+            let input = read_request(&args)?;
+            if subcommand == "get" && git::split_messages(&input).len() > 1 {
+                return get_logins_batch(
+                    config_path,
+                    &unlock_options,
+                    &args,
+                    &mut input.as_bytes(),
+                    &mut io::stdout(),
+                );
+            }
+            let get_args = args.subcommand_matches("get");
+            let cache_helper = if subcommand == "get"
+                && !get_args.map(|m| m.is_present("check")).unwrap_or(false)
+            {
+                Config::read_from(&config_path)?
+                    .get_cache_helper()
+                    .map(str::to_owned)
+            } else {
+                None
+            };
+            if let Some(ref helper) = cache_helper {
+                if let Some(response) = get_from_cache_helper(helper, &input) {
+                    io::stdout().write_all(response.as_bytes())?;
+                    record_access(&config_path, "get", &input, Some("cache"), &Ok(()));
+                    return Ok(());
+                }
+            }
+            // the daemon connects to its own socket, and only answers plain requests
+            #[cfg(unix)]
+            if !args.is_present("socket")
+                && !get_args
+                    .map(|m| {
+                        m.is_present("renew")
+                            || m.is_present("interactive-fallback")
+                            || m.is_present("check")
+                    })
+                    .unwrap_or(false)
+                && !args.is_present("interactive-fallback")
+            {
+                if let Some(response) = proxy_to_daemon(&config_path, &input) {
+                    io::stdout().write_all(response.output.as_bytes())?;
+                    if let (Some(helper), None) = (&cache_helper, &response.error) {
+                        store_in_cache_helper(helper, &input, &response.output);
+                    }
+                    let result = response.error.map_or(Ok(()), |e| Err(e.into()));
+                    record_access(&config_path, subcommand, &input, None, &result);
+                    if result.is_ok() {
+                        schedule_auto_lock(&config_path, &args);
+                        if is_json(&args) {
+                            print_json_success(subcommand);
+                        }
+                    }
+                    return result;
+                }
+            }
+            let mut request = input.as_bytes();
+            if subcommand == "get" {
+                let mut response = Vec::new();
+                let result = get_logins(
+                    &config_path,
+                    &unlock_options,
+                    &args,
+                    &mut request,
+                    &mut response,
+                );
+                io::stdout().write_all(&response)?;
+                if let (Some(helper), Ok(Some(_))) = (&cache_helper, &result) {
+                    store_in_cache_helper(helper, &input, &String::from_utf8_lossy(&response));
+                }
+                record_get(&config_path, &input, &result);
+                if let Ok(Some(_)) = result {
+                    schedule_auto_lock(&config_path, &args);
+                }
+                return result.map(|_| ());
+            }
+            let result = match subcommand {
+                "store" => store_login_in_group(
+                    &config_path,
+                    &unlock_options,
+                    &args,
+                    &mut request,
+                    global_value_of(&args, "group"),
+                ),
+                _ => erase_login(
+                    &config_path,
+                    &unlock_options,
+                    &args,
+                    &mut request,
+                    &mut io::stdout(),
+                ),
+            };
+            record_access(&config_path, subcommand, &input, None, &result);
+            if result.is_ok() {
+                schedule_auto_lock(&config_path, &args);
+                if is_json(&args) {
+                    print_json_success(subcommand);
+                }
+            }
+            result
+        }
+        "daemon" => session_daemon(config_path),
+        "ui" => ui(config_path, &unlock_options),
+        "cache" => cache(config_path, &args),
+        "bench" => bench(config_path, &args),
+        "completions" => completions(config_path, &args),
+        "install" => install(&args),
+        "manpage" => {
+            let yaml = clap::load_yaml!("cli.yml");
+            print!("{}", manpage::render(yaml, env!("CARGO_PKG_VERSION")));
+            Ok(())
+        }
+        "docker" => docker(config_path, &unlock_options, &args),
+        "cargo" => cargo_provider(config_path, &unlock_options, &args),
+        "npm" => npm_auth(config_path, &unlock_options, &args),
+        "keyring" => keyring(config_path, &unlock_options, &args),
+        "hg" => hg_auth(config_path, &unlock_options, &args),
+        "askpass" => askpass(config_path, &unlock_options, &args),
+        "serve" => serve(config_path, &unlock_options, &args),
+        "kube" => kube_credential(config_path, &unlock_options, &args),
+        "aws" => aws_credential(config_path, &unlock_options, &args),
+        "maven" => maven_credentials(config_path, &unlock_options, &args),
+        "get-secret" => get_secret(config_path, &unlock_options, &args),
+        "list" => list_logins(config_path, &unlock_options, &args),
+        "databases" => list_databases(config_path, &args),
+        "prune" => prune_logins(config_path, &unlock_options, &args),
+        "clear-clipboard" => clear_clipboard(&args),
+        "lock-database" => lock_database(config_path, &args),
+        "autotype" => autotype(config_path, &unlock_options, &args),
+        "rotate" => {
+            let result = rotate_password(&config_path, &unlock_options, &args);
+            let request = args
+                .subcommand_matches("rotate")
+                .and_then(|m| m.value_of("url"))
+                .map(|url| format!("url={}\n", url))
+                .unwrap_or_default();
+            record_access(&config_path, "rotate", &request, None, &result);
+            result
+        }
+        "raw" => raw_request(&args),
+        "gh" => gh_token(config_path, &unlock_options, &args),
+        "svn" => svn_credential(config_path, &unlock_options, &args),
+        "pinentry" => pinentry_session(config_path, &unlock_options, &args),
+        "import" => import_logins(config_path, &unlock_options, &args),
+        _ => Err(anyhow!(anyhow!("Unrecognised subcommand"))),
+    }
+}
+
+/// Subcommands printing a JSON document with `--json`, which is refused for others rather than
+/// having them print their usual output
+const JSON_SUBCOMMANDS: &[&str] = &[
+    "get",
+    "store",
+    "erase",
+    "get-secret",
+    "list",
+    "databases",
+    "cache",
+    "configure",
+    "forget-database",
+    "caller",
+];
+
+fn is_json(args: &ArgMatches) -> bool {
+    is_global_flag_present(args, "json") || is_json_stdin(args)
+}
+
+/// Prints `{"stored": true}` or `{"erased": true}` for `store` or `erase` with `--json`
+fn print_json_success(subcommand: &str) {
+    let key = if subcommand == "store" {
+        "stored"
+    } else {
+        "erased"
+    };
+    println!(
+        "{}",
+        output::json_document(serde_json::json!({ key: true }))
+    );
+}
+
+/// Whether `--stdin-format json` is given, before or after the subcommand
+fn is_json_stdin(args: &ArgMatches) -> bool {
+    global_value_of(args, "stdin-format") == Some("json")
+}
+
+fn unlock_options_of(args: &ArgMatches) -> Result<Option<UnlockOptions>> {
+    if let Some(unlock_options) = global_value_of(args, "unlock") {
+        // nobody would unlock it
+        if !is_interactive() || is_global_flag_present(args, "non-interactive") {
+            warn!("Not waiting for locked databases in a non-interactive session");
+            return Ok(None);
+        }
+        info!("Database unlock option is given by user");
+        Ok(Some(UnlockOptions::from_str(unlock_options)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Exit code of the process for an error, 1 unless it's of a documented class
+fn exit_code_of(e: &anyhow::Error) -> i32 {
+    if e.downcast_ref::<AmbiguousLoginsError>().is_some() {
+        AmbiguousLoginsError::EXIT_CODE
+    } else if e.downcast_ref::<DeadlineExceededError>().is_some() {
+        DeadlineExceededError::EXIT_CODE
+    } else if let Some(interrupted) = e.downcast_ref::<InterruptedError>() {
+        interrupted.exit_code()
+    } else if let Some(daemon_error) = e.downcast_ref::<DaemonError>() {
+        daemon_error.exit_code
+    } else if let Some(failure) = Failure::of(e) {
+        failure.exit_code()
+    } else {
+        1
+    }
+}
+
+/// Stable name, failing phase and suggestion of an error for `--json-errors`
+fn describe_error(e: &anyhow::Error) -> (&'static str, Option<String>, Option<&'static str>) {
+    if e.downcast_ref::<AmbiguousLoginsError>().is_some() {
+        (
+            "ambiguous-logins",
+            deadline::failed_phase(),
+            Some("Narrow down the login with a username, --filter-title or --filter-username"),
+        )
+    } else if let Some(deadline_error) = e.downcast_ref::<DeadlineExceededError>() {
+        (
+            "deadline-exceeded",
+            deadline_error.phase.clone(),
+            Some("Pass a longer --timeout, or check what the helper was waiting for"),
+        )
+    } else if let Some(interrupted) = e.downcast_ref::<InterruptedError>() {
+        ("interrupted", interrupted.phase.clone(), None)
+    } else if let Some(failure) = Failure::of(e) {
+        (
+            failure.code(),
+            deadline::failed_phase(),
+            Some(failure.suggestion()),
+        )
+    } else {
+        ("other", deadline::failed_phase(), None)
+    }
+}
+
+/// Logs an error, and prints it on stdout too with `--json` and on stderr with `--json-errors`.
+/// Returns the exit code for it.
+pub fn report_error(e: &anyhow::Error) -> i32 {
+    let source = e
+        .source()
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "N/A".to_string());
+    error!("{}, Caused by: {}", e, source);
+    let exit_code = exit_code_of(e);
+    if JSON_OUTPUT.load(Ordering::Relaxed) {
+        let error = serde_json::json!({
+            "message": e.to_string(),
+            "exit_code": exit_code,
+        });
+        println!(
+            "{}",
+            output::json_document(serde_json::json!({ "error": error }))
+        );
+    }
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        let (code, phase, suggestion) = describe_error(e);
+        let error = serde_json::json!({
+            "code": code,
+            "exit_code": exit_code,
+            "phase": phase,
+            "message": e.to_string(),
+            "suggestion": suggestion,
+        });
+        eprintln!(
+            "{}",
+            output::json_document(serde_json::json!({ "error": error }))
+        );
+    }
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_00_forward_env_args() {
+        let env_var = |var: &str| {
+            match var {
+                "GIT_CREDENTIAL_KEEPASSXC_CONFIG" => Some("/env/config.json"),
+                "GIT_CREDENTIAL_KEEPASSXC_PROFILE" => Some("work"),
+                "GIT_CREDENTIAL_KEEPASSXC_UNLOCK" => Some("3"),
+                "GIT_CREDENTIAL_KEEPASSXC_GROUP" => Some("Work/Git"),
+                "GIT_CREDENTIAL_KEEPASSXC_TIMEOUT" => Some("5"),
+                _ => None,
+            }
+            .map(str::to_owned)
+        };
+        let mut argv: Vec<String> = ["git-credential-keepassxc", "-c", "/cli/config.json"]
+            .iter()
+            .chain(["get", "--unlock=1"].iter())
+            .map(|arg| arg.to_string())
+            .collect();
+        forward_env_args(&mut argv, env_var);
+
+        // the daemon parses them like the command line
+        let yaml = clap::load_yaml!("cli.yml");
+        let args = App::from_yaml(yaml).get_matches_from_safe(&argv).unwrap();
+        assert_eq!(global_value_of(&args, "config"), Some("/cli/config.json"));
+        assert_eq!(global_value_of(&args, "unlock"), Some("1"));
+        assert_eq!(global_value_of(&args, "profile"), Some("work"));
+        assert_eq!(global_value_of(&args, "timeout"), Some("5"));
+        assert_eq!(args.value_of("group"), Some("Work/Git"));
+        assert_eq!(global_value_of(&args, "database"), None);
+    }
+}
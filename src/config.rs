@@ -1,3 +1,30 @@
+//! Configuration file of the helper, i.e. the KeePassXC database profiles, caller profiles and the
+//! encryption profiles protecting them, along with lookup rules and options
+//!
+//! Profiles can be provisioned programmatically, e.g. by a setup script:
+//!
+//! ```no_run
+//! use git_credential_keepassxc::config::{Caller, Config};
+//!
+//! let mut config = Config::read_from("config.json")?;
+//! config.remove_callers(|_, caller| caller.path == "/usr/bin/git")?;
+//! config.add_caller(Caller::new("/usr/bin/git"), false)?;
+//! config.write_to("config.json")?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+use crate::audit::AuditLog;
+use crate::daemon::SessionDaemon;
+use crate::git::CredentialSettings;
+use crate::hooks::Hook;
+use crate::keepassxc::messages::LoginEntry;
+use crate::lockout::Lockout;
+#[allow(unused_imports)]
+use crate::logging::timed;
+#[allow(unused_imports)]
+use crate::utils::{
+    get_socket_path, split_url_authority, text_matches, unix_now, url_host, url_matches, Failure,
+    Secret,
+};
 #[allow(unused_imports)]
 use crate::{debug, error, info, warn};
 use aes_gcm::aead::generic_array::{typenum, GenericArray};
@@ -6,11 +33,12 @@ use anyhow::{anyhow, Context, Result};
 use mockall::automock;
 use serde::{de, Deserialize, Serialize};
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::io::prelude::*;
 #[cfg(unix)]
 use std::os::unix::fs::OpenOptionsExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::string::ToString;
 
 #[cfg(feature = "encryption")]
@@ -39,9 +67,47 @@ const AES_KEY_LENGTH: usize = 32usize;
 #[cfg(feature = "encryption")]
 const AES_NONCE_LENGTH: usize = 12usize;
 
+#[cfg(feature = "encryption")]
+thread_local!(
+    // set by the session daemon, so that e.g. YubiKey is only asked once for each key
+    static KEPT_ENCRYPTION_KEYS: RefCell<Option<KeptEncryptionKeys>> = const { RefCell::new(None) }
+);
+
+/// Encryption keys kept in memory, for `ttl` seconds if set, by the encrypted keys in the
+/// configuration file they were derived from
+#[cfg(feature = "encryption")]
+struct KeptEncryptionKeys {
+    ttl: Option<u64>,
+    keys: Vec<(String, AesKey, u64)>,
+}
+
+#[cfg(feature = "encryption")]
+impl KeptEncryptionKeys {
+    fn expire(&mut self, now: u64) {
+        let ttl = self.ttl;
+        self.keys
+            .retain(|(_, _, kept_at)| ttl.map(|ttl| kept_at + ttl > now).unwrap_or(true));
+    }
+
+    fn get(&mut self, encrypted_key: &str, now: u64) -> Option<AesKey> {
+        self.expire(now);
+        self.keys
+            .iter()
+            .find(|(kept_key, _, _)| kept_key == encrypted_key)
+            .map(|(_, aes_key, _)| *aes_key)
+    }
+
+    fn insert(&mut self, encrypted_key: String, aes_key: AesKey, now: u64) {
+        self.keys.push((encrypted_key, aes_key, now));
+    }
+}
+
 type AesKey = GenericArray<u8, typenum::U32>;
 type AesNonce = GenericArray<u8, typenum::U12>;
 
+pub const GITHUB_HOST: &str = "github.com";
+const GIST_HOST: &str = "gist.github.com";
+
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct Config {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -53,11 +119,319 @@ pub struct Config {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     encrypted_callers: Vec<EncryptedProfile>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<Rule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    field_mappings: Vec<FieldMapping>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    password_policies: Vec<PasswordPolicy>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    deny: Vec<DenyRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<Alias>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    proxies: Vec<Proxy>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ssh_keys: Vec<SshKey>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    kube_clusters: Vec<KubeCluster>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    aws_profiles: Vec<AwsProfile>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    svn_realms: Vec<SvnRealm>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    maven_servers: Vec<MavenServer>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    gpg_keys: Vec<GpgKey>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pinentry_fallback: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cache_helper: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lockout: Option<Lockout>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    audit_log: Option<AuditLog>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    hooks: Vec<Hook>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session_daemon: Option<SessionDaemon>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    group_only: bool,
+    #[serde(default, skip_serializing_if = "EntryFilters::is_empty")]
+    filters: EntryFilters,
+    #[serde(default, skip_serializing_if = "UseHttpPath::is_default")]
+    use_http_path: UseHttpPath,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    advanced_fields: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    require_unique: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    notify_on_access: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    confirm_access: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rotate_after_days: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    remember_rejected: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auto_lock_after: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    read_only: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    confirm_overwrite: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    store_host_only: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    host_subgroups: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    namespaces: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    store_favicon: bool,
+    #[serde(default, skip_serializing_if = "EraseMode::is_disabled")]
+    erase_mode: EraseMode,
+    #[serde(default, skip_serializing_if = "HashMismatch::is_refuse")]
+    hash_mismatch: HashMismatch,
+    #[serde(default, skip_serializing_if = "UnsafeValues::is_reject")]
+    unsafe_values: UnsafeValues,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    ascii_only: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    git_config: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     encryptions: Vec<Encryption>,
     #[serde(skip)]
     encryption_key: RefCell<Option<AesKey>>,
 }
 
+/// The configuration file doesn't exist or has no databases, i.e. `configure` hasn't been run,
+/// which comes with instructions
+#[derive(Debug)]
+pub struct NotConfiguredError {
+    /// Path of the configuration file if it's missing, `None` if it has no databases
+    pub missing_path: Option<PathBuf>,
+    /// `--config` argument needed for `configure`, if not the default path
+    config_arg: Option<PathBuf>,
+    /// Whether KeePassXC's socket exists, i.e. browser integration is enabled
+    socket_found: bool,
+}
+impl NotConfiguredError {
+    pub fn new(config_path: Option<&Path>, missing: bool) -> Self {
+        Self {
+            missing_path: config_path.filter(|_| missing).map(Path::to_owned),
+            config_arg: config_path
+                .filter(|path| default_config_path().ok().as_deref() != Some(*path))
+                .map(Path::to_owned),
+            // named pipes can't be checked this way
+            socket_found: cfg!(windows) || get_socket_path().is_ok_and(|path| path.exists()),
+        }
+    }
+}
+impl fmt::Display for NotConfiguredError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.missing_path {
+            Some(path) => write!(
+                f,
+                "Configuration file {} doesn't exist",
+                path.to_string_lossy()
+            )?,
+            None => write!(f, "No databases are configured")?,
+        }
+        if self.socket_found {
+            write!(f, ". Run")?;
+        } else {
+            write!(f, ". KeePassXC isn't running or its browser integration is disabled, so start it, enable Tools > Settings > Browser Integration > Enable browser integration, and then run")?;
+        }
+        write!(f, " with a database unlocked: git-credential-keepassxc")?;
+        if let Some(config_arg) = &self.config_arg {
+            write!(f, " --config {}", config_arg.to_string_lossy())?;
+        }
+        write!(f, " configure")
+    }
+}
+impl std::error::Error for NotConfiguredError {}
+
+/// Configuration file used unless `--config` is given
+pub fn default_config_path() -> Result<PathBuf> {
+    let base_dirs = directories_next::BaseDirs::new()
+        .ok_or_else(|| anyhow!("Failed to initialise base_dirs"))?;
+    Ok(base_dirs.config_dir().join(clap::crate_name!()))
+}
+
+/// Configuration file of a named profile, e.g. `git-credential-keepassxc.d/work` next to the main
+/// one, which has its own databases, rules and callers
+pub fn profile_config_path<T: AsRef<Path>>(config_path: T, profile: &str) -> Result<PathBuf> {
+    let is_valid = !profile.is_empty()
+        && !profile.starts_with('.')
+        && profile
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if !is_valid {
+        return Err(anyhow!(
+            "Invalid profile name {}, use letters, digits, '-', '_' and '.' only",
+            profile
+        ));
+    }
+    let mut profiles_dir = config_path.as_ref().as_os_str().to_owned();
+    profiles_dir.push(".d");
+    Ok(PathBuf::from(profiles_dir).join(profile))
+}
+
+/// Exclusive advisory lock for changing the configuration file, released when dropped. Taken on a
+/// `.lock` file next to it, so that parallel processes (e.g. `configure`, `caller add`, or `store`
+/// updating the group of a database) read and write the configuration file one after another
+/// instead of losing each other's changes.
+pub struct ConfigLock {
+    _file: fs::File,
+}
+
+impl ConfigLock {
+    /// Waits until no other process holds the lock of the configuration file and takes it
+    pub fn acquire<T: AsRef<Path>>(config_path: T) -> Result<Self> {
+        let lock_path = crate::utils::state_file_path(config_path, "lock");
+        let mut file_options = fs::OpenOptions::new();
+        #[cfg(unix)]
+        file_options.mode(DEFAULT_CONFIG_MODE);
+        let file = file_options
+            .create(true)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("Failed to open lock file {}", lock_path.to_string_lossy()))?;
+        if !Self::lock(&file, false)? {
+            info!("Waiting for another process to finish changing the configuration");
+            Self::lock(&file, true)?;
+        }
+        debug!("Locked {}", lock_path.to_string_lossy());
+        Ok(Self { _file: file })
+    }
+
+    /// Locks the file, returns false if it's locked by another process and `wait` isn't set
+    #[cfg(unix)]
+    fn lock(file: &fs::File, wait: bool) -> Result<bool> {
+        use std::os::unix::io::AsRawFd;
+
+        let operation = if wait {
+            libc::LOCK_EX
+        } else {
+            libc::LOCK_EX | libc::LOCK_NB
+        };
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+            return Ok(true);
+        }
+        let error = std::io::Error::last_os_error();
+        if !wait && error.kind() == std::io::ErrorKind::WouldBlock {
+            return Ok(false);
+        }
+        Err(anyhow!("Failed to lock the configuration, {}", error))
+    }
+
+    #[cfg(windows)]
+    fn lock(file: &fs::File, wait: bool) -> Result<bool> {
+        use std::os::windows::io::AsRawHandle;
+        use winapi::um::minwinbase::{LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY};
+
+        let flags = if wait {
+            LOCKFILE_EXCLUSIVE_LOCK
+        } else {
+            LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY
+        };
+        let mut overlapped: winapi::um::minwinbase::OVERLAPPED = unsafe { std::mem::zeroed() };
+        let locked = unsafe {
+            winapi::um::fileapi::LockFileEx(
+                file.as_raw_handle() as winapi::um::winnt::HANDLE,
+                flags,
+                0,
+                !0,
+                !0,
+                &mut overlapped,
+            )
+        };
+        if locked != 0 {
+            return Ok(true);
+        }
+        let error = std::io::Error::last_os_error();
+        // ERROR_LOCK_VIOLATION
+        if !wait && error.raw_os_error() == Some(33) {
+            return Ok(false);
+        }
+        Err(anyhow!("Failed to lock the configuration, {}", error))
+    }
+}
+
+/// Permission bits of the configuration file others mustn't have, as it contains the keys of the
+/// databases
+#[cfg(unix)]
+const INSECURE_CONFIG_MODE: u32 = 0o077;
+/// Permission bits of the directory of the configuration file others mustn't have, as they could
+/// replace the file then
+#[cfg(unix)]
+const INSECURE_CONFIG_DIR_MODE: u32 = 0o022;
+
+/// Fails if others can read or write the configuration file, or write its directory, under Unix
+pub fn check_permissions<T: AsRef<Path>>(config_path: T) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let config_path = config_path.as_ref();
+        let hint = format!("run {} config fix-perms to fix it", clap::crate_name!());
+        if let Ok(metadata) = fs::metadata(config_path) {
+            let mode = metadata.permissions().mode();
+            if mode & INSECURE_CONFIG_MODE != 0 {
+                return Err(anyhow!(
+                    "Configuration file {} is accessible by other users (mode {:o}), {}",
+                    config_path.to_string_lossy(),
+                    mode & 0o777,
+                    hint
+                ));
+            }
+        }
+        if let Some(config_dir) = config_path.parent().filter(|dir| dir.is_dir()) {
+            let mode = fs::metadata(config_dir)?.permissions().mode();
+            if mode & INSECURE_CONFIG_DIR_MODE != 0 {
+                return Err(anyhow!(
+                    "Configuration directory {} is writable by other users (mode {:o}), {}",
+                    config_dir.to_string_lossy(),
+                    mode & 0o777,
+                    hint
+                ));
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = config_path;
+    Ok(())
+}
+
+/// Removes the permissions `check_permissions()` complains about, returning the paths changed
+pub fn fix_permissions<T: AsRef<Path>>(config_path: T) -> Result<Vec<PathBuf>> {
+    let mut fixed = Vec::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let config_path = config_path.as_ref();
+        let mut fix = |path: &Path, insecure_mode: u32| -> Result<()> {
+            let mut permissions = fs::metadata(path)?.permissions();
+            if permissions.mode() & insecure_mode != 0 {
+                permissions.set_mode(permissions.mode() & !insecure_mode);
+                fs::set_permissions(path, permissions).with_context(|| {
+                    format!("Failed to change mode of {}", path.to_string_lossy())
+                })?;
+                fixed.push(path.to_owned());
+            }
+            Ok(())
+        };
+        if config_path.exists() {
+            fix(config_path, INSECURE_CONFIG_MODE)?;
+        }
+        if let Some(config_dir) = config_path.parent().filter(|dir| dir.is_dir()) {
+            fix(config_dir, INSECURE_CONFIG_DIR_MODE)?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = config_path;
+    Ok(fixed)
+}
+
 impl Config {
     pub fn new() -> Self {
         Self {
@@ -65,11 +439,15 @@ impl Config {
         }
     }
 
+    /// Parses a configuration file
     pub fn read_from<T: AsRef<Path>>(config_path: T) -> Result<Self> {
         info!(
             "Reading configuration from {}",
             config_path.as_ref().to_string_lossy()
         );
+        if !config_path.as_ref().exists() {
+            return Err(NotConfiguredError::new(Some(config_path.as_ref()), true).into());
+        }
         let json = fs::read_to_string(config_path.as_ref()).with_context(|| {
             format!(
                 "Failed to read configuration from {}",
@@ -85,53 +463,259 @@ impl Config {
         Ok(config)
     }
 
+    /// Checks what deserialisation doesn't, i.e. keys of plain text database profiles, caller paths
+    /// and patterns, so that hand edits don't break the configuration
+    pub fn validate(&self) -> Result<()> {
+        for database in &self.databases {
+            if database.id.is_empty() {
+                return Err(anyhow!("Database profile without ID"));
+            }
+            let key = base64::decode(&database.key)
+                .ok()
+                .filter(|key| key.len() == crypto_box::KEY_SIZE)
+                .ok_or_else(|| anyhow!("Invalid key of database {}", database.id))?;
+            let mut secret_key = [0u8; crypto_box::KEY_SIZE];
+            secret_key.copy_from_slice(&key);
+            let public_key = crypto_box::SecretKey::from(secret_key).public_key();
+            if base64::encode(public_key.as_bytes()) != database.pkey {
+                return Err(anyhow!(
+                    "Public key of database {} doesn't match its key",
+                    database.id
+                ));
+            }
+        }
+        for caller in &self.callers {
+            if !Path::new(&caller.path).is_absolute() {
+                return Err(anyhow!("Caller path {} is not absolute", caller.path));
+            }
+        }
+        for rule in &self.rules {
+            glob::Pattern::new(&rule.pattern)
+                .with_context(|| format!("Invalid routing rule pattern {}", rule.pattern))?;
+            let known_database = self.databases.iter().any(|database| {
+                database.id == rule.database || database.alias.as_deref() == Some(&rule.database)
+            });
+            // encrypted ones can't be checked without decryption
+            if !known_database && self.encrypted_databases.is_empty() {
+                return Err(anyhow!(
+                    "Routing rule {} refers to unknown database {}",
+                    rule.pattern,
+                    rule.database
+                ));
+            }
+        }
+        for deny_rule in &self.deny {
+            glob::Pattern::new(&deny_rule.pattern)
+                .with_context(|| format!("Invalid deny pattern {}", deny_rule.pattern))?;
+        }
+        for filter in self
+            .filters
+            .title
+            .iter()
+            .chain(self.filters.username.iter())
+        {
+            text_matches(filter, "").with_context(|| format!("Invalid filter {}", filter))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the configuration, readable by the current user only under Unix
     pub fn write_to<T: AsRef<Path>>(&self, config_path: T) -> Result<()> {
         info!(
             "Writing configuration to {}",
             config_path.as_ref().to_string_lossy()
         );
         let json = serde_json::to_string_pretty(self)?;
-        let mut file_options = fs::OpenOptions::new();
-        #[cfg(unix)]
-        file_options.mode(DEFAULT_CONFIG_MODE);
-        let mut file = file_options
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(config_path.as_ref())
-            .with_context(|| {
-                format!(
-                    "Failed to open configuration to {}",
-                    config_path.as_ref().to_string_lossy()
-                )
-            })?;
-
-        file.write_all(&json.as_bytes()).with_context(|| {
+        // a half-written configuration file would lose all profiles
+        let _deferred = crate::interrupt::defer();
+        crate::utils::write_file_atomically(
+            config_path.as_ref(),
+            json.as_bytes(),
+            DEFAULT_CONFIG_MODE,
+        )
+        .with_context(|| {
             format!(
                 "Failed to write configuration to {}",
                 config_path.as_ref().to_string_lossy()
             )
-        })?;
+        })
+    }
+
+    /// Whether only entries in the configured groups should be returned
+    pub fn is_group_only(&self) -> bool {
+        self.group_only
+    }
+
+    /// Days after which `get` warns about logins saved by `store`
+    pub fn get_rotate_after_days(&self) -> Option<u64> {
+        self.rotate_after_days
+    }
+
+    /// Seconds to skip logins for in `get` after Git has erased them with the same password
+    pub fn get_remember_rejected(&self) -> Option<u64> {
+        self.remember_rejected
+    }
+
+    /// Seconds after the last `get`, `store` or `erase` to lock the databases after, if at all
+    pub fn get_auto_lock_after(&self) -> Option<u64> {
+        self.auto_lock_after
+    }
+
+    /// Whether `store` and `erase` should leave the databases alone
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Whether `store` should ask before changing the password of an existing login
+    pub fn is_confirm_overwrite(&self) -> bool {
+        self.confirm_overwrite
+    }
+
+    /// Whether `store` should save only the host of the URL in new entries
+    pub fn is_store_host_only(&self) -> bool {
+        self.store_host_only
+    }
+
+    /// Whether `store` should save new entries in a subgroup named after the host
+    pub fn is_host_subgroups(&self) -> bool {
+        self.host_subgroups
+    }
+
+    /// Whether logins of integrations, e.g. docker, are kept apart from each other's and Git's
+    pub fn is_namespaces(&self) -> bool {
+        self.namespaces
+    }
+
+    /// Whether new logins get the favicon of their site as icon
+    pub fn is_store_favicon(&self) -> bool {
+        self.store_favicon
+    }
+
+    /// What `erase` should do with matching logins
+    pub fn get_erase_mode(&self) -> EraseMode {
+        self.erase_mode
+    }
+
+    /// What to do when another database answers for the association of a database profile
+    pub fn get_hash_mismatch(&self) -> HashMismatch {
+        self.hash_mismatch
+    }
+
+    /// Whether `credential.*` settings of Git's configuration are honoured
+    pub fn is_git_config(&self) -> bool {
+        self.git_config
+    }
+
+    /// Applies the `credential.*` settings of Git's configuration for a URL on top of this
+    /// configuration, for the current request only
+    pub fn apply_git_settings(&mut self, url: &str, settings: &CredentialSettings) -> Result<()> {
+        if let (Some(use_http_path), Some(host)) = (settings.use_http_path, url_host(url)) {
+            self.use_http_path
+                .hosts
+                .insert(host.to_owned(), use_http_path);
+        }
+        if let Some(ref id_or_alias) = settings.database {
+            let database = self.find_database(id_or_alias)?;
+            info!(
+                "Using database {} as per credential.keepassxcDatabase",
+                database.id
+            );
+            // takes precedence over all other rules, this request being the only one it applies to
+            self.rules.insert(
+                0,
+                Rule {
+                    pattern: "*".to_owned(),
+                    database: database.id,
+                    group: None,
+                },
+            );
+        }
         Ok(())
     }
 
+    /// What `get` does with usernames and passwords Git's credential format can't carry
+    pub fn get_unsafe_values(&self) -> UnsafeValues {
+        self.unsafe_values
+    }
+
+    /// Whether non-ASCII characters in usernames and passwords are treated as unsafe too
+    pub fn is_ascii_only(&self) -> bool {
+        self.ascii_only
+    }
+
+    /// Whether `get` should fail instead of guessing when more than one login matches
+    pub fn is_require_unique(&self) -> bool {
+        self.require_unique
+    }
+
+    /// Whether to show a desktop notification whenever `get` returns a login
+    pub fn is_notify_on_access(&self) -> bool {
+        self.notify_on_access
+    }
+
+    /// Whether `get` should ask on the terminal before returning each login
+    pub fn is_confirm_access(&self) -> bool {
+        self.confirm_access
+    }
+
+    /// Names of `KPH: ` string fields `get --advanced-fields` may return, any if empty
+    pub fn get_advanced_fields(&self) -> &[String] {
+        &self.advanced_fields
+    }
+
+    pub fn get_filters(&self) -> &EntryFilters {
+        &self.filters
+    }
+
+    pub fn get_lockout(&self) -> Option<&Lockout> {
+        self.lockout.as_ref()
+    }
+
+    pub fn get_audit_log(&self) -> Option<&AuditLog> {
+        self.audit_log.as_ref()
+    }
+
+    /// Commands to run after `get`, `store` and `erase`
+    pub fn get_hooks(&self) -> &[Hook] {
+        &self.hooks
+    }
+
+    pub fn get_session_daemon(&self) -> Option<&SessionDaemon> {
+        self.session_daemon.as_ref()
+    }
+
+    #[cfg(test)]
     pub fn get_databases(&self) -> Result<Vec<Database>> {
         let mut databases: Vec<_> = self.databases.clone();
-        for encrypted_database in &self.encrypted_databases {
-            let database_json =
-                self.base64_decrypt(&encrypted_database.data, &encrypted_database.nonce);
-            if let Ok(database_json) = database_json {
-                databases.push(serde_json::from_str(database_json.as_str())?);
-            } else {
-                warn!(
-                    "Failed to decrypt database profile {}.. (omitted)",
-                    &encrypted_database.data[..8]
-                );
-            }
-        }
+        databases.extend(self.get_encrypted_databases()?);
         Ok(databases)
     }
 
+    /// Decrypts the encrypted database profiles, which may need e.g. YubiKey
+    fn get_encrypted_databases(&self) -> Result<Vec<Database>> {
+        timed("Database profile decryption", || {
+            let mut databases = Vec::new();
+            for encrypted_database in &self.encrypted_databases {
+                let database_json =
+                    self.base64_decrypt(&encrypted_database.data, &encrypted_database.nonce);
+                if let Ok(database_json) = database_json {
+                    databases.push(serde_json::from_str(database_json.as_str())?);
+                } else {
+                    warn!(
+                        "Failed to decrypt database profile {}.. (omitted)",
+                        &encrypted_database.data[..8]
+                    );
+                }
+            }
+            Ok(databases)
+        })
+    }
+
+    /// Database profiles which can be read without decryption
+    pub fn get_plain_text_databases(&self) -> &[Database] {
+        &self.databases
+    }
+
     pub fn count_databases(&self) -> usize {
         self.databases.len() + self.encrypted_databases.len()
     }
@@ -140,6 +724,46 @@ impl Config {
         self.encrypted_databases.len()
     }
 
+    pub fn count_plain_text_databases(&self) -> usize {
+        self.databases.len()
+    }
+
+    /// Returns all database profiles along with whether they are encrypted, failing if any can't
+    /// be decrypted
+    pub fn list_databases(&self) -> Result<Vec<(Database, bool)>> {
+        let mut databases: Vec<_> = self.databases.iter().map(|d| (d.clone(), false)).collect();
+        for encrypted_database in &self.encrypted_databases {
+            let database = serde_json::from_str(
+                &self.base64_decrypt(&encrypted_database.data, &encrypted_database.nonce)?,
+            )?;
+            databases.push((database, true));
+        }
+        Ok(databases)
+    }
+
+    /// Removes database profiles matching the predicate, which receives indices as in
+    /// list_databases()
+    pub fn remove_databases<F: Fn(usize, &Database) -> bool>(
+        &mut self,
+        predicate: F,
+    ) -> Result<usize> {
+        let count_plain_text_databases = self.databases.len();
+        let mut count_removed = 0;
+        for (idx, (database, _)) in self.list_databases()?.iter().enumerate().rev() {
+            if predicate(idx, database) {
+                if idx < count_plain_text_databases {
+                    self.databases.remove(idx);
+                } else {
+                    self.encrypted_databases
+                        .remove(idx - count_plain_text_databases);
+                }
+                count_removed += 1;
+            }
+        }
+        Ok(count_removed)
+    }
+
+    /// Adds a database profile, encrypted with the encryption profiles if `encrypted`
     pub fn add_database(&mut self, database: Database, encrypted: bool) -> Result<()> {
         if encrypted {
             let (data, nonce) = self.base64_encrypt(&serde_json::to_string(&database)?)?;
@@ -154,6 +778,42 @@ impl Config {
         Ok(())
     }
 
+    /// Replaces the database profile at an index as in list_databases(), which keeps its position
+    /// unless it's moved between plain text and encrypted ones
+    pub fn replace_database(
+        &mut self,
+        index: usize,
+        database: Database,
+        encrypted: bool,
+    ) -> Result<()> {
+        if index >= self.count_databases() {
+            return Err(anyhow!("No database profile at index {}", index));
+        }
+        let count_plain_text_databases = self.databases.len();
+        match (index < count_plain_text_databases, encrypted) {
+            (true, false) => self.databases[index] = database,
+            (false, true) => {
+                let (data, nonce) = self.base64_encrypt(&serde_json::to_string(&database)?)?;
+                self.encrypted_databases[index - count_plain_text_databases] = EncryptedProfile {
+                    data,
+                    nonce,
+                    ..Default::default()
+                };
+            }
+            (true, true) => {
+                self.databases.remove(index);
+                self.add_database(database, true)?;
+            }
+            (false, false) => {
+                self.encrypted_databases
+                    .remove(index - count_plain_text_databases);
+                self.add_database(database, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encrypts the plain text database profiles and returns how many were encrypted
     pub fn encrypt_databases(&mut self) -> Result<usize> {
         let result = self.databases.len();
         for database in &self.databases {
@@ -168,6 +828,7 @@ impl Config {
         Ok(result)
     }
 
+    /// Decrypts the encrypted database profiles that can be and returns how many were decrypted
     pub fn decrypt_databases(&mut self) -> Result<usize> {
         // TODO: check if Vec::drain_filter() can help simplifies this when it's stabilised
         let mut decrypted_database_indices = Vec::new();
@@ -193,40 +854,363 @@ impl Config {
         Ok(decrypted_database_indices.len())
     }
 
-    pub fn get_callers(&self) -> Result<Vec<Caller>> {
-        if self.count_encrypted_callers() > 0 {
-            if self.callers.len() > 0 {
+    /// Removes database profiles sharing the same ID and returns how many were removed. KeePassXC
+    /// overwrites the key when associating under an existing ID, so the latest profile is kept.
+    pub fn dedup_databases(&mut self) -> Result<usize> {
+        let mut ids = HashSet::new();
+        // walk backwards (encrypted ones come after plain text ones) to keep the latest profiles
+        let mut duplicate_encrypted_indices = Vec::new();
+        for (idx, encrypted_database) in self.encrypted_databases.iter().enumerate().rev() {
+            let database: Option<Database> = self
+                .base64_decrypt(&encrypted_database.data, &encrypted_database.nonce)
+                .ok()
+                .and_then(|json| serde_json::from_str(&json).ok());
+            if let Some(database) = database {
+                if !ids.insert(database.id) {
+                    duplicate_encrypted_indices.push(idx);
+                }
+            } else {
                 warn!(
-                    "{} unencrypted caller profile(s) ignored",
-                    self.callers.len()
+                    "Failed to decrypt database profile {}.. (omitted)",
+                    &encrypted_database.data[..8]
                 );
             }
-            let mut callers: Vec<_> = Vec::new();
-            for encrypted_caller in &self.encrypted_callers {
-                // must decrypt all encrypted callers
-                callers.push(serde_json::from_str(
-                    &self.base64_decrypt(&encrypted_caller.data, &encrypted_caller.nonce)?,
-                )?);
-            }
-            Ok(callers)
-        } else {
-            Ok(self.callers.clone())
         }
-    }
-
-    pub fn count_callers(&self) -> usize {
-        self.callers.len() + self.encrypted_callers.len()
-    }
+        // indices are in descending order already
+        for idx in &duplicate_encrypted_indices {
+            self.encrypted_databases.remove(*idx);
+        }
 
-    pub fn count_encrypted_callers(&self) -> usize {
-        self.encrypted_callers.len()
+        let count_databases = self.databases.len();
+        let mut databases: Vec<_> = self
+            .databases
+            .drain(..)
+            .rev()
+            .filter(|database| ids.insert(database.id.clone()))
+            .collect();
+        databases.reverse();
+        self.databases = databases;
+
+        Ok(duplicate_encrypted_indices.len() + count_databases - self.databases.len())
     }
 
-    pub fn clear_callers(&mut self) {
-        self.callers.clear();
-        self.encrypted_callers.clear();
+    /// Returns the databases that may answer for the URL, i.e. the one selected by the first
+    /// matching routing rule, or all of them when no rule matches. The encrypted ones are only
+    /// decrypted if no plaintext one is available
+    pub fn get_databases_for<T: AsRef<str>>(&self, url: T) -> Result<Vec<Database>> {
+        let databases = self.get_databases_in_tier_for(url.as_ref(), false)?;
+        if databases.is_empty() {
+            self.get_databases_in_tier_for(url.as_ref(), true)
+        } else {
+            Ok(databases)
+        }
     }
 
+    /// Plaintext (if not `encrypted`) or encrypted databases for a URL, following routing rules, so
+    /// that encrypted profiles are only decrypted when the plaintext ones aren't enough
+    pub fn get_databases_in_tier_for<T: AsRef<str>>(
+        &self,
+        url: T,
+        encrypted: bool,
+    ) -> Result<Vec<Database>> {
+        let rule = self.get_rule_for(url.as_ref());
+        let routed = |database: &Database| match rule {
+            Some(rule) => database.id == rule.database,
+            None => true,
+        };
+        if encrypted && self.encrypted_databases.is_empty() {
+            // don't bother decrypting
+        } else if encrypted {
+            let databases: Vec<_> = self
+                .get_encrypted_databases()?
+                .into_iter()
+                .filter(|database| routed(database))
+                .collect();
+            if !databases.is_empty() {
+                return Ok(databases);
+            }
+        } else {
+            if let Some(rule) = rule {
+                info!(
+                    "URL {} matches routing rule {}, using database {}",
+                    url.as_ref(),
+                    rule.pattern,
+                    rule.database
+                );
+            }
+            return Ok(self
+                .databases
+                .iter()
+                .filter(|database| routed(database))
+                .cloned()
+                .collect());
+        }
+        match rule {
+            Some(rule) if !self.databases.iter().any(routed) => Err(anyhow!(
+                "Database {} in routing rule {} is not configured",
+                rule.database,
+                rule.pattern
+            )),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Finds a database by its ID or alias
+    pub fn find_database<T: AsRef<str>>(&self, id_or_alias: T) -> Result<Database> {
+        let id_or_alias = id_or_alias.as_ref();
+        let matches = |database: &Database| {
+            database.id == id_or_alias || database.alias.as_deref() == Some(id_or_alias)
+        };
+        if let Some(database) = self.databases.iter().find(|database| matches(database)) {
+            return Ok(database.clone());
+        }
+        self.get_encrypted_databases()?
+            .into_iter()
+            .find(|database| matches(database))
+            .ok_or_else(|| anyhow!("Database {} is not configured", id_or_alias))
+    }
+
+    pub fn get_rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn get_rule_for<T: AsRef<str>>(&self, url: T) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(url.as_ref()))
+    }
+
+    pub fn get_field_mapping_for<T: AsRef<str>>(&self, url: T) -> Option<&FieldMapping> {
+        self.field_mappings
+            .iter()
+            .find(|mapping| mapping.matches(url.as_ref()))
+    }
+
+    pub fn get_password_policy_for<T: AsRef<str>>(&self, url: T) -> Option<&PasswordPolicy> {
+        self.password_policies
+            .iter()
+            .find(|policy| policy.matches(url.as_ref()))
+    }
+
+    /// Removes routing rules referring to a database by ID or alias, returning how many
+    pub fn remove_rules_for(&mut self, database: &Database) -> usize {
+        let count = self.rules.len();
+        self.rules.retain(|rule| {
+            rule.database != database.id && Some(&rule.database) != database.alias.as_ref()
+        });
+        count - self.rules.len()
+    }
+
+    /// Points routing rules referring to a database by ID to its new ID, returning how many
+    pub fn rename_database_in_rules(&mut self, old_id: &str, new_id: &str) -> usize {
+        let mut count = 0;
+        for rule in self.rules.iter_mut().filter(|rule| rule.database == old_id) {
+            rule.database = new_id.to_owned();
+            count += 1;
+        }
+        count
+    }
+
+    /// Rewrites URLs of aliased hosts to the URLs logins are stored under, e.g.
+    /// `https://github-work.example/org/repo.git` to `https://github.com/work/org/repo.git`
+    ///
+    /// The path is dropped first unless `use_http_path` says otherwise for the host.
+    pub fn rewrite_url<T: AsRef<str>>(&self, url: T) -> String {
+        let url = url.as_ref();
+        let url = match split_url_authority(url) {
+            Some((authority, rest)) if !rest.is_empty() && !self.uses_http_path(url) => {
+                info!("Ignoring the path of {} as per use_http_path", url);
+                authority
+            }
+            _ => url,
+        };
+        if let Some(proxy_url) = self.get_proxy_for(url).and_then(|proxy| proxy.url.as_ref()) {
+            if url_host(proxy_url) != url_host(url) {
+                info!("Proxy URL {} rewritten to {}", url, proxy_url);
+                return proxy_url.to_owned();
+            }
+        }
+        match (self.get_alias_for(url), split_url_authority(url)) {
+            (Some(alias), Some((_, rest))) => {
+                let rewritten = format!("{}{}", alias.url.trim_end_matches('/'), rest);
+                info!("URL {} rewritten to {}", url, rewritten);
+                rewritten
+            }
+            // gists are cloned with the same token as repositories, which gh also relies on
+            (None, Some((authority, rest)))
+                if url_host(url)
+                    .map(|host| host.eq_ignore_ascii_case(GIST_HOST))
+                    .unwrap_or(false) =>
+            {
+                let prefix = &authority[..authority.len() - GIST_HOST.len()];
+                let rewritten = format!("{}{}{}", prefix, GITHUB_HOST, rest);
+                info!("URL {} rewritten to {}", url, rewritten);
+                rewritten
+            }
+            _ => url.to_owned(),
+        }
+    }
+
+    /// Whether the path of a URL is used to look up and store logins
+    pub fn uses_http_path<T: AsRef<str>>(&self, url: T) -> bool {
+        url_host(url.as_ref())
+            .and_then(|host| {
+                self.use_http_path
+                    .hosts
+                    .iter()
+                    .find(|(pattern, _)| pattern.eq_ignore_ascii_case(host))
+                    .map(|(_, use_path)| *use_path)
+            })
+            .or(self.use_http_path.default)
+            .unwrap_or(true)
+    }
+
+    pub fn get_alias_for<T: AsRef<str>>(&self, url: T) -> Option<&Alias> {
+        let host = url_host(url.as_ref())?;
+        self.aliases
+            .iter()
+            .find(|alias| alias.host.eq_ignore_ascii_case(host))
+    }
+
+    /// Finds the proxy a URL is for, either as requested by Git or as rewritten to its `url`
+    pub fn get_proxy_for<T: AsRef<str>>(&self, url: T) -> Option<&Proxy> {
+        let host = url_host(url.as_ref())?;
+        self.proxies.iter().find(|proxy| {
+            proxy.host.eq_ignore_ascii_case(host)
+                || proxy
+                    .url
+                    .as_deref()
+                    .and_then(url_host)
+                    .map(|proxy_host| proxy_host.eq_ignore_ascii_case(host))
+                    .unwrap_or(false)
+        })
+    }
+
+    pub fn get_ssh_key_for<T: AsRef<Path>>(&self, key_path: T) -> Option<&SshKey> {
+        self.ssh_keys
+            .iter()
+            .find(|ssh_key| ssh_key.matches(&key_path))
+    }
+
+    pub fn get_kube_cluster(&self, name: &str) -> Option<&KubeCluster> {
+        self.kube_clusters
+            .iter()
+            .find(|cluster| cluster.name == name)
+    }
+
+    pub fn get_aws_profile(&self, name: &str) -> Option<&AwsProfile> {
+        self.aws_profiles
+            .iter()
+            .find(|profile| profile.name == name)
+    }
+
+    pub fn get_maven_server(&self, id: &str) -> Option<&MavenServer> {
+        self.maven_servers.iter().find(|server| server.id == id)
+    }
+
+    pub fn get_svn_realm_for(&self, realm: &str) -> Option<&SvnRealm> {
+        self.svn_realms.iter().find(|svn_realm| {
+            text_matches(&svn_realm.realm, realm).unwrap_or_else(|e| {
+                warn!("Invalid pattern {} of SVN realm, {}", svn_realm.realm, e);
+                false
+            })
+        })
+    }
+
+    /// Finds the GPG key whose keygrip, fingerprint or ID is mentioned by pinentry's
+    /// `SETKEYINFO` or `SETDESC`
+    pub fn get_gpg_key_for(&self, key_info: &str, description: &str) -> Option<&GpgKey> {
+        let key_info = key_info.to_ascii_uppercase();
+        let description = description.to_ascii_uppercase();
+        self.gpg_keys.iter().find(|gpg_key| {
+            let key = gpg_key.key.to_ascii_uppercase();
+            !key.is_empty() && (key_info.contains(&key) || description.contains(&key))
+        })
+    }
+
+    /// Program `pinentry` hands over to when it can't answer
+    pub fn get_pinentry_fallback(&self) -> Option<&str> {
+        self.pinentry_fallback.as_deref()
+    }
+
+    /// Git credential helper logins returned by `get` are handed to, and asked for first
+    pub fn get_cache_helper(&self) -> Option<&str> {
+        self.cache_helper.as_deref()
+    }
+
+    pub fn get_deny_rule_for<T: AsRef<str>>(&self, url: T) -> Option<&DenyRule> {
+        self.deny.iter().find(|rule| {
+            url_matches(&rule.pattern, url.as_ref()).unwrap_or_else(|e| {
+                warn!("Invalid pattern {} in deny list, {}", rule.pattern, e);
+                false
+            })
+        })
+    }
+
+    pub fn get_callers(&self) -> Result<Vec<Caller>> {
+        if self.count_encrypted_callers() > 0 {
+            if self.callers.len() > 0 {
+                warn!(
+                    "{} unencrypted caller profile(s) ignored",
+                    self.callers.len()
+                );
+            }
+            let mut callers: Vec<_> = Vec::new();
+            for encrypted_caller in &self.encrypted_callers {
+                // must decrypt all encrypted callers
+                callers.push(serde_json::from_str(
+                    &self.base64_decrypt(&encrypted_caller.data, &encrypted_caller.nonce)?,
+                )?);
+            }
+            Ok(callers)
+        } else {
+            Ok(self.callers.clone())
+        }
+    }
+
+    pub fn count_callers(&self) -> usize {
+        self.callers.len() + self.encrypted_callers.len()
+    }
+
+    pub fn count_encrypted_callers(&self) -> usize {
+        self.encrypted_callers.len()
+    }
+
+    /// Returns all caller profiles, including plain text ones ignored by get_callers(), along with
+    /// whether they are encrypted
+    pub fn list_callers(&self) -> Result<Vec<(Caller, bool)>> {
+        let mut callers: Vec<_> = self.callers.iter().map(|c| (c.clone(), false)).collect();
+        for encrypted_caller in &self.encrypted_callers {
+            let caller = serde_json::from_str(
+                &self.base64_decrypt(&encrypted_caller.data, &encrypted_caller.nonce)?,
+            )?;
+            callers.push((caller, true));
+        }
+        Ok(callers)
+    }
+
+    /// Removes caller profiles matching the predicate, which receives indices as in list_callers()
+    pub fn remove_callers<F: Fn(usize, &Caller) -> bool>(&mut self, predicate: F) -> Result<usize> {
+        let count_plain_text_callers = self.callers.len();
+        let mut count_removed = 0;
+        for (idx, (caller, _)) in self.list_callers()?.iter().enumerate().rev() {
+            if predicate(idx, caller) {
+                if idx < count_plain_text_callers {
+                    self.callers.remove(idx);
+                } else {
+                    self.encrypted_callers
+                        .remove(idx - count_plain_text_callers);
+                }
+                count_removed += 1;
+            }
+        }
+        Ok(count_removed)
+    }
+
+    pub fn clear_callers(&mut self) {
+        self.callers.clear();
+        self.encrypted_callers.clear();
+    }
+
+    /// Adds a caller profile, encrypted with the encryption profiles if `encrypted`
     pub fn add_caller(&mut self, caller: Caller, encrypted: bool) -> Result<()> {
         if encrypted {
             let description = Some(format!(
@@ -245,6 +1229,7 @@ impl Config {
         Ok(())
     }
 
+    /// Encrypts the plain text caller profiles and returns how many were encrypted
     pub fn encrypt_callers(&mut self) -> Result<usize> {
         let result = self.callers.len();
         for caller in &self.callers {
@@ -263,6 +1248,7 @@ impl Config {
         Ok(result)
     }
 
+    /// Decrypts the encrypted caller profiles that can be and returns how many were decrypted
     pub fn decrypt_callers(&mut self) -> Result<usize> {
         // TODO: check if Vec::drain_filter() can help simplifies this when it's stabilised
         let mut decrypted_caller_indices = Vec::new();
@@ -286,6 +1272,44 @@ impl Config {
         Ok(decrypted_caller_indices.len())
     }
 
+    /// Removes identical caller profiles and returns how many were removed
+    pub fn dedup_callers(&mut self) -> Result<usize> {
+        let mut callers = Vec::new();
+        let mut duplicate_encrypted_indices = Vec::new();
+        for (idx, encrypted_caller) in self.encrypted_callers.iter().enumerate() {
+            let caller: Option<Caller> = self
+                .base64_decrypt(&encrypted_caller.data, &encrypted_caller.nonce)
+                .ok()
+                .and_then(|json| serde_json::from_str(&json).ok());
+            if let Some(caller) = caller {
+                if callers.contains(&caller) {
+                    duplicate_encrypted_indices.push(idx);
+                } else {
+                    callers.push(caller);
+                }
+            } else {
+                warn!(
+                    "Failed to decrypt caller profile {}.. (omitted)",
+                    &encrypted_caller.data[..8]
+                );
+            }
+        }
+        for idx in duplicate_encrypted_indices.iter().rev() {
+            self.encrypted_callers.remove(*idx);
+        }
+
+        let count_callers = self.callers.len();
+        let mut callers = Vec::new();
+        for caller in self.callers.drain(..) {
+            if !callers.contains(&caller) {
+                callers.push(caller);
+            }
+        }
+        self.callers = callers;
+
+        Ok(duplicate_encrypted_indices.len() + count_callers - self.callers.len())
+    }
+
     #[cfg(not(feature = "encryption"))]
     fn base64_decrypt(&self, _data: &str, _nonce: &AesNonce) -> Result<String> {
         error!("Enable encryption to use this feature");
@@ -298,7 +1322,9 @@ impl Config {
 
         let decrypted = aead
             .decrypt(nonce, base64::decode(data)?.as_ref())
-            .map_err(|_| anyhow!("Failed to decrypt database key"))?;
+            .map_err(|_| {
+                anyhow!("Failed to decrypt database key").context(Failure::DecryptionFailed)
+            })?;
         Ok(decrypted)
     }
 
@@ -376,12 +1402,20 @@ impl Config {
         self.encryptions.len()
     }
 
+    /// Returns the encryption profiles in the format add_encryption() takes, i.e.
+    /// `challenge-response:<slot>:<challenge>`
+    pub fn list_encryptions(&self) -> Vec<String> {
+        self.encryptions.iter().map(|e| e.to_string()).collect()
+    }
+
     #[cfg(not(feature = "encryption"))]
     pub fn add_encryption(&mut self, _profile: &str) -> Result<()> {
         error!("Enable encryption to use this feature");
         Err(anyhow!("Encryption is not enabled in this build"))
     }
 
+    /// Adds an encryption profile, e.g. `challenge-response` for a YubiKey, unless one exists for
+    /// the same token already. Profiles of different tokens all unlock the same key.
     #[cfg(feature = "encryption")]
     pub fn add_encryption(&mut self, profile: &str) -> Result<()> {
         // strict match, so that we can add multiple tokens
@@ -428,138 +1462,746 @@ impl Config {
         }
     }
 
-    pub fn clear_encryptions(&mut self) {
-        self.encryptions.clear();
+    /// Removes all encryption profiles, which leaves encrypted profiles undecryptable, so decrypt
+    /// them first
+    pub fn clear_encryptions(&mut self) {
+        self.encryptions.clear();
+    }
+
+    /// Keeps encryption keys in memory for later configurations read by this thread, for `ttl`
+    /// seconds if given
+    #[cfg(feature = "encryption")]
+    pub fn keep_encryption_keys(ttl: Option<u64>) {
+        KEPT_ENCRYPTION_KEYS.with(|kept| {
+            *kept.borrow_mut() = Some(KeptEncryptionKeys {
+                ttl,
+                keys: Vec::new(),
+            })
+        });
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub fn keep_encryption_keys(_ttl: Option<u64>) {}
+
+    /// Number of keys kept by this thread, which haven't expired
+    #[cfg(feature = "encryption")]
+    pub fn count_kept_encryption_keys() -> usize {
+        KEPT_ENCRYPTION_KEYS.with(|kept| {
+            kept.borrow_mut()
+                .as_mut()
+                .map(|kept| {
+                    kept.expire(unix_now());
+                    kept.keys.len()
+                })
+                .unwrap_or(0)
+        })
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub fn count_kept_encryption_keys() -> usize {
+        0
+    }
+
+    /// Wipes the keys kept by this thread, so that e.g. YubiKey is asked again, returning how many
+    /// there were
+    #[cfg(feature = "encryption")]
+    pub fn forget_encryption_keys() -> usize {
+        KEPT_ENCRYPTION_KEYS.with(|kept| {
+            kept.borrow_mut()
+                .as_mut()
+                .map(|kept| {
+                    let count = kept.keys.len();
+                    for (_, aes_key, _) in kept.keys.iter_mut() {
+                        aes_key.iter_mut().for_each(|byte| *byte = 0);
+                    }
+                    kept.keys.clear();
+                    count
+                })
+                .unwrap_or(0)
+        })
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub fn forget_encryption_keys() -> usize {
+        0
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub fn get_encryption_key(&self) -> Result<std::cell::Ref<Option<AesKey>>> {
+        error!("Enable encryption to use this feature");
+        Err(anyhow!("Encryption is not enabled in this build"))
+    }
+
+    #[cfg(feature = "encryption")]
+    pub fn get_encryption_key(&self) -> Result<std::cell::Ref<Option<AesKey>>> {
+        if self.encryption_key.borrow().is_some() {
+            return Ok(self.encryption_key.borrow());
+        }
+        let encryption = self.get_encryption(false)?;
+        match encryption {
+            Encryption::ChallengeResponse { key, nonce, .. } => {
+                let kept_key = KEPT_ENCRYPTION_KEYS.with(|kept| {
+                    kept.borrow_mut()
+                        .as_mut()
+                        .and_then(|kept| kept.get(&key.borrow(), unix_now()))
+                });
+                let aes_key = match kept_key {
+                    Some(aes_key) => aes_key,
+                    None => {
+                        let aes_key = timed("Encryption key derivation", || {
+                            let response = encryption.get_response()?;
+                            Ok(AesKey::clone_from_slice(&Self::base64_decrypt_with(
+                                key.borrow().as_str(),
+                                response.as_ref().unwrap(),
+                                nonce,
+                            )?))
+                        })?;
+                        KEPT_ENCRYPTION_KEYS.with(|kept| {
+                            if let Some(kept) = kept.borrow_mut().as_mut() {
+                                kept.insert(key.borrow().clone(), aes_key, unix_now());
+                            }
+                        });
+                        aes_key
+                    }
+                };
+                *self.encryption_key.borrow_mut() = Some(aes_key);
+                Ok(self.encryption_key.borrow())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+fn aes_key() -> AesKey {
+    let mut rng = rand::thread_rng();
+    let mut key = AesKey::clone_from_slice(&[0u8; AES_KEY_LENGTH]);
+    rng.fill(key.as_mut_slice());
+    key
+}
+
+#[cfg(feature = "encryption")]
+fn aes_nonce() -> AesNonce {
+    let mut rng = rand::thread_rng();
+    let mut nonce = AesNonce::clone_from_slice(&[0u8; AES_NONCE_LENGTH]);
+    rng.fill(nonce.as_mut_slice());
+    nonce
+}
+
+fn aes_nonce_serialize<S>(nonce: &AesNonce, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let nonce = base64::encode(nonce);
+    serializer.serialize_str(&nonce)
+}
+
+fn aes_nonce_deserialize<'de, D>(deserializer: D) -> Result<AesNonce, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let nonce: &str = de::Deserialize::deserialize(deserializer)?;
+    let nonce = base64::decode(nonce).map_err(|_| {
+        de::Error::invalid_value(de::Unexpected::Str(nonce), &"base64 encoded data")
+    })?;
+    Ok(AesNonce::clone_from_slice(nonce.as_ref()))
+}
+
+#[cfg(feature = "encryption")]
+fn read_yubikey_serial() -> Result<u32> {
+    #[cfg(not(feature = "yubikey"))]
+    {
+        error!("YubiKey is not enabled in this build");
+        Err(anyhow!("YubiKey is not enabled in this build"))
+    }
+    #[cfg(feature = "yubikey")]
+    {
+        #[cfg(not(test))]
+        let mut yubikey = YubiKey::new()?;
+        #[cfg(test)]
+        let mut yubikey = MockYubiKeyTrait::new_mock();
+        yubikey
+            .read_serial_number()
+            .map_err(|_| anyhow!("Failed to read YubiKey serial number"))
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct EncryptedProfile {
+    data: String,
+    #[serde(
+        serialize_with = "aes_nonce_serialize",
+        deserialize_with = "aes_nonce_deserialize"
+    )]
+    nonce: AesNonce,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Database {
+    pub id: String,
+    pub key: String,
+    pub pkey: String,
+    pub group: String,
+    pub group_uuid: String,
+    /// Further groups, e.g. `CI tokens`, treated like `group` by `group_only` and `prune`, and
+    /// which routing rules may store new logins in
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_groups: Vec<String>,
+    /// String fields, e.g. `"KPH: git": "true"`, entries must have to be returned
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub required_fields: HashMap<String, String>,
+    /// Friendly name to refer to the database with, e.g. in `store --database`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// Hash KeePassXC reported for the database during `configure`, to notice another database
+    /// answering for the association, e.g. a copy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// File of the database, which `get` reads itself if KeePassXC isn't running
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kdbx: Option<KdbxFile>,
+}
+
+/// How to open a database file without KeePassXC
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KdbxFile {
+    /// Path of the database file, `~/` means the home directory
+    pub path: String,
+    /// Path of the key file, if the database has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<String>,
+    /// Environment variable holding the password, which is prompted for on the terminal otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+    /// Whether the database has a password at all, or only a key file
+    #[serde(default = "KdbxFile::default_password")]
+    pub password: bool,
+    #[serde(default, skip_serializing_if = "KdbxReader::is_built_in")]
+    pub reader: KdbxReader,
+}
+
+/// What reads database files
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KdbxReader {
+    /// The reader of the `kdbx` feature
+    #[default]
+    BuiltIn,
+    /// `keepassxc-cli` of KeePassXC, which needs to be installed but not running
+    KeepassxcCli,
+}
+
+impl KdbxReader {
+    fn is_built_in(&self) -> bool {
+        *self == KdbxReader::BuiltIn
+    }
+}
+
+impl KdbxFile {
+    fn default_password() -> bool {
+        true
+    }
+
+    fn expand_home(path: &str) -> PathBuf {
+        match (path.strip_prefix("~/"), directories_next::BaseDirs::new()) {
+            (Some(rest), Some(base_dirs)) => base_dirs.home_dir().join(rest),
+            _ => PathBuf::from(path),
+        }
+    }
+
+    pub fn file_path(&self) -> PathBuf {
+        Self::expand_home(&self.path)
+    }
+
+    pub fn key_file_path(&self) -> Option<PathBuf> {
+        self.key_file.as_deref().map(Self::expand_home)
+    }
+}
+
+impl Database {
+    /// Leaf names of `group` and `extra_groups`, which is all KeePassXC tells about the groups of
+    /// entries
+    pub fn group_names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(&self.group)
+            .chain(&self.extra_groups)
+            .map(|group| crate::keepassxc::Group::leaf_name(group))
+    }
+
+    pub fn is_eligible(&self, entry: &LoginEntry) -> bool {
+        self.required_fields.iter().all(|(key, value)| {
+            entry
+                .string_fields
+                .as_ref()
+                .map(|fields| fields.iter().any(|field| field.get(key) == Some(value)))
+                .unwrap_or(false)
+        })
+    }
+}
+
+impl Database {
+    /// Profile of a database associated under `id` with the key `id_seckey`, storing new logins
+    /// in `group`
+    pub fn new(
+        id: String,
+        id_seckey: crypto_box::SecretKey,
+        group: crate::keepassxc::Group,
+    ) -> Self {
+        let id_seckey_b64 = base64::encode(id_seckey.to_bytes());
+        let id_pubkey = id_seckey.public_key();
+        let id_pubkey_b64 = base64::encode(id_pubkey.as_bytes());
+        Self {
+            id,
+            key: id_seckey_b64,
+            pkey: id_pubkey_b64,
+            group: group.name,
+            group_uuid: group.uuid,
+            extra_groups: Vec::new(),
+            required_fields: HashMap::new(),
+            alias: None,
+            hash: None,
+            kdbx: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Caller {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Also matches when the executable is found further up the process tree, e.g. `git` spawning
+    /// the helper through `git-remote-https`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ancestor: bool,
+    /// macOS code requirement the caller executable must satisfy, see `man csreq`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_requirement: Option<String>,
+    /// Device and inode of the caller executable, so that it can't be replaced in place
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inode: Option<u64>,
+    /// Host/URL patterns the caller may request credentials for, any if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<String>,
+    /// Group `store` creates new logins of the caller in, unless a routing rule has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+impl Caller {
+    /// Profile allowing the executable at `path` without further checks
+    pub fn new<T: Into<String>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            uid: None,
+            gid: None,
+            sha256: None,
+            ancestor: false,
+            code_requirement: None,
+            device: None,
+            inode: None,
+            urls: Vec::new(),
+            group: None,
+        }
+    }
+
+    pub fn allows_url<T: AsRef<str>>(&self, url: T) -> bool {
+        self.urls.is_empty()
+            || self.urls.iter().any(|pattern| {
+                url_matches(pattern, url.as_ref()).unwrap_or_else(|e| {
+                    warn!(
+                        "Invalid URL pattern {} for caller {}, {}",
+                        pattern, self.path, e
+                    );
+                    false
+                })
+            })
+    }
+}
+
+impl fmt::Display for Caller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some(uid) = self.uid {
+            write!(f, " (UID: {})", uid)?;
+        }
+        if let Some(gid) = self.gid {
+            write!(f, " (GID: {})", gid)?;
+        }
+        if let Some(ref sha256) = self.sha256 {
+            write!(f, " (SHA-256: {})", sha256)?;
+        }
+        if let Some(ref requirement) = self.code_requirement {
+            write!(f, " (code requirement: {})", requirement)?;
+        }
+        if let (Some(device), Some(inode)) = (self.device, self.inode) {
+            write!(f, " (device: {}, inode: {})", device, inode)?;
+        }
+        if self.ancestor {
+            write!(f, " (ancestor)")?;
+        }
+        if !self.urls.is_empty() {
+            write!(f, " (URLs: {})", self.urls.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Looks up logins for `host` (with port if any) under `url` instead
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Alias {
+    pub host: String,
+    pub url: String,
+    /// Preferred login when Git doesn't specify a username
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+/// HTTP proxy at `host` (with port if any), whose logins are looked up under `url` if given and
+/// kept apart from those of the hosts behind it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Proxy {
+    pub host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Preferred login when Git doesn't specify a username
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+/// Looks up the passphrase of SSH private keys matching `path` under `url`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SshKey {
+    /// Glob pattern of the private key path, `~/` means the home directory
+    pub path: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+impl SshKey {
+    pub fn matches<T: AsRef<Path>>(&self, key_path: T) -> bool {
+        let pattern = match self.path.strip_prefix("~/") {
+            Some(rest) => match directories_next::BaseDirs::new() {
+                Some(base_dirs) => base_dirs
+                    .home_dir()
+                    .join(rest)
+                    .to_string_lossy()
+                    .into_owned(),
+                None => self.path.clone(),
+            },
+            None => self.path.clone(),
+        };
+        glob::Pattern::new(&pattern)
+            .map(|glob| glob.matches_path(key_path.as_ref()))
+            .unwrap_or_else(|e| {
+                warn!("Invalid pattern {} of SSH key, {}", self.path, e);
+                false
+            })
+    }
+}
+
+/// Looks up the token of the Kubernetes cluster `name` under `url`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KubeCluster {
+    pub name: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+/// Looks up the access key of the AWS profile `name` under `url`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AwsProfile {
+    pub name: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+/// Looks up the login of the Maven/Gradle repository with ID `id` under `url`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MavenServer {
+    pub id: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+/// Looks up the login of Subversion realms matching the glob (or `regex:` prefixed) pattern
+/// `realm` under `url`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SvnRealm {
+    pub realm: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+/// Looks up the passphrase of the GPG key with keygrip, fingerprint or ID `key` under `url`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GpgKey {
+    pub key: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+/// Hosts/URLs the helper never answers for
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DenyRule {
+    pub pattern: String,
+    /// Tells Git to stop asking other helpers (and the user) as well
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub quit: bool,
+}
+
+/// Whether request paths take part in looking up and storing logins, like Git's
+/// `credential.useHttpPath` but for all integrations, e.g. `docker`
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct UseHttpPath {
+    /// Used for hosts not listed in `hosts`, the path is used if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<bool>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub hosts: HashMap<String, bool>,
+}
+
+impl UseHttpPath {
+    pub fn is_default(&self) -> bool {
+        self.default.is_none() && self.hosts.is_empty()
     }
+}
 
-    #[cfg(not(feature = "encryption"))]
-    pub fn get_encryption_key(&self) -> Result<std::cell::Ref<Option<AesKey>>> {
-        error!("Enable encryption to use this feature");
-        Err(anyhow!("Encryption is not enabled in this build"))
+/// Glob (or `regex:` prefixed) patterns entries must match before being selected
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct EntryFilters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+impl EntryFilters {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.username.is_none()
     }
 
-    #[cfg(feature = "encryption")]
-    pub fn get_encryption_key(&self) -> Result<std::cell::Ref<Option<AesKey>>> {
-        if self.encryption_key.borrow().is_some() {
-            return Ok(self.encryption_key.borrow());
+    pub fn matches(&self, entry: &LoginEntry) -> Result<bool> {
+        if let Some(ref title) = self.title {
+            if !text_matches(title, &entry.name)
+                .with_context(|| format!("Invalid title filter {}", title))?
+            {
+                return Ok(false);
+            }
         }
-        let encryption = self.get_encryption(false)?;
-        match encryption {
-            Encryption::ChallengeResponse { key, nonce, .. } => {
-                let response = encryption.get_response()?;
-                *self.encryption_key.borrow_mut() =
-                    Some(AesKey::clone_from_slice(&Self::base64_decrypt_with(
-                        key.borrow().as_str(),
-                        response.as_ref().unwrap(),
-                        nonce,
-                    )?));
-                Ok(self.encryption_key.borrow())
+        if let Some(ref username) = self.username {
+            if !text_matches(username, &entry.login)
+                .with_context(|| format!("Invalid username filter {}", username))?
+            {
+                return Ok(false);
             }
         }
+        Ok(true)
     }
 }
 
-#[cfg(feature = "encryption")]
-fn aes_key() -> AesKey {
-    let mut rng = rand::thread_rng();
-    let mut key = AesKey::clone_from_slice(&[0u8; AES_KEY_LENGTH]);
-    rng.fill(key.as_mut_slice());
-    key
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Rule {
+    pub pattern: String,
+    pub database: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
 }
 
-#[cfg(feature = "encryption")]
-fn aes_nonce() -> AesNonce {
-    let mut rng = rand::thread_rng();
-    let mut nonce = AesNonce::clone_from_slice(&[0u8; AES_NONCE_LENGTH]);
-    rng.fill(nonce.as_mut_slice());
-    nonce
+impl Rule {
+    pub fn matches<T: AsRef<str>>(&self, url: T) -> bool {
+        url_matches(&self.pattern, url.as_ref()).unwrap_or_else(|e| {
+            warn!("Invalid pattern {} in routing rule, {}", self.pattern, e);
+            false
+        })
+    }
 }
 
-fn aes_nonce_serialize<S>(nonce: &AesNonce, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: serde::Serializer,
-{
-    let nonce = base64::encode(nonce);
-    serializer.serialize_str(&nonce)
+/// Returns something else than the username and password of logins for matching URLs, e.g. an
+/// API token stored in a string field of an entry with a TOTP secret in its password field
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FieldMapping {
+    /// Same as the pattern of routing rules
+    pub pattern: String,
+    /// `KPH: ` string field returned as the password, e.g. `token` for `KPH: token`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_field: Option<String>,
+    /// Returned as the username, whatever the username of the login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
 }
 
-fn aes_nonce_deserialize<'de, D>(deserializer: D) -> Result<AesNonce, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let nonce: &str = de::Deserialize::deserialize(deserializer)?;
-    let nonce = base64::decode(nonce).map_err(|_| {
-        de::Error::invalid_value(de::Unexpected::Str(nonce), &"base64 encoded data")
-    })?;
-    Ok(AesNonce::clone_from_slice(nonce.as_ref()))
+impl FieldMapping {
+    pub fn matches<T: AsRef<str>>(&self, url: T) -> bool {
+        url_matches(&self.pattern, url.as_ref()).unwrap_or_else(|e| {
+            warn!("Invalid pattern {} in field mapping, {}", self.pattern, e);
+            false
+        })
+    }
 }
 
-#[cfg(feature = "encryption")]
-fn read_yubikey_serial() -> Result<u32> {
-    #[cfg(not(feature = "yubikey"))]
-    {
-        error!("YubiKey is not enabled in this build");
-        Err(anyhow!("YubiKey is not enabled in this build"))
+/// Rules of the passwords `rotate` and `get --renew` generate for matching URLs, instead of
+/// having KeePassXC generate them with its global settings
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PasswordPolicy {
+    /// Same as the pattern of routing rules, all URLs if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(default = "PasswordPolicy::default_length")]
+    pub length: usize,
+    #[serde(default = "PasswordPolicy::default_true")]
+    pub lowercase: bool,
+    #[serde(default = "PasswordPolicy::default_true")]
+    pub uppercase: bool,
+    #[serde(default = "PasswordPolicy::default_true")]
+    pub digits: bool,
+    /// Symbols to use, none if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbols: Option<String>,
+    /// Characters never used, e.g. look-alikes like `l1O0`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub exclude: String,
+}
+
+impl PasswordPolicy {
+    fn default_length() -> usize {
+        32
     }
-    #[cfg(feature = "yubikey")]
-    {
-        #[cfg(not(test))]
-        let mut yubikey = YubiKey::new()?;
-        #[cfg(test)]
-        let mut yubikey = MockYubiKeyTrait::new_mock();
-        yubikey
-            .read_serial_number()
-            .map_err(|_| anyhow!("Failed to read YubiKey serial number"))
+
+    fn default_true() -> bool {
+        true
+    }
+
+    pub fn matches<T: AsRef<str>>(&self, url: T) -> bool {
+        match self.pattern {
+            Some(ref pattern) => url_matches(pattern, url.as_ref()).unwrap_or_else(|e| {
+                warn!("Invalid pattern {} in password policy, {}", pattern, e);
+                false
+            }),
+            None => true,
+        }
+    }
+
+    /// Generates a password with at least one character of each class
+    pub fn generate(&self) -> Result<String> {
+        use rand::{seq::SliceRandom, Rng};
+
+        let classes: Vec<Vec<char>> = [
+            (self.lowercase, "abcdefghijklmnopqrstuvwxyz"),
+            (self.uppercase, "ABCDEFGHIJKLMNOPQRSTUVWXYZ"),
+            (self.digits, "0123456789"),
+            (true, self.symbols.as_deref().unwrap_or_default()),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, chars)| {
+            chars
+                .chars()
+                .filter(|c| !self.exclude.contains(*c))
+                .collect()
+        })
+        .filter(|chars: &Vec<char>| !chars.is_empty())
+        .collect();
+        if classes.is_empty() {
+            return Err(anyhow!("Password policy allows no characters"));
+        }
+        if self.length < classes.len() {
+            return Err(anyhow!(
+                "Password policy needs a length of at least {} for its character classes",
+                classes.len()
+            ));
+        }
+        let mut rng = rand::thread_rng();
+        let all: Vec<char> = classes.iter().flatten().copied().collect();
+        let mut password: Vec<char> = classes
+            .iter()
+            .map(|chars| chars[rng.gen_range(0, chars.len())])
+            .collect();
+        password.extend((classes.len()..self.length).map(|_| all[rng.gen_range(0, all.len())]));
+        password.shuffle(&mut rng);
+        Ok(password.into_iter().collect())
     }
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
-struct EncryptedProfile {
-    data: String,
-    #[serde(
-        serialize_with = "aes_nonce_serialize",
-        deserialize_with = "aes_nonce_deserialize"
-    )]
-    nonce: AesNonce,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    description: Option<String>,
+/// What `erase` does with matching logins. Whether KeePassXC moves them to the recycle bin or
+/// deletes them permanently depends on the recycle bin setting of the database
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EraseMode {
+    /// Leave logins alone, since Git also erases logins rejected due to configuration issues
+    #[default]
+    Disabled,
+    /// Delete logins via KeePassXC, which moves them to the recycle bin if it's enabled and deletes
+    /// them permanently otherwise
+    Delete,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct Database {
-    pub id: String,
-    pub key: String,
-    pub pkey: String,
-    pub group: String,
-    pub group_uuid: String,
+impl EraseMode {
+    fn is_disabled(&self) -> bool {
+        *self == EraseMode::Disabled
+    }
 }
 
-impl Database {
-    pub fn new(
-        id: String,
-        id_seckey: crypto_box::SecretKey,
-        group: crate::keepassxc::Group,
-    ) -> Self {
-        let id_seckey_b64 = base64::encode(id_seckey.to_bytes());
-        let id_pubkey = id_seckey.public_key();
-        let id_pubkey_b64 = base64::encode(id_pubkey.as_bytes());
-        Self {
-            id,
-            key: id_seckey_b64,
-            pkey: id_pubkey_b64,
-            group: group.name,
-            group_uuid: group.uuid,
-        }
+/// What to do with a database profile whose association another database than the configured one
+/// answers for, i.e. whose hash differs
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HashMismatch {
+    /// Skip the database, so that logins aren't read from or stored in the wrong one
+    #[default]
+    Refuse,
+    /// Use the database anyway, e.g. when syncing copies of it
+    Warn,
+}
+
+impl HashMismatch {
+    fn is_refuse(&self) -> bool {
+        *self == HashMismatch::Refuse
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct Caller {
-    pub path: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub uid: Option<u32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub gid: Option<u32>,
+/// What to do with usernames and passwords containing characters Git's line-based credential
+/// format can't carry, i.e. line breaks and NUL (and non-ASCII ones with `ascii_only`)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnsafeValues {
+    /// Fail instead of returning a value Git would misread
+    #[default]
+    Reject,
+    /// Leave the characters out
+    Strip,
+    /// Replace them (and `%`) with `%XX` escapes of their UTF-8 bytes
+    PercentEncode,
+}
+
+impl UnsafeValues {
+    fn is_reject(&self) -> bool {
+        *self == UnsafeValues::Reject
+    }
+}
+
+impl std::str::FromStr for EraseMode {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "disabled" => Ok(EraseMode::Disabled),
+            "delete" => Ok(EraseMode::Delete),
+            _ => Err(anyhow!("Unknown erase mode: {}", mode)),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -614,6 +2256,8 @@ impl Encryption {
                 let mut yubikey = YubiKey::new()?;
                 #[cfg(test)]
                 let mut yubikey = MockYubiKeyTrait::new_mock();
+                let _waiting =
+                    crate::progress::waiting("Waiting for YubiKey", "(touch it if it blinks)");
                 let mut hmac_response = yubikey.challenge_response_hmac(&challenge, slot)?;
                 debug_assert_eq!(hmac_response.len(), HMAC_SHA1_RESPONSE_LENGTH);
                 hmac_response.extend_from_slice(&[0u8; AES_KEY_LENGTH - HMAC_SHA1_RESPONSE_LENGTH]);
@@ -756,7 +2400,7 @@ impl YubiKeyTrait for YubiKey {
             .set_variable_size(true)
             .set_mode(yubico_config::Mode::Sha1)
             .set_slot(slot);
-        debug!("Challenge: {}", challenge);
+        debug!("Challenge: {}", Secret(challenge));
         info!("Sending HMAC challenge, tap your YubiKey if needed");
         #[cfg(feature = "notification")]
         {
@@ -776,7 +2420,7 @@ impl YubiKeyTrait for YubiKey {
         let hmac_result = self
             .yubi
             .challenge_response_hmac(challenge.as_bytes(), config)?;
-        debug!("HMAC response: {:?}", &*hmac_result);
+        debug!("HMAC response: {:?}", Secret(&*hmac_result));
         info!("HMAC response received");
         Ok((*hmac_result).iter().cloned().collect())
     }
@@ -904,6 +2548,13 @@ mod tests {
             path: "/mock/path".to_owned(),
             uid: None,
             gid: None,
+            sha256: None,
+            ancestor: false,
+            code_requirement: None,
+            device: None,
+            inode: None,
+            urls: Vec::new(),
+            group: None,
         };
 
         {
@@ -924,7 +2575,298 @@ mod tests {
         fs::remove_file(config_path).unwrap();
     }
 
+    #[test]
+    fn test_03_routing_rules() {
+        let group = Group::new("mock group", "mock uuid");
+        let mut config = Config::new();
+        for id in &["personal", "work"] {
+            let database = Database::new((*id).to_owned(), generate_secret_key(), group.clone());
+            config.add_database(database, false).unwrap();
+        }
+        config.rules.push(Rule {
+            pattern: "https://github.com/work-org/*".to_owned(),
+            database: "work".to_owned(),
+            group: Some("Work".to_owned()),
+        });
+        config.rules.push(Rule {
+            pattern: "*.work.example.com".to_owned(),
+            database: "work".to_owned(),
+            group: None,
+        });
+
+        let databases = config
+            .get_databases_for("https://github.com/work-org/repo.git")
+            .unwrap();
+        assert_eq!(databases.len(), 1);
+        assert_eq!(databases[0].id, "work");
+        let databases = config
+            .get_databases_for("https://git.work.example.com:8443/repo.git")
+            .unwrap();
+        assert_eq!(databases.len(), 1);
+        assert_eq!(databases[0].id, "work");
+        let databases = config
+            .get_databases_for("https://github.com/someone/repo.git")
+            .unwrap();
+        assert_eq!(databases.len(), 2);
+        assert!(config
+            .get_databases_in_tier_for("https://github.com/someone/repo.git", true)
+            .unwrap()
+            .is_empty());
+        assert!(config.get_rule_for("https://work.example.com/").is_none());
+
+        config.rules.push(Rule {
+            pattern: "gitlab.com".to_owned(),
+            database: "missing".to_owned(),
+            group: None,
+        });
+        assert!(config.get_databases_for("https://gitlab.com/").is_err());
+    }
+
+    #[test]
+    fn test_04_dedup_profiles() {
+        let group = Group::new("mock group", "mock uuid");
+        let caller = Caller {
+            path: "/mock/path".to_owned(),
+            uid: None,
+            gid: None,
+            sha256: None,
+            ancestor: false,
+            code_requirement: None,
+            device: None,
+            inode: None,
+            urls: Vec::new(),
+            group: None,
+        };
+        let mut config = Config::new();
+        let stale = Database::new("mock".to_owned(), generate_secret_key(), group.clone());
+        let latest = Database::new("mock".to_owned(), generate_secret_key(), group.clone());
+        let other = Database::new("other".to_owned(), generate_secret_key(), group.clone());
+        config.add_database(stale, false).unwrap();
+        config.add_database(other, false).unwrap();
+        config.add_database(latest.clone(), false).unwrap();
+        config.add_caller(caller.clone(), false).unwrap();
+        config.add_caller(caller.clone(), false).unwrap();
+
+        assert_eq!(config.dedup_databases().unwrap(), 1);
+        assert_eq!(config.dedup_callers().unwrap(), 1);
+        let databases = config.get_databases().unwrap();
+        assert_eq!(databases.len(), 2);
+        assert_eq!(databases[0].id, "other");
+        assert_eq!(databases[1].key, latest.key);
+        assert_eq!(config.get_callers().unwrap(), vec![caller]);
+        assert_eq!(config.dedup_databases().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_05_caller_url_patterns() {
+        let mut caller = Caller {
+            path: "/mock/path".to_owned(),
+            uid: None,
+            gid: None,
+            sha256: None,
+            ancestor: false,
+            code_requirement: None,
+            device: None,
+            inode: None,
+            urls: Vec::new(),
+            group: None,
+        };
+        assert!(caller.allows_url("https://example.com/repo.git"));
+
+        caller.urls = vec![
+            "github.com".to_owned(),
+            "https://gitlab.com/me/*".to_owned(),
+        ];
+        assert!(caller.allows_url("https://github.com/someone/repo.git"));
+        assert!(caller.allows_url("https://gitlab.com/me/repo.git"));
+        assert!(!caller.allows_url("https://gitlab.com/someone/repo.git"));
+        assert!(!caller.allows_url("https://example.com/repo.git"));
+    }
+
+    #[test]
+    fn test_06_database_required_fields() {
+        let group = Group::new("mock group", "mock uuid");
+        let mut database = Database::new("mock".to_owned(), generate_secret_key(), group);
+        let entry: LoginEntry = serde_json::from_str(
+            r#"{"login": "foo", "name": "bar", "password": "baz", "uuid": "mock uuid",
+                "stringFields": [{"KPH: git": "true"}]}"#,
+        )
+        .unwrap();
+        assert!(database.is_eligible(&entry));
+
+        database
+            .required_fields
+            .insert("KPH: git".to_owned(), "true".to_owned());
+        assert!(database.is_eligible(&entry));
+        database
+            .required_fields
+            .insert("KPH: git".to_owned(), "false".to_owned());
+        assert!(!database.is_eligible(&entry));
+    }
+
+    #[test]
+    fn test_07_deny_rules() {
+        let mut config = Config::new();
+        config.deny.push(DenyRule {
+            pattern: "*.example.org".to_owned(),
+            quit: false,
+        });
+        config.deny.push(DenyRule {
+            pattern: "https://github.com/public-org/*".to_owned(),
+            quit: true,
+        });
+
+        let rule = config.get_deny_rule_for("https://git.example.org/repo.git");
+        assert!(rule.is_some());
+        assert!(!rule.unwrap().quit);
+        let rule = config.get_deny_rule_for("https://github.com/public-org/repo.git");
+        assert!(rule.is_some());
+        assert!(rule.unwrap().quit);
+        assert!(config
+            .get_deny_rule_for("https://github.com/private-org/repo.git")
+            .is_none());
+    }
+
+    #[test]
+    fn test_08_host_aliases() {
+        let mut config = Config::new();
+        config.aliases.push(Alias {
+            host: "github-work.example".to_owned(),
+            url: "https://github.com/work/".to_owned(),
+            username: None,
+        });
+
+        assert_eq!(
+            config.rewrite_url("https://github-work.example/org/repo.git"),
+            "https://github.com/work/org/repo.git"
+        );
+        assert_eq!(
+            config.rewrite_url("https://alice@GitHub-Work.example"),
+            "https://github.com/work"
+        );
+        assert_eq!(
+            config.rewrite_url("https://github.com/org/repo.git"),
+            "https://github.com/org/repo.git"
+        );
+        assert_eq!(
+            config.rewrite_url("https://alice@gist.github.com/123.git"),
+            "https://alice@github.com/123.git"
+        );
+    }
+
+    #[test]
+    fn test_09_ssh_keys() {
+        let mut config = Config::new();
+        config.ssh_keys.push(SshKey {
+            path: "/home/*/.ssh/id_ed25519*".to_owned(),
+            url: "ssh://id_ed25519".to_owned(),
+            username: None,
+        });
+
+        assert!(config.get_ssh_key_for("/home/me/.ssh/id_ed25519").is_some());
+        assert!(config.get_ssh_key_for("/home/me/.ssh/id_rsa").is_none());
+    }
+
+    #[test]
+    fn test_10_svn_realms() {
+        let mut config = Config::new();
+        config.svn_realms.push(SvnRealm {
+            realm: "<https://svn.example.com:443> *".to_owned(),
+            url: "https://svn.example.com".to_owned(),
+            username: None,
+        });
+
+        assert!(config
+            .get_svn_realm_for("<https://svn.example.com:443> Example Repository")
+            .is_some());
+        assert!(config
+            .get_svn_realm_for("<https://svn.example.org:443> Example Repository")
+            .is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_11_remove_databases() {
+        let mut config = Config::new();
+        for id in &["foo", "bar", "baz"] {
+            let group = Group::new("mock group", "mock uuid");
+            let database = Database::new((*id).to_owned(), generate_secret_key(), group);
+            config.add_database(database, false).unwrap();
+        }
+        assert_eq!(config.remove_databases(|_, db| db.id == "bar").unwrap(), 1);
+        assert_eq!(config.remove_databases(|idx, _| idx == 0).unwrap(), 1);
+        let databases = config.list_databases().unwrap();
+        assert_eq!(databases.len(), 1);
+        assert_eq!(databases[0].0.id, "baz");
+        assert!(!databases[0].1);
+    }
+
+    #[test]
+    fn test_12_validate() {
+        let mut config = Config::new();
+        let group = Group::new("mock group", "mock uuid");
+        let database = Database::new("foo".to_owned(), generate_secret_key(), group);
+        config.add_database(database, false).unwrap();
+        config.rules.push(Rule {
+            pattern: "*.example.com".to_owned(),
+            database: "foo".to_owned(),
+            group: None,
+        });
+        config.validate().unwrap();
+
+        config.rules[0].database = "bar".to_owned();
+        assert!(config.validate().is_err());
+        config.rules[0].database = "foo".to_owned();
+        config.rules[0].pattern = "[".to_owned();
+        assert!(config.validate().is_err());
+        config.rules.clear();
+        config.databases[0].pkey = base64::encode([0u8; 32]);
+        assert!(config.validate().is_err());
+        config.databases[0].key = "foo".to_owned();
+        assert!(config.validate().is_err());
+    }
+
     #[cfg(unix)]
+    #[test]
+    fn test_13_check_and_fix_permissions() {
+        let config_dir = std::env::temp_dir().join(format!("{}.test_13", clap::crate_name!()));
+        fs::create_dir(&config_dir).unwrap();
+        fs::set_permissions(&config_dir, fs::Permissions::from_mode(0o777)).unwrap();
+        let config_path = config_dir.join("config.json");
+        Config::new().write_to(&config_path).unwrap();
+        fs::set_permissions(&config_path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        assert!(check_permissions(&config_path).is_err());
+        assert_eq!(
+            fix_permissions(&config_path).unwrap(),
+            vec![config_path.clone(), config_dir.clone()]
+        );
+        assert!(check_permissions(&config_path).is_ok());
+        let mode = |path: &Path| path.metadata().unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&config_path), 0o600);
+        assert_eq!(mode(&config_dir), 0o755);
+        assert!(fix_permissions(&config_path).unwrap().is_empty());
+
+        fs::remove_dir_all(config_dir).unwrap();
+    }
+
+    #[test]
+    fn test_14_replace_database() {
+        let mut config = Config::new();
+        for id in &["foo", "bar"] {
+            let group = Group::new("mock group", "mock uuid");
+            let database = Database::new((*id).to_owned(), generate_secret_key(), group);
+            config.add_database(database, false).unwrap();
+        }
+        let mut database = config.list_databases().unwrap()[0].0.clone();
+        database.alias = Some("work".to_owned());
+        config.replace_database(0, database.clone(), false).unwrap();
+        let databases = config.list_databases().unwrap();
+        assert_eq!(databases[0].0.alias.as_deref(), Some("work"));
+        assert_eq!(databases[1].0.id, "bar");
+        assert!(config.replace_database(2, database, false).is_err());
+    }
+
     #[test]
     fn test_github_15_00_new_config_file_permissions() {
         let config_path = {
@@ -1010,4 +2952,187 @@ mod tests {
 
         fs::remove_file(config_path).unwrap();
     }
+
+    #[test]
+    fn test_16_use_http_path() {
+        let mut config = Config::new();
+        assert_eq!(
+            config.rewrite_url("https://example.com/org/repo.git"),
+            "https://example.com/org/repo.git"
+        );
+
+        config.use_http_path.default = Some(false);
+        config
+            .use_http_path
+            .hosts
+            .insert("Gitlab.example.com".to_owned(), true);
+        assert_eq!(
+            config.rewrite_url("https://example.com/org/repo.git"),
+            "https://example.com"
+        );
+        assert_eq!(
+            config.rewrite_url("https://gitlab.example.com/org/repo.git"),
+            "https://gitlab.example.com/org/repo.git"
+        );
+
+        // the path of the alias itself is kept
+        config.aliases.push(Alias {
+            host: "github-work.example".to_owned(),
+            url: "https://github.com/work/".to_owned(),
+            username: None,
+        });
+        assert_eq!(
+            config.rewrite_url("https://github-work.example/org/repo.git"),
+            "https://github.com/work"
+        );
+    }
+
+    #[test]
+    fn test_17_field_mappings() {
+        let mut config = Config::new();
+        config.field_mappings.push(FieldMapping {
+            pattern: "*.example.com".to_owned(),
+            password_field: Some("token".to_owned()),
+            username: Some("x-access-token".to_owned()),
+        });
+        let mapping = config.get_field_mapping_for("https://git.example.com/repo.git");
+        assert_eq!(
+            mapping.and_then(|m| m.password_field.as_deref()),
+            Some("token")
+        );
+        assert!(config
+            .get_field_mapping_for("https://example.org/repo.git")
+            .is_none());
+    }
+
+    #[test]
+    fn test_18_database_group_names() {
+        let group = Group::new("Work/Git", "mock uuid");
+        let mut database = Database::new("mock".to_owned(), generate_secret_key(), group);
+        database.extra_groups.push("CI tokens".to_owned());
+        assert_eq!(
+            database.group_names().collect::<Vec<_>>(),
+            vec!["Git", "CI tokens"]
+        );
+    }
+
+    #[test]
+    fn test_19_proxies() {
+        let mut config = Config::new();
+        config.proxies.push(Proxy {
+            host: "proxy.corp.example.com:3128".to_owned(),
+            url: Some("https://proxy.corp.example.com".to_owned()),
+            username: None,
+        });
+        assert_eq!(
+            config.rewrite_url("http://proxy.corp.example.com:3128"),
+            "https://proxy.corp.example.com"
+        );
+        assert!(config
+            .get_proxy_for("https://proxy.corp.example.com")
+            .is_some());
+        assert!(config
+            .get_proxy_for("http://proxy.corp.example.com:8080")
+            .is_none());
+        assert_eq!(
+            config.rewrite_url("https://git.corp.example.com/repo.git"),
+            "https://git.corp.example.com/repo.git"
+        );
+    }
+
+    #[test]
+    fn test_20_profile_config_path() {
+        assert_eq!(
+            profile_config_path("/home/foo/.config/git-credential-keepassxc", "work").unwrap(),
+            PathBuf::from("/home/foo/.config/git-credential-keepassxc.d/work")
+        );
+        assert!(profile_config_path("/tmp/config.json", "../work").is_err());
+        assert!(profile_config_path("/tmp/config.json", "").is_err());
+    }
+
+    #[test]
+    fn test_21_password_policy() {
+        let policy: PasswordPolicy =
+            serde_json::from_str(r#"{"length": 12, "symbols": "!", "exclude": "abc"}"#).unwrap();
+        assert!(policy.matches("https://example.com"));
+        let password = policy.generate().unwrap();
+        assert_eq!(password.chars().count(), 12);
+        assert!(password.contains('!'));
+        assert!(password.chars().any(|c| c.is_ascii_digit()));
+        assert!(password.chars().all(|c| !"abc".contains(c)));
+
+        let policy: PasswordPolicy = serde_json::from_str(
+            r#"{"length": 1, "lowercase": false, "uppercase": false, "digits": false}"#,
+        )
+        .unwrap();
+        assert!(policy.generate().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn test_22_decrypt_databases_only_when_needed() {
+        let group = Group::new("mock group", "mock uuid");
+        let mut config = Config::new();
+        // as if derived with YubiKey
+        *config.encryption_key.borrow_mut() = Some(aes_key());
+        for (id, encrypted) in &[("personal", false), ("work", true)] {
+            let database = Database::new((*id).to_owned(), generate_secret_key(), group.clone());
+            config.add_database(database, *encrypted).unwrap();
+        }
+        config.rules.push(Rule {
+            pattern: "gitlab.com".to_owned(),
+            database: "work".to_owned(),
+            group: None,
+        });
+        let ids_for = |url: &str| -> Vec<String> {
+            config
+                .get_databases_for(url)
+                .unwrap()
+                .into_iter()
+                .map(|database| database.id)
+                .collect()
+        };
+
+        // the encrypted one only when no plaintext one is available
+        assert_eq!(ids_for("https://github.com/someone/repo.git"), ["personal"]);
+        assert_eq!(ids_for("https://gitlab.com/"), ["work"]);
+        assert!(config
+            .get_databases_in_tier_for("https://gitlab.com/", false)
+            .unwrap()
+            .is_empty());
+        let databases = config
+            .get_databases_in_tier_for("https://github.com/someone/repo.git", true)
+            .unwrap();
+        assert_eq!(databases.len(), 1);
+        assert_eq!(databases[0].id, "work");
+        assert_eq!(config.find_database("work").unwrap().id, "work");
+    }
+
+    #[test]
+    fn test_23_config_lock() {
+        let config_path = std::env::temp_dir().join(format!(
+            "{}.test_23.{}.json",
+            clap::crate_name!(),
+            std::process::id()
+        ));
+        let lock = ConfigLock::acquire(&config_path).unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let waiting = {
+            let config_path = config_path.clone();
+            std::thread::spawn(move || {
+                let _lock = ConfigLock::acquire(&config_path).unwrap();
+                sender.send(()).unwrap();
+            })
+        };
+        // the other one waits until the lock is released
+        let timeout = std::time::Duration::from_millis(200);
+        assert!(receiver.recv_timeout(timeout).is_err());
+        drop(lock);
+        assert!(receiver.recv_timeout(timeout * 10).is_ok());
+        waiting.join().unwrap();
+
+        // and the configuration file itself isn't touched
+        assert!(!config_path.exists());
+        fs::remove_file(crate::utils::state_file_path(&config_path, "lock")).unwrap();
+    }
 }
@@ -0,0 +1,402 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Keeps the KeePassXC session and decrypted keys in a background process, which `get`, `store`
+/// and `erase` are proxied to. It exits after `idle_timeout` seconds without requests, remembers
+/// the answers to `get` for `cache_ttl` seconds if it's not 0, and keeps the keys derived from
+/// e.g. YubiKey for `key_ttl` seconds if set, or as long as it runs
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SessionDaemon {
+    #[serde(default = "SessionDaemon::default_idle_timeout")]
+    pub idle_timeout: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub cache_ttl: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_ttl: Option<u64>,
+    /// Loopback address and port to serve metrics in the Prometheus text format on, e.g.
+    /// `127.0.0.1:9817`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics_listen: Option<String>,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+impl SessionDaemon {
+    fn default_idle_timeout() -> u64 {
+        900
+    }
+}
+
+impl Default for SessionDaemon {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Self::default_idle_timeout(),
+            cache_ttl: 0,
+            key_ttl: None,
+            metrics_listen: None,
+        }
+    }
+}
+
+/// A `get`, `store` or `erase` invocation proxied to the daemon
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DaemonRequest {
+    pub config: PathBuf,
+    pub argv: Vec<String>,
+    pub input: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DaemonResponse {
+    pub output: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<DaemonError>,
+}
+
+/// Failure of a proxied request, exiting with the same code as if it was handled locally
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DaemonError {
+    pub message: String,
+    pub exit_code: i32,
+}
+
+impl fmt::Display for DaemonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for DaemonError {}
+
+/// Answers to `get` kept in memory by the daemon, keyed by the arguments and the request
+#[derive(Debug)]
+pub struct ResponseCache {
+    ttl: u64,
+    entries: HashMap<(Vec<String>, String), (u64, String)>,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: u64) -> Self {
+        Self {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, argv: &[String], input: &str, now: u64) -> Option<&str> {
+        self.count(now);
+        self.entries
+            .get(&(argv.to_vec(), input.to_owned()))
+            .map(|(_, output)| output.as_str())
+    }
+
+    pub fn insert(&mut self, argv: &[String], input: &str, output: String, now: u64) {
+        if self.ttl > 0 {
+            self.entries
+                .insert((argv.to_vec(), input.to_owned()), (now, output));
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.ttl > 0
+    }
+
+    pub fn ttl(&self) -> u64 {
+        self.ttl
+    }
+
+    /// Number of answers which haven't expired
+    pub fn count(&mut self, now: u64) -> usize {
+        let ttl = self.ttl;
+        self.entries
+            .retain(|_, (cached_at, _)| *cached_at + ttl > now);
+        self.entries.len()
+    }
+
+    /// Forgets everything, e.g. after `store` or `erase` may have changed the answers
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// What the daemon keeps in memory, as answered to `cache status` and `cache clear`
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct CacheStatus {
+    /// Answers to `get`
+    pub entries: usize,
+    pub cache_ttl: u64,
+    /// Keys of encrypted configurations, e.g. derived from YubiKey responses
+    pub encryption_keys: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_ttl: Option<u64>,
+}
+
+/// Upper bounds of the buckets of request durations, in seconds
+const LATENCY_BUCKETS: [f64; 8] = [0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 30.0];
+
+/// Counters of the requests the daemon has answered, since it started
+#[derive(Debug, Default)]
+pub struct DaemonMetrics {
+    /// By subcommand and whether it succeeded
+    requests: BTreeMap<(String, bool), u64>,
+    /// Failed requests by the code of `--json-errors`
+    errors: BTreeMap<String, u64>,
+    keepassxc_errors: u64,
+    cache_hits: u64,
+    cache_misses: u64,
+    /// Requests which took at most as long as each of `LATENCY_BUCKETS`
+    latency_buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+    latency_count: u64,
+}
+
+impl DaemonMetrics {
+    pub fn record_request(&mut self, subcommand: &str, seconds: f64, error_code: Option<&str>) {
+        *self
+            .requests
+            .entry((subcommand.to_owned(), error_code.is_none()))
+            .or_default() += 1;
+        if let Some(code) = error_code {
+            *self.errors.entry(code.to_owned()).or_default() += 1;
+        }
+        for (count, bound) in self.latency_buckets.iter_mut().zip(&LATENCY_BUCKETS) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+        self.latency_sum += seconds;
+        self.latency_count += 1;
+    }
+
+    /// An error KeePassXC answered with, e.g. for a locked database
+    pub fn record_keepassxc_error(&mut self) {
+        self.keepassxc_errors += 1;
+    }
+
+    pub fn record_cache(&mut self, hit: bool) {
+        if hit {
+            self.cache_hits += 1;
+        } else {
+            self.cache_misses += 1;
+        }
+    }
+
+    /// The Prometheus text exposition format
+    pub fn render(&self) -> String {
+        const PREFIX: &str = "git_credential_keepassxc_daemon";
+        let mut text = String::new();
+        let mut family = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            text.push_str(&format!("# HELP {}_{} {}\n", PREFIX, name, help));
+            text.push_str(&format!("# TYPE {}_{} {}\n", PREFIX, name, kind));
+            for (suffix, value) in samples {
+                text.push_str(&format!("{}_{}{} {}\n", PREFIX, name, suffix, value));
+            }
+        };
+        family(
+            "requests_total",
+            "counter",
+            "Requests answered, by subcommand and outcome",
+            self.requests
+                .iter()
+                .map(|((subcommand, success), count)| {
+                    let outcome = if *success { "success" } else { "error" };
+                    (
+                        format!("{{subcommand=\"{}\",outcome=\"{}\"}}", subcommand, outcome),
+                        count.to_string(),
+                    )
+                })
+                .collect(),
+        );
+        family(
+            "errors_total",
+            "counter",
+            "Failed requests, by error code",
+            self.errors
+                .iter()
+                .map(|(code, count)| (format!("{{code=\"{}\"}}", code), count.to_string()))
+                .collect(),
+        );
+        family(
+            "keepassxc_errors_total",
+            "counter",
+            "Errors KeePassXC answered with",
+            vec![(String::new(), self.keepassxc_errors.to_string())],
+        );
+        family(
+            "cache_requests_total",
+            "counter",
+            "Lookups of get answers in the cache, by result",
+            vec![
+                ("{result=\"hit\"}".to_owned(), self.cache_hits.to_string()),
+                (
+                    "{result=\"miss\"}".to_owned(),
+                    self.cache_misses.to_string(),
+                ),
+            ],
+        );
+        let mut samples: Vec<_> = self
+            .latency_buckets
+            .iter()
+            .zip(&LATENCY_BUCKETS)
+            .map(|(count, bound)| (format!("_bucket{{le=\"{}\"}}", bound), count.to_string()))
+            .collect();
+        samples.push((
+            "_bucket{le=\"+Inf\"}".to_owned(),
+            self.latency_count.to_string(),
+        ));
+        samples.push(("_sum".to_owned(), self.latency_sum.to_string()));
+        samples.push(("_count".to_owned(), self.latency_count.to_string()));
+        family(
+            "request_duration_seconds",
+            "histogram",
+            "Time taken to answer requests",
+            samples,
+        );
+        text
+    }
+}
+
+/// Path of the daemon socket, in a directory only the current user can access
+pub fn socket_path() -> Result<PathBuf> {
+    let base_dirs = directories_next::BaseDirs::new()
+        .ok_or_else(|| anyhow!("Failed to initialise base_dirs"))?;
+    let socket_dir = base_dirs
+        .runtime_dir()
+        .unwrap_or_else(|| base_dirs.cache_dir())
+        .join(clap::crate_name!());
+    Ok(socket_dir.join("daemon.socket"))
+}
+
+/// Listening socket passed by systemd socket activation (see `sd_listen_fds(3)`), if the daemon
+/// has been started that way
+#[cfg(unix)]
+pub fn systemd_listener() -> Result<Option<std::os::unix::net::UnixListener>> {
+    use std::os::unix::io::FromRawFd;
+
+    // the first passed descriptor, SD_LISTEN_FDS_START
+    const FD: i32 = 3;
+
+    let listen_pid = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok());
+    if listen_pid != Some(std::process::id()) {
+        return Ok(None);
+    }
+    let listen_fds = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|fds| fds.parse::<u32>().ok())
+        .unwrap_or(0);
+    // not meant for child processes, e.g. hooks
+    for name in &["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(name);
+    }
+    if listen_fds != 1 {
+        return Err(anyhow!(
+            "Expected 1 socket from systemd, got {}, check the socket unit",
+            listen_fds
+        ));
+    }
+    let mut socket_type: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let mut address: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut address_len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    let is_unix_stream = unsafe {
+        libc::getsockopt(
+            FD,
+            libc::SOL_SOCKET,
+            libc::SO_TYPE,
+            &mut socket_type as *mut _ as *mut libc::c_void,
+            &mut len,
+        ) == 0
+            && libc::getsockname(
+                FD,
+                &mut address as *mut _ as *mut libc::sockaddr,
+                &mut address_len,
+            ) == 0
+    } && socket_type == libc::SOCK_STREAM
+        && libc::c_int::from(address.ss_family) == libc::AF_UNIX;
+    if !is_unix_stream {
+        return Err(anyhow!(
+            "Socket from systemd is not a Unix stream socket, set ListenStream to a path"
+        ));
+    }
+    unsafe { libc::fcntl(FD, libc::F_SETFD, libc::FD_CLOEXEC) };
+    Ok(Some(unsafe {
+        std::os::unix::net::UnixListener::from_raw_fd(FD)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_session_daemon_defaults() {
+        let policy: SessionDaemon = serde_json::from_str("{}").unwrap();
+        assert_eq!(policy, SessionDaemon::default());
+        let response: DaemonResponse =
+            serde_json::from_str(r#"{"output":"username=foo\n"}"#).unwrap();
+        assert!(response.error.is_none());
+    }
+
+    #[test]
+    fn test_01_response_cache() {
+        let argv = vec!["get".to_owned()];
+        let mut cache = ResponseCache::new(60);
+        cache.insert(
+            &argv,
+            "url=https://example.com\n",
+            "password=foo\n".to_owned(),
+            1000,
+        );
+        assert_eq!(
+            cache.get(&argv, "url=https://example.com\n", 1059),
+            Some("password=foo\n")
+        );
+        assert!(cache
+            .get(&argv, "url=https://example.org\n", 1059)
+            .is_none());
+        assert!(cache
+            .get(&argv, "url=https://example.com\n", 1060)
+            .is_none());
+
+        let mut cache = ResponseCache::new(0);
+        cache.insert(
+            &argv,
+            "url=https://example.com\n",
+            "password=foo\n".to_owned(),
+            1000,
+        );
+        assert!(cache
+            .get(&argv, "url=https://example.com\n", 1000)
+            .is_none());
+    }
+
+    #[test]
+    fn test_02_daemon_metrics() {
+        let mut metrics = DaemonMetrics::default();
+        metrics.record_request("get", 0.02, None);
+        metrics.record_request("get", 2.0, Some("database-locked"));
+        metrics.record_keepassxc_error();
+        metrics.record_cache(false);
+        let text = metrics.render();
+        assert!(text.contains(
+            "git_credential_keepassxc_daemon_requests_total{subcommand=\"get\",outcome=\"success\"} 1\n"
+        ));
+        assert!(text.contains(
+            "git_credential_keepassxc_daemon_errors_total{code=\"database-locked\"} 1\n"
+        ));
+        assert!(text.contains("git_credential_keepassxc_daemon_keepassxc_errors_total 1\n"));
+        assert!(text.contains(
+            "git_credential_keepassxc_daemon_request_duration_seconds_bucket{le=\"0.05\"} 1\n"
+        ));
+        assert!(text.contains(
+            "git_credential_keepassxc_daemon_request_duration_seconds_bucket{le=\"5\"} 2\n"
+        ));
+    }
+}
@@ -0,0 +1,129 @@
+//! Overall deadline of a run, enforced by a watchdog thread since socket reads, unlock waits and
+//! YubiKey challenges all block without timeouts of their own
+use std::fmt;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Phases entered through `logging::timed()` and not left yet, innermost last
+static PHASES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Innermost phase that failed and how deep it was, for `--json-errors`
+static FAILED_PHASE: Mutex<Option<(usize, String)>> = Mutex::new(None);
+
+#[derive(Debug)]
+pub struct DeadlineExceededError {
+    pub seconds: u64,
+    /// Innermost phase running at the deadline, if any
+    pub phase: Option<String>,
+}
+impl DeadlineExceededError {
+    /// Same as coreutils' timeout
+    pub const EXIT_CODE: i32 = 124;
+}
+impl fmt::Display for DeadlineExceededError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.phase {
+            Some(phase) => write!(
+                f,
+                "Timed out after {} second(s) during {}",
+                self.seconds, phase
+            ),
+            None => write!(f, "Timed out after {} second(s)", self.seconds),
+        }
+    }
+}
+impl std::error::Error for DeadlineExceededError {}
+
+pub fn enter_phase(phase: &str) {
+    if let Ok(mut phases) = PHASES.lock() {
+        // a failure of an earlier phase at this depth was recovered from
+        if let Ok(mut failed) = FAILED_PHASE.lock() {
+            if matches!(&*failed, Some((depth, _)) if *depth >= phases.len()) {
+                *failed = None;
+            }
+        }
+        phases.push(phase.to_owned());
+    }
+}
+
+pub fn leave_phase() {
+    if let Ok(mut phases) = PHASES.lock() {
+        phases.pop();
+    }
+}
+
+pub(crate) fn current_phase() -> Option<String> {
+    PHASES.lock().ok().and_then(|phases| phases.last().cloned())
+}
+
+/// Records that a phase, just left, failed, unless a phase inside it did already
+pub fn fail_phase(phase: &str) {
+    let depth = match PHASES.lock() {
+        Ok(phases) => phases.len(),
+        Err(_) => return,
+    };
+    if let Ok(mut failed) = FAILED_PHASE.lock() {
+        if !matches!(&*failed, Some((failed_depth, _)) if *failed_depth > depth) {
+            *failed = Some((depth, phase.to_owned()));
+        }
+    }
+}
+
+/// Innermost phase the run failed in, if any
+pub fn failed_phase() -> Option<String> {
+    FAILED_PHASE
+        .lock()
+        .ok()
+        .and_then(|failed| failed.as_ref().map(|(_, phase)| phase.clone()))
+}
+
+/// Calls `on_expiry` from another thread once `seconds` have passed, which is expected to exit
+/// the process
+pub fn start<F: FnOnce(DeadlineExceededError) + Send + 'static>(seconds: u64, on_expiry: F) {
+    thread::spawn(move || {
+        thread::sleep(Duration::from_secs(seconds));
+        on_expiry(DeadlineExceededError {
+            seconds,
+            phase: current_phase(),
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_phases() {
+        enter_phase("Session setup");
+        enter_phase("change-public-keys request");
+        assert_eq!(
+            current_phase().as_deref(),
+            Some("change-public-keys request")
+        );
+        leave_phase();
+        assert_eq!(current_phase().as_deref(), Some("Session setup"));
+        leave_phase();
+        let e = DeadlineExceededError {
+            seconds: 5,
+            phase: Some("Session setup".to_owned()),
+        };
+        assert_eq!(
+            e.to_string(),
+            "Timed out after 5 second(s) during Session setup"
+        );
+
+        // shares the phases with the above, so not a test of its own
+        enter_phase("get-logins request");
+        enter_phase("Session setup");
+        leave_phase();
+        fail_phase("Session setup");
+        leave_phase();
+        fail_phase("get-logins request");
+        assert_eq!(failed_phase().as_deref(), Some("Session setup"));
+        // retried successfully
+        enter_phase("get-logins request");
+        leave_phase();
+        assert_eq!(failed_phase(), None);
+    }
+}
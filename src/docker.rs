@@ -0,0 +1,92 @@
+use crate::utils::{read_state_file, write_state_file};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// What Docker expects on stdout when there are no credentials for a server
+pub const CREDENTIALS_NOT_FOUND: &str = "credentials not found in native keychain";
+
+/// Credential in the format of Docker credential helpers
+/// https://github.com/docker/docker-credential-helpers
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DockerCredential {
+    #[serde(rename = "ServerURL")]
+    pub server_url: String,
+    #[serde(rename = "Username")]
+    pub username: String,
+    #[serde(rename = "Secret")]
+    pub secret: String,
+}
+
+/// Turns a Docker server URL, which is often a bare host (e.g. `registry.example.com`), into a URL
+pub fn server_url_to_url(server_url: &str) -> String {
+    if server_url.contains("://") {
+        server_url.to_owned()
+    } else {
+        format!("https://{}", server_url)
+    }
+}
+
+/// Servers stored by Docker and their usernames, persisted next to the configuration file as
+/// KeePassXC doesn't allow listing entries
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct DockerServers {
+    #[serde(default)]
+    servers: BTreeMap<String, String>,
+}
+
+impl DockerServers {
+    pub fn read_from<T: AsRef<Path>>(state_path: T) -> Result<Self> {
+        read_state_file(state_path)
+    }
+
+    pub fn write_to<T: AsRef<Path>>(&self, state_path: T) -> Result<()> {
+        write_state_file(self, state_path)
+    }
+
+    pub fn insert<T: Into<String>>(&mut self, server_url: T, username: T) {
+        self.servers.insert(server_url.into(), username.into());
+    }
+
+    pub fn remove(&mut self, server_url: &str) {
+        self.servers.remove(server_url);
+    }
+
+    pub fn get_username(&self, server_url: &str) -> Option<&str> {
+        self.servers.get(server_url).map(String::as_str)
+    }
+
+    /// Server URLs and usernames, as expected by `docker-credential-* list`
+    pub fn servers(&self) -> &BTreeMap<String, String> {
+        &self.servers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_server_url_to_url() {
+        assert_eq!(
+            server_url_to_url("registry.example.com"),
+            "https://registry.example.com"
+        );
+        assert_eq!(
+            server_url_to_url("https://index.docker.io/v1/"),
+            "https://index.docker.io/v1/"
+        );
+    }
+
+    #[test]
+    fn test_01_credential_json() {
+        let credential: DockerCredential = serde_json::from_str(
+            r#"{"ServerURL":"registry.example.com","Username":"foo","Secret":"bar"}"#,
+        )
+        .unwrap();
+        assert_eq!(credential.server_url, "registry.example.com");
+        assert_eq!(credential.username, "foo");
+        assert_eq!(credential.secret, "bar");
+    }
+}
@@ -0,0 +1,510 @@
+//! C ABI for embedding KeePassXC credential lookup, built with
+//! `cargo rustc --lib --release --features cdylib --crate-type cdylib`
+//!
+//! Requests are handled like those of Git, and recorded in the audit log. Sessions are bound to
+//! the thread that opened them, which keeps the KeePassXC session until they're closed. Functions returning `int` return 0 on success, 1 if no login is
+//! found and -1 on errors, whose message is then available from `kpxc_last_error()` until the
+//! next error on the same thread.
+use crate::config::Config;
+use crate::git::GitCredentialMessage;
+use crate::keepassxc::messages::LoginEntry;
+use crate::requests::{get_logins, record_access, record_get, store_login};
+use crate::session::{reset_session, retry_on_lost_session, start_session};
+use crate::utils::Failure;
+use crate::LOGGER;
+#[allow(unused_imports)]
+use crate::{debug, error, info, warn};
+use anyhow::{anyhow, Result};
+use clap::{App, ArgMatches};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::thread::{self, ThreadId};
+
+const KPXC_OK: c_int = 0;
+const KPXC_NOT_FOUND: c_int = 1;
+const KPXC_ERROR: c_int = -1;
+
+thread_local!(static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) });
+
+/// Opened session, opaque to C, owning the KeePassXC session of the thread that opened it
+pub struct KpxcSession {
+    config_path: PathBuf,
+    thread: ThreadId,
+}
+
+impl KpxcSession {
+    fn open(config_path: &Path) -> Result<Self> {
+        // the embedding application is the caller, which can't be verified here
+        if cfg!(feature = "strict-caller") {
+            return Err(anyhow!(
+                "The C API is not available in builds with strict-caller, which only allow verified callers"
+            ));
+        }
+        // C callers have no way to set a logger up, so messages are discarded
+        let _ = LOGGER.set(slog::Logger::root(slog::Discard, slog::o!()));
+        let config = Config::read_from(config_path)?;
+        if config.count_callers() > 0 {
+            return Err(anyhow!(
+                "Caller profiles are configured, which the C API can't verify"
+            ));
+        }
+        // database files can still be read if KeePassXC isn't running
+        match start_session() {
+            Err(e) if Failure::of(&e) != Some(Failure::SocketNotFound) => return Err(e),
+            _ => {}
+        }
+        Ok(Self {
+            config_path: config_path.to_owned(),
+            thread: thread::current().id(),
+        })
+    }
+
+    /// Fails on other threads than the one that opened the session, which have their own
+    /// KeePassXC session
+    fn check_thread(&self) -> Result<()> {
+        if thread::current().id() != self.thread {
+            return Err(anyhow!(
+                "Session used on another thread than the one that opened it"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Runs `f` with the arguments of the subcommand handling a request without any options, so
+    /// that requests from C go through the same filters as those of Git
+    fn with_args<T>(
+        &self,
+        subcommand: &str,
+        f: impl FnOnce(&ArgMatches) -> Result<T>,
+    ) -> Result<T> {
+        let yaml = clap::load_yaml!("cli.yml");
+        let args = App::from_yaml(yaml).get_matches_from_safe([clap::crate_name!(), subcommand])?;
+        f(&args)
+    }
+
+    /// The login `get` returns for a URL, if it's of `username` when given
+    fn get_login(&self, url: &str, username: Option<&str>) -> Result<Option<LoginEntry>> {
+        self.check_thread()?;
+        let request = GitCredentialMessage {
+            url: Some(url.to_owned()),
+            username: username.map(str::to_owned),
+            ..Default::default()
+        }
+        .to_string();
+        // KeePassXC may have been restarted since the session was opened
+        let result = self.with_args("get", |args| {
+            retry_on_lost_session(|| {
+                get_logins(
+                    &self.config_path,
+                    &None,
+                    args,
+                    &mut request.as_bytes(),
+                    &mut std::io::sink(),
+                )
+            })
+        });
+        record_get(&self.config_path, &request, &result);
+        let login = match result {
+            Err(e) if Failure::of(&e) == Some(Failure::NoLoginFound) => return Ok(None),
+            result => result?,
+        };
+        // get prefers the logins of the username, but falls back to the other ones
+        Ok(login.filter(|login| username.map(|u| login.login == u).unwrap_or(true)))
+    }
+
+    fn store_login(&self, url: &str, username: &str, password: &str) -> Result<()> {
+        self.check_thread()?;
+        let request = GitCredentialMessage {
+            url: Some(url.to_owned()),
+            username: Some(username.to_owned()),
+            password: Some(password.to_owned()),
+            ..Default::default()
+        }
+        .to_string();
+        let result = self.with_args("store", |args| {
+            retry_on_lost_session(|| {
+                store_login(&self.config_path, &None, args, &mut request.as_bytes())
+            })
+        });
+        record_access(&self.config_path, "store", &request, None, &result);
+        result
+    }
+}
+
+impl Drop for KpxcSession {
+    fn drop(&mut self) {
+        // the next session opened on the thread starts over with KeePassXC
+        if thread::current().id() == self.thread {
+            reset_session();
+        }
+    }
+}
+
+fn set_last_error(e: anyhow::Error) -> c_int {
+    error!("{}", e);
+    let message = CString::new(e.to_string().replace('\0', ""))
+        .expect("Failed to convert error message, bug?");
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+    KPXC_ERROR
+}
+
+/// Borrows a C string argument, which must be valid UTF-8
+unsafe fn str_arg<'a>(arg: *const c_char, name: &str) -> Result<&'a str> {
+    if arg.is_null() {
+        return Err(anyhow!("{} must not be null", name));
+    }
+    CStr::from_ptr(arg)
+        .to_str()
+        .map_err(|_| anyhow!("{} is not valid UTF-8", name))
+}
+
+fn string_result(s: &str) -> Result<*mut c_char> {
+    Ok(CString::new(s)
+        .map_err(|_| anyhow!("Value contains NUL"))?
+        .into_raw())
+}
+
+/// Reads the configuration file and connects to KeePassXC, unless it isn't running, in which case
+/// the first request on the thread tries again. Returns null on errors.
+///
+/// # Safety
+///
+/// `config_path` must be a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn kpxc_session_open(config_path: *const c_char) -> *mut KpxcSession {
+    let session = str_arg(config_path, "config_path")
+        .and_then(|config_path| KpxcSession::open(Path::new(config_path)));
+    match session {
+        Ok(session) => Box::into_raw(Box::new(session)),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Closes a session opened by `kpxc_session_open()`, and the KeePassXC session with it if called
+/// on the thread that opened it
+///
+/// # Safety
+///
+/// `session` must be null or returned by `kpxc_session_open()`, and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn kpxc_session_close(session: *mut KpxcSession) {
+    if !session.is_null() {
+        drop(Box::from_raw(session));
+    }
+}
+
+/// Looks up the login `get` would return for `url`, with the same filters, rules and field
+/// mappings, of `username` if not null, and sets `*out_username` and `*out_password` to strings
+/// to be freed with `kpxc_string_free()`
+///
+/// # Safety
+///
+/// `session` must be returned by `kpxc_session_open()`, `url` and `username` (if not null) valid
+/// C strings, and `out_username` and `out_password` valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn kpxc_get_login(
+    session: *const KpxcSession,
+    url: *const c_char,
+    username: *const c_char,
+    out_username: *mut *mut c_char,
+    out_password: *mut *mut c_char,
+) -> c_int {
+    let result = (|| -> Result<c_int> {
+        let session = session
+            .as_ref()
+            .ok_or_else(|| anyhow!("session must not be null"))?;
+        if out_username.is_null() || out_password.is_null() {
+            return Err(anyhow!("Output pointers must not be null"));
+        }
+        let url = str_arg(url, "url")?;
+        let username = if username.is_null() {
+            None
+        } else {
+            Some(str_arg(username, "username")?)
+        };
+        let login = match session.get_login(url, username)? {
+            Some(login) => login,
+            None => return Ok(KPXC_NOT_FOUND),
+        };
+        let login_username = string_result(&login.login)?;
+        let login_password = match string_result(&login.password) {
+            Ok(password) => password,
+            Err(e) => {
+                drop(CString::from_raw(login_username));
+                return Err(e);
+            }
+        };
+        *out_username = login_username;
+        *out_password = login_password;
+        Ok(KPXC_OK)
+    })();
+    result.unwrap_or_else(set_last_error)
+}
+
+/// Stores a login for `url` like `store`, updating the existing one of `username` if any
+///
+/// # Safety
+///
+/// `session` must be returned by `kpxc_session_open()`, and the other arguments valid C strings.
+#[no_mangle]
+pub unsafe extern "C" fn kpxc_store_login(
+    session: *const KpxcSession,
+    url: *const c_char,
+    username: *const c_char,
+    password: *const c_char,
+) -> c_int {
+    let result = (|| -> Result<c_int> {
+        let session = session
+            .as_ref()
+            .ok_or_else(|| anyhow!("session must not be null"))?;
+        session.store_login(
+            str_arg(url, "url")?,
+            str_arg(username, "username")?,
+            str_arg(password, "password")?,
+        )?;
+        Ok(KPXC_OK)
+    })();
+    result.unwrap_or_else(set_last_error)
+}
+
+/// Frees a string returned by this library
+///
+/// # Safety
+///
+/// `s` must be null or returned by this library, and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn kpxc_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Message of the last error on the current thread, valid until the next error, or null
+#[no_mangle]
+pub extern "C" fn kpxc_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map(|message| message.as_ptr())
+            .unwrap_or(ptr::null())
+    })
+}
+
+// the C API refuses to open sessions with strict-caller
+#[cfg(all(test, feature = "kdbx", not(feature = "strict-caller")))]
+mod tests {
+    use super::*;
+    use crate::utils::{test_guard, ExchangeMessageContext, MockMessengingUtils};
+    use serde_json::json;
+    use std::sync::PoisonError;
+
+    /// Read instead of asking KeePassXC, which isn't around, see `testdata/kdbx/generate.py`
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/testdata/kdbx/kdbx4-argon2-chacha20.kdbx"
+    );
+    const URL: &str = "https://git.example.com/repo.git";
+
+    /// Opens a session on a configuration with the fixture as its database and `options` added,
+    /// returning it along with the path of the configuration file
+    fn open_session(
+        name: &str,
+        password: &str,
+        options: serde_json::Value,
+    ) -> (*mut KpxcSession, PathBuf) {
+        let password_env = format!("GIT_CREDENTIAL_KEEPASSXC_{}", name.to_uppercase());
+        std::env::set_var(&password_env, password);
+        let mut config = json!({
+            "databases": [{
+                "id": "fixture",
+                "key": "",
+                "pkey": "",
+                "group": "Git",
+                "group_uuid": "",
+                "kdbx": { "path": FIXTURE, "password_env": password_env },
+            }],
+        });
+        for (key, value) in options.as_object().unwrap() {
+            config[key] = value.clone();
+        }
+        let config_path =
+            std::env::temp_dir().join(format!("git-credential-keepassxc.{}.json", name));
+        std::fs::write(&config_path, config.to_string()).unwrap();
+        let config_path_c = CString::new(config_path.to_str().unwrap()).unwrap();
+        let session = unsafe { kpxc_session_open(config_path_c.as_ptr()) };
+        assert!(!session.is_null(), "{}", last_error());
+        (session, config_path)
+    }
+
+    /// Has requests to KeePassXC fail as if it weren't running, so that the database file is read
+    fn mock_keepassxc_not_running() -> ExchangeMessageContext {
+        let exchange_message_context = MockMessengingUtils::exchange_message_context();
+        exchange_message_context
+            .expect()
+            .returning(|_| Err(Failure::SocketNotFound.into()));
+        exchange_message_context
+    }
+
+    fn close_session(session: *mut KpxcSession, config_path: PathBuf) {
+        unsafe { kpxc_session_close(session) };
+        std::fs::remove_file(config_path).unwrap();
+    }
+
+    fn last_error() -> String {
+        let message = kpxc_last_error();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Username and password `kpxc_get_login()` returns, `None` if no login is found
+    fn get_login(
+        session: *const KpxcSession,
+        username: Option<&str>,
+    ) -> Result<Option<(String, String)>> {
+        let url = CString::new(URL).unwrap();
+        let username = username.map(|username| CString::new(username).unwrap());
+        let (mut out_username, mut out_password) = (ptr::null_mut(), ptr::null_mut());
+        let result = unsafe {
+            kpxc_get_login(
+                session,
+                url.as_ptr(),
+                username.as_ref().map(|u| u.as_ptr()).unwrap_or(ptr::null()),
+                &mut out_username,
+                &mut out_password,
+            )
+        };
+        match result {
+            KPXC_OK => unsafe {
+                let login = (
+                    CStr::from_ptr(out_username).to_str()?.to_owned(),
+                    CStr::from_ptr(out_password).to_str()?.to_owned(),
+                );
+                kpxc_string_free(out_username);
+                kpxc_string_free(out_password);
+                Ok(Some(login))
+            },
+            KPXC_NOT_FOUND => Ok(None),
+            _ => Err(anyhow!(last_error())),
+        }
+    }
+
+    fn login(username: &str, password: &str) -> Option<(String, String)> {
+        Some((username.to_owned(), password.to_owned()))
+    }
+
+    #[test]
+    fn test_00_get_login_filters_like_get() {
+        let _guard = test_guard().lock().unwrap_or_else(PoisonError::into_inner);
+        let _keepassxc = mock_keepassxc_not_running();
+        let (session, config_path) = open_session("ffi_test_00", "test", json!({}));
+        assert_eq!(
+            get_login(session, Some("alice")).unwrap(),
+            login("alice", "secret1")
+        );
+        assert_eq!(
+            get_login(session, Some("erin")).unwrap(),
+            login("erin", "secret5")
+        );
+        // expired
+        assert_eq!(get_login(session, Some("bob")).unwrap(), None);
+        // in the recycle bin
+        assert_eq!(get_login(session, Some("dave")).unwrap(), None);
+        // KPH: git = false
+        assert_eq!(get_login(session, Some("carol")).unwrap(), None);
+        close_session(session, config_path);
+    }
+
+    #[test]
+    fn test_01_get_login_applies_configuration() {
+        let _guard = test_guard().lock().unwrap_or_else(PoisonError::into_inner);
+        let _keepassxc = mock_keepassxc_not_running();
+        let (session, config_path) = open_session(
+            "ffi_test_01",
+            "test",
+            json!({
+                "group_only": true,
+                "field_mappings": [{ "pattern": "https://git.example.com/*", "username": "mapped" }],
+            }),
+        );
+        // not in the group of the database
+        assert_eq!(get_login(session, Some("alice")).unwrap(), None);
+        assert_eq!(
+            get_login(session, None).unwrap(),
+            login("mapped", "secret5")
+        );
+        close_session(session, config_path);
+
+        let (session, config_path) = open_session(
+            "ffi_test_01_deny",
+            "test",
+            json!({ "deny": [{ "pattern": "https://*.example.com/*" }] }),
+        );
+        assert_eq!(get_login(session, None).unwrap(), None);
+        close_session(session, config_path);
+    }
+
+    #[test]
+    fn test_02_get_login_reports_database_errors() {
+        let _guard = test_guard().lock().unwrap_or_else(PoisonError::into_inner);
+        let _keepassxc = mock_keepassxc_not_running();
+        let (session, config_path) = open_session("ffi_test_02", "wrong", json!({}));
+        let error = get_login(session, Some("alice")).unwrap_err();
+        assert!(
+            error.to_string().contains("Failed to open database file"),
+            "{}",
+            error
+        );
+        close_session(session, config_path);
+    }
+
+    #[test]
+    fn test_03_store_login_applies_configuration() {
+        let _guard = test_guard().lock().unwrap_or_else(PoisonError::into_inner);
+        let exchange_message_context = mock_keepassxc_not_running();
+        let (session, config_path) = open_session(
+            "ffi_test_03",
+            "test",
+            json!({ "deny": [{ "pattern": "https://*.example.com/*" }] }),
+        );
+        exchange_message_context.checkpoint();
+        exchange_message_context.expect().never();
+        let url = CString::new(URL).unwrap();
+        let username = CString::new("alice").unwrap();
+        let password = CString::new("secret").unwrap();
+        let result = unsafe {
+            kpxc_store_login(session, url.as_ptr(), username.as_ptr(), password.as_ptr())
+        };
+        assert_eq!(result, KPXC_OK, "{}", last_error());
+        exchange_message_context.checkpoint();
+        close_session(session, config_path);
+    }
+
+    #[test]
+    fn test_04_session_is_bound_to_its_thread() {
+        let _guard = test_guard().lock().unwrap_or_else(PoisonError::into_inner);
+        let _keepassxc = mock_keepassxc_not_running();
+        let (session, config_path) = open_session("ffi_test_04", "test", json!({}));
+        // raw pointers can't be sent to other threads
+        let session_address = session as usize;
+        let error = thread::spawn(move || {
+            get_login(session_address as *const KpxcSession, Some("alice")).unwrap_err()
+        })
+        .join()
+        .unwrap();
+        assert!(error.to_string().contains("another thread"), "{}", error);
+        assert_eq!(
+            get_login(session, Some("alice")).unwrap(),
+            login("alice", "secret1")
+        );
+        close_session(session, config_path);
+    }
+}
@@ -1,4 +1,11 @@
+use crate::config::UnsafeValues;
+use crate::utils::Secret;
+#[allow(unused_imports)]
+use crate::{debug, error, info, warn};
 use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
 use std::str::FromStr;
 
 #[derive(Debug)]
@@ -11,9 +18,16 @@ impl fmt::Display for GitMessageParsingError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Failed to parse Git credential message: {}\nOriginal message:\n{}",
-            self.message, self.source
-        )
+            "Failed to parse Git credential message: {}\nOriginal message:\n",
+            self.message
+        )?;
+        for line in self.source.lines() {
+            match line.strip_prefix("password=") {
+                Some(password) => writeln!(f, "password={}", Secret(password))?,
+                None => writeln!(f, "{}", line)?,
+            }
+        }
+        Ok(())
     }
 }
 
@@ -22,10 +36,28 @@ impl std::error::Error for GitMessageParsingError {}
 macro_rules! message_from_to_string {
     ($vis:vis struct $name:ident {
         $($field_vis:vis $field_name:ident: $field_type:ty,)*
-    }) => {
-        #[derive(Default, Debug)]
+    } $(read_only {
+        $($list_vis:vis $list_name:ident: Vec<String>,)*
+    })?) => {
+        #[derive(Default)]
         $vis struct $name {
             $($field_vis $field_name: $field_type,)*
+            $($($list_vis $list_name: Vec<String>,)*)?
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let mut s = f.debug_struct(stringify!($name));
+                $(
+                    if stringify!($field_name) == "password" {
+                        s.field(stringify!($field_name), &self.$field_name.as_ref().map(Secret));
+                    } else {
+                        s.field(stringify!($field_name), &self.$field_name);
+                    }
+                )*
+                $($(s.field(stringify!($list_name), &self.$list_name);)*)?
+                s.finish()
+            }
         }
 
         impl ToString for $name {
@@ -51,6 +83,8 @@ macro_rules! message_from_to_string {
                 let pairs: Vec<_> = s.split("\n").collect();
                 let mut msg = $name { ..Default::default() };
                 for pair in pairs {
+                    // e.g. from Git for Windows
+                    let pair = pair.strip_suffix('\r').unwrap_or(pair);
                     if pair.len() == 0 {
                         continue;
                     }
@@ -65,15 +99,63 @@ macro_rules! message_from_to_string {
                                 msg.$field_name = Some(pair[split_at + 1..].to_owned());
                             },
                         )*
-                            _ => return Err(GitMessageParsingError {
-                                message: format!("Unknown key {}", key),
-                                source: s.to_owned(),
-                            }),
+                        $($(
+                            concat!(stringify!($list_name), "[]") => {
+                                msg.$list_name.push(pair[split_at + 1..].to_owned());
+                            },
+                        )*)?
+                            // e.g. authtype and capability[] from newer Git, which can't be kept
+                            // anywhere as KeePassXC doesn't allow setting custom fields via socket
+                            _ => {
+                                debug!("Ignoring {} in Git credential message", key);
+                            }
                     }
                 }
                 Ok(msg)
             }
         }
+
+        impl $name {
+            /// Parses a JSON object with the same keys as the wire format, ignoring others like
+            /// extra attributes
+            pub fn from_json(s: &str) -> Result<Self, GitMessageParsingError> {
+                let error = |message: &str| GitMessageParsingError {
+                    message: message.to_owned(),
+                    // may contain the password
+                    source: "(JSON omitted)\n".to_owned(),
+                };
+                let value: serde_json::Value =
+                    serde_json::from_str(s).map_err(|e| error(&e.to_string()))?;
+                let object = value
+                    .as_object()
+                    .ok_or_else(|| error("Request is not a JSON object"))?;
+                let mut msg = $name { ..Default::default() };
+                $(
+                    if let Some(value) = object.get(stringify!($field_name)) {
+                        if !value.is_null() {
+                            let value = value.as_str().ok_or_else(|| {
+                                error(concat!(stringify!($field_name), " is not a string"))
+                            })?;
+                            msg.$field_name = Some(value.to_owned());
+                        }
+                    }
+                )*
+                $($(
+                    if let Some(values) = object.get(concat!(stringify!($list_name), "[]")) {
+                        let values = values.as_array().ok_or_else(|| {
+                            error(concat!(stringify!($list_name), "[] is not an array"))
+                        })?;
+                        for value in values {
+                            let value = value.as_str().ok_or_else(|| {
+                                error(concat!(stringify!($list_name), "[] is not an array of strings"))
+                            })?;
+                            msg.$list_name.push(value.to_owned());
+                        }
+                    }
+                )*)?
+                Ok(msg)
+            }
+        }
     }
 }
 
@@ -85,9 +167,208 @@ message_from_to_string!(
         pub username: Option<String>,
         pub password: Option<String>,
         pub url: Option<String>,
+        pub quit: Option<String>,
+    }
+    // lists like wwwauth[] are only read, as responses never carry them
+    read_only {
+        pub wwwauth: Vec<String>,
     }
 );
 
+impl GitCredentialMessage {
+    /// Realm of the `Basic` challenge among the `WWW-Authenticate` headers Git forwards, e.g.
+    /// `Gitea` from `wwwauth[]=Basic realm="Gitea"`
+    pub fn basic_realm(&self) -> Option<&str> {
+        self.wwwauth.iter().find_map(|challenge| {
+            let (scheme, params) = challenge.trim().split_once(' ')?;
+            if !scheme.eq_ignore_ascii_case("basic") {
+                return None;
+            }
+            params.split(',').find_map(|param| {
+                let (key, value) = param.trim().split_once('=')?;
+                if key.trim().eq_ignore_ascii_case("realm") {
+                    Some(value.trim().trim_matches('"'))
+                } else {
+                    None
+                }
+            })
+        })
+    }
+}
+
+/// Splits blank-line-separated messages, e.g. when several requests are given at once
+pub fn split_messages(s: &str) -> Vec<&str> {
+    let mut messages = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+    // blank lines may end with CRLF too
+    for line in s.split_inclusive('\n') {
+        if line.trim_end_matches(&['\r', '\n'][..]).is_empty() {
+            messages.push(&s[start..end]);
+            start = end + line.len();
+        }
+        end += line.len();
+    }
+    messages.push(&s[start..]);
+    messages
+        .into_iter()
+        .map(|message| message.trim_matches(&['\r', '\n'][..]))
+        .filter(|message| !message.is_empty())
+        .collect()
+}
+
+/// Whether a character can't be sent in Git's credential format as is
+fn is_unsafe_char(c: char, ascii_only: bool) -> bool {
+    c == '\n' || c == '\r' || c == '\0' || (ascii_only && !c.is_ascii())
+}
+
+/// Makes a value safe to send in Git's credential format, failing with `UnsafeValues::Reject` if
+/// it isn't already. `what` names the value in the error, which never contains the value itself
+pub fn escape_value(
+    value: &str,
+    what: &str,
+    mode: UnsafeValues,
+    ascii_only: bool,
+) -> anyhow::Result<String> {
+    match mode {
+        UnsafeValues::Reject => match value.chars().find(|c| is_unsafe_char(*c, ascii_only)) {
+            Some(c) => {
+                let found = if c.is_ascii() {
+                    format!("'{}'", c.escape_default())
+                } else {
+                    "non-ASCII characters".to_owned()
+                };
+                Err(anyhow::anyhow!(
+                    "{} contains {}, which Git's credential format can't carry, see unsafe_values in the configuration file",
+                    what,
+                    found
+                ))
+            }
+            None => Ok(value.to_owned()),
+        },
+        UnsafeValues::Strip => Ok(value
+            .chars()
+            .filter(|c| !is_unsafe_char(*c, ascii_only))
+            .collect()),
+        UnsafeValues::PercentEncode => {
+            let mut escaped = String::with_capacity(value.len());
+            for c in value.chars() {
+                if c == '%' || is_unsafe_char(c, ascii_only) {
+                    let mut bytes = [0; 4];
+                    for byte in c.encode_utf8(&mut bytes).bytes() {
+                        escaped.push_str(&format!("%{:02X}", byte));
+                    }
+                } else {
+                    escaped.push(c);
+                }
+            }
+            Ok(escaped)
+        }
+    }
+}
+
+/// `credential.*` settings of Git's configuration that apply to a URL
+#[derive(Default, Debug, PartialEq)]
+pub struct CredentialSettings {
+    /// `credential.<url>.username`
+    pub username: Option<String>,
+    /// `credential.<url>.useHttpPath`
+    pub use_http_path: Option<bool>,
+    /// `credential.<url>.keepassxcDatabase`, ID or alias of a database profile
+    pub database: Option<String>,
+}
+
+impl CredentialSettings {
+    /// Parses the output of `git config --get-urlmatch credential <url>`, whose keys are lower case
+    pub fn parse(output: &str) -> Self {
+        let mut settings = Self::default();
+        for line in output.lines() {
+            let (key, value) = match line.split_once(' ') {
+                Some((key, value)) => (key, value.trim()),
+                // e.g. `useHttpPath` without a value, which means true
+                None => (line, "true"),
+            };
+            match key {
+                "credential.username" => settings.username = Some(value.to_owned()),
+                "credential.usehttppath" => settings.use_http_path = parse_bool(value),
+                "credential.keepassxcdatabase" => settings.database = Some(value.to_owned()),
+                _ => continue,
+            }
+        }
+        settings
+    }
+
+    /// Looks up the settings for a URL, including those of the repository Git runs in
+    pub fn for_url(url: &str) -> anyhow::Result<Self> {
+        let output = Command::new("git")
+            .args(["config", "--get-urlmatch", "credential", url])
+            .output()
+            .map_err(|e| anyhow::anyhow!("Failed to run git, {}", e))?;
+        match output.status.code() {
+            Some(0) => Ok(Self::parse(&String::from_utf8_lossy(&output.stdout))),
+            // nothing set for the URL
+            Some(1) => Ok(Self::default()),
+            _ => Err(anyhow::anyhow!(
+                "git config --get-urlmatch credential {} failed: {}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+        }
+    }
+}
+
+/// Parses a Git boolean, e.g. `yes` or `0`
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// Shell command Git runs for the `credential.helper` value `helper`, e.g. `cache --timeout=900`
+pub fn helper_command(helper: &str, operation: &str) -> String {
+    if let Some(command) = helper.strip_prefix('!') {
+        format!("{} {}", command, operation)
+    } else if Path::new(helper).is_absolute() {
+        format!("{} {}", helper, operation)
+    } else {
+        format!("git credential-{} {}", helper, operation)
+    }
+}
+
+/// Runs a credential helper through the shell like Git does, returning what it prints
+pub fn run_helper(helper: &str, operation: &str, input: &str) -> anyhow::Result<String> {
+    let command = helper_command(helper, operation);
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell
+        .arg(&command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to run credential helper {}, {}", command, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Credential helper {} failed: {}",
+            command,
+            output.status
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -111,4 +392,143 @@ mod tests {
         assert_eq!(message.username.as_ref().unwrap().as_str(), "foo");
         assert_eq!(string + "\n", message.to_string());
     }
+
+    #[test]
+    fn test_02_extra_attributes_message() {
+        let string =
+            "protocol=https\nhost=example.com\nwwwauth[]=Basic realm=\"Gitea\"\nauthtype=Basic\n";
+        let message = GitCredentialMessage::from_str(string).unwrap();
+        assert_eq!(message.host.as_ref().unwrap().as_str(), "example.com");
+        assert_eq!(message.basic_realm(), Some("Gitea"));
+        assert_eq!("protocol=https\nhost=example.com\n\n", message.to_string());
+    }
+
+    #[test]
+    fn test_03_split_messages() {
+        assert_eq!(
+            split_messages("url=http://example.com\n\n\nurl=http://example.org\nusername=foo\n\n"),
+            vec![
+                "url=http://example.com",
+                "url=http://example.org\nusername=foo"
+            ]
+        );
+        assert_eq!(
+            split_messages("url=http://example.com\n"),
+            vec!["url=http://example.com"]
+        );
+        assert_eq!(
+            split_messages("url=http://example.com\r\n\r\nurl=http://example.org\r\n\r\n"),
+            vec!["url=http://example.com", "url=http://example.org"]
+        );
+    }
+
+    #[test]
+    fn test_04_password_redacted() {
+        let message =
+            GitCredentialMessage::from_str("host=example.com\npassword=secret\n").unwrap();
+        let debug = format!("{:?}", message);
+        assert!(!debug.contains("secret"));
+        assert!(debug.contains("password: Some(<redacted>)"));
+
+        let error = GitCredentialMessage::from_str("password=secret\ninvalid\n").unwrap_err();
+        assert!(!error.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn test_05_json_message() {
+        let message = GitCredentialMessage::from_json(
+            r#"{"url": "https://example.com", "username": "foo", "wwwauth[]": ["Basic"], "path": null}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            "username=foo\nurl=https://example.com\n\n",
+            message.to_string()
+        );
+
+        let error =
+            GitCredentialMessage::from_json(r#"{"password": "secret", "url": 1}"#).unwrap_err();
+        assert!(!error.to_string().contains("secret"));
+        assert!(GitCredentialMessage::from_json("[]").is_err());
+    }
+
+    #[test]
+    fn test_06_crlf_message() {
+        let message =
+            GitCredentialMessage::from_str("username=foo\r\nurl=http://example.com\r\n\r\n")
+                .unwrap();
+        assert_eq!(message.url.as_deref(), Some("http://example.com"));
+        assert_eq!(message.username.as_deref(), Some("foo"));
+    }
+
+    #[test]
+    fn test_07_helper_command() {
+        assert_eq!(
+            helper_command("cache --timeout=900", "get"),
+            "git credential-cache --timeout=900 get"
+        );
+        assert_eq!(
+            helper_command("!f() { cat; }; f", "store"),
+            "f() { cat; }; f store"
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            helper_command("/usr/lib/helper --foo", "get"),
+            "/usr/lib/helper --foo get"
+        );
+    }
+
+    #[test]
+    fn test_08_escape_value() {
+        let reject = |value| escape_value(value, "Password", UnsafeValues::Reject, false);
+        assert_eq!(reject("pässwörd 🔑").unwrap(), "pässwörd 🔑");
+        assert!(reject("line\nbreak").is_err());
+        assert!(!reject("secret\r")
+            .unwrap_err()
+            .to_string()
+            .contains("secret"));
+        assert!(escape_value("pässwörd", "Password", UnsafeValues::Reject, true).is_err());
+        assert_eq!(
+            escape_value("a\r\nb\0", "Password", UnsafeValues::Strip, false).unwrap(),
+            "ab"
+        );
+        assert_eq!(
+            escape_value("100%\nü", "Password", UnsafeValues::PercentEncode, true).unwrap(),
+            "100%25%0A%C3%BC"
+        );
+    }
+
+    #[test]
+    fn test_09_utf8_message_round_trip() {
+        for password in &["pässwörd", "密码🔑", "שלום", "e\u{301}=\\\"'$`"] {
+            let message = GitCredentialMessage {
+                password: Some((*password).to_owned()),
+                ..Default::default()
+            };
+            let parsed = GitCredentialMessage::from_str(&message.to_string()).unwrap();
+            assert_eq!(parsed.password.as_deref(), Some(*password));
+            let json = serde_json::json!({ "password": password }).to_string();
+            let parsed = GitCredentialMessage::from_json(&json).unwrap();
+            assert_eq!(parsed.password.as_deref(), Some(*password));
+        }
+    }
+
+    #[test]
+    fn test_10_credential_settings() {
+        let settings = CredentialSettings::parse(
+            "credential.helper keepassxc\ncredential.keepassxcdatabase work\ncredential.usehttppath yes\ncredential.username me\n",
+        );
+        assert_eq!(
+            settings,
+            CredentialSettings {
+                username: Some("me".to_owned()),
+                use_http_path: Some(true),
+                database: Some("work".to_owned()),
+            }
+        );
+        assert_eq!(
+            CredentialSettings::parse("credential.usehttppath\n").use_http_path,
+            Some(true)
+        );
+        assert_eq!(CredentialSettings::parse(""), CredentialSettings::default());
+    }
 }
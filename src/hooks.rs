@@ -0,0 +1,119 @@
+use crate::audit::AuditRecord;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::process::{Command, Stdio};
+
+/// Command run through the shell after `get`, `store` or `erase`, with the event described in
+/// `GIT_CREDENTIAL_KEEPASSXC_*` environment variables
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Hook {
+    pub command: String,
+    /// Operations to run on, all if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on: Vec<String>,
+    /// Outcomes to run on, i.e. `success`, `denied` or `error`, all if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outcomes: Vec<String>,
+}
+
+impl Hook {
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        (self.on.is_empty() || self.on.contains(&record.operation))
+            && (self.outcomes.is_empty() || self.outcomes.contains(&record.outcome))
+    }
+
+    pub fn run(&self, record: &AuditRecord) -> Result<()> {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+        command
+            .arg(&self.command)
+            // Git reads the response from stdout
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .env("GIT_CREDENTIAL_KEEPASSXC_EVENT", &record.operation)
+            .env("GIT_CREDENTIAL_KEEPASSXC_OUTCOME", &record.outcome)
+            .env(
+                "GIT_CREDENTIAL_KEEPASSXC_TIMESTAMP",
+                record.timestamp.to_string(),
+            );
+        let optional_vars = [
+            ("GIT_CREDENTIAL_KEEPASSXC_CALLER", &record.caller),
+            ("GIT_CREDENTIAL_KEEPASSXC_URL", &record.url),
+            ("GIT_CREDENTIAL_KEEPASSXC_DATABASE", &record.database),
+            ("GIT_CREDENTIAL_KEEPASSXC_ERROR", &record.error),
+        ];
+        for (name, value) in optional_vars.iter() {
+            match value {
+                Some(value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+        let status = command
+            .status()
+            .with_context(|| format!("Failed to run hook {}", self.command))?;
+        if !status.success() {
+            return Err(anyhow!("Hook {} failed: {}", self.command, status));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(operation: &str, outcome: &str) -> AuditRecord {
+        AuditRecord {
+            timestamp: 0,
+            operation: operation.to_owned(),
+            caller: None,
+            url: Some("https://example.com".to_owned()),
+            database: None,
+            outcome: outcome.to_owned(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_00_matches() {
+        let hook = Hook {
+            command: "true".to_owned(),
+            on: vec!["store".to_owned(), "erase".to_owned()],
+            outcomes: Vec::new(),
+        };
+        assert!(hook.matches(&record("store", "success")));
+        assert!(hook.matches(&record("erase", "error")));
+        assert!(!hook.matches(&record("get", "success")));
+        let hook = Hook {
+            command: "true".to_owned(),
+            on: Vec::new(),
+            outcomes: vec!["error".to_owned()],
+        };
+        assert!(hook.matches(&record("get", "error")));
+        assert!(!hook.matches(&record("get", "success")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_01_run() {
+        let hook = Hook {
+            command: r#"test "$GIT_CREDENTIAL_KEEPASSXC_EVENT $GIT_CREDENTIAL_KEEPASSXC_URL" = "get https://example.com" && test -z "$GIT_CREDENTIAL_KEEPASSXC_ERROR""#.to_owned(),
+            on: Vec::new(),
+            outcomes: Vec::new(),
+        };
+        hook.run(&record("get", "success")).unwrap();
+        let hook = Hook {
+            command: "exit 1".to_owned(),
+            on: Vec::new(),
+            outcomes: Vec::new(),
+        };
+        assert!(hook.run(&record("get", "success")).is_err());
+    }
+}
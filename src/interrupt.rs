@@ -0,0 +1,123 @@
+//! Clean shutdown on SIGINT and SIGTERM (Ctrl-C and closing the console under Windows), which
+//! would otherwise kill the process halfway through e.g. writing the configuration file
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+
+/// Held while the process mustn't exit, e.g. while a file is being written
+static CRITICAL: Mutex<()> = Mutex::new(());
+
+#[derive(Debug)]
+pub struct InterruptedError {
+    pub signal: i32,
+    /// Innermost phase running when interrupted, if any
+    pub phase: Option<String>,
+}
+impl InterruptedError {
+    /// Exit code of a shell for a process killed by the signal
+    pub fn exit_code(&self) -> i32 {
+        128 + self.signal
+    }
+}
+impl fmt::Display for InterruptedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.signal {
+            2 => write!(f, "Interrupted by SIGINT")?,
+            15 => write!(f, "Interrupted by SIGTERM")?,
+            signal => write!(f, "Interrupted by signal {}", signal)?,
+        }
+        match &self.phase {
+            Some(phase) => write!(f, " during {}", phase),
+            None => Ok(()),
+        }
+    }
+}
+impl std::error::Error for InterruptedError {}
+
+/// Keeps an interruption from ending the process until the guard is dropped
+pub fn defer() -> MutexGuard<'static, ()> {
+    CRITICAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Calls `on_interrupt` from another thread on SIGINT or SIGTERM, once nothing defers it, which is
+/// expected to exit the process. Must be called before any other thread is started, as they'd
+/// receive the signals otherwise.
+#[cfg(unix)]
+pub fn install<F: FnOnce(InterruptedError) + Send + 'static>(on_interrupt: F) {
+    let mut signals: libc::sigset_t = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGINT);
+        libc::sigaddset(&mut signals, libc::SIGTERM);
+        // inherited by threads started afterwards, and reset for child processes by std
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+    }
+    std::thread::spawn(move || {
+        let mut signal = 0;
+        if unsafe { libc::sigwait(&signals, &mut signal) } != 0 {
+            return;
+        }
+        let phase = crate::deadline::current_phase();
+        let _guard = defer();
+        on_interrupt(InterruptedError { signal, phase });
+    });
+}
+
+#[cfg(windows)]
+type Handler = Box<dyn FnOnce(InterruptedError) + Send>;
+#[cfg(windows)]
+static HANDLER: Mutex<Option<Handler>> = Mutex::new(None);
+
+#[cfg(windows)]
+unsafe extern "system" fn console_ctrl_handler(ctrl_type: u32) -> i32 {
+    use winapi::um::wincon::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+
+    // same as the signals of Unix, for the exit code
+    let signal = match ctrl_type {
+        CTRL_C_EVENT | CTRL_BREAK_EVENT => 2,
+        _ => 15,
+    };
+    let handler = HANDLER.lock().ok().and_then(|mut handler| handler.take());
+    match handler {
+        Some(handler) => {
+            let phase = crate::deadline::current_phase();
+            let _guard = defer();
+            handler(InterruptedError { signal, phase });
+            1
+        }
+        None => 0,
+    }
+}
+
+/// Calls `on_interrupt` on Ctrl-C, Ctrl-Break or when the console is closed, once nothing defers
+/// it, which is expected to exit the process
+#[cfg(windows)]
+pub fn install<F: FnOnce(InterruptedError) + Send + 'static>(on_interrupt: F) {
+    if let Ok(mut handler) = HANDLER.lock() {
+        *handler = Some(Box::new(on_interrupt));
+    }
+    unsafe { winapi::um::consoleapi::SetConsoleCtrlHandler(Some(console_ctrl_handler), 1) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_interrupted_error() {
+        let e = InterruptedError {
+            signal: 2,
+            phase: None,
+        };
+        assert_eq!(e.exit_code(), 130);
+        assert_eq!(e.to_string(), "Interrupted by SIGINT");
+        let e = InterruptedError {
+            signal: 15,
+            phase: Some("get-logins request".to_owned()),
+        };
+        assert_eq!(e.exit_code(), 143);
+        assert_eq!(
+            e.to_string(),
+            "Interrupted by SIGTERM during get-logins request"
+        );
+    }
+}
@@ -0,0 +1,831 @@
+//! Reads logins from KDBX 3.1 and 4 database files directly, so that `get` still works on
+//! headless machines or while KeePassXC isn't running. Only what `get` needs is supported, i.e.
+//! reading with a password and/or key file, but not writing, YubiKey challenge-response or
+//! Twofish.
+use crate::keepassxc::messages::LoginEntry;
+use crate::memlock;
+use crate::utils::entry_url_matches;
+use aes::cipher::{
+    block_padding::Pkcs7, generic_array::GenericArray, BlockDecryptMut, BlockEncrypt, KeyInit,
+    KeyIvInit, StreamCipher,
+};
+use anyhow::{anyhow, Context, Result};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+const SIGNATURE_1: u32 = 0x9aa2_d903;
+const SIGNATURE_2: u32 = 0xb54b_fb67;
+
+const CIPHER_AES256: [u8; 16] = [
+    0x31, 0xc1, 0xf2, 0xe6, 0xbf, 0x71, 0x43, 0x50, 0xbe, 0x58, 0x05, 0x21, 0x6a, 0xfc, 0x5a, 0xff,
+];
+const CIPHER_CHACHA20: [u8; 16] = [
+    0xd6, 0x03, 0x8a, 0x2b, 0x8b, 0x6f, 0x4c, 0xb5, 0xa5, 0x24, 0x33, 0x9a, 0x31, 0xdb, 0xb5, 0x9a,
+];
+const KDF_AES_KDBX3: [u8; 16] = [
+    0xc9, 0xd9, 0xf3, 0x9a, 0x62, 0x8a, 0x44, 0x60, 0xbf, 0x74, 0x0d, 0x08, 0xc1, 0x8a, 0x4f, 0xea,
+];
+const KDF_AES_KDBX4: [u8; 16] = [
+    0x7c, 0x02, 0xbb, 0x82, 0x79, 0xa7, 0x4a, 0xc0, 0x92, 0x7d, 0x11, 0x4a, 0x00, 0x64, 0x82, 0x38,
+];
+const KDF_ARGON2D: [u8; 16] = [
+    0xef, 0x63, 0x6d, 0xdf, 0x8c, 0x29, 0x44, 0x4b, 0x91, 0xf7, 0xa9, 0xa4, 0x03, 0xe3, 0x0a, 0x0c,
+];
+const KDF_ARGON2ID: [u8; 16] = [
+    0x9e, 0x29, 0x8b, 0x19, 0x56, 0xdb, 0x47, 0x73, 0xb2, 0x3d, 0xfc, 0x3e, 0xc6, 0xf0, 0xa1, 0xe6,
+];
+
+const INNER_STREAM_SALSA20: u32 = 2;
+const INNER_STREAM_CHACHA20: u32 = 3;
+const SALSA20_NONCE: [u8; 8] = [0xe8, 0x30, 0x09, 0x4b, 0x97, 0x20, 0x5d, 0x2a];
+
+/// Seconds from 0001-01-01, where KDBX 4 times start, to the Unix epoch
+const EPOCH_OFFSET: i64 = 62_135_596_800;
+
+/// Reads little-endian fields of the binary parts of a file
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| anyhow!("Database file is truncated"))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let mut bytes = [0u8; 2];
+        bytes.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.position..];
+        self.position = self.data.len();
+        rest
+    }
+}
+
+fn le_u32(bytes: &[u8]) -> Result<u32> {
+    Reader::new(bytes).u32()
+}
+
+fn le_u64(bytes: &[u8]) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    if bytes.len() != 8 {
+        return Err(anyhow!("Invalid 64-bit field in database file"));
+    }
+    buf.copy_from_slice(bytes);
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Parses a KDBX 4 variant dictionary, e.g. the KDF parameters, keeping the raw values
+fn parse_variant_dictionary(data: &[u8]) -> Result<HashMap<String, Vec<u8>>> {
+    let mut reader = Reader::new(data);
+    let version = reader.u16()?;
+    if version >> 8 != 1 {
+        return Err(anyhow!(
+            "Unsupported variant dictionary version {:#06x}",
+            version
+        ));
+    }
+    let mut dictionary = HashMap::new();
+    loop {
+        let kind = reader.u8()?;
+        if kind == 0 {
+            break;
+        }
+        let key_len = reader.u32()? as usize;
+        let key = String::from_utf8(reader.bytes(key_len)?.to_vec())?;
+        let value_len = reader.u32()? as usize;
+        dictionary.insert(key, reader.bytes(value_len)?.to_vec());
+    }
+    Ok(dictionary)
+}
+
+/// 32-byte key of a key file, which may be a KeePass XML key file (version 1.0 or 2.0), 32 raw
+/// bytes, 64 hex digits, or any other file, which is hashed
+fn key_file_key(content: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    if let Some(xml) = std::str::from_utf8(content)
+        .ok()
+        .filter(|text| text.trim_start().starts_with('<'))
+    {
+        let doc = roxmltree::Document::parse(xml).context("Failed to parse key file")?;
+        let data = doc
+            .descendants()
+            .find(|node| node.has_tag_name("Data"))
+            .and_then(|node| node.text())
+            .ok_or_else(|| anyhow!("Key file has no key data"))?;
+        let version = doc
+            .descendants()
+            .find(|node| node.has_tag_name("Version"))
+            .and_then(|node| node.text())
+            .unwrap_or("1.0");
+        let bytes = if version.starts_with("2.") {
+            let hex: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+            decode_hex(&hex)?
+        } else {
+            base64::decode(data.trim())?
+        };
+        if bytes.len() != key.len() {
+            return Err(anyhow!("Key file has a key of {} bytes", bytes.len()));
+        }
+        key.copy_from_slice(&bytes);
+    } else if content.len() == 32 {
+        key.copy_from_slice(content);
+    } else if let Some(bytes) = std::str::from_utf8(content)
+        .ok()
+        .filter(|text| text.len() == 64)
+        .and_then(|text| decode_hex(text).ok())
+    {
+        key.copy_from_slice(&bytes);
+    } else {
+        key.copy_from_slice(&Sha256::digest(content));
+    }
+    Ok(key)
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 == 1 {
+        return Err(anyhow!("Invalid hex string"));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| anyhow!("Invalid hex string"))
+        })
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Checks the HMAC-SHA256 of the concatenated `parts`, in constant time
+fn verify_hmac(key: &[u8; 64], parts: &[&[u8]], mac: &[u8]) -> bool {
+    let mut hmac = Hmac::<Sha256>::new_varkey(key).expect("Failed to create HMAC, bug?");
+    for part in parts {
+        hmac.update(part);
+    }
+    hmac.verify(mac).is_ok()
+}
+
+/// HMAC key of a block of a KDBX 4 file, `u64::MAX` being the header
+fn block_hmac_key(index: u64, hmac_key: &[u8]) -> [u8; 64] {
+    let mut hasher = Sha512::new();
+    hasher.update(index.to_le_bytes());
+    hasher.update(hmac_key);
+    let mut key = [0u8; 64];
+    key.copy_from_slice(&hasher.finalize());
+    key
+}
+
+/// Derives the transformed key from the composite key with the key derivation function
+fn transform_key(composite_key: &[u8; 32], kdf: &HashMap<String, Vec<u8>>) -> Result<[u8; 32]> {
+    let uuid = kdf
+        .get("$UUID")
+        .ok_or_else(|| anyhow!("Database file has no key derivation function"))?;
+    let param = |name: &str| {
+        kdf.get(name)
+            .ok_or_else(|| anyhow!("Key derivation parameter {} is missing", name))
+    };
+    let mut transformed = [0u8; 32];
+    if uuid[..] == KDF_AES_KDBX3 || uuid[..] == KDF_AES_KDBX4 {
+        let rounds = le_u64(param("R")?)?;
+        let cipher = aes::Aes256::new_from_slice(param("S")?)
+            .map_err(|_| anyhow!("Invalid AES key derivation seed"))?;
+        let mut blocks = [
+            *GenericArray::from_slice(&composite_key[..16]),
+            *GenericArray::from_slice(&composite_key[16..]),
+        ];
+        for _ in 0..rounds {
+            cipher.encrypt_blocks(&mut blocks);
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(blocks[0]);
+        hasher.update(blocks[1]);
+        transformed.copy_from_slice(&hasher.finalize());
+    } else if uuid[..] == KDF_ARGON2D || uuid[..] == KDF_ARGON2ID {
+        let algorithm = if uuid[..] == KDF_ARGON2D {
+            argon2::Algorithm::Argon2d
+        } else {
+            argon2::Algorithm::Argon2id
+        };
+        let version = match kdf.get("V").map(|v| le_u32(v)).transpose()? {
+            Some(0x10) => argon2::Version::V0x10,
+            _ => argon2::Version::V0x13,
+        };
+        let memory_kib = le_u64(param("M")?)? / 1024;
+        let iterations = le_u64(param("I")?)?;
+        let parallelism = le_u32(param("P")?)?;
+        let params = argon2::Params::new(
+            memory_kib as u32,
+            iterations as u32,
+            parallelism,
+            Some(transformed.len()),
+        )
+        .map_err(|e| anyhow!("Invalid Argon2 parameters, {}", e))?;
+        argon2::Argon2::new(algorithm, version, params)
+            .hash_password_into(composite_key, param("S")?, &mut transformed)
+            .map_err(|e| anyhow!("Failed to derive key with Argon2, {}", e))?;
+    } else {
+        return Err(anyhow!(
+            "Unsupported key derivation function {}",
+            encode_hex(uuid)
+        ));
+    }
+    Ok(transformed)
+}
+
+/// Decrypts the payload with the outer cipher of the file
+fn decrypt(cipher: &[u8], key: &[u8; 32], iv: &[u8], mut data: Vec<u8>) -> Result<Vec<u8>> {
+    if cipher == CIPHER_AES256 {
+        let len = cbc::Decryptor::<aes::Aes256>::new_from_slices(key, iv)
+            .map_err(|_| anyhow!("Invalid encryption IV"))?
+            .decrypt_padded_mut::<Pkcs7>(&mut data)
+            .map_err(|_| anyhow!("Wrong password or key file"))?
+            .len();
+        data.truncate(len);
+    } else if cipher == CIPHER_CHACHA20 {
+        chacha20::ChaCha20::new_from_slices(key, iv)
+            .map_err(|_| anyhow!("Invalid encryption IV"))?
+            .apply_keystream(&mut data);
+    } else {
+        return Err(anyhow!(
+            "Unsupported cipher {}, only AES-256 and ChaCha20 are",
+            encode_hex(cipher)
+        ));
+    }
+    Ok(data)
+}
+
+/// Cipher protecting values in the XML, e.g. passwords, whose key stream runs through all of them
+/// in document order
+enum InnerStream {
+    Salsa20(salsa20::Salsa20),
+    ChaCha20(chacha20::ChaCha20),
+}
+
+impl InnerStream {
+    fn new(id: u32, key: &[u8]) -> Result<Self> {
+        match id {
+            INNER_STREAM_SALSA20 => Ok(InnerStream::Salsa20(salsa20::Salsa20::new(
+                GenericArray::from_slice(&Sha256::digest(key)),
+                GenericArray::from_slice(&SALSA20_NONCE),
+            ))),
+            INNER_STREAM_CHACHA20 => {
+                let hash = Sha512::digest(key);
+                Ok(InnerStream::ChaCha20(chacha20::ChaCha20::new(
+                    GenericArray::from_slice(&hash[..32]),
+                    GenericArray::from_slice(&hash[32..44]),
+                )))
+            }
+            _ => Err(anyhow!("Unsupported inner stream cipher {}", id)),
+        }
+    }
+
+    fn unprotect(&mut self, value: &str) -> Result<String> {
+        let mut data = base64::decode(value.trim())?;
+        match self {
+            InnerStream::Salsa20(cipher) => cipher.apply_keystream(&mut data),
+            InnerStream::ChaCha20(cipher) => cipher.apply_keystream(&mut data),
+        }
+        Ok(String::from_utf8(data)?)
+    }
+}
+
+fn decompress(data: Vec<u8>, compressed: bool) -> Result<Vec<u8>> {
+    if !compressed {
+        return Ok(data);
+    }
+    let mut decompressed = Vec::new();
+    flate2::read::GzDecoder::new(&data[..])
+        .read_to_end(&mut decompressed)
+        .context("Failed to decompress database")?;
+    Ok(decompressed)
+}
+
+/// Decrypts a KDBX file into its XML and inner stream
+fn decrypt_file(data: &[u8], composite_key: &[u8; 32]) -> Result<(Vec<u8>, InnerStream)> {
+    let mut reader = Reader::new(data);
+    if reader.u32()? != SIGNATURE_1 || reader.u32()? != SIGNATURE_2 {
+        return Err(anyhow!("Not a KeePass database file"));
+    }
+    let version = reader.u32()?;
+    let major = version >> 16;
+    if major != 3 && major != 4 {
+        return Err(anyhow!("Unsupported KDBX version {}", major));
+    }
+    let mut fields: HashMap<u8, &[u8]> = HashMap::new();
+    loop {
+        let id = reader.u8()?;
+        let len = if major == 4 {
+            reader.u32()? as usize
+        } else {
+            reader.u16()? as usize
+        };
+        let value = reader.bytes(len)?;
+        if id == 0 {
+            break;
+        }
+        fields.insert(id, value);
+    }
+    let header = &data[..reader.position];
+    let field = |id: u8, name: &str| {
+        fields
+            .get(&id)
+            .copied()
+            .ok_or_else(|| anyhow!("Database file has no {}", name))
+    };
+    let cipher = field(2, "cipher")?;
+    let compressed = le_u32(field(3, "compression flags")?)? == 1;
+    let master_seed = field(4, "master seed")?;
+    let iv = field(7, "encryption IV")?;
+    let kdf = if major == 4 {
+        parse_variant_dictionary(field(11, "key derivation parameters")?)?
+    } else {
+        let mut kdf = HashMap::new();
+        kdf.insert("$UUID".to_owned(), KDF_AES_KDBX3.to_vec());
+        kdf.insert("S".to_owned(), field(5, "transform seed")?.to_vec());
+        kdf.insert("R".to_owned(), field(6, "transform rounds")?.to_vec());
+        kdf
+    };
+    let transformed = transform_key(composite_key, &kdf)?;
+    let mut master_key = [0u8; 32];
+    {
+        let mut hasher = Sha256::new();
+        hasher.update(master_seed);
+        hasher.update(transformed);
+        master_key.copy_from_slice(&hasher.finalize());
+    }
+
+    if major == 3 {
+        let start_bytes = field(9, "stream start bytes")?;
+        let decrypted = decrypt(cipher, &master_key, iv, reader.rest().to_vec())?;
+        if !decrypted.starts_with(start_bytes) {
+            return Err(anyhow!("Wrong password or key file"));
+        }
+        // hashed blocks of index, SHA-256 and size
+        let mut blocks = Reader::new(&decrypted[start_bytes.len()..]);
+        let mut payload = Vec::new();
+        loop {
+            blocks.u32()?;
+            let hash = blocks.bytes(32)?;
+            let size = blocks.u32()? as usize;
+            if size == 0 {
+                break;
+            }
+            let block = blocks.bytes(size)?;
+            if Sha256::digest(block)[..] != hash[..] {
+                return Err(anyhow!("Database file is corrupted"));
+            }
+            payload.extend_from_slice(block);
+        }
+        let stream = InnerStream::new(
+            le_u32(field(10, "inner stream cipher")?)?,
+            field(8, "inner stream key")?,
+        )?;
+        return Ok((decompress(payload, compressed)?, stream));
+    }
+
+    let mut hmac_key = Vec::with_capacity(65);
+    hmac_key.extend_from_slice(master_seed);
+    hmac_key.extend_from_slice(&transformed);
+    hmac_key.push(1);
+    let hmac_key = Sha512::digest(&hmac_key);
+    if reader.bytes(32)? != &Sha256::digest(header)[..] {
+        return Err(anyhow!("Database file is corrupted"));
+    }
+    let header_mac = reader.bytes(32)?;
+    if !verify_hmac(&block_hmac_key(u64::MAX, &hmac_key), &[header], header_mac) {
+        return Err(anyhow!("Wrong password or key file"));
+    }
+    // HMAC-authenticated blocks of HMAC, size and data
+    let mut encrypted = Vec::new();
+    for index in 0u64.. {
+        let mac = reader.bytes(32)?;
+        let size = reader.u32()?;
+        let block = reader.bytes(size as usize)?;
+        let block_key = block_hmac_key(index, &hmac_key);
+        let parts: [&[u8]; 3] = [&index.to_le_bytes(), &size.to_le_bytes(), block];
+        if !verify_hmac(&block_key, &parts, mac) {
+            return Err(anyhow!("Database file is corrupted"));
+        }
+        if size == 0 {
+            break;
+        }
+        encrypted.extend_from_slice(block);
+    }
+    let payload = decompress(decrypt(cipher, &master_key, iv, encrypted)?, compressed)?;
+    let mut inner = Reader::new(&payload);
+    let mut stream_id = None;
+    let mut stream_key = None;
+    loop {
+        let id = inner.u8()?;
+        let len = inner.u32()? as usize;
+        let value = inner.bytes(len)?;
+        match id {
+            0 => break,
+            1 => stream_id = Some(le_u32(value)?),
+            2 => stream_key = Some(value),
+            // attachments
+            _ => {}
+        }
+    }
+    let stream = InnerStream::new(
+        stream_id.ok_or_else(|| anyhow!("Database file has no inner stream cipher"))?,
+        stream_key.ok_or_else(|| anyhow!("Database file has no inner stream key"))?,
+    )?;
+    Ok((inner.rest().to_vec(), stream))
+}
+
+/// Unix time of a KDBX time, which is ISO 8601 in KDBX 3.1 and base64 seconds since 0001-01-01
+/// in KDBX 4
+fn parse_time(text: &str) -> Option<i64> {
+    if text.contains('-') {
+        return chrono::DateTime::parse_from_rfc3339(text)
+            .ok()
+            .map(|time| time.timestamp());
+    }
+    let bytes = base64::decode(text.trim()).ok()?;
+    if bytes.len() != 8 {
+        return None;
+    }
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&bytes);
+    Some(i64::from_le_bytes(buf) - EPOCH_OFFSET)
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+/// Text of a child element, empty if there's none
+fn text(node: roxmltree::Node<'_, '_>, name: &str) -> String {
+    child(node, name)
+        .and_then(|child| child.text())
+        .unwrap_or("")
+        .to_owned()
+}
+
+#[derive(Debug)]
+struct Entry {
+    uuid: String,
+    title: String,
+    username: String,
+    password: String,
+    urls: Vec<String>,
+    /// `KPH: ` string fields, which KeePassXC returns along with logins
+    fields: Vec<(String, String)>,
+    group: String,
+    recycled: bool,
+    expires: Option<i64>,
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        memlock::unlock(self.password.as_bytes());
+    }
+}
+
+/// Composite key of a password and/or the content of a key file
+fn composite_key(password: Option<&str>, key_file: Option<&[u8]>) -> Result<[u8; 32]> {
+    let mut composite = Sha256::new();
+    if let Some(password) = password {
+        composite.update(Sha256::digest(password.as_bytes()));
+    }
+    if let Some(key_file) = key_file {
+        composite.update(key_file_key(key_file)?);
+    }
+    let mut composite_key = [0u8; 32];
+    composite_key.copy_from_slice(&composite.finalize());
+    Ok(composite_key)
+}
+
+/// Logins of an opened database file
+pub struct KdbxDatabase {
+    entries: Vec<Entry>,
+}
+
+impl KdbxDatabase {
+    /// Opens a database file with its password and/or key file
+    pub fn open<P: AsRef<Path>, K: AsRef<Path>>(
+        path: P,
+        password: Option<&str>,
+        key_file: Option<K>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)
+            .with_context(|| format!("Failed to read database file {}", path.display()))?;
+        let key_file =
+            match key_file {
+                Some(key_file) => {
+                    let key_file = key_file.as_ref();
+                    Some(std::fs::read(key_file).with_context(|| {
+                        format!("Failed to read key file {}", key_file.display())
+                    })?)
+                }
+                None => None,
+            };
+        let composite_key = composite_key(password, key_file.as_deref())?;
+        let (xml, stream) = decrypt_file(&data, &composite_key)
+            .with_context(|| format!("Failed to open database file {}", path.display()))?;
+        Self::parse(&String::from_utf8(xml)?, stream)
+    }
+
+    fn parse(xml: &str, mut stream: InnerStream) -> Result<Self> {
+        let doc = roxmltree::Document::parse(xml).context("Failed to parse database XML")?;
+        // all protected values share the key stream, including those of history entries
+        let mut unprotected = HashMap::new();
+        for node in doc.descendants().filter(|node| {
+            node.has_tag_name("Value") && node.attribute("Protected") == Some("True")
+        }) {
+            unprotected.insert(node.id(), stream.unprotect(node.text().unwrap_or(""))?);
+        }
+        let uuid_hex = |text: &str| {
+            base64::decode(text.trim())
+                .map(|bytes| encode_hex(&bytes))
+                .unwrap_or_default()
+        };
+        let file = doc.root_element();
+        let recycle_bin = child(file, "Meta")
+            .filter(|meta| text(*meta, "RecycleBinEnabled") != "False")
+            .map(|meta| uuid_hex(&text(meta, "RecycleBinUUID")))
+            .filter(|uuid| !uuid.is_empty() && uuid.chars().any(|c| c != '0'));
+        let root_group = child(file, "Root")
+            .and_then(|root| child(root, "Group"))
+            .ok_or_else(|| anyhow!("Database has no root group"))?;
+
+        let mut entries = Vec::new();
+        let mut groups = vec![(root_group, false)];
+        while let Some((group, parent_recycled)) = groups.pop() {
+            let recycled = parent_recycled
+                || recycle_bin.as_deref() == Some(uuid_hex(&text(group, "UUID")).as_str());
+            let group_name = text(group, "Name");
+            for node in group.children().filter(|node| node.is_element()) {
+                if node.has_tag_name("Group") {
+                    groups.push((node, recycled));
+                    continue;
+                }
+                if !node.has_tag_name("Entry") {
+                    continue;
+                }
+                let hidden = child(node, "CustomData")
+                    .map(|data| {
+                        data.children().any(|item| {
+                            text(item, "Key") == "BrowserHideEntry" && text(item, "Value") == "true"
+                        })
+                    })
+                    .unwrap_or(false);
+                if hidden {
+                    continue;
+                }
+                let mut strings = HashMap::new();
+                for string in node.children().filter(|c| c.has_tag_name("String")) {
+                    let value = child(string, "Value")
+                        .map(|value| {
+                            unprotected
+                                .get(&value.id())
+                                .cloned()
+                                .unwrap_or_else(|| value.text().unwrap_or("").to_owned())
+                        })
+                        .unwrap_or_default();
+                    strings.insert(text(string, "Key"), value);
+                }
+                let expires = child(node, "Times")
+                    .filter(|times| text(*times, "Expires") == "True")
+                    .and_then(|times| parse_time(&text(times, "ExpiryTime")));
+                let urls = strings
+                    .iter()
+                    .filter(|(key, _)| *key == "URL" || key.starts_with("KP2A_URL"))
+                    .map(|(_, url)| url.clone())
+                    .collect();
+                let fields = strings
+                    .iter()
+                    .filter(|(key, _)| key.starts_with("KPH: "))
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                let entry = Entry {
+                    uuid: uuid_hex(&text(node, "UUID")),
+                    title: strings.remove("Title").unwrap_or_default(),
+                    username: strings.remove("UserName").unwrap_or_default(),
+                    password: strings.remove("Password").unwrap_or_default(),
+                    urls,
+                    fields,
+                    group: group_name.clone(),
+                    recycled,
+                    expires,
+                };
+                memlock::lock(entry.password.as_bytes());
+                entries.push(entry);
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    /// Logins for a URL as KeePassXC would return them, marking expired ones instead of leaving
+    /// them out
+    pub fn logins_for(&self, url: &str, include_recycled: bool, now: i64) -> Vec<LoginEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| include_recycled || !entry.recycled)
+            .filter(|entry| {
+                entry
+                    .urls
+                    .iter()
+                    .any(|entry_url| entry_url_matches(entry_url, url))
+            })
+            .map(|entry| {
+                let login = LoginEntry {
+                    login: entry.username.clone(),
+                    name: entry.title.clone(),
+                    password: entry.password.clone(),
+                    uuid: entry.uuid.clone(),
+                    group: Some(entry.group.clone()),
+                    string_fields: if entry.fields.is_empty() {
+                        None
+                    } else {
+                        Some(
+                            entry
+                                .fields
+                                .iter()
+                                .map(|(key, value)| {
+                                    std::iter::once((key.clone(), value.clone())).collect()
+                                })
+                                .collect(),
+                        )
+                    },
+                    expired: Some(crate::keepassxc::messages::KeePassBoolean(
+                        entry.expires.map(|expires| expires <= now).unwrap_or(false),
+                    )),
+                    database_id: None,
+                };
+                memlock::lock(login.password.as_bytes());
+                login
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_key_file_key() {
+        let raw = [7u8; 32];
+        assert_eq!(key_file_key(&raw).unwrap(), raw);
+        let hex = "07".repeat(32);
+        assert_eq!(key_file_key(hex.as_bytes()).unwrap(), raw);
+        let xml = format!(
+            "<?xml version=\"1.0\"?><KeyFile><Meta><Version>1.0</Version></Meta><Key><Data>{}</Data></Key></KeyFile>",
+            base64::encode(raw)
+        );
+        assert_eq!(key_file_key(xml.as_bytes()).unwrap(), raw);
+        let xml = format!(
+            "<?xml version=\"1.0\"?><KeyFile><Meta><Version>2.0</Version></Meta><Key><Data Hash=\"00000000\">{} {}</Data></Key></KeyFile>",
+            "07".repeat(16),
+            "07".repeat(16)
+        );
+        assert_eq!(key_file_key(xml.as_bytes()).unwrap(), raw);
+        let other = b"any other file";
+        assert_eq!(key_file_key(other).unwrap()[..], Sha256::digest(other)[..]);
+    }
+
+    #[test]
+    fn test_01_parse_time() {
+        assert_eq!(parse_time("1970-01-01T00:01:00Z"), Some(60));
+        assert_eq!(
+            parse_time(&base64::encode((EPOCH_OFFSET + 60).to_le_bytes())),
+            Some(60)
+        );
+    }
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/kdbx/");
+
+    fn fixture(name: &str) -> String {
+        format!("{}{}", FIXTURES, name)
+    }
+
+    fn open(name: &str, password: Option<&str>, key_file: bool) -> Result<KdbxDatabase> {
+        KdbxDatabase::open(
+            fixture(name),
+            password,
+            Some(fixture("keyfile.keyx")).filter(|_| key_file),
+        )
+    }
+
+    /// Usernames of the logins for a URL, sorted
+    fn usernames(logins: &[LoginEntry]) -> Vec<&str> {
+        let mut usernames: Vec<_> = logins.iter().map(|login| login.login.as_str()).collect();
+        usernames.sort_unstable();
+        usernames
+    }
+
+    /// Checks the logins of a fixture written by `testdata/kdbx/generate.py`
+    fn check_logins(database: &KdbxDatabase) {
+        let url = "https://git.example.com/repo.git";
+        let logins = database.logins_for(url, false, 120);
+        // carol's login is for another host, frank's is hidden and dave's recycled
+        assert_eq!(usernames(&logins), ["alice", "bob", "erin"]);
+        let login = |username: &str| logins.iter().find(|login| login.login == username).unwrap();
+        assert_eq!(login("alice").password, "secret1");
+        assert_eq!(login("alice").name, "Example");
+        assert_eq!(login("alice").group.as_deref(), Some("Root"));
+        assert_eq!(
+            login("alice").uuid,
+            encode_hex(&[1u8; 16]),
+            "history entries aren't logins"
+        );
+        assert_eq!(login("bob").password, "secret2");
+        assert!(login("bob").expired.as_ref().unwrap().0);
+        assert!(!login("alice").expired.as_ref().unwrap().0);
+        // via KP2A_URL_1, in a subgroup
+        assert_eq!(login("erin").password, "secret5");
+        assert_eq!(login("erin").group.as_deref(), Some("Git"));
+
+        let logins = database.logins_for(url, true, 120);
+        assert_eq!(usernames(&logins), ["alice", "bob", "dave", "erin"]);
+        assert_eq!(
+            logins
+                .iter()
+                .find(|login| login.login == "dave")
+                .unwrap()
+                .password,
+            "secret4"
+        );
+
+        let logins = database.logins_for("https://other.org", false, 120);
+        assert_eq!(usernames(&logins), ["carol"]);
+        assert_eq!(logins[0].password, "secret3");
+        let fields = logins[0].string_fields.as_ref().unwrap();
+        assert_eq!(fields[0].get("KPH: git").map(String::as_str), Some("false"));
+    }
+
+    #[test]
+    fn test_02_kdbx3_aes_salsa20() {
+        check_logins(&open("kdbx3-aes.kdbx", Some("test"), false).unwrap());
+        check_logins(&open("kdbx3-aes-keyfile.kdbx", Some("test"), true).unwrap());
+        assert!(open("kdbx3-aes-keyfile.kdbx", Some("test"), false).is_err());
+    }
+
+    #[test]
+    fn test_03_kdbx4_argon2_chacha20() {
+        check_logins(&open("kdbx4-argon2-chacha20.kdbx", Some("test"), false).unwrap());
+        check_logins(&open("kdbx4-argon2-chacha20-keyfile.kdbx", None, true).unwrap());
+        assert!(open("kdbx4-argon2-chacha20-keyfile.kdbx", Some("test"), true).is_err());
+    }
+
+    #[test]
+    fn test_04_wrong_password_or_corrupted() {
+        let wrong_key = composite_key(Some("wrong"), None).unwrap();
+        for name in &["kdbx3-aes.kdbx", "kdbx4-argon2-chacha20.kdbx"] {
+            let data = std::fs::read(fixture(name)).unwrap();
+            let e = decrypt_file(&data, &wrong_key).err().unwrap();
+            assert_eq!(e.to_string(), "Wrong password or key file", "{}", name);
+        }
+
+        // the HMAC of the block no longer matches
+        let key = composite_key(Some("test"), None).unwrap();
+        let mut data = std::fs::read(fixture("kdbx4-argon2-chacha20.kdbx")).unwrap();
+        let last = data.len() - 40;
+        data[last] ^= 1;
+        let e = decrypt_file(&data, &key).err().unwrap();
+        assert_eq!(e.to_string(), "Database file is corrupted");
+        // and in KDBX 3.1, the hash of the block
+        let mut data = std::fs::read(fixture("kdbx3-aes.kdbx")).unwrap();
+        let last = data.len() - 20;
+        data[last] ^= 1;
+        assert!(decrypt_file(&data, &key).is_err());
+    }
+}
@@ -1,11 +1,13 @@
-pub mod messages;
 pub mod errors;
+pub mod messages;
+pub mod version;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Serialize, Deserialize, Default, Debug)]
 pub struct Group {
     pub name: String,
     pub uuid: String,
+    #[serde(default)]
     pub children: Vec<Group>,
 }
 
@@ -17,4 +19,78 @@ impl Group {
             ..Default::default()
         }
     }
+
+    /// Finds a group by its `/`-separated path below the root group(s), as returned by
+    /// get-database-groups
+    pub fn find_path<'a>(roots: &'a [Group], path: &str) -> Option<&'a Group> {
+        roots.iter().find_map(|root| {
+            path.split('/')
+                .filter(|name| !name.is_empty())
+                .try_fold(root, |group, name| {
+                    group.children.iter().find(|child| child.name == name)
+                })
+                .filter(|group| !std::ptr::eq(*group, root))
+        })
+    }
+
+    /// `/`-separated path below the root group(s) of the group with `uuid`, the reverse of
+    /// `find_path()`
+    pub fn path_of(roots: &[Group], uuid: &str) -> Option<String> {
+        fn names_to<'a>(group: &'a Group, uuid: &str) -> Option<Vec<&'a str>> {
+            if group.uuid == uuid {
+                return Some(Vec::new());
+            }
+            group.children.iter().find_map(|child| {
+                let mut names = names_to(child, uuid)?;
+                names.insert(0, child.name.as_str());
+                Some(names)
+            })
+        }
+        roots
+            .iter()
+            .find_map(|root| names_to(root, uuid))
+            .filter(|names| !names.is_empty())
+            .map(|names| names.join("/"))
+    }
+
+    /// Last component of a group path, which is what KeePassXC reports for entries
+    pub fn leaf_name(path: &str) -> &str {
+        path.rsplit('/')
+            .find(|name| !name.is_empty())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_find_path() {
+        let mut git = Group::new("Git", "2");
+        git.children.push(Group::new("GitHub", "3"));
+        let mut work = Group::new("Work", "1");
+        work.children.push(git);
+        let mut root = Group::new("Root", "0");
+        root.children.push(work);
+        let roots = vec![root];
+        assert_eq!(
+            Group::find_path(&roots, "Work/Git/GitHub").map(|g| g.uuid.as_str()),
+            Some("3")
+        );
+        assert_eq!(
+            Group::find_path(&roots, "/Work/Git/").map(|g| g.uuid.as_str()),
+            Some("2")
+        );
+        assert!(Group::find_path(&roots, "Work/GitLab").is_none());
+        assert!(Group::find_path(&roots, "").is_none());
+        assert_eq!(
+            Group::path_of(&roots, "3").as_deref(),
+            Some("Work/Git/GitHub")
+        );
+        assert!(Group::path_of(&roots, "0").is_none());
+        assert!(Group::path_of(&roots, "4").is_none());
+        assert_eq!(Group::leaf_name("Work/Git/"), "Git");
+        assert_eq!(Group::leaf_name("Git"), "Git");
+    }
 }
@@ -16,6 +16,21 @@ impl KeePassError {
             false
         }
     }
+
+    pub fn is_no_logins_found(&self) -> bool {
+        self.response.error_code.as_deref() == Some("15")
+    }
+
+    /// Whether KeePassXC doesn't know the action of the request, i.e. it's too old
+    pub fn is_unknown_action(&self) -> bool {
+        self.response.error_code.as_deref() == Some("12")
+    }
+
+    /// Whether KeePassXC doesn't know the session key, e.g. after it restarted
+    pub fn is_session_unknown(&self) -> bool {
+        // client public key not received, cannot decrypt message
+        matches!(self.response.error_code.as_deref(), Some("3") | Some("4"))
+    }
 }
 
 impl Display for KeePassError {
@@ -26,3 +41,32 @@ impl Display for KeePassError {
 }
 
 impl Error for KeePassError {}
+
+/// The running KeePassXC doesn't support a request
+#[derive(Debug)]
+pub struct UnsupportedActionError {
+    pub action: String,
+    /// First version supporting it, if known
+    pub minimum: Option<&'static str>,
+    pub version: Option<String>,
+}
+
+impl Display for UnsupportedActionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let version = self.version.as_deref().unwrap_or("unknown");
+        match self.minimum {
+            Some(minimum) => write!(
+                f,
+                "{} requires KeePassXC {} or newer, but {} is running",
+                self.action, minimum, version
+            ),
+            None => write!(
+                f,
+                "{} isn't supported by the running KeePassXC ({}), update it",
+                self.action, version
+            ),
+        }
+    }
+}
+
+impl Error for UnsupportedActionError {}
@@ -93,4 +93,6 @@ define_action!([
     (DatabaseLocked, "database-locked"),
     (DatabaseUnlocked, "database-unlocked"),
     (CreateNewGroup, "create-new-group"),
+    (DeleteEntry, "delete-entry"),
+    (RequestAutotype, "request-autotype"),
 ]);
@@ -1,5 +1,10 @@
-use super::super::errors::KeePassError;
+use super::super::errors::{KeePassError, UnsupportedActionError};
+use super::super::version;
+use super::super::Group;
 use super::primitives::*;
+use crate::logging::timed;
+use crate::memlock;
+use crate::progress;
 use crate::utils::*;
 #[allow(unused_imports)]
 use crate::{debug, error, info, warn};
@@ -14,14 +19,16 @@ where
     Self: Serialize,
 {
     fn send(&self) -> Result<R> {
-        info!("Sending {} request", self.get_action().to_string());
-        let request_json = serde_json::to_string(self)?;
-        #[cfg(not(test))]
-        let response_json = MessengingUtils::exchange_message(request_json)?;
-        #[cfg(test)]
-        let response_json = MockMessengingUtils::exchange_message(request_json)?;
-        let response: R = serde_json::from_str(&response_json)?;
-        Ok(response)
+        let phase = format!("{} request", self.get_action().to_string());
+        timed(&phase, || {
+            let request_json = serde_json::to_string(self)?;
+            #[cfg(not(test))]
+            let response_json = MessengingUtils::exchange_message(request_json)?;
+            #[cfg(test)]
+            let response_json = MockMessengingUtils::exchange_message(request_json)?;
+            let response: R = serde_json::from_str(&response_json)?;
+            Ok(response)
+        })
     }
 
     fn get_action(&self) -> KeePassAction;
@@ -33,56 +40,75 @@ where
     Self: Serialize,
 {
     fn send<T: Into<String>>(&self, client_id: T, trigger_unlock: bool) -> Result<R> {
-        info!("Sending {} request", self.get_action().to_string());
-        let (nonce, nonce_b64) = nacl_nonce();
-        let encrypted_request_json = to_encrypted_json(&self, &nonce)?;
-        let trigger_unlock = if trigger_unlock {
-            "true".to_owned()
-        } else {
-            "".to_owned()
-        };
-        let request_wrapper = GenericRequestWrapper {
-            action: self.get_action(),
-            message: encrypted_request_json,
-            nonce: nonce_b64,
-            client_id: client_id.into(),
-            trigger_unlock,
-        };
-        #[cfg(not(test))]
-        MessengingUtils::send_message(serde_json::to_string(&request_wrapper)?)?;
-        #[cfg(test)]
-        MockMessengingUtils::send_message(serde_json::to_string(&request_wrapper)?)?;
-        let response_wrapper = loop {
+        let action = self.get_action().to_string();
+        version::check_action(&action)?;
+        let phase = format!("{} request", action);
+        timed(&phase, || {
+            let (nonce, nonce_b64) = nacl_nonce();
+            let encrypted_request_json = to_encrypted_json(&self, &nonce)?;
+            let trigger_unlock = if trigger_unlock {
+                "true".to_owned()
+            } else {
+                "".to_owned()
+            };
+            let request_wrapper = GenericRequestWrapper {
+                action: self.get_action(),
+                message: encrypted_request_json,
+                nonce: nonce_b64,
+                client_id: client_id.into(),
+                trigger_unlock,
+            };
             #[cfg(not(test))]
-            let response_wrapper_json = MessengingUtils::receive_message()?;
+            MessengingUtils::send_message(serde_json::to_string(&request_wrapper)?)?;
             #[cfg(test)]
-            let response_wrapper_json = MockMessengingUtils::receive_message()?;
-            let response_wrapper: GenericResponseWrapper =
-                serde_json::from_str(&response_wrapper_json)?;
-            if response_wrapper.action == self.get_action() {
-                break response_wrapper;
-            }
-            warn!(
-                "Unexpected action {} in response, hence discarded: {}",
-                response_wrapper.action.to_string(),
-                response_wrapper_json
+            MockMessengingUtils::send_message(serde_json::to_string(&request_wrapper)?)?;
+            // KeePassXC may ask the user to confirm access or unlock the database first
+            let _waiting = progress::waiting(
+                "Waiting for approval in KeePassXC",
+                "(its dialog may be behind other windows)",
             );
-        };
-        response_wrapper.log();
-        if response_wrapper.message.is_some() && response_wrapper.nonce.is_some() {
-            let (message, nonce) = (
-                response_wrapper.message.unwrap(),
-                response_wrapper.nonce.unwrap(),
-            );
-            let decrypted_response_json = to_decrypted_json(message, nonce)?;
-            let response: R = serde_json::from_str(&decrypted_response_json)?;
-            Ok(response)
-        } else {
-            Err(KeePassError {
-                message: response_wrapper.error_message(),
-                response: response_wrapper,
-            })?
-        }
+            let response_wrapper = loop {
+                #[cfg(not(test))]
+                let response_wrapper_json = MessengingUtils::receive_message()?;
+                #[cfg(test)]
+                let response_wrapper_json = MockMessengingUtils::receive_message()?;
+                let response_wrapper: GenericResponseWrapper =
+                    serde_json::from_str(&response_wrapper_json)?;
+                if response_wrapper.action == self.get_action() {
+                    break response_wrapper;
+                }
+                warn!(
+                    "Unexpected action {} in response, hence discarded: {}",
+                    response_wrapper.action.to_string(),
+                    response_wrapper_json
+                );
+            };
+            response_wrapper.log();
+            if response_wrapper.message.is_some() && response_wrapper.nonce.is_some() {
+                let (message, nonce) = (
+                    response_wrapper.message.unwrap(),
+                    response_wrapper.nonce.unwrap(),
+                );
+                let decrypted_response_json = to_decrypted_json(message, nonce)?;
+                memlock::lock(decrypted_response_json.as_bytes());
+                let response = serde_json::from_str(&decrypted_response_json);
+                memlock::unlock(decrypted_response_json.as_bytes());
+                Ok(response?)
+            } else {
+                let keepass_error = KeePassError {
+                    message: response_wrapper.error_message(),
+                    response: response_wrapper,
+                };
+                if keepass_error.is_unknown_action() {
+                    Err(UnsupportedActionError {
+                        minimum: version::minimum_version_of(&action),
+                        action,
+                        version: version::get_version(),
+                    })?
+                }
+                Err(keepass_error)?
+            }
+        })
     }
 
     fn get_action(&self) -> KeePassAction;
@@ -108,8 +134,12 @@ impl_cipher_text!([
     (TestAssociateRequest, TestAssociateResponse),
     (GetLoginsRequest, GetLoginsResponse),
     (SetLoginRequest, SetLoginResponse),
-    // (GetDatabaseGroupsRequest, GetDatabaseGroupsResponse),
+    (GetDatabaseGroupsRequest, GetDatabaseGroupsResponse),
     (CreateNewGroupRequest, CreateNewGroupResponse),
+    (DeleteEntryRequest, DeleteEntryResponse),
+    (LockDatabaseRequest, LockDatabaseResponse),
+    (RequestAutotypeRequest, RequestAutotypeResponse),
+    (GeneratePasswordRequest, GeneratePasswordResponse),
 ]);
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -150,6 +180,61 @@ impl GenericResponseWrapper {
     }
 }
 
+/// Sends any message, e.g. of an action new KeePassXC releases have added, encrypted like
+/// `CipherTextRequest` does and returns the decrypted message of the response, or the whole
+/// response if it has none, e.g. on errors
+pub fn send_raw<T: Into<String>>(
+    message: &serde_json::Value,
+    client_id: T,
+    trigger_unlock: bool,
+) -> Result<serde_json::Value> {
+    let action = message["action"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Message without action"))?
+        .to_owned();
+    timed(&format!("{} request", action), || {
+        let (nonce, nonce_b64) = nacl_nonce();
+        let mut request_wrapper = serde_json::json!({
+            "action": action,
+            "message": to_encrypted_json(message, &nonce)?,
+            "nonce": nonce_b64,
+            "clientID": client_id.into(),
+        });
+        if trigger_unlock {
+            request_wrapper["triggerUnlock"] = "true".into();
+        }
+        #[cfg(not(test))]
+        MessengingUtils::send_message(request_wrapper.to_string())?;
+        #[cfg(test)]
+        MockMessengingUtils::send_message(request_wrapper.to_string())?;
+        let response_wrapper = loop {
+            #[cfg(not(test))]
+            let response_wrapper_json = MessengingUtils::receive_message()?;
+            #[cfg(test)]
+            let response_wrapper_json = MockMessengingUtils::receive_message()?;
+            // not GenericResponseWrapper, which only knows the actions implemented here
+            let response_wrapper: serde_json::Value = serde_json::from_str(&response_wrapper_json)?;
+            if response_wrapper["action"].as_str() == Some(&action) {
+                break response_wrapper;
+            }
+            warn!(
+                "Unexpected action {} in response, hence discarded",
+                response_wrapper["action"]
+            );
+        };
+        match (
+            response_wrapper["message"].as_str(),
+            response_wrapper["nonce"].as_str(),
+        ) {
+            (Some(message), Some(nonce)) => {
+                let decrypted_response_json = to_decrypted_json(message, nonce)?;
+                Ok(serde_json::from_str(&decrypted_response_json)?)
+            }
+            _ => Ok(response_wrapper),
+        }
+    })
+}
+
 /*
  * change-public-keys
  * https://github.com/keepassxreboot/keepassxc-browser/blob/develop/keepassxc-protocol.md#change-public-keys
@@ -220,6 +305,12 @@ impl GetDatabaseHashRequest {
     }
 }
 
+impl Default for GetDatabaseHashRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GetDatabaseHashResponse {
     pub hash: Option<String>,
@@ -304,15 +395,59 @@ pub struct TestAssociateResponse {
 }
 
 /*
- * generate-password (not needed)
+ * generate-password
  * https://github.com/keepassxreboot/keepassxc-browser/blob/develop/keepassxc-protocol.md#generate-password
  */
 
-// message_req_type!(
-//     GeneratePasswordReq,
-//     GeneratePassword,
-//     "generate-password-req"
-// );
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GeneratePasswordRequest {
+    action: KeePassAction,
+}
+
+impl GeneratePasswordRequest {
+    pub fn new() -> Self {
+        Self {
+            action: KeePassAction::GeneratePassword,
+        }
+    }
+}
+
+impl Default for GeneratePasswordRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GeneratedPassword {
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GeneratePasswordResponse {
+    /// KeePassXC 2.7+, once the password is applied in its generator dialog
+    pub password: Option<String>,
+    /// Older versions
+    pub entries: Option<Vec<GeneratedPassword>>,
+    /* generic fields */
+    pub version: Option<String>,
+    pub nonce: Option<String>,
+    pub success: Option<KeePassBoolean>,
+    pub error: Option<String>,
+    #[serde(rename = "errorCode")]
+    pub error_code: Option<String>,
+}
+
+impl GeneratePasswordResponse {
+    pub fn into_password(self) -> Option<String> {
+        let entries = self.entries;
+        self.password.or_else(|| {
+            entries
+                .and_then(|entries| entries.into_iter().next())
+                .map(|entry| entry.password)
+        })
+    }
+}
 
 /*
  * get-logins
@@ -337,17 +472,13 @@ pub struct GetLoginsRequest {
 }
 
 impl GetLoginsRequest {
-    pub fn new<T: Into<String>>(
-        url: T,
-        submit_url: Option<T>,
-        http_auth: Option<KeePassBoolean>,
-        keys: &[(&str, &str)],
-    ) -> Self {
+    /// Request for logins of `url` in the databases identified by the given (id, key) pairs
+    pub fn new<T: Into<String>>(url: T, keys: &[(&str, &str)]) -> Self {
         Self {
             action: KeePassAction::GetLogins,
             url: url.into(),
-            submit_url: submit_url.map(|u| u.into()),
-            http_auth,
+            submit_url: None,
+            http_auth: None,
             keys: keys
                 .iter()
                 .map(|(id, key)| DatabaseIdentificationKeyPair {
@@ -357,17 +488,58 @@ impl GetLoginsRequest {
                 .collect(),
         }
     }
+
+    pub fn submit_url<T: Into<String>>(mut self, submit_url: T) -> Self {
+        self.submit_url = Some(submit_url.into());
+        self
+    }
+
+    /// Whether to include entries which only allow HTTP authentication
+    pub fn http_auth(mut self, http_auth: bool) -> Self {
+        self.http_auth = Some(KeePassBoolean(http_auth));
+        self
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct LoginEntry {
     pub login: String,
     pub name: String,
+    #[serde(deserialize_with = "deserialize_locked")]
     pub password: String,
     pub uuid: String,
+    /// Name of the group the entry is in, since KeePassXC 2.6
+    pub group: Option<String>,
     #[serde(rename = "stringFields")]
     pub string_fields: Option<Vec<HashMap<String, String>>>,
     pub expired: Option<KeePassBoolean>,
+    /// ID of the database the entry is from, filled in by us as KeePassXC doesn't tell
+    #[serde(skip)]
+    pub database_id: Option<String>,
+}
+
+/// Passwords are kept out of swap until the entry is dropped
+fn deserialize_locked<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    let password = String::deserialize(deserializer)?;
+    memlock::lock(password.as_bytes());
+    Ok(password)
+}
+
+impl LoginEntry {
+    /// Replaces the password, keeping the new one out of swap instead
+    pub fn set_password(&mut self, password: String) {
+        memlock::unlock(self.password.as_bytes());
+        self.password = password;
+        memlock::lock(self.password.as_bytes());
+    }
+}
+
+impl Drop for LoginEntry {
+    fn drop(&mut self) {
+        memlock::unlock(self.password.as_bytes());
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -406,34 +578,57 @@ pub struct SetLoginRequest {
     pub group_uuid: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub uuid: Option<String>,
+    #[serde(rename = "downloadFavicon", skip_serializing_if = "Option::is_none")]
+    pub download_favicon: Option<KeePassBoolean>,
 }
 
 impl SetLoginRequest {
-    #[allow(clippy::too_many_arguments)]
-    pub fn new<T: Into<String>>(
-        url: T,
-        submit_url: T,
-        id: T,
-        login: T,
-        password: T,
-        group: Option<T>,
-        group_uuid: Option<T>,
-        uuid: Option<T>,
-    ) -> Self {
+    /// Request for creating a login of `url` in database `id`, in its default group unless
+    /// [`group`](Self::group) is given, or updating entry [`uuid`](Self::uuid)
+    pub fn new<T: Into<String>>(url: T, id: T, login: T, password: T) -> Self {
         let (_, nonce) = nacl_nonce();
+        let url = url.into();
         Self {
             action: KeePassAction::SetLogin,
-            url: url.into(),
-            submit_url: submit_url.into(),
+            submit_url: url.clone(),
+            url,
             id: id.into(),
             nonce,
             login: login.into(),
             password: password.into(),
-            group: group.map(|v| v.into()),
-            group_uuid: group_uuid.map(|v| v.into()),
-            uuid: uuid.map(|v| v.into()),
+            group: None,
+            group_uuid: None,
+            uuid: None,
+            download_favicon: None,
         }
     }
+
+    /// Defaults to the URL of the login
+    pub fn submit_url<T: Into<String>>(mut self, submit_url: T) -> Self {
+        self.submit_url = submit_url.into();
+        self
+    }
+
+    pub fn group(mut self, group: &Group) -> Self {
+        self.group = Some(group.name.clone());
+        self.group_uuid = Some(group.uuid.clone());
+        self
+    }
+
+    /// UUID of an existing entry to update instead of creating a new one
+    pub fn uuid<T: Into<String>>(mut self, uuid: T) -> Self {
+        self.uuid = Some(uuid.into());
+        self
+    }
+
+    /// Makes KeePassXC download the favicon of the site as the icon of a new entry, which older
+    /// versions and builds without networking ignore
+    pub fn download_favicon(mut self, download_favicon: bool) -> Self {
+        if download_favicon {
+            self.download_favicon = Some(KeePassBoolean(true));
+        }
+        self
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -450,55 +645,94 @@ pub struct SetLoginResponse {
 }
 
 /*
- * lock-database (not needed)
+ * lock-database
  * https://github.com/keepassxreboot/keepassxc-browser/blob/develop/keepassxc-protocol.md#lock-database
  */
 
-// message_req_type!(LockDatabaseReq, LockDatabase, "lock-database-req");
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LockDatabaseRequest {
+    action: KeePassAction,
+}
+
+impl LockDatabaseRequest {
+    pub fn new() -> Self {
+        Self {
+            action: KeePassAction::LockDatabase,
+        }
+    }
+}
+
+impl Default for LockDatabaseRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// KeePassXC answers with a "database not opened" error once it has locked the databases
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LockDatabaseResponse {
+    /* generic fields */
+    pub version: Option<String>,
+    pub id: Option<String>,
+    pub nonce: Option<String>,
+    pub success: Option<KeePassBoolean>,
+    pub error: Option<String>,
+    #[serde(rename = "errorCode")]
+    pub error_code: Option<String>,
+}
 
 /*
  * get-database-groups
  * https://github.com/keepassxreboot/keepassxc-browser/blob/develop/keepassxc-protocol.md#get-database-groups
  */
 
-// #[derive(Serialize, Deserialize, Debug)]
-// pub struct GetDatabaseGroupsRequest {
-//     action: KeePassAction,
-// }
-//
-// impl GetDatabaseGroupsRequest {
-//     pub fn new() -> Self {
-//         Self {
-//             action: KeePassAction::GetDatabaseGroups,
-//         }
-//     }
-// }
-//
-// #[derive(Serialize, Deserialize, Debug)]
-// struct InnerGroups {
-//     pub groups: Vec<crate::keepassxc::Group>,
-// }
-//
-// #[derive(Serialize, Deserialize, Debug)]
-// pub struct GetDatabaseGroupsResponse {
-//     #[serde(rename = "defaultGroup")]
-//     pub default_group: Option<String>,
-//     #[serde(rename = "defaultGroupAlwaysAllow")]
-//     pub default_group_always_allow: Option<bool>,
-//     groups: InnerGroups,
-//     [> generic fields <]
-//     pub version: Option<String>,
-//     pub success: Option<KeePassBoolean>,
-//     pub error: Option<String>,
-//     #[serde(rename = "errorCode")]
-//     pub error_code: Option<String>,
-// }
-//
-// impl GetDatabaseGroupsResponse {
-//     pub fn get_groups(&self) -> &[crate::keepassxc::Group] {
-//         &self.groups.groups
-//     }
-// }
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetDatabaseGroupsRequest {
+    action: KeePassAction,
+}
+
+impl GetDatabaseGroupsRequest {
+    pub fn new() -> Self {
+        Self {
+            action: KeePassAction::GetDatabaseGroups,
+        }
+    }
+}
+
+impl Default for GetDatabaseGroupsRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct InnerGroups {
+    pub groups: Vec<Group>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetDatabaseGroupsResponse {
+    #[serde(rename = "defaultGroup")]
+    pub default_group: Option<String>,
+    #[serde(rename = "defaultGroupAlwaysAllow")]
+    pub default_group_always_allow: Option<bool>,
+    groups: InnerGroups,
+    /* generic fields */
+    pub version: Option<String>,
+    pub id: Option<String>,
+    pub nonce: Option<String>,
+    pub success: Option<KeePassBoolean>,
+    pub error: Option<String>,
+    #[serde(rename = "errorCode")]
+    pub error_code: Option<String>,
+}
+
+impl GetDatabaseGroupsResponse {
+    /// The root group of the database, whose children are the top-level groups
+    pub fn get_groups(&self) -> &[Group] {
+        &self.groups.groups
+    }
+}
 
 /*
  * create-new-group
@@ -535,6 +769,71 @@ pub struct CreateNewGroupResponse {
     pub error_code: Option<String>,
 }
 
+/*
+ * delete-entry
+ * https://github.com/keepassxreboot/keepassxc-browser/blob/develop/keepassxc-protocol.md#delete-entry
+ */
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteEntryRequest {
+    action: KeePassAction,
+    uuid: String,
+}
+
+impl DeleteEntryRequest {
+    pub fn new<T: Into<String>>(uuid: T) -> Self {
+        Self {
+            action: KeePassAction::DeleteEntry,
+            uuid: uuid.into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteEntryResponse {
+    /* generic fields */
+    pub version: Option<String>,
+    pub id: Option<String>,
+    pub nonce: Option<String>,
+    pub success: Option<KeePassBoolean>,
+    pub error: Option<String>,
+    #[serde(rename = "errorCode")]
+    pub error_code: Option<String>,
+}
+
+/*
+ * request-autotype
+ * https://github.com/keepassxreboot/keepassxc-browser/blob/develop/keepassxc-protocol.md#request-autotype
+ */
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RequestAutotypeRequest {
+    action: KeePassAction,
+    /// Domain KeePassXC looks for entries of, before showing its auto-type selection dialog
+    search: String,
+}
+
+impl RequestAutotypeRequest {
+    pub fn new<T: Into<String>>(search: T) -> Self {
+        Self {
+            action: KeePassAction::RequestAutotype,
+            search: search.into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RequestAutotypeResponse {
+    /* generic fields */
+    pub version: Option<String>,
+    pub id: Option<String>,
+    pub nonce: Option<String>,
+    pub success: Option<KeePassBoolean>,
+    pub error: Option<String>,
+    #[serde(rename = "errorCode")]
+    pub error_code: Option<String>,
+}
+
 // no specs, need to dig into codes
 //
 // message_req_type!(DatabaseLockedReq, DatabaseLocked, "database-locked-req");
@@ -550,7 +849,9 @@ mod tests {
 
     #[test]
     fn test_00_exchange_public_keys() {
-        let _guard = test_guard().lock().unwrap();
+        let _guard = test_guard()
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
         let host_seckey = test_host_secret_key();
         let host_pubkey = host_seckey.public_key();
         let session_seckey = test_session_secret_key();
@@ -573,7 +874,9 @@ mod tests {
 
     #[test]
     fn test_01_successful_test_associate() {
-        let _guard = test_guard().lock().unwrap();
+        let _guard = test_guard()
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
         let host_seckey = test_host_secret_key();
         let host_pubkey = host_seckey.public_key();
         let session_seckey = test_session_secret_key();
@@ -640,4 +943,26 @@ mod tests {
         receive_message_context.checkpoint();
         send_message_context.checkpoint();
     }
+
+    #[test]
+    fn test_02_set_login_request_builder() {
+        let group = Group::new("Git", "group-uuid");
+        let request = SetLoginRequest::new("https://example.com", "db", "user", "pass")
+            .group(&group)
+            .uuid("entry-uuid");
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["url"], "https://example.com");
+        assert_eq!(json["submitUrl"], "https://example.com");
+        assert_eq!(json["id"], "db");
+        assert_eq!(json["login"], "user");
+        assert_eq!(json["password"], "pass");
+        assert_eq!(json["group"], "Git");
+        assert_eq!(json["groupUuid"], "group-uuid");
+        assert_eq!(json["uuid"], "entry-uuid");
+
+        let request = SetLoginRequest::new("https://example.com", "db", "user", "pass");
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json.get("group").is_none());
+        assert!(json.get("uuid").is_none());
+    }
 }
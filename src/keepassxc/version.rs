@@ -0,0 +1,81 @@
+//! Version of KeePassXC as told when starting a session, so that requests older versions don't
+//! know fail with a clear error rather than an unknown action or a malformed response
+use super::errors::UnsupportedActionError;
+use anyhow::Result;
+use std::sync::Mutex;
+
+static VERSION: Mutex<Option<String>> = Mutex::new(None);
+
+/// Requests not every KeePassXC version with the browser integration knows, and the first version
+/// that does
+const MINIMUM_VERSIONS: &[(&str, &str)] = &[
+    ("get-database-groups", "2.6.0"),
+    ("create-new-group", "2.6.0"),
+    ("request-autotype", "2.7.0"),
+];
+
+pub fn set_version(version: Option<String>) {
+    if let Ok(mut current) = VERSION.lock() {
+        *current = version;
+    }
+}
+
+pub fn get_version() -> Option<String> {
+    VERSION.lock().ok().and_then(|version| version.clone())
+}
+
+pub fn minimum_version_of(action: &str) -> Option<&'static str> {
+    MINIMUM_VERSIONS
+        .iter()
+        .find(|(name, _)| *name == action)
+        .map(|(_, minimum)| *minimum)
+}
+
+/// Numeric components of a version, ignoring suffixes like `-snapshot`
+fn parse(version: &str) -> Vec<u32> {
+    version
+        .split('.')
+        .map(|part| {
+            part.chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>()
+                .parse()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+fn is_at_least(version: &str, minimum: &str) -> bool {
+    parse(version) >= parse(minimum)
+}
+
+/// Fails if the KeePassXC of the session is known to be too old for a request
+pub fn check_action(action: &str) -> Result<()> {
+    let (minimum, version) = match (minimum_version_of(action), get_version()) {
+        (Some(minimum), Some(version)) => (minimum, version),
+        _ => return Ok(()),
+    };
+    if is_at_least(&version, minimum) {
+        return Ok(());
+    }
+    Err(UnsupportedActionError {
+        action: action.to_owned(),
+        minimum: Some(minimum),
+        version: Some(version),
+    }
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_is_at_least() {
+        assert!(is_at_least("2.6.0", "2.6.0"));
+        assert!(is_at_least("2.7.10", "2.7.9"));
+        assert!(is_at_least("2.8.0-snapshot", "2.7.0"));
+        assert!(!is_at_least("2.5.4", "2.6.0"));
+        assert!(!is_at_least("2.6", "2.6.1"));
+    }
+}
@@ -0,0 +1,176 @@
+//! Reads logins from database files with `keepassxc-cli`, which comes with KeePassXC and works
+//! without its GUI running, e.g. in CI
+use crate::config::KdbxFile;
+use crate::keepassxc::messages::LoginEntry;
+use crate::memlock::{self, SecretBuffer};
+use crate::utils::entry_url_matches;
+use anyhow::{anyhow, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const PROGRAM: &str = "keepassxc-cli";
+
+/// Splits CSV into records, with fields optionally quoted and quotes in them doubled, handing them
+/// to `f` one by one. Fields `f` leaves in the record are wiped afterwards, as the export has the
+/// passwords of all entries.
+fn parse_csv<F: FnMut(&mut Vec<String>)>(csv: &str, mut f: F) {
+    fn end_field(record: &mut Vec<String>, field: &mut String) {
+        // allocated for its length, unlike `field` which would leave copies behind when growing
+        record.push(field.as_str().to_owned());
+        memlock::wipe_string(field);
+    }
+    fn end_record<F: FnMut(&mut Vec<String>)>(record: &mut Vec<String>, f: &mut F) {
+        f(record);
+        record.iter_mut().for_each(memlock::wipe_string);
+        record.clear();
+    }
+
+    let mut record = Vec::new();
+    let mut field = String::with_capacity(csv.len());
+    let mut quoted = false;
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => end_field(&mut record, &mut field),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                end_field(&mut record, &mut field);
+                end_record(&mut record, &mut f);
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        end_field(&mut record, &mut field);
+        end_record(&mut record, &mut f);
+    }
+}
+
+/// Indices of the group, title, username, password and URL columns of the exported CSV
+fn columns_of(header: &[String]) -> Result<(usize, usize, usize, usize, usize)> {
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| column == name)
+            .ok_or_else(|| anyhow!("{} exported no {} column", PROGRAM, name))
+    };
+    Ok((
+        column("Group")?,
+        column("Title")?,
+        column("Username")?,
+        column("Password")?,
+        column("URL")?,
+    ))
+}
+
+/// Logins of the exported CSV for a URL, which only tells the group path, title, username,
+/// password and URL of entries, but not e.g. their UUID or expiry
+fn parse_logins(csv: &str, url: &str) -> Result<Vec<LoginEntry>> {
+    let mut columns = None;
+    let mut logins = Vec::new();
+    parse_csv(csv, |record| {
+        let (group, title, username, password, entry_url) = match columns {
+            Some(Ok(columns)) => columns,
+            Some(Err(_)) => return,
+            None => {
+                columns = Some(columns_of(record));
+                return;
+            }
+        };
+        let matches = record
+            .get(entry_url)
+            .map(|entry_url| entry_url_matches(entry_url, url))
+            .unwrap_or(false);
+        if !matches {
+            return;
+        }
+        let mut field = |index: usize| {
+            record
+                .get_mut(index)
+                .map(std::mem::take)
+                .unwrap_or_default()
+        };
+        let login = LoginEntry {
+            login: field(username),
+            name: field(title),
+            password: field(password),
+            uuid: String::new(),
+            // only the name of the immediate group, like KeePassXC tells
+            group: record
+                .get(group)
+                .and_then(|path| path.rsplit('/').next())
+                .map(str::to_owned),
+            string_fields: None,
+            expired: None,
+            database_id: None,
+        };
+        memlock::lock(login.password.as_bytes());
+        logins.push(login);
+    });
+    columns.ok_or_else(|| anyhow!("{} exported nothing", PROGRAM))??;
+    Ok(logins)
+}
+
+/// Logins for a URL in a database file, exported with `keepassxc-cli` which is given the password
+/// on standard input
+pub fn logins_for(kdbx: &KdbxFile, password: Option<&str>, url: &str) -> Result<Vec<LoginEntry>> {
+    let mut command = Command::new(PROGRAM);
+    command.args(["export", "--quiet", "--format", "csv"]);
+    if let Some(key_file) = kdbx.key_file_path() {
+        command.arg("--key-file").arg(key_file);
+    }
+    if password.is_none() {
+        command.arg("--no-password");
+    }
+    let mut child = command
+        .arg(kdbx.file_path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to run {}, {}", PROGRAM, e))?;
+    if let (Some(password), Some(mut stdin)) = (password, child.stdin.take()) {
+        stdin.write_all(password.as_bytes())?;
+        stdin.write_all(b"\n")?;
+    }
+    // the export has the passwords of all entries, not only of those for the URL
+    let csv = match child.stdout.take() {
+        Some(stdout) => SecretBuffer::read_from(stdout)?,
+        None => return Err(anyhow!("Failed to read the output of {}", PROGRAM)),
+    };
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} failed to export {}: {}",
+            PROGRAM,
+            kdbx.path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    parse_logins(std::str::from_utf8(csv.as_slice())?, url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_parse_logins() {
+        let csv = "\"Group\",\"Title\",\"Username\",\"Password\",\"URL\",\"Notes\"\n\
+                   \"Root/Git\",\"Example\",\"foo\",\"b\"\"a,r\",\"https://example.com\",\"multi\nline\"\n\
+                   \"Root\",\"Other\",\"bar\",\"baz\",\"https://example.org\",\"\"\n";
+        let logins = parse_logins(csv, "https://git.example.com/repo.git").unwrap();
+        assert_eq!(logins.len(), 1);
+        assert_eq!(logins[0].login, "foo");
+        assert_eq!(logins[0].password, "b\"a,r");
+        assert_eq!(logins[0].group.as_deref(), Some("Git"));
+
+        assert!(parse_logins("", "https://example.com").is_err());
+        assert!(parse_logins("\"Title\",\"URL\"\n", "https://example.com").is_err());
+    }
+}
@@ -0,0 +1,41 @@
+use serde::Serialize;
+
+/// Output of client-go credential plugins
+/// https://kubernetes.io/docs/reference/access-authn-authz/authentication/#client-go-credential-plugins
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecCredential {
+    api_version: &'static str,
+    kind: &'static str,
+    status: ExecCredentialStatus,
+}
+
+#[derive(Serialize, Debug)]
+struct ExecCredentialStatus {
+    token: String,
+}
+
+impl ExecCredential {
+    pub fn new<T: Into<String>>(token: T) -> Self {
+        Self {
+            api_version: "client.authentication.k8s.io/v1",
+            kind: "ExecCredential",
+            status: ExecCredentialStatus {
+                token: token.into(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_exec_credential_json() {
+        assert_eq!(
+            serde_json::to_string(&ExecCredential::new("foo")).unwrap(),
+            r#"{"apiVersion":"client.authentication.k8s.io/v1","kind":"ExecCredential","status":{"token":"foo"}}"#
+        );
+    }
+}
@@ -0,0 +1,50 @@
+//! KeePassXC browser integration protocol client, configuration handling and the handling of Git
+//! credential requests behind git-credential-keepassxc. The subcommands of the command line
+//! interface are in [`commands`], which the binary hands its arguments to
+mod askpass;
+mod audit;
+mod autolock;
+mod aws;
+mod cargo;
+pub mod cli;
+mod clipboard;
+pub mod commands;
+pub mod config;
+mod daemon;
+mod deadline;
+mod docker;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+pub mod git;
+mod hooks;
+mod import;
+mod interrupt;
+#[cfg(feature = "kdbx")]
+mod kdbx;
+pub mod keepassxc;
+mod keepassxc_cli;
+mod kube;
+mod lockout;
+mod logging;
+mod manpage;
+mod memlock;
+mod npm;
+mod output;
+mod ownership;
+#[cfg(unix)]
+mod peer;
+mod pinentry;
+mod progress;
+mod rejected;
+pub mod requests;
+mod rotation;
+pub mod session;
+pub mod transport;
+pub mod utils;
+
+use once_cell::sync::OnceCell;
+use slog::Logger;
+
+/// Logger of the `error!`, `warn!`, `info!` and `debug!` macros, which must be set before using
+/// anything that logs
+pub static LOGGER: OnceCell<Logger> = OnceCell::new();
@@ -0,0 +1,117 @@
+use crate::utils::{read_state_file, write_state_file};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Refuses further requests for `cooldown` seconds after `max_failures` caller verification
+/// failures within `window` seconds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Lockout {
+    #[serde(default = "Lockout::default_max_failures")]
+    pub max_failures: usize,
+    #[serde(default = "Lockout::default_window")]
+    pub window: u64,
+    #[serde(default = "Lockout::default_cooldown")]
+    pub cooldown: u64,
+}
+
+impl Lockout {
+    fn default_max_failures() -> usize {
+        5
+    }
+
+    fn default_window() -> u64 {
+        60
+    }
+
+    fn default_cooldown() -> u64 {
+        300
+    }
+}
+
+impl Default for Lockout {
+    fn default() -> Self {
+        Self {
+            max_failures: Self::default_max_failures(),
+            window: Self::default_window(),
+            cooldown: Self::default_cooldown(),
+        }
+    }
+}
+
+/// Recent caller verification failures, persisted next to the configuration file
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct LockoutState {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    failures: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    locked_until: Option<u64>,
+}
+
+impl LockoutState {
+    pub fn read_from<T: AsRef<Path>>(state_path: T) -> Result<Self> {
+        read_state_file(state_path)
+    }
+
+    pub fn write_to<T: AsRef<Path>>(&self, state_path: T) -> Result<()> {
+        write_state_file(self, state_path)
+    }
+
+    /// Remaining seconds of the current lockout, if any
+    pub fn locked_for(&self, now: u64) -> Option<u64> {
+        self.locked_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    /// Records a failure and returns whether it has triggered a lockout
+    pub fn record_failure(&mut self, policy: &Lockout, now: u64) -> bool {
+        self.failures
+            .retain(|timestamp| timestamp + policy.window > now);
+        self.failures.push(now);
+        if self.failures.len() >= policy.max_failures {
+            self.failures.clear();
+            self.locked_until = Some(now + policy.cooldown);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_lockout_after_max_failures() {
+        let policy = Lockout {
+            max_failures: 3,
+            window: 10,
+            cooldown: 100,
+        };
+        let mut state = LockoutState::default();
+        assert!(!state.record_failure(&policy, 1000));
+        assert!(!state.record_failure(&policy, 1005));
+        assert!(state.locked_for(1005).is_none());
+        assert!(state.record_failure(&policy, 1009));
+        assert_eq!(state.locked_for(1009), Some(100));
+        assert_eq!(state.locked_for(1100), Some(9));
+        assert!(state.locked_for(1109).is_none());
+    }
+
+    #[test]
+    fn test_01_failures_expire_after_window() {
+        let policy = Lockout {
+            max_failures: 3,
+            window: 10,
+            cooldown: 100,
+        };
+        let mut state = LockoutState::default();
+        assert!(!state.record_failure(&policy, 1000));
+        assert!(!state.record_failure(&policy, 1005));
+        assert!(!state.record_failure(&policy, 1010));
+        assert!(!state.record_failure(&policy, 1016));
+        assert!(state.locked_for(1016).is_none());
+    }
+}
@@ -0,0 +1,213 @@
+use crate::deadline;
+use crate::utils::redact_secrets;
+#[allow(unused_imports)]
+use crate::{debug, error, info, warn};
+use anyhow::Result;
+use serde_json::{Map, Value};
+use slog::{Drain, Key, OwnedKVList, Record, KV};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Runs a phase, e.g. a request to KeePassXC, logging how long it took and whether it failed. The
+/// phase is reported if the deadline passes meanwhile.
+pub fn timed<T, F: FnOnce() -> Result<T>>(phase: &str, f: F) -> Result<T> {
+    debug!("{} started", phase);
+    let started = Instant::now();
+    deadline::enter_phase(phase);
+    let result = f();
+    deadline::leave_phase();
+    let elapsed = started.elapsed().as_millis();
+    match &result {
+        Ok(_) => {
+            info!("{} took {} ms", phase, elapsed);
+        }
+        Err(e) => {
+            deadline::fail_phase(phase);
+            info!("{} failed after {} ms, {}", phase, elapsed, e);
+        }
+    }
+    result
+}
+
+/// Drain writing one JSON object per log record, i.e. `level`, `timestamp` (RFC 3339), `event`
+/// (the message) and `fields` (the key-value pairs of the record and logger)
+pub struct JsonDrain<W: Write> {
+    writer: Mutex<W>,
+    redact: bool,
+}
+
+impl<W: Write> JsonDrain<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+            redact: false,
+        }
+    }
+
+    /// Passes events and fields through redact_secrets(), e.g. for log files
+    pub fn redacted(mut self) -> Self {
+        self.redact = true;
+        self
+    }
+
+    fn format(&self, record: &Record, values: &OwnedKVList) -> slog::Result<Value> {
+        let mut fields = FieldSerializer {
+            fields: Map::new(),
+            redact: self.redact,
+        };
+        record.kv().serialize(record, &mut fields)?;
+        values.serialize(record, &mut fields)?;
+        let mut event = record.msg().to_string();
+        if self.redact {
+            event = redact_secrets(&event);
+        }
+        Ok(serde_json::json!({
+            "level": record.level().as_str().to_lowercase(),
+            "timestamp": chrono::Local::now().to_rfc3339(),
+            "event": event,
+            "fields": fields.fields,
+        }))
+    }
+}
+
+impl<W: Write> Drain for JsonDrain<W> {
+    type Ok = ();
+    type Err = io::Error;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> io::Result<()> {
+        let document = self
+            .format(record, values)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| io::Error::other("Log writer poisoned"))?;
+        writeln!(writer, "{}", document)?;
+        writer.flush()
+    }
+}
+
+struct FieldSerializer {
+    fields: Map<String, Value>,
+    redact: bool,
+}
+
+impl slog::Serializer for FieldSerializer {
+    fn emit_arguments(&mut self, key: Key, value: &fmt::Arguments) -> slog::Result {
+        let mut value = value.to_string();
+        if self.redact {
+            value = redact_secrets(&value);
+        }
+        self.fields.insert(key.to_string(), Value::String(value));
+        Ok(())
+    }
+}
+
+/// Where trace output goes, following the conventions of `GIT_TRACE`
+#[derive(Debug, PartialEq)]
+pub enum TraceTarget {
+    Stderr,
+    /// An open file descriptor, 3 to 9
+    Descriptor(i32),
+    /// An absolute path, appended to
+    File(PathBuf),
+}
+
+impl TraceTarget {
+    /// Parses a value like Git does: `1`, `2` or `true` for stderr, `3` to `9` for that file
+    /// descriptor, or an absolute path. `0`, `false`, empty and anything else turn tracing off.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "1" | "2" | "true" => Some(TraceTarget::Stderr),
+            "3" | "4" | "5" | "6" | "7" | "8" | "9" => {
+                value.parse().ok().map(TraceTarget::Descriptor)
+            }
+            _ if std::path::Path::new(value).is_absolute() => {
+                Some(TraceTarget::File(PathBuf::from(value)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Target of `GIT_CREDENTIAL_KEEPASSXC_TRACE`, or else of `GIT_TRACE`, if any
+    pub fn from_env() -> Option<Self> {
+        std::env::var("GIT_CREDENTIAL_KEEPASSXC_TRACE")
+            .or_else(|_| std::env::var("GIT_TRACE"))
+            .ok()
+            .and_then(|value| Self::parse(&value))
+    }
+
+    /// Opens the file to write trace output to, `None` for stderr
+    pub fn open(&self) -> io::Result<Option<File>> {
+        match self {
+            TraceTarget::Stderr => Ok(None),
+            #[cfg(unix)]
+            TraceTarget::Descriptor(fd) => {
+                use std::os::unix::io::FromRawFd;
+
+                // duplicated, so that the descriptor Git gave stays open whatever happens to it
+                let fd = unsafe { libc::dup(*fd) };
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(Some(unsafe { File::from_raw_fd(fd) }))
+            }
+            #[cfg(not(unix))]
+            TraceTarget::Descriptor(_) => Ok(None),
+            TraceTarget::File(path) => {
+                let mut file_options = std::fs::OpenOptions::new();
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut file_options, 0o600);
+                file_options.create(true).append(true).open(path).map(Some)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_json_drain() {
+        let output = std::sync::Arc::new(Mutex::new(Vec::new()));
+        struct SharedWriter(std::sync::Arc<Mutex<Vec<u8>>>);
+        impl Write for SharedWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let drain = JsonDrain::new(SharedWriter(output.clone())).redacted();
+        let logger = slog::Logger::root(drain.fuse(), slog::o!("pid" => 42));
+        slog::info!(logger, "DEC : {}", r#"{"password":"bar"}"#; "url" => "https://example.com");
+
+        let output = output.lock().unwrap();
+        let document: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(document["level"], "info");
+        assert_eq!(document["event"], r#"DEC : {"password":"<redacted>"}"#);
+        assert_eq!(document["fields"]["url"], "https://example.com");
+        assert_eq!(document["fields"]["pid"], "42");
+        assert!(document["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_01_trace_target() {
+        assert_eq!(TraceTarget::parse("1"), Some(TraceTarget::Stderr));
+        assert_eq!(TraceTarget::parse("True"), Some(TraceTarget::Stderr));
+        assert_eq!(TraceTarget::parse("5"), Some(TraceTarget::Descriptor(5)));
+        assert_eq!(
+            TraceTarget::parse("/tmp/trace.log"),
+            Some(TraceTarget::File(PathBuf::from("/tmp/trace.log")))
+        );
+        assert_eq!(TraceTarget::parse("0"), None);
+        assert_eq!(TraceTarget::parse(""), None);
+        assert_eq!(TraceTarget::parse("trace.log"), None);
+    }
+}
@@ -0,0 +1,319 @@
+//! Renders a roff man page from the CLI definition in `cli.yml`, so that it never goes out of
+//! date with the actual arguments
+use yaml_rust::Yaml;
+
+/// Keys of the configuration file and what they do
+const CONFIG_OPTIONS: &[(&str, &str)] = &[
+    (
+        "databases",
+        "KeePassXC database profiles, added by configure",
+    ),
+    ("encrypted_databases", "Encrypted database profiles"),
+    (
+        "callers",
+        "Processes allowed to use the helper, added by caller add",
+    ),
+    ("encrypted_callers", "Encrypted caller profiles"),
+    (
+        "encryptions",
+        "Encryption profiles, e.g. YubiKey challenge-response",
+    ),
+    ("rules", "Routing rules mapping URL patterns to databases"),
+    ("deny", "URL patterns credentials are never looked up for"),
+    (
+        "aliases",
+        "Hosts whose URLs are rewritten before looking up logins",
+    ),
+    (
+        "proxies",
+        "HTTP proxies whose logins are kept apart from those of other hosts",
+    ),
+    (
+        "group_only",
+        "Only return logins in the group of each database",
+    ),
+    ("filters", "Title and username patterns logins must match"),
+    (
+        "field_mappings",
+        "Fields returned as the password or username per URL pattern",
+    ),
+    (
+        "git_config",
+        "Honour credential.<url>.* settings of Git's configuration",
+    ),
+    (
+        "use_http_path",
+        "Whether URL paths are used, by default and per host",
+    ),
+    (
+        "advanced_fields",
+        "KPH: string fields get --advanced-fields may return",
+    ),
+    (
+        "unsafe_values",
+        "What get does with line breaks in values, reject, strip or percent-encode",
+    ),
+    (
+        "ascii_only",
+        "Treat non-ASCII characters in values like line breaks",
+    ),
+    (
+        "require_unique",
+        "Fail instead of guessing when more than one login matches",
+    ),
+    ("read_only", "Make store and erase do nothing"),
+    (
+        "hash_mismatch",
+        "Whether databases answered for by another database are skipped, refuse or warn",
+    ),
+    (
+        "confirm_overwrite",
+        "Ask before changing the password of an existing login",
+    ),
+    (
+        "password_policies",
+        "Length and characters of generated passwords per URL pattern",
+    ),
+    (
+        "store_host_only",
+        "Store new logins under the host instead of the full URL",
+    ),
+    (
+        "namespaces",
+        "Keep logins of docker, cargo, npm, kube, aws and maven apart from each other's and Git's",
+    ),
+    (
+        "host_subgroups",
+        "Store new logins in a subgroup named after the host",
+    ),
+    (
+        "store_favicon",
+        "Have KeePassXC download the favicon of the site for new logins",
+    ),
+    (
+        "erase_mode",
+        "What erase does with matching logins, disabled or delete",
+    ),
+    (
+        "rotate_after_days",
+        "Warn about logins stored longer ago than this",
+    ),
+    (
+        "remember_rejected",
+        "Seconds get skips logins Git has erased for, unless their password changes",
+    ),
+    (
+        "auto_lock_after",
+        "Lock the databases this many seconds after the last access",
+    ),
+    (
+        "lockout",
+        "Refuse all requests for a while after too many rejected callers",
+    ),
+    ("audit_log", "Append-only log of every get, store and erase"),
+    ("hooks", "Commands to run after get, store and erase"),
+    (
+        "notify_on_access",
+        "Show a desktop notification whenever a login is returned",
+    ),
+    (
+        "confirm_access",
+        "Ask on the terminal before returning each login",
+    ),
+    ("session_daemon", "Settings of the session daemon"),
+    (
+        "cache_helper",
+        "Git credential helper asked before KeePassXC and given the logins found",
+    ),
+    (
+        "ssh_keys",
+        "SSH keys askpass answers passphrase prompts for",
+    ),
+    (
+        "gpg_keys",
+        "GPG keys pinentry answers passphrase prompts for",
+    ),
+    (
+        "pinentry_fallback",
+        "Program pinentry hands over to if no login is found",
+    ),
+    ("kube_clusters", "Kubernetes clusters to provide tokens for"),
+    ("aws_profiles", "AWS profiles to provide access keys for"),
+    ("svn_realms", "Subversion realms to provide logins for"),
+    (
+        "maven_servers",
+        "Maven/Gradle repositories to provide logins for",
+    ),
+];
+
+/// Exit codes and when they're used
+const EXIT_STATUSES: &[(i32, &str)] = &[
+    (0, "Success"),
+    (1, "Any other error"),
+    (
+        crate::utils::AmbiguousLoginsError::EXIT_CODE,
+        "More than one login matches and --require-unique is set",
+    ),
+];
+
+/// Escapes text for roff, including leading control characters
+fn escape(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\\', "\\e").replace('-', "\\-");
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{}", line)
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n.br\n")
+}
+
+/// Name and definition of each single-key mapping in a list, e.g. of `args` or `subcommands`
+pub(crate) fn entries_of<'a>(yaml: &'a Yaml, key: &str) -> Vec<(&'a str, &'a Yaml)> {
+    yaml[key]
+        .as_vec()
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|entry| entry.as_hash())
+                .filter_map(|entry| entry.iter().next())
+                .filter_map(|(name, definition)| Some((name.as_str()?, definition)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn render_args(page: &mut String, command: &Yaml) {
+    for (name, arg) in entries_of(command, "args") {
+        if arg["hidden"].as_bool().unwrap_or(false) {
+            continue;
+        }
+        let mut flags = Vec::new();
+        if let Some(short) = arg["short"].as_str() {
+            flags.push(format!("\\fB\\-{}\\fR", short));
+        }
+        if let Some(long) = arg["long"].as_str() {
+            flags.push(format!("\\fB\\-\\-{}\\fR", escape(long)));
+        }
+        let mut term = if flags.is_empty() {
+            format!("\\fI{}\\fR", escape(name))
+        } else {
+            flags.join(", ")
+        };
+        if !flags.is_empty() && arg["takes_value"].as_bool().unwrap_or(false) {
+            term.push_str(&format!(" \\fI{}\\fR", escape(&name.to_uppercase())));
+        }
+        page.push_str(&format!(".TP\n{}\n", term));
+        if let Some(help) = arg["help"].as_str() {
+            page.push_str(&format!("{}\n", escape(help)));
+        }
+        if let Some(values) = arg["possible_values"].as_vec() {
+            let values: Vec<_> = values.iter().filter_map(|v| v.as_str()).collect();
+            page.push_str(&format!(
+                ".br\nPossible values: {}\n",
+                escape(&values.join(", "))
+            ));
+        }
+        if let Some(default_value) = arg["default_value"].as_str() {
+            page.push_str(&format!(".br\nDefault: {}\n", escape(default_value)));
+        }
+        if let Some(env) = arg["env"].as_str() {
+            page.push_str(&format!(".br\nEnvironment: \\fB{}\\fR\n", escape(env)));
+        }
+    }
+}
+
+fn render_subcommands(page: &mut String, command: &Yaml, prefix: &str) {
+    for (name, subcommand) in entries_of(command, "subcommands") {
+        let full_name = if prefix.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{} {}", prefix, name)
+        };
+        page.push_str(&format!(".SS {}\n", escape(&full_name)));
+        if let Some(about) = subcommand["about"].as_str() {
+            page.push_str(&format!("{}\n", escape(about)));
+        }
+        render_args(page, subcommand);
+        render_subcommands(page, subcommand, &full_name);
+    }
+}
+
+/// Renders the man page of a CLI definition loaded with `clap::load_yaml!`
+pub fn render(cli: &Yaml, version: &str) -> String {
+    let name = cli["name"].as_str().unwrap_or_default();
+    let mut page = format!(
+        ".TH {} 1 \"\" \"{} {}\"\n",
+        escape(&name.to_uppercase()),
+        escape(name),
+        escape(version)
+    );
+    page.push_str(&format!(
+        ".SH NAME\n{} \\- {}\n",
+        escape(name),
+        escape(cli["about"].as_str().unwrap_or_default())
+    ));
+    page.push_str(&format!(
+        ".SH SYNOPSIS\n\\fB{}\\fR [\\fIOPTIONS\\fR] \\fISUBCOMMAND\\fR\n",
+        escape(name)
+    ));
+    page.push_str(".SH OPTIONS\n");
+    render_args(&mut page, cli);
+    page.push_str(".SH SUBCOMMANDS\n");
+    render_subcommands(&mut page, cli, "");
+    page.push_str(".SH CONFIGURATION\nThe configuration file is a JSON object with the following keys, all optional:\n");
+    for (key, description) in CONFIG_OPTIONS {
+        page.push_str(&format!(
+            ".TP\n\\fB{}\\fR\n{}\n",
+            escape(key),
+            escape(description)
+        ));
+    }
+    page.push_str(&format!(
+        ".SH FILES\n.TP\n\\fI$XDG_CONFIG_HOME/{}\\fR\nDefault configuration file, see \\fB\\-\\-config\\fR\n",
+        escape(name)
+    ));
+    page.push_str(".SH EXIT STATUS\n");
+    let failures = crate::utils::Failure::ALL
+        .iter()
+        .map(|failure| (failure.exit_code(), failure.to_string()));
+    let exit_statuses = EXIT_STATUSES
+        .iter()
+        .map(|(code, description)| (*code, description.to_string()))
+        .chain(failures)
+        .chain(std::iter::once((
+            crate::deadline::DeadlineExceededError::EXIT_CODE,
+            "The --timeout deadline passed".to_owned(),
+        )))
+        .chain(std::iter::once((130, "Interrupted by SIGINT".to_owned())))
+        .chain(std::iter::once((143, "Interrupted by SIGTERM".to_owned())));
+    for (code, description) in exit_statuses {
+        page.push_str(&format!(".TP\n{}\n{}\n", code, escape(&description)));
+    }
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_render() {
+        let cli = clap::load_yaml!("cli.yml");
+        let page = render(cli, "1.0.0");
+        assert!(page.starts_with(".TH GIT\\-CREDENTIAL\\-KEEPASSXC 1"));
+        assert!(page.contains(".SS caller add\n"));
+        assert!(page.contains("\\fB\\-c\\fR, \\fB\\-\\-config\\fR \\fICONFIG\\fR\n"));
+        assert!(!page.contains("list\\-databases"));
+        assert!(page.contains(".TP\n2\n"));
+    }
+
+    #[test]
+    fn test_01_escape() {
+        assert_eq!(escape(".foo\n'bar"), "\\&.foo\n.br\n\\&'bar");
+        assert_eq!(escape("a\\b-c"), "a\\eb\\-c");
+    }
+}
@@ -0,0 +1,285 @@
+//! Locks the memory pages holding secrets, e.g. session keys and decrypted passwords, so that
+//! they're never written to swap. This is best effort, as the memory lock limit (`ulimit -l`) is
+//! often low for unprivileged processes.
+#[allow(unused_imports)]
+use crate::{debug, error, info, warn};
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Locked ranges and how many of them each page is shared by, as `mlock()` doesn't count
+#[derive(Default)]
+struct LockedPages {
+    ranges: HashSet<(usize, usize)>,
+    pages: HashMap<usize, usize>,
+}
+
+static LOCKED: Lazy<Mutex<LockedPages>> = Lazy::new(|| Mutex::new(LockedPages::default()));
+/// Failures are only logged once, as they usually all have the same reason
+static FAILURE_LOGGED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+fn page_size() -> usize {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+#[cfg(windows)]
+fn page_size() -> usize {
+    let mut info: winapi::um::sysinfoapi::SYSTEM_INFO = unsafe { std::mem::zeroed() };
+    unsafe { winapi::um::sysinfoapi::GetSystemInfo(&mut info) };
+    info.dwPageSize as usize
+}
+
+#[cfg(unix)]
+fn lock_page(page: usize, size: usize) -> io::Result<()> {
+    if unsafe { libc::mlock(page as *const libc::c_void, size) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn unlock_page(page: usize, size: usize) {
+    unsafe { libc::munlock(page as *const libc::c_void, size) };
+}
+
+#[cfg(windows)]
+fn lock_page(page: usize, size: usize) -> io::Result<()> {
+    if unsafe { winapi::um::memoryapi::VirtualLock(page as winapi::um::winnt::PVOID, size) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn unlock_page(page: usize, size: usize) {
+    unsafe { winapi::um::memoryapi::VirtualUnlock(page as winapi::um::winnt::PVOID, size) };
+}
+
+/// Start addresses of the pages a range spans
+fn pages_of(address: usize, len: usize, page_size: usize) -> impl Iterator<Item = usize> {
+    let first = address / page_size;
+    let last = (address + len - 1) / page_size;
+    (first..=last).map(move |page| page * page_size)
+}
+
+fn lock_range(address: usize, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let mut locked = match LOCKED.lock() {
+        Ok(locked) => locked,
+        Err(_) => return,
+    };
+    if !locked.ranges.insert((address, len)) {
+        return;
+    }
+    let page_size = page_size();
+    for page in pages_of(address, len, page_size) {
+        if let Some(count) = locked.pages.get_mut(&page) {
+            *count += 1;
+            continue;
+        }
+        match lock_page(page, page_size) {
+            Ok(_) => {
+                locked.pages.insert(page, 1);
+            }
+            Err(e) => {
+                if !FAILURE_LOGGED.swap(true, Ordering::Relaxed) {
+                    debug!(
+                        "Failed to lock memory of secrets, they may be swapped out: {}",
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn unlock_range(address: usize, len: usize) {
+    let mut locked = match LOCKED.lock() {
+        Ok(locked) => locked,
+        Err(_) => return,
+    };
+    // never locked, e.g. a clone
+    if !locked.ranges.remove(&(address, len)) {
+        return;
+    }
+    let page_size = page_size();
+    for page in pages_of(address, len, page_size) {
+        if let Some(count) = locked.pages.get_mut(&page) {
+            *count -= 1;
+            if *count == 0 {
+                locked.pages.remove(&page);
+                unlock_page(page, page_size);
+            }
+        }
+    }
+}
+
+/// Keeps the pages of a buffer in memory until `unlock()` is called with it
+pub fn lock(bytes: &[u8]) {
+    lock_range(bytes.as_ptr() as usize, bytes.len());
+}
+
+/// Releases a buffer locked by `lock()`, which must be done before it's freed
+pub fn unlock(bytes: &[u8]) {
+    unlock_range(bytes.as_ptr() as usize, bytes.len());
+}
+
+/// Overwrites all locked buffers with zeros, right before the process exits, e.g. when interrupted,
+/// as they won't be dropped then. Anything still using them afterwards reads zeros.
+pub fn wipe_all() {
+    let locked = match LOCKED.lock() {
+        Ok(locked) => locked,
+        Err(_) => return,
+    };
+    for (address, len) in &locked.ranges {
+        for offset in 0..*len {
+            unsafe { std::ptr::write_volatile((address + offset) as *mut u8, 0) };
+        }
+    }
+}
+
+/// Overwrites a buffer with zeros, e.g. before it's freed
+pub fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+}
+
+/// Same as `wipe()` for a string, which stays valid UTF-8
+pub fn wipe_string(string: &mut String) {
+    wipe(unsafe { string.as_bytes_mut() });
+    string.clear();
+}
+
+/// Locked buffer for output full of secrets, e.g. an export of a database, which is wiped instead
+/// of leaving copies behind when it grows or is dropped
+pub struct SecretBuffer(Vec<u8>);
+
+impl SecretBuffer {
+    const INITIAL_CAPACITY: usize = 64 * 1024;
+
+    fn with_capacity(capacity: usize) -> Self {
+        let buffer = Vec::with_capacity(capacity);
+        lock_range(buffer.as_ptr() as usize, buffer.capacity());
+        SecretBuffer(buffer)
+    }
+
+    /// Reads until the end, unlike `Read::read_to_end()` without reallocating
+    pub fn read_from<R: io::Read>(mut reader: R) -> io::Result<Self> {
+        let mut buffer = Self::with_capacity(Self::INITIAL_CAPACITY);
+        loop {
+            if buffer.0.len() == buffer.0.capacity() {
+                let mut grown = Self::with_capacity(buffer.0.capacity() * 2);
+                grown.0.extend_from_slice(&buffer.0);
+                buffer = grown;
+            }
+            let len = buffer.0.len();
+            let spare = buffer.0.capacity() - len;
+            // zeros, so that the uninitialised memory is never read
+            buffer.0.resize(len + spare, 0);
+            match reader.read(&mut buffer.0[len..]) {
+                Ok(0) => {
+                    buffer.0.truncate(len);
+                    return Ok(buffer);
+                }
+                Ok(read) => buffer.0.truncate(len + read),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => buffer.0.truncate(len),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for SecretBuffer {
+    fn drop(&mut self) {
+        let capacity = self.0.capacity();
+        self.0.resize(capacity, 0);
+        wipe(&mut self.0);
+        unlock_range(self.0.as_ptr() as usize, capacity);
+    }
+}
+
+/// Same as `lock()` for the memory of a value, e.g. a key
+pub fn lock_value<T>(value: &T) {
+    lock_range(value as *const T as usize, std::mem::size_of::<T>());
+}
+
+/// Same as `unlock()` for the memory of a value
+pub fn unlock_value<T>(value: &T) {
+    unlock_range(value as *const T as usize, std::mem::size_of::<T>());
+}
+
+/// Whether the memory of a value is locked, which can be checked after it's gone, e.g. after its
+/// thread exited
+#[cfg(test)]
+pub fn is_value_locked_at<T>(address: usize) -> bool {
+    LOCKED
+        .lock()
+        .unwrap()
+        .ranges
+        .contains(&(address, std::mem::size_of::<T>()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_lock_and_unlock() {
+        let page_size = page_size();
+        assert_eq!(pages_of(page_size - 1, 2, page_size).count(), 2);
+        assert_eq!(pages_of(page_size, page_size, page_size).count(), 1);
+
+        let first = vec![1u8; 16];
+        let second = first.clone();
+        lock(&first);
+        lock(&first);
+        lock(&second);
+        unlock(&first);
+        // unlocking again leaves the pages alone
+        unlock(&first);
+        assert!(LOCKED
+            .lock()
+            .unwrap()
+            .ranges
+            .contains(&(second.as_ptr() as usize, 16)));
+        unlock(&second);
+        // other tests may have locked buffers meanwhile
+        let locked = LOCKED.lock().unwrap();
+        assert!(!locked.ranges.contains(&(first.as_ptr() as usize, 16)));
+        assert!(!locked.ranges.contains(&(second.as_ptr() as usize, 16)));
+    }
+
+    #[test]
+    fn test_01_secret_buffer() {
+        let content: Vec<u8> = (0..SecretBuffer::INITIAL_CAPACITY * 3)
+            .map(|i| i as u8)
+            .collect();
+        let buffer = SecretBuffer::read_from(&content[..]).unwrap();
+        assert_eq!(buffer.as_slice(), &content[..]);
+        let address = buffer.as_slice().as_ptr() as usize;
+        assert!(LOCKED
+            .lock()
+            .unwrap()
+            .ranges
+            .iter()
+            .any(|(locked, _)| *locked == address));
+        drop(buffer);
+
+        let mut string = "secret".to_owned();
+        wipe_string(&mut string);
+        assert!(string.is_empty());
+    }
+}
@@ -0,0 +1,35 @@
+/// Key of registry settings in `.npmrc`, e.g. `//registry.npmjs.org/` for
+/// `https://registry.npmjs.org`
+pub fn npmrc_key(registry_url: &str) -> String {
+    let registry = match registry_url.find("://") {
+        Some(idx) => &registry_url[idx + 3..],
+        None => registry_url,
+    };
+    let mut key = format!("//{}", registry);
+    if !key.ends_with('/') {
+        key.push('/');
+    }
+    key
+}
+
+/// Auth token setting in `.npmrc` format, e.g. `//registry.npmjs.org/:_authToken=foo`
+pub fn npmrc_auth_token(registry_url: &str, token: &str) -> String {
+    format!("{}:_authToken={}", npmrc_key(registry_url), token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_npmrc_auth_token() {
+        assert_eq!(
+            npmrc_auth_token("https://registry.npmjs.org", "foo"),
+            "//registry.npmjs.org/:_authToken=foo"
+        );
+        assert_eq!(
+            npmrc_auth_token("https://npm.example.com/api/npm/", "foo"),
+            "//npm.example.com/api/npm/:_authToken=foo"
+        );
+    }
+}
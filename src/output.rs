@@ -0,0 +1,187 @@
+use crate::utils::url_host;
+use anyhow::{anyhow, Result};
+
+/// Formats a login as a netrc entry, e.g. `machine example.com login foo password bar`
+pub fn netrc_entry(url: &str, username: &str, password: &str) -> String {
+    let host = url_host(url).unwrap_or(url);
+    // netrc has no ports, but mind IPv6 addresses such as [::1]:8080
+    let host = match host.rfind(':') {
+        Some(idx) if !host[idx..].contains(']') => &host[..idx],
+        _ => host,
+    };
+    format!(
+        "machine {} login {} password {}",
+        host,
+        netrc_token(username),
+        netrc_token(password)
+    )
+}
+
+/// Quotes netrc tokens with whitespace or quotes, which curl and most other tools understand
+fn netrc_token(token: &str) -> String {
+    if !token.is_empty() && !token.contains(|c: char| c.is_whitespace() || c == '"') {
+        return token.to_owned();
+    }
+    let mut quoted = String::with_capacity(token.len() + 2);
+    quoted.push('"');
+    for c in token.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Escapes text for XML elements and attributes
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats the `<servers>` section of Maven's `settings.xml` for `(id, username, password)`s
+pub fn maven_servers(servers: &[(&str, &str, &str)]) -> String {
+    let mut xml = "<servers>\n".to_owned();
+    for (id, username, password) in servers {
+        xml.push_str(&format!(
+            "  <server>\n    <id>{}</id>\n    <username>{}</username>\n    <password>{}</password>\n  </server>\n",
+            xml_escape(id),
+            xml_escape(username),
+            xml_escape(password)
+        ));
+    }
+    xml.push_str("</servers>\n");
+    xml
+}
+
+/// Escapes keys and values of Java properties files, e.g. `gradle.properties`
+fn properties_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            '\\' | '=' | ':' | '#' | '!' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' ' if i == 0 => escaped.push_str("\\ "),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats the `<id>Username` and `<id>Password` properties Gradle reads the `PasswordCredentials`
+/// of a repository named `<id>` from
+pub fn gradle_properties(servers: &[(&str, &str, &str)]) -> String {
+    let mut properties = String::new();
+    for (id, username, password) in servers {
+        properties.push_str(&format!(
+            "{}={}\n{}={}\n",
+            properties_escape(&format!("{}Username", id)),
+            properties_escape(username),
+            properties_escape(&format!("{}Password", id)),
+            properties_escape(password)
+        ));
+    }
+    properties
+}
+
+/// Version of the documents printed with `--json`, bumped on incompatible changes only
+pub const JSON_VERSION: u32 = 1;
+
+/// Formats a JSON object printed with `--json`, tagged with its version
+pub fn json_document(mut document: serde_json::Value) -> String {
+    if let Some(object) = document.as_object_mut() {
+        object.insert("version".to_owned(), JSON_VERSION.into());
+    }
+    document.to_string()
+}
+
+/// Formats `export NAME='value'` lines for `eval` in POSIX shells
+pub fn shell_exports(variables: &[(&str, &str)]) -> Result<String> {
+    let mut lines = String::new();
+    for (name, value) in variables {
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(anyhow!("Invalid shell variable name {}", name));
+        }
+        lines.push_str(&format!(
+            "export {}='{}'\n",
+            name,
+            value.replace('\'', "'\\''")
+        ));
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_netrc_entry() {
+        assert_eq!(
+            netrc_entry("https://example.com:8443/repo.git", "foo", "bar"),
+            "machine example.com login foo password bar"
+        );
+        assert_eq!(
+            netrc_entry("https://[::1]/repo.git", "foo", "b a\"r"),
+            "machine [::1] login foo password \"b a\\\"r\""
+        );
+    }
+
+    #[test]
+    fn test_01_shell_exports() {
+        assert_eq!(
+            shell_exports(&[("GIT_USERNAME", "foo"), ("GIT_PASSWORD", "it's")]).unwrap(),
+            "export GIT_USERNAME='foo'\nexport GIT_PASSWORD='it'\\''s'\n"
+        );
+        assert!(shell_exports(&[("1FOO", "bar")]).is_err());
+        assert!(shell_exports(&[("FOO;rm", "bar")]).is_err());
+    }
+
+    #[test]
+    fn test_02_json_document() {
+        let document = json_document(serde_json::json!({ "removed": 2 }));
+        let document: serde_json::Value = serde_json::from_str(&document).unwrap();
+        assert_eq!(document["version"], JSON_VERSION);
+        assert_eq!(document["removed"], 2);
+    }
+
+    #[test]
+    fn test_03_maven_servers() {
+        assert_eq!(
+            maven_servers(&[("central", "foo", "b<a>&r")]),
+            "<servers>\n  <server>\n    <id>central</id>\n    <username>foo</username>\n    <password>b&lt;a&gt;&amp;r</password>\n  </server>\n</servers>\n"
+        );
+    }
+
+    #[test]
+    fn test_04_gradle_properties() {
+        assert_eq!(
+            gradle_properties(&[("myRepo", "foo", " b=a\\r")]),
+            "myRepoUsername=foo\nmyRepoPassword=\\ b\\=a\\\\r\n"
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use crate::utils::{read_state_file, write_state_file};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// UUIDs of the entries created by `store`, persisted next to the configuration file since
+/// KeePassXC doesn't allow tagging entries with custom fields
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct OwnedEntries {
+    #[serde(default)]
+    uuids: BTreeSet<String>,
+}
+
+impl OwnedEntries {
+    pub fn read_from<T: AsRef<Path>>(state_path: T) -> Result<Self> {
+        read_state_file(state_path)
+    }
+
+    pub fn write_to<T: AsRef<Path>>(&self, state_path: T) -> Result<()> {
+        write_state_file(self, state_path)
+    }
+
+    pub fn insert<T: Into<String>>(&mut self, uuid: T) {
+        self.uuids.insert(uuid.into());
+    }
+
+    pub fn remove(&mut self, uuid: &str) {
+        self.uuids.remove(uuid);
+    }
+
+    pub fn contains(&self, uuid: &str) -> bool {
+        self.uuids.contains(uuid)
+    }
+}
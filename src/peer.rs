@@ -0,0 +1,134 @@
+//! Credentials of the process on the other end of a Unix socket, which the daemon and `serve`
+//! verify callers by, as their own parent process is whoever started them rather than Git
+use anyhow::{anyhow, Result};
+use std::cell::Cell;
+use std::os::unix::net::UnixStream;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeerCredentials {
+    pub pid: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+thread_local! {
+    static PEER: Cell<Option<PeerCredentials>> = const { Cell::new(None) };
+}
+
+/// Credentials the kernel recorded for the peer when it connected
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn peer_credentials(stream: &UnixStream) -> Result<PeerCredentials> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut ucred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut ucred as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result != 0 || ucred.pid <= 0 {
+        return Err(anyhow!(
+            "Failed to get peer credentials, {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(PeerCredentials {
+        pid: ucred.pid as u32,
+        uid: ucred.uid,
+        gid: ucred.gid,
+    })
+}
+
+/// Credentials the kernel recorded for the peer when it connected
+#[cfg(target_os = "macos")]
+pub fn peer_credentials(stream: &UnixStream) -> Result<PeerCredentials> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = stream.as_raw_fd();
+    let (mut uid, mut gid) = (0, 0);
+    let mut pid: libc::pid_t = 0;
+    let mut len = std::mem::size_of::<libc::pid_t>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getpeereid(fd, &mut uid, &mut gid) == 0
+            && libc::getsockopt(
+                fd,
+                libc::SOL_LOCAL,
+                libc::LOCAL_PEERPID,
+                &mut pid as *mut _ as *mut libc::c_void,
+                &mut len,
+            ) == 0
+    };
+    if !result || pid <= 0 {
+        return Err(anyhow!(
+            "Failed to get peer credentials, {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(PeerCredentials {
+        pid: pid as u32,
+        uid,
+        gid,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+pub fn peer_credentials(_stream: &UnixStream) -> Result<PeerCredentials> {
+    Err(anyhow!(
+        "Verifying callers over sockets is not supported on this platform"
+    ))
+}
+
+/// Has callers verified as the peer instead of the parent process until dropped
+pub struct PeerGuard(());
+
+impl PeerGuard {
+    pub fn set(peer: PeerCredentials) -> Self {
+        PEER.with(|current| current.set(Some(peer)));
+        PeerGuard(())
+    }
+}
+
+impl Drop for PeerGuard {
+    fn drop(&mut self) {
+        PEER.with(|current| current.set(None));
+    }
+}
+
+/// Peer of the request being handled, if any
+pub fn current_peer() -> Option<PeerCredentials> {
+    PEER.with(|current| current.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_peer_guard() {
+        let peer = PeerCredentials {
+            pid: 1,
+            uid: 1000,
+            gid: 1000,
+        };
+        assert_eq!(current_peer(), None);
+        {
+            let _guard = PeerGuard::set(peer);
+            assert_eq!(current_peer(), Some(peer));
+        }
+        assert_eq!(current_peer(), None);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn test_01_peer_credentials() {
+        let (stream, _) = UnixStream::pair().unwrap();
+        let peer = peer_credentials(&stream).unwrap();
+        assert_eq!(peer.pid, std::process::id());
+        assert_eq!(peer.uid, unsafe { libc::getuid() });
+    }
+}
@@ -0,0 +1,19 @@
+/// Escapes `%`, CR and LF in Assuan data lines
+pub fn percent_encode(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Error sent when there's no PIN to return, i.e. `GPG_ERR_CANCELED` from pinentry
+pub const ERR_CANCELED: &str = "ERR 83886179 Operation cancelled <Pinentry>";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_percent_encoding() {
+        assert_eq!(percent_encode("100%\n"), "100%25%0A");
+    }
+}
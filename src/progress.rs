@@ -0,0 +1,93 @@
+//! Spinner on stderr while waiting for the user, e.g. to approve access in KeePassXC, so that the
+//! helper doesn't look frozen
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Only the outermost wait is shown, e.g. the unlock wait rather than its requests
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Nothing is shown for waits shorter than this, i.e. without a dialog
+const DELAY: Duration = Duration::from_millis(750);
+/// After this, a hint to look for the dialog is added
+const HINT_AFTER: Duration = Duration::from_secs(5);
+const FRAMES: &[char] = &['|', '/', '-', '\\'];
+
+/// Shows spinners from now on, which should only be done if stderr is a terminal
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Spinner shown until dropped
+pub struct Waiting {
+    done: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        let (lock, condvar) = &*self.done;
+        if let Ok(mut done) = lock.lock() {
+            *done = true;
+            condvar.notify_one();
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        ACTIVE.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Shows `message` with a spinner if the wait takes a while, `None` if spinners are disabled or
+/// another one is shown already
+pub fn waiting(message: &'static str, hint: &'static str) -> Option<Waiting> {
+    if !ENABLED.load(Ordering::Relaxed) || ACTIVE.swap(true, Ordering::Relaxed) {
+        return None;
+    }
+    let done = Arc::new((Mutex::new(false), Condvar::new()));
+    let thread_done = Arc::clone(&done);
+    let handle = thread::spawn(move || {
+        let (lock, condvar) = &*thread_done;
+        let started = Instant::now();
+        let mut timeout = DELAY;
+        let mut shown = false;
+        let mut frame = 0;
+        let mut done = match lock.lock() {
+            Ok(done) => done,
+            Err(_) => return,
+        };
+        loop {
+            done = match condvar.wait_timeout_while(done, timeout, |done| !*done) {
+                Ok((done, _)) => done,
+                Err(_) => return,
+            };
+            if *done {
+                break;
+            }
+            let elapsed = started.elapsed();
+            let mut line = format!(
+                "\r\x1b[K{} {}… ({}s)",
+                FRAMES[frame % FRAMES.len()],
+                message,
+                elapsed.as_secs()
+            );
+            if elapsed >= HINT_AFTER {
+                line.push_str(&format!(" {}", hint));
+            }
+            let _ = io::stderr().write_all(line.as_bytes());
+            shown = true;
+            frame += 1;
+            timeout = Duration::from_millis(150);
+        }
+        if shown {
+            let _ = io::stderr().write_all(b"\r\x1b[K");
+        }
+    });
+    Some(Waiting {
+        done,
+        handle: Some(handle),
+    })
+}
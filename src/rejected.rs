@@ -0,0 +1,108 @@
+use crate::utils::{read_state_file, url_host, write_state_file};
+use anyhow::Result;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A password Git reported as rejected, kept as a salted hash rather than as is
+#[derive(Serialize, Deserialize, Debug)]
+struct Rejection {
+    salt: String,
+    hash: String,
+    until: u64,
+}
+
+impl Rejection {
+    fn hash(salt: &str, password: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(salt.as_bytes());
+        hasher.update(password.as_bytes());
+        base64::encode(hasher.finalize())
+    }
+
+    fn matches(&self, password: &str) -> bool {
+        Self::hash(&self.salt, password) == self.hash
+    }
+}
+
+/// Logins Git has erased recently, persisted next to the configuration file so that `get` doesn't
+/// keep returning a password the server has just rejected
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct RejectedLogins {
+    #[serde(default)]
+    rejected: HashMap<String, Vec<Rejection>>,
+}
+
+impl RejectedLogins {
+    pub fn read_from<T: AsRef<Path>>(state_path: T) -> Result<Self> {
+        read_state_file(state_path)
+    }
+
+    pub fn write_to<T: AsRef<Path>>(&self, state_path: T) -> Result<()> {
+        write_state_file(self, state_path)
+    }
+
+    fn key(username: &str, url: &str) -> Option<String> {
+        url_host(url).map(|host| format!("{}@{}", username, host))
+    }
+
+    /// Remembers a rejected password until `until`, and drops those that have run out
+    pub fn record(&mut self, username: &str, url: &str, password: &str, now: u64, until: u64) {
+        self.expire(now);
+        if let Some(key) = Self::key(username, url) {
+            let salt = base64::encode(rand::thread_rng().gen::<[u8; 16]>());
+            let hash = Rejection::hash(&salt, password);
+            self.rejected
+                .entry(key)
+                .or_default()
+                .push(Rejection { salt, hash, until });
+        }
+    }
+
+    /// Whether the password of a login was rejected and that's still remembered
+    pub fn is_rejected(&self, username: &str, url: &str, password: &str, now: u64) -> bool {
+        Self::key(username, url)
+            .and_then(|key| self.rejected.get(&key))
+            .map(|rejections| {
+                rejections
+                    .iter()
+                    .any(|rejection| rejection.until > now && rejection.matches(password))
+            })
+            .unwrap_or(false)
+    }
+
+    /// Forgets the rejections of a login, e.g. after Git has reported a new password works.
+    /// Returns whether there were any.
+    pub fn forget(&mut self, username: &str, url: &str) -> bool {
+        Self::key(username, url)
+            .and_then(|key| self.rejected.remove(&key))
+            .is_some()
+    }
+
+    fn expire(&mut self, now: u64) {
+        for rejections in self.rejected.values_mut() {
+            rejections.retain(|rejection| rejection.until > now);
+        }
+        self.rejected.retain(|_, rejections| !rejections.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_rejected_passwords() {
+        let mut state = RejectedLogins::default();
+        state.record("foo", "https://example.com/repo.git", "bad", 0, 60);
+        assert!(state.is_rejected("foo", "https://example.com/other.git", "bad", 30));
+        assert!(!state.is_rejected("foo", "https://example.com/repo.git", "good", 30));
+        assert!(!state.is_rejected("bar", "https://example.com/repo.git", "bad", 30));
+        assert!(!state.is_rejected("foo", "https://example.com/repo.git", "bad", 60));
+        assert!(!serde_json::to_string(&state).unwrap().contains("bad"));
+        assert!(state.forget("foo", "https://example.com/repo.git"));
+        assert!(!state.is_rejected("foo", "https://example.com/repo.git", "bad", 30));
+    }
+}
@@ -0,0 +1,2510 @@
+//! Handling of the credential requests of Git, i.e. `get`, `store` and `erase`, from verifying the
+//! caller to filtering the logins KeePassXC returns, shared by the command line interface, its
+//! integrations and the C API
+use crate::audit::{self, AuditRecord};
+use crate::cli::UnlockOptions;
+use crate::config::{
+    self, Caller, Config, ConfigLock, Database, EraseMode, HashMismatch, KdbxFile, KdbxReader,
+    NotConfiguredError,
+};
+use crate::git::{self, GitCredentialMessage};
+use crate::keepassxc::{errors::*, messages::*, Group};
+use crate::lockout::LockoutState;
+use crate::logging::timed;
+use crate::ownership::OwnedEntries;
+#[cfg(unix)]
+use crate::peer;
+use crate::rejected::RejectedLogins;
+use crate::rotation::RotationState;
+use crate::session::{is_session_lost, retry_on_lost_session, start_session};
+use crate::transport::ConnectionLostError;
+use crate::utils::*;
+#[allow(unused_imports)]
+use crate::{debug, error, info, warn};
+use crate::{keepassxc_cli, progress};
+use anyhow::{anyhow, Result};
+use clap::ArgMatches;
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use sysinfo::{get_current_pid, Process, ProcessExt, System, SystemExt};
+
+const RECYCLE_BIN_GROUP_NAME: &str = "Recycle Bin";
+
+fn read_git_request(input: &mut dyn Read) -> Result<(GitCredentialMessage, String)> {
+    // read credential request
+    let git_req = {
+        let mut git_req_string = String::with_capacity(256);
+        input.read_to_string(&mut git_req_string)?;
+        GitCredentialMessage::from_str(&git_req_string)?
+    };
+    debug!("Git credential request: {:?}", git_req);
+    let url = {
+        if let Some(ref url_string) = git_req.url {
+            url_string.clone()
+        } else {
+            if git_req.protocol.is_none() || git_req.host.is_none() {
+                return Err(anyhow!(
+                    "Protocol and host are both required when URL is not provided"
+                ));
+            }
+            format!(
+                "{}://{}/{}",
+                git_req.protocol.clone().unwrap(),
+                git_req.host.clone().unwrap(),
+                git_req.path.clone().unwrap_or_else(|| "".to_owned())
+            )
+        }
+    };
+    Ok((git_req, url))
+}
+
+/// Outcome of test-associate against a database
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Association {
+    Authenticated,
+    Rejected,
+    Locked,
+    /// The session is gone, e.g. as KeePassXC restarted
+    Lost,
+}
+
+pub(crate) fn test_associate(
+    database: &Database,
+    client_id: &str,
+    trigger_unlock: bool,
+) -> Association {
+    let taso_req = TestAssociateRequest::new(database.id.as_str(), database.pkey.as_str());
+    // trigger unlock if command line argument is given
+    let taso_resp = taso_req.send(client_id, trigger_unlock);
+    if let Ok(ref taso_resp) = taso_resp {
+        let success: bool = taso_resp
+            .success
+            .clone()
+            .unwrap_or_else(|| KeePassBoolean(false))
+            .into();
+        if success {
+            return Association::Authenticated;
+        }
+    }
+    if taso_resp.as_ref().err().is_some_and(is_session_lost) {
+        return Association::Lost;
+    }
+    warn!(
+        "Failed to authenticate against database {} using stored key",
+        database.id
+    );
+    let database_locked = taso_resp
+        .err()
+        .as_ref()
+        .and_then(|e| e.downcast_ref::<KeePassError>())
+        .map(|keepass_error| keepass_error.is_database_locked())
+        .unwrap_or(false);
+    if database_locked {
+        Association::Locked
+    } else {
+        Association::Rejected
+    }
+}
+
+/// Drops databases whose pinned hash differs from the one of the database KeePassXC has open, or
+/// only warns about them
+fn check_database_hashes(
+    databases: Vec<Database>,
+    client_id: &str,
+    on_mismatch: HashMismatch,
+) -> Result<Vec<Database>> {
+    if databases.iter().all(|database| database.hash.is_none()) {
+        return Ok(databases);
+    }
+    // test-associate only succeeds for the active database, so that's the one answering
+    let hash = match GetDatabaseHashRequest::new().send(client_id, false)?.hash {
+        Some(hash) => hash,
+        None => return Ok(databases),
+    };
+    Ok(databases
+        .into_iter()
+        .filter(|database| match database.hash {
+            Some(ref pinned) if *pinned != hash => match on_mismatch {
+                HashMismatch::Refuse => {
+                    error!(
+                        "Database {} is now answered for by another database (hash {} instead of {}), skipping it (run configure or reassociate with the right database open to update it)",
+                        database.id, hash, pinned
+                    );
+                    false
+                }
+                HashMismatch::Warn => {
+                    warn!(
+                        "Database {} is now answered for by another database (hash {} instead of {})",
+                        database.id, hash, pinned
+                    );
+                    true
+                }
+            },
+            _ => true,
+        })
+        .collect())
+}
+
+pub(crate) fn associated_databases<T: AsRef<str>>(
+    candidates: Vec<Database>,
+    client_id: T,
+    unlock_options: &Option<UnlockOptions>,
+    on_hash_mismatch: HashMismatch,
+) -> Result<Vec<Database>> {
+    // KeePassXC answers the requests on a connection one by one, and more connections would need
+    // their own key exchanges, so they aren't sent concurrently. Instead, all databases are tested
+    // first, then the locked ones are waited for together rather than one after another
+    let mut associations: Vec<_> = candidates
+        .iter()
+        .map(|db| test_associate(db, client_id.as_ref(), unlock_options.is_some()))
+        .collect();
+    if let Some(unlock_options) = unlock_options
+        .as_ref()
+        .filter(|_| associations.contains(&Association::Locked))
+    {
+        timed("Database unlock", || {
+            let _waiting = progress::waiting(
+                "Waiting for the database to be unlocked in KeePassXC",
+                "(or run without --unlock to fail right away)",
+            );
+            let mut remain_retries = unlock_options.max_retries;
+            // loop get-databasehash until unlocked
+            while associations.contains(&Association::Locked)
+                && (remain_retries > 0 || unlock_options.max_retries == 0)
+            {
+                warn!(
+                    "{} database(s) locked, gonna retry in {}ms (Remaining: {})",
+                    associations
+                        .iter()
+                        .filter(|association| **association == Association::Locked)
+                        .count(),
+                    unlock_options.interval,
+                    remain_retries
+                );
+                thread::sleep(Duration::from_millis(unlock_options.interval));
+                if unlock_options.max_retries != 0 {
+                    remain_retries -= 1;
+                }
+
+                let gh_req = GetDatabaseHashRequest::new();
+                if gh_req.send(client_id.as_ref(), false).is_err() {
+                    continue;
+                }
+                info!("Database is unlocked");
+                for (db, association) in candidates.iter().zip(associations.iter_mut()) {
+                    if *association == Association::Locked {
+                        *association = test_associate(db, client_id.as_ref(), true);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+    }
+    if associations.contains(&Association::Lost) {
+        return Err(ConnectionLostError.into());
+    }
+    let any_locked = associations.contains(&Association::Locked);
+    // in the configured order, as the first one is used for new logins
+    let databases: Vec<_> = candidates
+        .into_iter()
+        .zip(associations)
+        .filter(|(_, association)| *association == Association::Authenticated)
+        .map(|(db, _)| db)
+        .collect();
+    let databases = check_database_hashes(databases, client_id.as_ref(), on_hash_mismatch)?;
+    if databases.is_empty() {
+        let failure = if any_locked {
+            Failure::DatabaseLocked
+        } else {
+            Failure::AssociationInvalid
+        };
+        Err(anyhow!("No valid database associations found in configuration file").context(failure))
+    } else {
+        info!(
+            "Successfully authenticated against {} database(s)",
+            databases.len()
+        );
+        Ok(databases)
+    }
+}
+
+/// Database profile with the current path of its group, looked up by UUID, so that new logins
+/// don't end up in a group with the old name after it's renamed or moved in KeePassXC. The
+/// configuration file is updated as well
+fn refresh_database_group(config_path: &Path, client_id: &str, database: &Database) -> Database {
+    let mut database = database.clone();
+    if database.group_uuid.is_empty() {
+        return database;
+    }
+    let gdg_resp = match GetDatabaseGroupsRequest::new().send(client_id, false) {
+        Ok(gdg_resp) => gdg_resp,
+        Err(e) => {
+            warn!("Failed to get groups of the database, {}", e);
+            return database;
+        }
+    };
+    // KeePassXC only returns the groups of the active database
+    let group_path = match Group::path_of(gdg_resp.get_groups(), &database.group_uuid) {
+        Some(group_path) => group_path,
+        None => {
+            info!(
+                "Group {} of database {} not found in the active database",
+                database.group, database.id
+            );
+            return database;
+        }
+    };
+    if group_path == database.group {
+        return database;
+    }
+    info!(
+        "Group {} of database {} has been renamed or moved to {}",
+        database.group, database.id, group_path
+    );
+    database.group = group_path;
+    let save = || -> Result<()> {
+        let _lock = ConfigLock::acquire(config_path)?;
+        let mut config_file = Config::read_from(config_path)?;
+        let (index, encrypted) = config_file
+            .list_databases()?
+            .into_iter()
+            .enumerate()
+            .find(|(_, (profile, _))| profile.id == database.id)
+            .map(|(index, (_, encrypted))| (index, encrypted))
+            .ok_or_else(|| anyhow!("Database profile {} not found", database.id))?;
+        config_file.replace_database(index, database.clone(), encrypted)?;
+        config_file.write_to(config_path)
+    };
+    if let Err(e) = save() {
+        warn!(
+            "Failed to update the group of database {} in the configuration, {}",
+            database.id, e
+        );
+    }
+    database
+}
+
+pub(crate) fn get_current_and_parent_process(system: &System) -> Result<(&Process, &Process)> {
+    #[cfg(unix)]
+    if let Some(peer) = peer::current_peer() {
+        return get_peer_and_caller_process(system, peer);
+    }
+    let pid = get_current_pid().map_err(|s| anyhow!("Failed to retrieve current PID: {}", s))?;
+    info!("PID: {}", pid);
+    let proc = system
+        .get_process(pid)
+        .ok_or_else(|| anyhow!("Failed to retrieve information of current process"))?;
+    let ppid = proc
+        .parent()
+        .ok_or_else(|| anyhow!("Failed to retrieve parent PID"))?;
+    info!("PPID: {}", ppid);
+    let pproc = system
+        .get_process(ppid)
+        .ok_or_else(|| anyhow!("Failed to retrieve parent process information"))?;
+    Ok((proc, pproc))
+}
+
+/// Returns the process on the other end of the socket a request came in on, along with the caller
+/// it's verified as: the parent of the peer if it's another instance of this program proxying a
+/// request of Git, or the peer itself otherwise, e.g. `git credential-cache`
+#[cfg(unix)]
+fn get_peer_and_caller_process(
+    system: &System,
+    peer: peer::PeerCredentials,
+) -> Result<(&Process, &Process)> {
+    info!("Peer PID: {}", peer.pid);
+    let proc = system
+        .get_process(peer.pid as _)
+        .ok_or_else(|| anyhow!("Failed to retrieve peer process information"))?;
+    // the PID may have been reused since the peer connected
+    if proc.uid != peer.uid || proc.gid != peer.gid {
+        return Err(anyhow!("Peer process has changed since it connected"));
+    }
+    let current_exe = std::env::current_exe()?;
+    if !same_executable(&current_exe, proc.exe()) {
+        return Ok((proc, proc));
+    }
+    let ppid = proc
+        .parent()
+        .ok_or_else(|| anyhow!("Failed to retrieve parent PID of peer"))?;
+    info!("Peer PPID: {}", ppid);
+    let pproc = system
+        .get_process(ppid)
+        .ok_or_else(|| anyhow!("Failed to retrieve parent process information of peer"))?;
+    Ok((proc, pproc))
+}
+
+/// Like `match_caller`, but refuses to verify at all during a lockout, and starts one after too
+/// many failures if configured
+pub(crate) fn verify_caller<T: AsRef<Path>>(
+    config: &Config,
+    config_path: T,
+) -> Result<Option<(usize, PathBuf, Vec<Caller>)>> {
+    let policy = match config.get_lockout() {
+        Some(policy) => policy,
+        None => return match_caller(config),
+    };
+    let state_path = state_file_path(&config_path, "lockout");
+    let mut state = LockoutState::read_from(&state_path)?;
+    if let Some(remaining) = state.locked_for(unix_now()) {
+        error!(
+            "Caller verification is locked out after too many failures, try again in {}s",
+            remaining
+        );
+        return Err(
+            anyhow!("Too many rejected callers, locked out for {}s", remaining)
+                .context(Failure::CallerRejected),
+        );
+    }
+    let result = match_caller(config);
+    if result.is_err() {
+        if state.record_failure(policy, unix_now()) {
+            error!(
+                "Too many rejected callers within {}s, refusing all requests for {}s",
+                policy.window, policy.cooldown
+            );
+            #[cfg(feature = "notification")]
+            {
+                use notify_rust::Notification;
+                let notification = Notification::new()
+                    .summary("Credential requests locked out")
+                    .body(&format!(
+                        "Too many rejected callers, refusing all requests for {}s",
+                        policy.cooldown
+                    ))
+                    .show();
+                if let Err(e) = notification {
+                    warn!("Failed to show notification for lockout, {}", e);
+                }
+            }
+        }
+        state.write_to(&state_path)?;
+    }
+    result
+}
+
+/// Returns the PID and path of the parent process along with the caller profiles it matches, or
+/// `None` if caller verification is disabled
+fn match_caller(config: &Config) -> Result<Option<(usize, PathBuf, Vec<Caller>)>> {
+    if config.count_callers() == 0
+        && (cfg!(not(feature = "strict-caller")) || config.count_databases() == 0)
+    {
+        info!(
+            "Caller verification skipped as no caller profiles defined and strict-caller disabled"
+        );
+        return Ok(None);
+    }
+    let system = System::new_all();
+    let (proc, pproc) = get_current_and_parent_process(&system)?;
+    let ppid = pproc.pid();
+    let callers = config.get_callers()?;
+    let hash_needed = callers.iter().any(|caller| caller.sha256.is_some());
+    let file_id_needed = callers
+        .iter()
+        .any(|caller| caller.device.is_some() || caller.inode.is_some());
+    let ancestry_needed = callers.iter().any(|caller| caller.ancestor);
+
+    // the immediate parent comes first, followed by its ancestors if any caller asks for them
+    let mut ancestry = Vec::new();
+    let mut current = Some(pproc);
+    while let Some(process) = current {
+        if ancestry
+            .iter()
+            .any(|(pid, _, _, _, _)| *pid == process.pid())
+        {
+            break;
+        }
+        let path = process.exe();
+        info!(
+            "{} path: {} (PID: {})",
+            if ancestry.is_empty() {
+                "Parent process"
+            } else {
+                "Ancestor process"
+            },
+            path.to_string_lossy(),
+            process.pid()
+        );
+        let canonical_path = path.canonicalize();
+        if canonical_path
+            .as_ref()
+            .map(|p| p != path)
+            .unwrap_or_else(|_| false)
+        {
+            info!(
+                "Canonical process path: {}",
+                canonical_path.as_ref().unwrap().to_string_lossy()
+            );
+        }
+        let sha256 = if hash_needed && !path.as_os_str().is_empty() {
+            let sha256 = sha256_file(path);
+            match sha256 {
+                Ok(ref sha256) => {
+                    info!("Process SHA-256: {}", sha256);
+                }
+                Err(ref e) => {
+                    warn!("Failed to hash process executable, {}", e);
+                }
+            }
+            sha256.ok()
+        } else {
+            None
+        };
+        let file_id = if file_id_needed {
+            // under Linux, stat the image that is actually running rather than whatever is at its
+            // path now
+            #[cfg(target_os = "linux")]
+            let file_id = get_file_id(format!("/proc/{}/exe", process.pid()));
+            #[cfg(not(target_os = "linux"))]
+            let file_id = get_file_id(path);
+            match file_id {
+                Ok((device, inode)) => {
+                    info!("Process executable device: {}, inode: {}", device, inode);
+                }
+                Err(ref e) => {
+                    warn!("Failed to stat process executable, {}", e);
+                }
+            }
+            file_id.ok()
+        } else {
+            None
+        };
+        ancestry.push((process.pid(), path, canonical_path, sha256, file_id));
+        if !ancestry_needed {
+            break;
+        }
+        current = process.parent().and_then(|pid| system.get_process(pid));
+    }
+
+    let matching_callers: Vec<_> = callers
+        .into_iter()
+        .filter(|caller| {
+            let canonical_caller = PathBuf::from(&caller.path).canonicalize();
+            if canonical_caller
+                .as_ref()
+                .map(|p| p.to_string_lossy() != caller.path)
+                .unwrap_or_else(|_| false)
+            {
+                info!(
+                    "Canonical caller path: {}",
+                    canonical_caller.as_ref().unwrap().to_string_lossy()
+                );
+            }
+            #[cfg(unix)]
+            {
+                if !caller.uid.map(|id| id == proc.uid).unwrap_or(true)
+                    || !caller.gid.map(|id| id == proc.gid).unwrap_or(true)
+                {
+                    return false;
+                }
+            }
+            #[cfg(windows)]
+            let _ = proc;
+            let depth = if caller.ancestor { ancestry.len() } else { 1 };
+            ancestry
+                .iter()
+                .take(depth)
+                .any(|(_, path, canonical_path, sha256, file_id)| {
+                    (same_executable(&caller.path, path)
+                        || (canonical_path.is_ok()
+                            && canonical_caller.is_ok()
+                            && same_executable(
+                                canonical_path.as_ref().unwrap(),
+                                canonical_caller.as_ref().unwrap(),
+                            )))
+                        && caller
+                            .sha256
+                            .as_ref()
+                            .map(|expected| Some(expected.to_lowercase()) == *sha256)
+                            .unwrap_or(true)
+                        && (caller.device.is_none() && caller.inode.is_none()
+                            || file_id.map(|(device, inode)| (Some(device), Some(inode)))
+                                == Some((caller.device, caller.inode)))
+                        && caller
+                            .code_requirement
+                            .as_ref()
+                            .map(|requirement| {
+                                satisfies_code_requirement(path, requirement).unwrap_or_else(|e| {
+                                    warn!("Failed to verify code signature, {}", e);
+                                    false
+                                })
+                            })
+                            .unwrap_or(true)
+                })
+        })
+        .collect();
+    if matching_callers.is_empty() {
+        Err(anyhow!("You are not allowed to use this program").context(Failure::CallerRejected))
+    } else {
+        Ok(Some((
+            ppid as usize,
+            pproc.exe().to_owned(),
+            matching_callers,
+        )))
+    }
+}
+
+pub(crate) fn verify_caller_url<T: AsRef<str>>(
+    verified_caller: &Option<(usize, PathBuf, Vec<Caller>)>,
+    url: T,
+) -> Result<()> {
+    if let Some((_, ref ppath, ref callers)) = verified_caller {
+        if !callers.iter().any(|caller| caller.allows_url(url.as_ref())) {
+            return Err(anyhow!(
+                "{} is not allowed to request credentials for {}",
+                ppath.to_string_lossy(),
+                url.as_ref()
+            )
+            .context(Failure::CallerRejected));
+        }
+    }
+    Ok(())
+}
+
+/// Sends a get-logins request for the given database, treating "no logins found" as an empty result
+pub(crate) fn request_logins<T: AsRef<str>>(
+    client_id: T,
+    url: T,
+    database: &Database,
+) -> Result<Vec<LoginEntry>> {
+    let id_key_pairs = [(database.id.as_str(), database.pkey.as_str())];
+
+    // ask KeePassXC for logins
+    let gl_req = GetLoginsRequest::new(url.as_ref(), &id_key_pairs[..]);
+    match gl_req.send(client_id.as_ref(), false) {
+        Ok(gl_resp) => Ok(gl_resp.entries),
+        Err(e)
+            if e.downcast_ref::<KeePassError>()
+                .map(|e| e.is_no_logins_found())
+                .unwrap_or(false) =>
+        {
+            Ok(Vec::new())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Fails with instructions to run `configure` if there are no databases, before connecting to
+/// KeePassXC for nothing
+pub(crate) fn ensure_configured(config: &Config, config_path: &Path) -> Result<()> {
+    if config.count_databases() == 0 && config.count_encrypted_databases() == 0 {
+        return Err(NotConfiguredError::new(Some(config_path), false).into());
+    }
+    Ok(())
+}
+
+pub(crate) fn get_logins_for<T: AsRef<str>>(
+    config: &Config,
+    client_id: T,
+    url: T,
+    unlock_options: &Option<UnlockOptions>,
+    include_recycled: bool,
+    allow_expired: bool,
+) -> Result<Vec<LoginEntry>> {
+    let mut databases = Vec::new();
+    let mut login_entries = Vec::new();
+    let mut association_error = None;
+    // plaintext databases first, so that encrypted profiles are only decrypted (e.g. with YubiKey)
+    // if the former don't have any logins
+    for encrypted in &[false, true] {
+        if !login_entries.is_empty() {
+            break;
+        }
+        let candidates = config.get_databases_in_tier_for(url.as_ref(), *encrypted)?;
+        if candidates.is_empty() {
+            continue;
+        }
+        let tier = match associated_databases(
+            candidates,
+            client_id.as_ref(),
+            unlock_options,
+            config.get_hash_mismatch(),
+        ) {
+            Ok(tier) => tier,
+            Err(e) => {
+                association_error = Some(e);
+                continue;
+            }
+        };
+        // databases are queried one by one, so that we know where each entry comes from
+        for database in &tier {
+            let entries = request_logins(&client_id, &url, database)?;
+            let count = entries.len();
+            let eligible: Vec<_> = entries
+                .into_iter()
+                .filter(|e| database.is_eligible(e))
+                .map(|mut e| {
+                    e.database_id = Some(database.id.clone());
+                    e
+                })
+                .collect();
+            if !database.required_fields.is_empty() {
+                info!(
+                    "{} of {} login(s) from database {} have the required field(s)",
+                    eligible.len(),
+                    count,
+                    database.id
+                );
+            }
+            login_entries.extend(eligible);
+        }
+        databases.extend(tier);
+    }
+    if databases.is_empty() {
+        return Err(association_error
+            .unwrap_or_else(|| anyhow!("No databases configured for {}", url.as_ref())));
+    }
+    filter_logins(
+        config,
+        url.as_ref(),
+        &databases,
+        login_entries,
+        include_recycled,
+        allow_expired,
+    )
+}
+
+/// Logins for a URL in a database file with the built-in reader
+#[cfg(feature = "kdbx")]
+fn read_kdbx_file(
+    kdbx: &KdbxFile,
+    password: Option<&str>,
+    url: &str,
+    include_recycled: bool,
+) -> Result<Vec<LoginEntry>> {
+    let file = timed("Database file decryption", || {
+        crate::kdbx::KdbxDatabase::open(kdbx.file_path(), password, kdbx.key_file_path())
+    })?;
+    Ok(file.logins_for(url, include_recycled, unix_now() as i64))
+}
+
+#[cfg(not(feature = "kdbx"))]
+fn read_kdbx_file(
+    _kdbx: &KdbxFile,
+    _password: Option<&str>,
+    _url: &str,
+    _include_recycled: bool,
+) -> Result<Vec<LoginEntry>> {
+    Err(anyhow!(
+        "Reading database files is not enabled in this build, enable the kdbx feature or set reader to keepassxc-cli"
+    ))
+}
+
+/// Logins for a URL from the files of the databases, for when KeePassXC isn't running, or `None`
+/// if none of the databases has a file
+fn kdbx_logins_for(
+    config: &Config,
+    url: &str,
+    include_recycled: bool,
+    allow_expired: bool,
+) -> Result<Option<Vec<LoginEntry>>> {
+    let mut databases = Vec::new();
+    let mut login_entries = Vec::new();
+    for encrypted in &[false, true] {
+        if !login_entries.is_empty() {
+            break;
+        }
+        for database in config.get_databases_in_tier_for(url, *encrypted)? {
+            let kdbx = match database.kdbx {
+                Some(ref kdbx) => kdbx,
+                None => continue,
+            };
+            let password = if !kdbx.password {
+                None
+            } else if let Some(ref password_env) = kdbx.password_env {
+                Some(std::env::var(password_env).map_err(|_| {
+                    anyhow!(
+                        "Password of database file {} is not set in {}",
+                        kdbx.path,
+                        password_env
+                    )
+                })?)
+            } else {
+                Some(prompt_on_terminal(
+                    &format!("Password of {}: ", kdbx.path),
+                    true,
+                )?)
+            };
+            let entries = match kdbx.reader {
+                KdbxReader::BuiltIn => {
+                    read_kdbx_file(kdbx, password.as_deref(), url, include_recycled)?
+                }
+                KdbxReader::KeepassxcCli => timed("Database file export", || {
+                    keepassxc_cli::logins_for(kdbx, password.as_deref(), url)
+                })?,
+            };
+            info!("Database file {} has {} login(s)", kdbx.path, entries.len());
+            login_entries.extend(entries.into_iter().filter(|e| database.is_eligible(e)).map(
+                |mut e| {
+                    e.database_id = Some(database.id.clone());
+                    e
+                },
+            ));
+            databases.push(database);
+        }
+    }
+    if databases.is_empty() {
+        return Ok(None);
+    }
+    filter_logins(
+        config,
+        url,
+        &databases,
+        login_entries,
+        include_recycled,
+        allow_expired,
+    )
+    .map(Some)
+}
+
+/// Drops logins `get` shouldn't return, e.g. in the recycle bin, from those of `databases`
+fn filter_logins(
+    config: &Config,
+    url: &str,
+    databases: &[Database],
+    login_entries: Vec<LoginEntry>,
+    include_recycled: bool,
+    allow_expired: bool,
+) -> Result<Vec<LoginEntry>> {
+    if login_entries.is_empty() {
+        return Err(Failure::NoLoginFound.into());
+    }
+
+    let mut login_entries: Vec<_> = login_entries
+        .into_iter()
+        .filter(|e| allow_expired || e.expired.is_none() || !e.expired.as_ref().unwrap().0)
+        .collect();
+    if !include_recycled {
+        // only the name of the immediate group is available, so entries in subgroups of the
+        // recycle bin slip through
+        let count = login_entries.len();
+        login_entries.retain(|e| e.group.as_deref() != Some(RECYCLE_BIN_GROUP_NAME));
+        if login_entries.len() < count {
+            info!(
+                "{} login(s) in the recycle bin were skipped",
+                count - login_entries.len()
+            );
+        }
+    }
+    if config.is_group_only() {
+        // KeePassXC only tells group names, not UUIDs
+        let mut groups: Vec<_> = databases.iter().flat_map(Database::group_names).collect();
+        if config.is_namespaces() {
+            groups.extend(NAMESPACES);
+        }
+        if let Some(group) = config
+            .get_rule_for(url)
+            .and_then(|rule| rule.group.as_ref())
+        {
+            groups.push(Group::leaf_name(group));
+        }
+        if login_entries.iter().any(|entry| entry.group.is_none()) {
+            warn!("KeePassXC didn't report entry groups, KeePassXC 2.6+ is required to filter by group");
+        }
+        login_entries.retain(|entry| {
+            entry
+                .group
+                .as_ref()
+                .map(|group| groups.contains(&group.as_str()))
+                .unwrap_or(false)
+        });
+        info!(
+            "{} login(s) left after filtering by group(s) {}",
+            login_entries.len(),
+            groups.join(", ")
+        );
+    }
+    Ok(login_entries)
+}
+
+fn filter_kph_logins(login_entries: &[LoginEntry]) -> (u32, Vec<&LoginEntry>) {
+    let mut kph_false = 0u32;
+    let login_entries: Vec<&LoginEntry> = login_entries
+        .iter()
+        .filter(|entry| {
+            if let Some(ref string_fields) = entry.string_fields {
+                let kph_false_fields = string_fields.iter().find(|m| {
+                    if let Some(v) = m.get("KPH: git") {
+                        v == "false"
+                    } else {
+                        false
+                    }
+                });
+                if kph_false_fields.is_some() {
+                    kph_false += 1;
+                }
+                kph_false_fields.is_none()
+            } else {
+                true
+            }
+        })
+        .collect();
+    (kph_false, login_entries)
+}
+
+/// Asks for a login on the terminal like Git does when no helper has one, returning it to Git and
+/// optionally storing it right away
+fn prompt_login(
+    config_path: &Path,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+    git_req: GitCredentialMessage,
+    url: &str,
+    username: Option<String>,
+    output: &mut dyn Write,
+) -> Result<LoginEntry> {
+    let username = match username {
+        Some(username) => username,
+        None => prompt_on_terminal(&format!("Username for '{}'", url), false)?,
+    };
+    let password = prompt_on_terminal(&format!("Password for '{}' ({})", url, username), true)?;
+    let mut git_resp = git_req;
+    git_resp.username = Some(username.clone());
+    git_resp.password = Some(password.clone());
+    output.write_all(git_resp.to_string().as_bytes())?;
+    let store_prompted = args.is_present("store-prompted")
+        || args
+            .subcommand_matches("get")
+            .map(|m| m.is_present("store-prompted"))
+            .unwrap_or(false);
+    if store_prompted {
+        let git_req = GitCredentialMessage {
+            url: Some(url.to_owned()),
+            username: Some(username.clone()),
+            password: Some(password.clone()),
+            ..Default::default()
+        };
+        if let Err(e) = store_login(
+            config_path,
+            unlock_options,
+            args,
+            &mut git_req.to_string().as_bytes(),
+        ) {
+            warn!("Failed to store the login entered, {}", e);
+        }
+    }
+    let mut login = LoginEntry {
+        login: username,
+        name: url_host(url).unwrap_or(url).to_owned(),
+        password: String::new(),
+        uuid: String::new(),
+        group: None,
+        string_fields: None,
+        expired: None,
+        database_id: None,
+    };
+    login.set_password(password);
+    Ok(login)
+}
+
+/// Generates a new password for a URL following the first password policy matching it, or with
+/// the generator of KeePassXC otherwise
+pub(crate) fn generate_password(config: &Config, url: &str, client_id: &str) -> Result<String> {
+    if let Some(policy) = config.get_password_policy_for(url) {
+        info!(
+            "Generating a password of {} characters for {} by policy",
+            policy.length, url
+        );
+        return policy.generate();
+    }
+    // KeePassXC 2.7+ shows its generator dialog and only answers once a password is applied
+    GeneratePasswordRequest::new()
+        .send(client_id, false)?
+        .into_password()
+        .filter(|password| !password.is_empty())
+        .ok_or_else(|| anyhow!("KeePassXC didn't generate a password"))
+}
+
+/// Replaces the password of an expired login with a generated or typed one, returning the login
+/// with the new password
+fn renew_login(
+    config: &Config,
+    config_path: &Path,
+    args: &ArgMatches,
+    url: &str,
+    login: &LoginEntry,
+    source: &str,
+) -> Result<LoginEntry> {
+    if config.is_read_only() || args.is_present("read-only") {
+        return Err(anyhow!(
+            "Login {} has expired, but can't be renewed in read-only mode",
+            login.name
+        ));
+    }
+    let database = login
+        .database_id
+        .as_ref()
+        .and_then(|id| config.find_database(id).ok())
+        .ok_or_else(|| anyhow!("Failed to find the database of login {}", login.name))?;
+    let (client_id, _, _) = start_session()?;
+    let password = match source {
+        "prompt" => prompt_on_terminal(
+            &format!("New password of expired login {}", login.name),
+            true,
+        )?,
+        _ => generate_password(config, url, &client_id)?,
+    };
+    let sl_resp = SetLoginRequest::new(
+        &normalise_url(url, config.is_store_host_only()),
+        &database.id,
+        &login.login,
+        &password,
+    )
+    .group(&Group::new(&database.group, &database.group_uuid))
+    .uuid(&login.uuid)
+    .send(&client_id, false)?;
+    if !sl_resp.success.as_ref().map(|s| s.0).unwrap_or(false) {
+        return Err(anyhow!(
+            "Failed to renew login {}: {}",
+            login.name,
+            sl_resp.error.unwrap_or_else(|| "N/A".to_owned())
+        ));
+    }
+    if config.get_rotate_after_days().is_some() {
+        let state_path = state_file_path(config_path, "rotation");
+        let mut state = RotationState::read_from(&state_path)?;
+        state.record(&login.login, url, unix_now());
+        state.write_to(&state_path)?;
+    }
+    warn!(
+        "Login {} renewed, but KeePassXC doesn't allow changing its expiry date, extend or clear it in KeePassXC so that it isn't skipped again",
+        login.name
+    );
+    let mut renewed = login.clone();
+    renewed.set_password(password);
+    Ok(renewed)
+}
+
+/// Whether the `KPH: git_proxy` string field marks a login as one of a proxy
+fn is_proxy_login(login_entry: &LoginEntry) -> bool {
+    login_entry
+        .string_fields
+        .as_ref()
+        .and_then(|fields| fields.iter().find_map(|m| m.get("KPH: git_proxy")))
+        .map(|v| v == "true")
+        .unwrap_or(false)
+}
+
+/// Drops logins whose `KPH: realm` string field names another realm than the one the server asked
+/// for, and puts those naming it first
+fn filter_realm_logins(login_entries: Vec<LoginEntry>, realm: &str) -> Vec<LoginEntry> {
+    let (mut matching, others): (Vec<_>, Vec<_>) = login_entries
+        .into_iter()
+        .filter(|entry| match string_field_of(entry, "realm") {
+            Some(entry_realm) if entry_realm != realm => {
+                info!("Login {} is for realm {}, skipped", entry.name, entry_realm);
+                false
+            }
+            _ => true,
+        })
+        .partition(|entry| string_field_of(entry, "realm").is_some());
+    if !matching.is_empty() {
+        info!("{} login(s) for realm {} preferred", matching.len(), realm);
+    }
+    matching.extend(others);
+    matching
+}
+
+/// Value of the `KPH: git_priority` string field, 0 if absent or invalid
+fn login_priority(login_entry: &LoginEntry) -> i64 {
+    login_entry
+        .string_fields
+        .as_ref()
+        .and_then(|fields| fields.iter().find_map(|m| m.get("KPH: git_priority")))
+        .and_then(|v| {
+            i64::from_str(v.trim())
+                .map_err(|_| {
+                    warn!("Invalid KPH: git_priority {} of {}", v, login_entry.name);
+                })
+                .ok()
+        })
+        .unwrap_or(0)
+}
+
+/// Logins for a URL in the order `get` picks them, after applying filters and preferring those of
+/// `username`
+pub(crate) fn matching_logins(
+    config: &Config,
+    args: &ArgMatches,
+    url: &str,
+    username: Option<&str>,
+    unlock_options: &Option<UnlockOptions>,
+) -> Result<Vec<LoginEntry>> {
+    // also given to the other subcommands looking up logins, e.g. get-secret
+    let get_args = args.subcommand().1;
+    let include_recycled = get_args
+        .map(|m| m.is_present("include-recycled"))
+        .unwrap_or(false);
+    // expired logins may be renewed instead
+    let allow_expired = get_args
+        .map(|m| m.is_present("allow-expired") || m.is_present("renew"))
+        .unwrap_or(false);
+    // start session
+    let login_entries = match start_session() {
+        Ok((client_id, _, _)) => get_logins_for(
+            config,
+            client_id.as_str(),
+            url,
+            unlock_options,
+            include_recycled,
+            allow_expired,
+        )?,
+        Err(e) if Failure::of(&e) == Some(Failure::SocketNotFound) => {
+            match kdbx_logins_for(config, url, include_recycled, allow_expired)? {
+                Some(login_entries) => {
+                    warn!("KeePassXC is not running, read the database files instead");
+                    login_entries
+                }
+                None => return Err(e),
+            }
+        }
+        Err(e) => return Err(e),
+    };
+    info!("KeePassXC return {} login(s)", login_entries.len());
+    let (kph_false, mut login_entries) = filter_kph_logins(&login_entries);
+    if kph_false > 0 {
+        info!("{} login(s) were labeled as KPH: git == false", kph_false);
+    }
+    // proxy logins are never returned for the hosts behind the proxy, even on the same host
+    let (proxy_entries, other_entries): (Vec<_>, Vec<_>) = login_entries
+        .into_iter()
+        .partition(|entry| is_proxy_login(entry));
+    login_entries = if config.get_proxy_for(url).is_none() || proxy_entries.is_empty() {
+        other_entries
+    } else {
+        info!("{} proxy login(s) preferred", proxy_entries.len());
+        proxy_entries
+    };
+    let filters = {
+        let mut filters = config.get_filters().clone();
+        if let Some(title) = get_args.and_then(|m| m.value_of("filter-title")) {
+            filters.title = Some(title.to_owned());
+        }
+        if let Some(username) = get_args.and_then(|m| m.value_of("filter-username")) {
+            filters.username = Some(username.to_owned());
+        }
+        filters
+    };
+    if !filters.is_empty() {
+        let mut filtered_entries = Vec::with_capacity(login_entries.len());
+        for entry in login_entries {
+            if filters.matches(entry)? {
+                filtered_entries.push(entry);
+            }
+        }
+        info!(
+            "{} login(s) left after applying filters",
+            filtered_entries.len()
+        );
+        login_entries = filtered_entries;
+    }
+    if let (true, Some(username)) = (login_entries.len() > 1, username) {
+        let login_entries_name_matches: Vec<_> = login_entries
+            .iter()
+            .filter(|entry| entry.login == username)
+            .cloned()
+            .collect();
+        if !login_entries_name_matches.is_empty() {
+            info!(
+                "{} login(s) left after filtering by username",
+                login_entries_name_matches.len()
+            );
+            login_entries = login_entries_name_matches;
+        }
+    }
+    if login_entries.len() > 1 {
+        // stable, so KeePassXC's order is kept among logins of the same priority
+        login_entries.sort_by_key(|entry| std::cmp::Reverse(login_priority(entry)));
+    }
+    Ok(login_entries.into_iter().cloned().collect())
+}
+
+pub fn get_logins<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<Option<LoginEntry>> {
+    let mut config = Config::read_from(config_path.as_ref())?;
+    let verified_caller = verify_caller(&config, config_path.as_ref())?;
+    // read credential request
+    let (git_req, url) = read_git_request(input)?;
+    let git_settings = git_settings_for(&mut config, &url)?;
+    // explicit username > username in URL > credential.<url>.username > username of host alias
+    let username = git_req
+        .username
+        .clone()
+        .or_else(|| url_username(&url).map(str::to_owned))
+        .or(git_settings.username)
+        .or_else(|| {
+            config
+                .get_alias_for(&url)
+                .and_then(|alias| alias.username.clone())
+        })
+        .or_else(|| {
+            config
+                .get_proxy_for(&url)
+                .and_then(|proxy| proxy.username.clone())
+        });
+    let url = config.rewrite_url(url);
+    verify_caller_url(&verified_caller, &url)?;
+    if let Some(deny_rule) = config.get_deny_rule_for(&url) {
+        info!("{} is denied by pattern {}", url, deny_rule.pattern);
+        if deny_rule.quit {
+            let git_resp = GitCredentialMessage {
+                quit: Some("1".to_owned()),
+                ..Default::default()
+            };
+            output.write_all(git_resp.to_string().as_bytes())?;
+        }
+        return Ok(None);
+    }
+
+    #[cfg(feature = "notification")]
+    {
+        if let Some((ppid, ref ppath, _)) = verified_caller {
+            use notify_rust::{Notification, Timeout};
+            let notification = Notification::new()
+                .summary("Credential request")
+                .body(&format!(
+                    "{} ({}) has requested credential for {}",
+                    ppath.file_name().unwrap_or_default().to_string_lossy(),
+                    ppid,
+                    url
+                ))
+                .timeout(Timeout::Milliseconds(6000))
+                .show();
+            if let Err(e) = notification {
+                warn!("Failed to show notification for credential request, {}", e);
+            }
+        }
+    }
+
+    ensure_configured(&config, config_path.as_ref())?;
+    let get_args = args.subcommand_matches("get");
+    // global argument, given either before or after the subcommand
+    let interactive_fallback = args.is_present("interactive-fallback")
+        || get_args
+            .map(|m| m.is_present("interactive-fallback"))
+            .unwrap_or(false);
+    let mut login_entries =
+        match matching_logins(&config, args, &url, username.as_deref(), unlock_options) {
+            Err(e) if interactive_fallback && Failure::of(&e) == Some(Failure::NoLoginFound) => {
+                Vec::new()
+            }
+            result => result?,
+        };
+    if config.get_remember_rejected().is_some() {
+        let rejected = RejectedLogins::read_from(state_file_path(&config_path, "rejected"))?;
+        let now = unix_now();
+        let mut remaining = Vec::with_capacity(login_entries.len());
+        for login in login_entries {
+            // Git rejected the password it was given, i.e. after mapping fields
+            let mapped = map_login_fields(&config, &url, &login)?;
+            if rejected.is_rejected(&login.login, &url, &mapped.password, now) {
+                warn!(
+                    "Login {} was rejected recently and hasn't changed since, skipped",
+                    login.name
+                );
+            } else {
+                remaining.push(login);
+            }
+        }
+        login_entries = remaining;
+    }
+    if let Some(realm) = git_req.basic_realm() {
+        login_entries = filter_realm_logins(login_entries, realm);
+    }
+    if let Some(namespace) = namespace_of(args).filter(|_| config.is_namespaces()) {
+        login_entries.retain(|entry| {
+            let entry_namespace = login_namespace(entry);
+            if entry_namespace != namespace {
+                info!(
+                    "Login {} is in namespace {}, skipped",
+                    entry.name, entry_namespace
+                );
+            }
+            entry_namespace == namespace
+        });
+    }
+    if let Some(group) = global_value_of(args, "group") {
+        // KeePassXC only tells the name of the immediate group
+        let group = Group::leaf_name(group);
+        login_entries.retain(|entry| entry.group.as_deref() == Some(group));
+        info!(
+            "{} login(s) left after filtering by group {}",
+            login_entries.len(),
+            group
+        );
+    }
+    let renew = get_args
+        .filter(|m| m.is_present("renew"))
+        .map(|m| m.value_of("renew").unwrap_or("generate"));
+    let is_expired = |login: &LoginEntry| login.expired.as_ref().map(|e| e.0).unwrap_or(false);
+    if renew.is_some()
+        && !get_args.unwrap().is_present("allow-expired")
+        && login_entries.iter().any(|login| !is_expired(login))
+    {
+        // only renew if there's nothing else
+        login_entries.retain(|login| !is_expired(login));
+    }
+    if login_entries.is_empty() && interactive_fallback {
+        info!("No matching login found, prompting for one");
+        return prompt_login(
+            config_path.as_ref(),
+            unlock_options,
+            args,
+            git_req,
+            &url,
+            username,
+            output,
+        )
+        .map(Some);
+    }
+    if login_entries.is_empty() {
+        return Err(Failure::NoLoginFound.into());
+    }
+    let require_unique =
+        config.is_require_unique() || is_global_flag_present(args, "require-unique");
+    if require_unique && login_entries.len() > 1 {
+        for entry in &login_entries {
+            error!("Ambiguous login: {} ({})", entry.name, entry.login);
+        }
+        return Err(AmbiguousLoginsError(login_entries.len()).into());
+    }
+    if login_entries.len() > 1 {
+        warn!("More than 1 matching logins found, only the first one will be returned");
+    }
+
+    let login = login_entries.first().unwrap();
+    if get_args.map(|m| m.is_present("check")).unwrap_or(false) {
+        // no password leaves the helper, so there's nothing to confirm or notify about
+        output.write_all(format!("{}\n", login_entries.len()).as_bytes())?;
+        return Ok(Some(login.clone()));
+    }
+    // KeePassXC only tells whether a login has expired, not when it expires
+    if login.expired.as_ref().map(|e| e.0).unwrap_or(false) {
+        warn!("Login {} has expired, consider rotating it", login.name);
+    }
+    if let Some(rotate_after_days) = config.get_rotate_after_days() {
+        let state = RotationState::read_from(state_file_path(&config_path, "rotation"))?;
+        if let Some(age) = state.age_in_days(&login.login, &url, unix_now()) {
+            if age >= rotate_after_days {
+                warn!(
+                    "Login {} was stored {} day(s) ago, consider rotating it",
+                    login.name, age
+                );
+            }
+        }
+    }
+    if config.is_confirm_access() {
+        let question = format!(
+            "Allow {} to get the password of {} for {}?",
+            describe_caller(&verified_caller),
+            login.login,
+            url
+        );
+        if !confirm_on_terminal(&question)? {
+            info!("Access to {} for {} declined", login.login, url);
+            return Ok(None);
+        }
+    }
+    let renewed;
+    let login = match renew {
+        Some(source) if is_expired(login) => {
+            renewed = renew_login(&config, config_path.as_ref(), args, &url, login, source)?;
+            &renewed
+        }
+        _ => login,
+    };
+    let login = &map_login_fields(&config, &url, login)?;
+    let mut git_resp = git_req;
+    if get_args
+        .map(|m| m.is_present("no-username"))
+        .unwrap_or(false)
+    {
+        // Git keeps the username it asked for, if any
+        git_resp.username = None;
+    } else if login.login.is_empty()
+        && url_host(&url)
+            .map(|host| host.eq_ignore_ascii_case(config::GITHUB_HOST))
+            .unwrap_or(false)
+    {
+        // GitHub takes any username with personal access tokens, and gh uses this one
+        git_resp.username = Some("x-access-token".to_owned());
+    } else {
+        git_resp.username = Some(git::escape_value(
+            &login.login,
+            &format!("Username of login {}", login.name),
+            config.get_unsafe_values(),
+            config.is_ascii_only(),
+        )?);
+    }
+    let password = git::escape_value(
+        &login.password,
+        &format!("Password of login {}", login.name),
+        config.get_unsafe_values(),
+        config.is_ascii_only(),
+    )?;
+    git_resp.password = Some(password.clone());
+
+    let mut git_resp = git_resp.to_string();
+    if let Some(get_args) = get_args.filter(|m| m.is_present("advanced-fields")) {
+        let allowed_fields: Vec<String> = match get_args.values_of("advanced-fields") {
+            Some(names) => names.map(str::to_owned).collect(),
+            None => config.get_advanced_fields().to_vec(),
+        };
+        // insert before the terminating blank line
+        git_resp.pop();
+        git_resp.push_str(&advanced_fields_of(login, &allowed_fields));
+        git_resp.push('\n');
+    }
+    if get_args
+        .map(|m| m.is_present("password-only"))
+        .unwrap_or(false)
+    {
+        git_resp = format!("{}\n", password);
+    }
+    output.write_all(git_resp.as_bytes())?;
+
+    #[cfg(feature = "notification")]
+    if config.is_notify_on_access() {
+        use notify_rust::{Notification, Timeout};
+        let notification = Notification::new()
+            .summary("Credential returned")
+            .body(&format!(
+                "{} got the password of {} for {}",
+                describe_caller(&verified_caller),
+                login.login,
+                url_host(&url).unwrap_or(&url)
+            ))
+            .timeout(Timeout::Milliseconds(6000))
+            .show();
+        if let Err(e) = notification {
+            warn!("Failed to show notification for credential access, {}", e);
+        }
+    }
+
+    Ok(Some((*login).clone()))
+}
+
+/// Reads the `credential.*` settings of Git's configuration for a URL with `git_config`, and
+/// applies them to the configuration
+fn git_settings_for(config: &mut Config, url: &str) -> Result<git::CredentialSettings> {
+    if !config.is_git_config() {
+        return Ok(Default::default());
+    }
+    let settings = git::CredentialSettings::for_url(url)?;
+    debug!("Git credential settings of {}: {:?}", url, settings);
+    config.apply_git_settings(url, &settings)?;
+    Ok(settings)
+}
+
+/// Applies the field mapping of a URL, if any, to a copy of a login
+fn map_login_fields(config: &Config, url: &str, login: &LoginEntry) -> Result<LoginEntry> {
+    let mut login = login.clone();
+    let mapping = match config.get_field_mapping_for(url) {
+        Some(mapping) => mapping,
+        None => return Ok(login),
+    };
+    if let Some(ref password_field) = mapping.password_field {
+        let password = string_field_of(&login, password_field)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Login {} has no KPH: {} string field to return as the password (field mapping {})",
+                    login.name,
+                    password_field,
+                    mapping.pattern
+                )
+            })?
+            .to_owned();
+        login.set_password(password);
+    }
+    if let Some(ref username) = mapping.username {
+        login.login = username.clone();
+    }
+    info!(
+        "Login {} mapped as per field mapping {}",
+        login.name, mapping.pattern
+    );
+    Ok(login)
+}
+
+/// Name and PID of the process requesting credentials, for the user
+fn describe_caller(verified_caller: &Option<(usize, PathBuf, Vec<Caller>)>) -> String {
+    match verified_caller {
+        Some((ppid, ref ppath, _)) => format!(
+            "{} ({})",
+            ppath.file_name().unwrap_or_default().to_string_lossy(),
+            ppid
+        ),
+        None => {
+            let system = System::new_all();
+            get_current_and_parent_process(&system)
+                .map(|(_, pproc)| format!("{} ({})", pproc.name(), pproc.pid()))
+                .unwrap_or_else(|_| "Unknown process".to_owned())
+        }
+    }
+}
+
+/// Title, username, password and `KPH: ` string fields of a login, as printed with `--json`
+pub(crate) fn login_json(login: &LoginEntry) -> serde_json::Value {
+    let fields: std::collections::BTreeMap<&str, &str> = login
+        .string_fields
+        .iter()
+        .flatten()
+        .flat_map(|field| field.iter())
+        .filter_map(|(key, value)| Some((key.strip_prefix("KPH: ")?, value.as_str())))
+        .collect();
+    serde_json::json!({
+        "title": login.name,
+        "username": login.login,
+        "password": login.password,
+        "fields": fields,
+    })
+}
+
+/// Integrations whose logins `namespaces` keeps apart, in subgroups of the same names
+const NAMESPACES: &[&str] = &["docker", "cargo", "npm", "kube", "aws", "maven"];
+
+/// Namespace of the integration handling the invocation, the other ones answering Git-style
+/// requests being `git`, or `None` for subcommands looking at all logins, e.g. `list`
+fn namespace_of(args: &ArgMatches) -> Option<&'static str> {
+    match args.subcommand_name()? {
+        "get" | "store" | "erase" | "serve" | "askpass" | "hg" | "gh" | "svn" => Some("git"),
+        name => NAMESPACES
+            .iter()
+            .find(|namespace| **namespace == name)
+            .copied(),
+    }
+}
+
+/// Namespace of a login, by its `KPH: namespace` field or the subgroup `store` created it in
+fn login_namespace(login_entry: &LoginEntry) -> &str {
+    string_field_of(login_entry, "namespace")
+        .or_else(|| {
+            login_entry
+                .group
+                .as_deref()
+                .filter(|group| NAMESPACES.contains(group))
+        })
+        .unwrap_or("git")
+}
+
+/// Value of the `KPH: <name>` string field of a login
+pub(crate) fn string_field_of<'a>(login_entry: &'a LoginEntry, name: &str) -> Option<&'a str> {
+    let key = format!("KPH: {}", name);
+    login_entry
+        .string_fields
+        .iter()
+        .flatten()
+        .find_map(|field| field.get(&key))
+        .map(String::as_str)
+}
+
+/// Formats `KPH: ` string fields of a login as `key=value` lines, e.g. `token=foo` for
+/// `KPH: token`, limited to `allowed_fields` unless it's empty
+fn advanced_fields_of(login_entry: &LoginEntry, allowed_fields: &[String]) -> String {
+    const RESERVED_KEYS: &[&str] = &[
+        "protocol", "host", "path", "username", "password", "url", "quit",
+    ];
+    let mut lines = String::new();
+    for field in login_entry.string_fields.iter().flatten() {
+        for (key, value) in field {
+            let name = match key.strip_prefix("KPH: ") {
+                Some(name) => name,
+                None => continue,
+            };
+            if !allowed_fields.is_empty() && !allowed_fields.iter().any(|f| f == name) {
+                continue;
+            }
+            if RESERVED_KEYS.contains(&name) || name.contains('=') || value.contains('\n') {
+                warn!("String field {} can't be returned", key);
+                continue;
+            }
+            lines.push_str(&format!("{}={}\n", name, value));
+        }
+    }
+    lines
+}
+
+pub fn store_login<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+    input: &mut dyn Read,
+) -> Result<()> {
+    store_login_in_group(config_path, unlock_options, args, input, None)
+}
+
+/// Stores a login, creating it in `group` (instead of the one of the rule or the database) if it's
+/// new
+pub fn store_login_in_group<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+    input: &mut dyn Read,
+    group: Option<&str>,
+) -> Result<()> {
+    let mut config = Config::read_from(config_path.as_ref())?;
+    // global argument, given either before or after the subcommand
+    let read_only = config.is_read_only()
+        || args.is_present("read-only")
+        || args
+            .subcommand_matches("store")
+            .map(|m| m.is_present("read-only"))
+            .unwrap_or(false);
+    if read_only {
+        info!("Read-only mode, not storing login");
+        let _ = read_git_request(input);
+        return Ok(());
+    }
+    let verified_caller = verify_caller(&config, config_path.as_ref())?;
+    // read credential request
+    let (mut git_req, url) = read_git_request(input)?;
+    let git_settings = git_settings_for(&mut config, &url)?;
+    if git_req.username.is_none() {
+        git_req.username = git_settings.username;
+    }
+    let url = config.rewrite_url(url);
+    verify_caller_url(&verified_caller, &url)?;
+    if let Some(deny_rule) = config.get_deny_rule_for(&url) {
+        info!("{} is denied by pattern {}", url, deny_rule.pattern);
+        return Ok(());
+    }
+    if let (Some(_), Some(username)) = (config.get_remember_rejected(), &git_req.username) {
+        // Git only stores logins that worked
+        let state_path = state_file_path(&config_path, "rejected");
+        let mut state = RejectedLogins::read_from(&state_path)?;
+        if state.forget(username, &url) {
+            state.write_to(&state_path)?;
+        }
+    }
+    ensure_configured(&config, config_path.as_ref())?;
+    // start session
+    let (client_id, _, _) = start_session()?;
+
+    if git_req.username.is_none() {
+        return Err(anyhow!("Username is missing"));
+    }
+    if git_req.password.is_none() {
+        return Err(anyhow!("Password is missing"));
+    }
+    let username = git_req.username.clone().unwrap();
+
+    let login_entries = get_logins_for(&config, &client_id, &url, unlock_options, false, false)
+        .and_then(|entries| {
+            let (kph_false, entries) = filter_kph_logins(&entries);
+            if kph_false > 0 {
+                info!("{} login(s) were labeled as KPH: git == false", kph_false);
+            }
+            let username = git_req.username.as_ref().unwrap();
+            let entries: Vec<_> = entries
+                .into_iter()
+                .filter(|entry| entry.login == *username)
+                .cloned()
+                .collect();
+            info!(
+                "{} login(s) left after filtering by username",
+                entries.len()
+            );
+            if entries.is_empty() {
+                // this Err is never used
+                Err(anyhow!(
+                    "No remaining logins after filtering out {} KPH: git == false one(s)",
+                    kph_false
+                ))
+            } else {
+                Ok(entries)
+            }
+        });
+
+    // avoid near-duplicates such as https://foo@example.com/a.git?x=1 in the databases
+    let entry_url = normalise_url(&url, config.is_store_host_only());
+    let creating = login_entries.is_err();
+    debug!("URL of the entry: {}", entry_url);
+    let sl_req = if let Ok(login_entries) = login_entries {
+        if login_entries.len() == 1 {
+            warn!("Existing login found, gonna update the entry");
+        } else {
+            warn!("More than 1 existing logins found, gonna update the first entry");
+        }
+        let login_entry = login_entries.first().unwrap();
+
+        if &login_entry.login == git_req.username.as_ref().unwrap()
+            && &login_entry.password == git_req.password.as_ref().unwrap()
+        {
+            // KeePassXC treats this as error, and Git sometimes does this as the operation should
+            // be idempotent
+            return Ok(());
+        }
+
+        // global argument, given either before or after the subcommand
+        let confirm_overwrite = config.is_confirm_overwrite()
+            || args.is_present("confirm-overwrite")
+            || args
+                .subcommand_matches("store")
+                .map(|m| m.is_present("confirm-overwrite"))
+                .unwrap_or(false);
+        if confirm_overwrite && &login_entry.password != git_req.password.as_ref().unwrap() {
+            let question = format!("Overwrite the password of login {}?", login_entry.name);
+            match confirm_on_terminal(&question) {
+                Ok(true) => (),
+                Ok(false) => {
+                    info!("Login {} not updated", login_entry.name);
+                    return Ok(());
+                }
+                Err(e) => {
+                    return Err(anyhow!(
+                        "Refusing to overwrite the password of login {} without confirmation: {}",
+                        login_entry.name,
+                        e
+                    ));
+                }
+            }
+        }
+
+        let database = login_entry
+            .database_id
+            .as_ref()
+            .and_then(|id| config.find_database(id).ok())
+            .ok_or_else(|| anyhow!("Failed to find the database of login {}", login_entry.name))?;
+        SetLoginRequest::new(
+            &entry_url,
+            &database.id,
+            &git_req.username.unwrap(),
+            &git_req.password.unwrap(),
+        )
+        // KeePassXC won't move the existing entry though
+        .group(&Group::new(&database.group, &database.group_uuid))
+        .uuid(&login_entry.uuid)
+    } else {
+        info!("No existing logins found, gonna create a new one");
+        let databases = if let Some(id_or_alias) = global_value_of(args, "database") {
+            vec![config.find_database(id_or_alias)?]
+        } else {
+            config.get_databases_for(&url)?
+        };
+        if databases.len() > 1 {
+            warn!(
+                "More than 1 databases configured, gonna save the new login in the first database"
+            );
+        }
+        // the group may have been renamed or moved since configure
+        let database = &refresh_database_group(config_path.as_ref(), &client_id, &databases[0]);
+        // the first caller matching the request may have its own group, e.g. for docker
+        let caller_group = verified_caller.as_ref().and_then(|(_, _, callers)| {
+            callers
+                .iter()
+                .filter(|caller| caller.allows_url(&url))
+                .find_map(|caller| caller.group.as_deref())
+        });
+        let rule_group = group
+            .or_else(|| {
+                config
+                    .get_rule_for(&url)
+                    .and_then(|rule| rule.group.as_deref())
+            })
+            .or(caller_group);
+        // KeePassXC only tells the name of the immediate group, which must be the namespace
+        let namespace_subgroup =
+            namespace_of(args).filter(|namespace| config.is_namespaces() && *namespace != "git");
+        let subgroup = namespace_subgroup
+            .or_else(|| url_host(&entry_url).filter(|_| config.is_host_subgroups()));
+        let group = match (rule_group, subgroup) {
+            (None, None) => Group::new(database.group.clone(), database.group_uuid.clone()),
+            (rule_group, subgroup) => {
+                let mut group_path = rule_group.unwrap_or(&database.group).to_owned();
+                if let Some(subgroup) = subgroup {
+                    group_path.push('/');
+                    group_path.push_str(subgroup);
+                }
+                ensure_groups_created_in(&config, &client_id, database)?;
+                // KeePassXC returns the existing group if there's one with the same path, and
+                // creates the missing ones along the path otherwise
+                let cng_req = CreateNewGroupRequest::new(group_path);
+                let cng_resp = cng_req.send(&client_id, false)?;
+                Group::new(cng_resp.name, cng_resp.uuid)
+            }
+        };
+        SetLoginRequest::new(
+            &entry_url,
+            &database.id,
+            &git_req.username.unwrap(),
+            &git_req.password.unwrap(),
+        )
+        .group(&group)
+        .download_favicon(config.is_store_favicon())
+    };
+    let sl_resp = sl_req.send(&client_id, false)?;
+    if let Some(success) = sl_resp.success {
+        // wtf?!?!
+        if success.0
+            && (sl_resp.error.is_none()
+                || sl_resp.error.as_ref().unwrap().is_empty()
+                || sl_resp.error.as_ref().unwrap() == "success")
+        {
+            if config.get_rotate_after_days().is_some() {
+                let state_path = state_file_path(&config_path, "rotation");
+                let mut state = RotationState::read_from(&state_path)?;
+                state.record(&username, &url, unix_now());
+                state.write_to(&state_path)?;
+            }
+            if creating {
+                record_owned_entries(&config, &config_path, &client_id, &url, &username);
+            }
+            Ok(())
+        } else {
+            error!(
+                "Failed to store login. Error: {}, Error Code: {}",
+                sl_resp.error.unwrap_or_else(|| "N/A".to_owned()),
+                sl_resp.error_code.unwrap_or_else(|| "N/A".to_owned())
+            );
+            Err(anyhow!("Failed to store login"))
+        }
+    } else {
+        error!("Set login request failed");
+        Err(anyhow!("Set login request failed"))
+    }
+}
+
+/// Makes sure that groups created for a new login end up in its database, as KeePassXC always
+/// creates them in the active one, which is only known to be the right one if its hash is pinned
+fn ensure_groups_created_in(config: &Config, client_id: &str, database: &Database) -> Result<()> {
+    match database.hash {
+        Some(ref pinned) => {
+            let active_hash = GetDatabaseHashRequest::new().send(client_id, false)?.hash;
+            if active_hash.as_ref() != Some(pinned) {
+                return Err(anyhow!(
+                    "Refusing to create a group for database {}, as KeePassXC would create it in another database that is active (activate the database and try again)",
+                    database.id
+                ));
+            }
+        }
+        None if config.count_databases() > 1 => {
+            return Err(anyhow!(
+                "Refusing to create a group for database {}, as KeePassXC would create it in the active database, which may be another one (run configure or reassociate with it active to pin its hash)",
+                database.id
+            ));
+        }
+        None => (),
+    }
+    Ok(())
+}
+
+/// Asks the `cache_helper` of the configuration for a login before KeePassXC is, returning its
+/// response if it has a password
+pub(crate) fn get_from_cache_helper(helper: &str, input: &str) -> Option<String> {
+    let response = match git::run_helper(helper, "get", input) {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("{}", e);
+            return None;
+        }
+    };
+    let message = GitCredentialMessage::from_str(&response).ok()?;
+    message.password.as_ref()?;
+    info!("Login found by credential helper {}", helper);
+    Some(message.to_string())
+}
+
+/// Hands a login returned by `get` to the `cache_helper` of the configuration, so that it's
+/// answered from there next time
+pub(crate) fn store_in_cache_helper(helper: &str, input: &str, response: &str) {
+    let (mut git_req, response) = match (
+        GitCredentialMessage::from_str(input),
+        GitCredentialMessage::from_str(response),
+    ) {
+        (Ok(git_req), Ok(response)) if response.password.is_some() => (git_req, response),
+        // e.g. quit=1 of deny rules
+        _ => return,
+    };
+    git_req.username = response.username.or(git_req.username);
+    git_req.password = response.password;
+    if let Err(e) = git::run_helper(helper, "store", &git_req.to_string()) {
+        warn!("{}", e);
+    }
+}
+
+/// Answers blank-line-separated `get` requests one after another over the same KeePassXC session,
+/// each as soon as it's complete. Failed requests are answered with an empty response
+pub fn get_logins_batch<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let mut request = String::new();
+    loop {
+        let mut line = String::new();
+        let eof = input.read_line(&mut line)? == 0;
+        if !line.trim().is_empty() {
+            request.push_str(&line);
+            if !eof {
+                continue;
+            }
+        }
+        if !request.is_empty() {
+            let mut response = Vec::new();
+            let result = retry_on_lost_session(|| {
+                response.clear();
+                get_logins(
+                    &config_path,
+                    unlock_options,
+                    args,
+                    &mut request.as_bytes(),
+                    &mut response,
+                )
+            });
+            record_get(&config_path, &request, &result);
+            if let Err(e) = result {
+                error!("Failed to get login, {}", e);
+                response.clear();
+            }
+            if response.is_empty() {
+                response.push(b'\n');
+            }
+            output.write_all(&response)?;
+            output.flush()?;
+            request.clear();
+        }
+        if eof {
+            return Ok(());
+        }
+    }
+}
+
+/// Records a get, store or erase in the audit log if there's one and runs the matching hooks,
+/// with the database of the login if known
+pub(crate) fn record_access<T: AsRef<Path>>(
+    config_path: T,
+    operation: &str,
+    request: &str,
+    database: Option<&str>,
+    result: &Result<()>,
+) {
+    let config = match Config::read_from(config_path.as_ref()) {
+        Ok(config) => config,
+        Err(_) => return,
+    };
+    let hooks = config.get_hooks();
+    if config.get_audit_log().is_none() && hooks.is_empty() {
+        return;
+    }
+    let system = System::new_all();
+    let caller = get_current_and_parent_process(&system)
+        .ok()
+        .map(|(_, pproc)| pproc.exe().to_string_lossy().into_owned());
+    let url = read_git_request(&mut request.as_bytes())
+        .ok()
+        .map(|(_, url)| audit::strip_url_userinfo(&url));
+    let (outcome, error) = match result {
+        Ok(_) if database.is_none() && operation == "get" => ("denied", None),
+        Ok(_) => ("success", None),
+        Err(e) => ("error", Some(e.to_string())),
+    };
+    let record = AuditRecord {
+        timestamp: unix_now(),
+        operation: operation.to_owned(),
+        caller,
+        url,
+        database: database.map(str::to_owned),
+        outcome: outcome.to_owned(),
+        error,
+    };
+    if let Some(audit_log) = config.get_audit_log() {
+        if let Err(e) = audit_log.record(&record) {
+            warn!("Failed to record access in audit log, {}", e);
+        }
+    }
+    for hook in hooks.iter().filter(|hook| hook.matches(&record)) {
+        if let Err(e) = hook.run(&record) {
+            warn!("{}", e);
+        }
+    }
+}
+
+pub(crate) fn record_get<T: AsRef<Path>>(
+    config_path: T,
+    request: &str,
+    result: &Result<Option<LoginEntry>>,
+) {
+    let database = match result {
+        Ok(Some(login)) => Some(login.database_id.as_deref().unwrap_or("unknown")),
+        _ => None,
+    };
+    let result = match result {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!("{}", e)),
+    };
+    record_access(config_path, "get", request, database, &result);
+}
+
+/// Remembers the UUIDs of new entries, which KeePassXC doesn't return, so that `erase` can tell
+/// them from the ones created manually
+fn record_owned_entries<T: AsRef<Path>>(
+    config: &Config,
+    config_path: T,
+    client_id: &str,
+    url: &str,
+    username: &str,
+) {
+    let result = get_logins_for(config, client_id, url, &None, false, false).and_then(|entries| {
+        let state_path = state_file_path(&config_path, "owned");
+        let mut state = OwnedEntries::read_from(&state_path)?;
+        let (_, entries) = filter_kph_logins(&entries);
+        // there weren't any matching logins before, so all of them are new
+        for entry in entries.into_iter().filter(|entry| entry.login == username) {
+            debug!("Recording login {} as created by store", entry.name);
+            state.insert(&entry.uuid);
+        }
+        state.write_to(&state_path)
+    });
+    if let Err(e) = result {
+        warn!(
+            "Failed to record the new login, erase won't touch it, {}",
+            e
+        );
+    }
+}
+
+/// Remembers the password of an erased login for `remember_rejected` seconds, so that `get`
+/// doesn't return it again in the meantime
+fn remember_rejection(
+    config: &Config,
+    config_path: &Path,
+    git_req: &GitCredentialMessage,
+    url: &str,
+) -> Result<()> {
+    let remember_for = match config.get_remember_rejected() {
+        Some(remember_for) => remember_for,
+        None => return Ok(()),
+    };
+    let (username, password) = match (
+        git_req.username.as_deref().or_else(|| url_username(url)),
+        &git_req.password,
+    ) {
+        (Some(username), Some(password)) => (username, password),
+        _ => return Ok(()),
+    };
+    // anyone could otherwise hide logins from the allowed callers
+    if config.count_callers() > 0 && match_caller(config).is_err() {
+        warn!("Caller is not allowed, not remembering the rejected login");
+        return Ok(());
+    }
+    let url = config.rewrite_url(url);
+    let state_path = state_file_path(config_path, "rejected");
+    let mut state = RejectedLogins::read_from(&state_path)?;
+    let now = unix_now();
+    state.record(username, &url, password, now, now + remember_for);
+    state.write_to(&state_path)?;
+    info!(
+        "Login {} for {} won't be returned for {}s unless its password changes",
+        username, url, remember_for
+    );
+    Ok(())
+}
+
+/// Erases the logins matching a Git request, or with `erase --dry-run` writes those it would
+/// erase to `output`
+pub fn erase_login<T: AsRef<Path>>(
+    config_path: T,
+    unlock_options: &Option<UnlockOptions>,
+    args: &ArgMatches,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let config = Config::read_from(config_path.as_ref());
+    let request = read_git_request(input);
+    if let (Ok(config), Ok((git_req, url))) = (&config, &request) {
+        // whether or not the login is erased below, it shouldn't be returned again right away
+        remember_rejection(config, config_path.as_ref(), git_req, url)?;
+    }
+    let read_only = args.is_present("read-only")
+        || args
+            .subcommand_matches("erase")
+            .map(|m| m.is_present("read-only"))
+            .unwrap_or(false)
+        || config
+            .as_ref()
+            .map(|config| config.is_read_only())
+            .unwrap_or(false);
+    if read_only {
+        info!("Read-only mode, not erasing login");
+        return Ok(());
+    }
+    let erase_mode = match global_value_of(args, "erase-mode") {
+        Some(erase_mode) => EraseMode::from_str(erase_mode)?,
+        None => config
+            .as_ref()
+            .map(|config| config.get_erase_mode())
+            .unwrap_or_default(),
+    };
+    let erase_args = args.subcommand_matches("erase");
+    let dry_run = erase_args.map(|m| m.is_present("dry-run")).unwrap_or(false);
+    if erase_mode == EraseMode::Disabled && !dry_run {
+        // Don't treat this as error as when server rejects a login Git may try to erase it. This
+        // is not desirable since sometimes it's merely a configuration issue, e.g. a lot of Git
+        // servers reject logins over HTTP(S) when SSH keys have been uploaded
+        warn!("Erasing logins is disabled, set erase mode to delete to enable it");
+        return Ok(());
+    }
+    let mut config = config?;
+    let verified_caller = verify_caller(&config, config_path.as_ref())?;
+    let (mut git_req, url) = request?;
+    let git_settings = git_settings_for(&mut config, &url)?;
+    if git_req.username.is_none() {
+        git_req.username = git_settings.username;
+    }
+    let url = config.rewrite_url(url);
+    verify_caller_url(&verified_caller, &url)?;
+    if let Some(deny_rule) = config.get_deny_rule_for(&url) {
+        info!("{} is denied by pattern {}", url, deny_rule.pattern);
+        return Ok(());
+    }
+    ensure_configured(&config, config_path.as_ref())?;
+    // start session
+    let (client_id, _, _) = start_session()?;
+
+    let login_entries = get_logins_for(&config, &client_id, &url, unlock_options, false, true)?;
+    let (_, login_entries) = filter_kph_logins(&login_entries);
+    let force = erase_args.map(|m| m.is_present("force")).unwrap_or(false);
+    let login_entries: Vec<_> = match git_req.username {
+        Some(ref username) => login_entries
+            .into_iter()
+            .filter(|entry| entry.login == *username)
+            .collect(),
+        None if force => {
+            warn!("Username is missing, erasing all logins for {}", url);
+            login_entries
+        }
+        None => {
+            return Err(anyhow!(
+                "Username is missing, refusing to erase all logins for {} without --force",
+                url
+            ));
+        }
+    };
+    let owned_state_path = state_file_path(&config_path, "owned");
+    let mut owned_entries = OwnedEntries::read_from(&owned_state_path)?;
+    let include_manual = erase_args
+        .map(|m| m.is_present("include-manual"))
+        .unwrap_or(false);
+    let login_entries: Vec<_> = if include_manual {
+        login_entries
+    } else {
+        login_entries
+            .into_iter()
+            .filter(|entry| {
+                let owned = owned_entries.contains(&entry.uuid);
+                if !owned {
+                    info!("Login {} wasn't created by store, skipped", entry.name);
+                }
+                owned
+            })
+            .collect()
+    };
+    info!("{} login(s) to erase", login_entries.len());
+
+    if dry_run {
+        for login_entry in login_entries {
+            writeln!(
+                output,
+                "Would erase {} (username: {}, group: {}, database: {})",
+                login_entry.name,
+                login_entry.login,
+                login_entry.group.as_deref().unwrap_or("?"),
+                login_entry.database_id.as_deref().unwrap_or("?")
+            )?;
+        }
+        return Ok(());
+    }
+
+    for login_entry in login_entries {
+        let de_resp = DeleteEntryRequest::new(&login_entry.uuid).send(&client_id, false)?;
+        if de_resp.success.as_ref().map(|s| s.0).unwrap_or(false) {
+            info!("Login {} erased", login_entry.name);
+            owned_entries.remove(&login_entry.uuid);
+            owned_entries.write_to(&owned_state_path)?;
+        } else {
+            error!("Failed to erase login {}", login_entry.name);
+            return Err(anyhow!("Failed to erase login {}", login_entry.name));
+        }
+    }
+    Ok(())
+}
+
+/// Whether a global flag is given, before or after (nested) subcommands
+pub(crate) fn is_global_flag_present(args: &ArgMatches, name: &str) -> bool {
+    let mut matches = Some(args);
+    while let Some(m) = matches {
+        if m.is_present(name) {
+            return true;
+        }
+        matches = m.subcommand().1;
+    }
+    false
+}
+
+/// Value of an option given before or after (nested) subcommands, the innermost one if several
+pub(crate) fn global_value_of<'a>(args: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    let mut matches = Some(args);
+    let mut value = None;
+    while let Some(m) = matches {
+        value = m.value_of(name).or(value);
+        matches = m.subcommand().1;
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{
+        mock_kpxc_serve, test_guard, test_host_secret_key, ExchangeMessageContext,
+        ReceiveMessageContext, SendMessageContext,
+    };
+    use clap::App;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex, PoisonError};
+
+    const URL: &str = "https://git.example.com/repo.git";
+
+    /// KeePassXC with the given databases open, keeping track of the requests changing them
+    struct MockKeePassXC {
+        _contexts: (
+            ExchangeMessageContext,
+            SendMessageContext,
+            ReceiveMessageContext,
+        ),
+        /// set-login and delete-entry requests in the order they were sent
+        changes: Arc<Mutex<Vec<serde_json::Value>>>,
+    }
+
+    impl MockKeePassXC {
+        /// Serves `logins` as (database ID, entry) pairs, adding those created by set-login and
+        /// removing those deleted by delete-entry
+        fn new(logins: Vec<(&str, serde_json::Value)>) -> Self {
+            let mut logins: Vec<_> = logins
+                .into_iter()
+                .map(|(id, entry)| (id.to_owned(), entry))
+                .collect();
+            let changes = Arc::new(Mutex::new(Vec::new()));
+            let recorded = changes.clone();
+            let contexts =
+                mock_kpxc_serve(
+                    &test_host_secret_key(),
+                    move |action, request| match action {
+                        KeePassAction::TestAssociate => {
+                            json!({ "id": request["id"], "success": "true" })
+                        }
+                        KeePassAction::GetLogins => {
+                            let ids: Vec<_> = request["keys"]
+                                .as_array()
+                                .unwrap()
+                                .iter()
+                                .map(|key| key["id"].as_str().unwrap().to_owned())
+                                .collect();
+                            let entries: Vec<_> = logins
+                                .iter()
+                                .filter(|(id, _)| ids.contains(id))
+                                .map(|(_, entry)| entry.clone())
+                                .collect();
+                            json!({ "count": entries.len(), "entries": entries, "success": "true" })
+                        }
+                        KeePassAction::SetLogin => {
+                            recorded.lock().unwrap().push(request.clone());
+                            if request["uuid"].is_null() {
+                                let entry = json!({
+                                    "login": request["login"],
+                                    "name": request["url"],
+                                    "password": request["password"],
+                                    "uuid": format!("new-{}", logins.len()),
+                                });
+                                logins.push((request["id"].as_str().unwrap().to_owned(), entry));
+                            }
+                            json!({ "success": "true" })
+                        }
+                        KeePassAction::DeleteEntry => {
+                            recorded.lock().unwrap().push(request.clone());
+                            logins.retain(|(_, entry)| entry["uuid"] != request["uuid"]);
+                            json!({ "success": "true" })
+                        }
+                        _ => json!({ "success": "false" }),
+                    },
+                );
+            Self {
+                _contexts: contexts,
+                changes,
+            }
+        }
+
+        fn changes(&self) -> Vec<serde_json::Value> {
+            self.changes.lock().unwrap().clone()
+        }
+    }
+
+    fn login(username: &str, password: &str, uuid: &str) -> serde_json::Value {
+        json!({ "login": username, "name": URL, "password": password, "uuid": uuid })
+    }
+
+    /// Writes a configuration with the databases of the given IDs and `options` added, returning
+    /// its path
+    fn write_config(name: &str, database_ids: &[&str], options: serde_json::Value) -> PathBuf {
+        let databases: Vec<_> = database_ids
+            .iter()
+            .map(|id| json!({ "id": id, "key": "", "pkey": "", "group": "Git", "group_uuid": "" }))
+            .collect();
+        let mut config = json!({ "databases": databases });
+        if cfg!(feature = "strict-caller") {
+            // the test runner, which would be Git
+            let system = System::new_all();
+            let (_, pproc) = get_current_and_parent_process(&system).unwrap();
+            config["callers"] = json!([{ "path": pproc.exe() }]);
+        }
+        for (key, value) in options.as_object().unwrap() {
+            config[key] = value.clone();
+        }
+        let config_path =
+            std::env::temp_dir().join(format!("git-credential-keepassxc.{}.json", name));
+        std::fs::write(&config_path, config.to_string()).unwrap();
+        config_path
+    }
+
+    fn remove_config(config_path: PathBuf) {
+        for extension in &["owned", "rejected"] {
+            let _ = std::fs::remove_file(state_file_path(&config_path, extension));
+        }
+        std::fs::remove_file(config_path).unwrap();
+    }
+
+    /// Runs `f` with the arguments parsed from `argv` like the command line
+    fn with_args<T>(argv: &[&str], f: impl FnOnce(&ArgMatches) -> T) -> T {
+        let yaml = clap::load_yaml!("cli.yml");
+        let args = App::from_yaml(yaml)
+            .get_matches_from_safe(std::iter::once(clap::crate_name!()).chain(argv.iter().copied()))
+            .unwrap();
+        f(&args)
+    }
+
+    fn git_request(username: Option<&str>, password: Option<&str>) -> String {
+        GitCredentialMessage {
+            url: Some(URL.to_owned()),
+            username: username.map(str::to_owned),
+            password: password.map(str::to_owned),
+            ..Default::default()
+        }
+        .to_string()
+    }
+
+    /// Records logins as created by store
+    fn own(config_path: &Path, uuids: &[&str]) {
+        let state_path = state_file_path(config_path, "owned");
+        let mut owned_entries = OwnedEntries::read_from(&state_path).unwrap();
+        for uuid in uuids {
+            owned_entries.insert(*uuid);
+        }
+        owned_entries.write_to(&state_path).unwrap();
+    }
+
+    /// UUIDs of the logins erased by `erase` with `argv`, or its error
+    fn erase(
+        keepassxc: &MockKeePassXC,
+        config_path: &Path,
+        argv: &[&str],
+        username: Option<&str>,
+    ) -> Result<Vec<String>> {
+        let count = keepassxc.changes().len();
+        let request = git_request(username, Some("rejected"));
+        with_args(argv, |args| {
+            erase_login(
+                config_path,
+                &None,
+                args,
+                &mut request.as_bytes(),
+                &mut std::io::sink(),
+            )
+        })?;
+        Ok(keepassxc.changes()[count..]
+            .iter()
+            .map(|change| change["uuid"].as_str().unwrap().to_owned())
+            .collect())
+    }
+
+    #[test]
+    fn test_00_store_updates_login_in_its_database() {
+        let _guard = test_guard().lock().unwrap_or_else(PoisonError::into_inner);
+        // new logins go to the first database, but alice is only in the second one
+        let keepassxc = MockKeePassXC::new(vec![
+            ("work", login("bob", "secret2", "uuid-bob")),
+            ("personal", login("alice", "secret1", "uuid-alice")),
+        ]);
+        let config_path = write_config("requests_test_00", &["work", "personal"], json!({}));
+        let request = git_request(Some("alice"), Some("changed"));
+        with_args(&["store"], |args| {
+            store_login(&config_path, &None, args, &mut request.as_bytes())
+        })
+        .unwrap();
+        let changes = keepassxc.changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0]["id"], "personal");
+        assert_eq!(changes[0]["uuid"], "uuid-alice");
+        assert_eq!(changes[0]["password"], "changed");
+        remove_config(config_path);
+    }
+
+    #[test]
+    fn test_01_erase_only_logins_of_username() {
+        let _guard = test_guard().lock().unwrap_or_else(PoisonError::into_inner);
+        let keepassxc = MockKeePassXC::new(vec![
+            ("work", login("alice", "secret1", "uuid-alice")),
+            ("work", login("bob", "secret2", "uuid-bob")),
+        ]);
+        let config_path = write_config(
+            "requests_test_01",
+            &["work"],
+            json!({ "erase_mode": "delete" }),
+        );
+        own(&config_path, &["uuid-alice", "uuid-bob"]);
+        assert_eq!(
+            erase(&keepassxc, &config_path, &["erase"], Some("carol")).unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            erase(&keepassxc, &config_path, &["erase"], Some("alice")).unwrap(),
+            vec!["uuid-alice"]
+        );
+        remove_config(config_path);
+    }
+
+    #[test]
+    fn test_02_erase_without_username_only_if_forced() {
+        let _guard = test_guard().lock().unwrap_or_else(PoisonError::into_inner);
+        let keepassxc = MockKeePassXC::new(vec![
+            ("work", login("alice", "secret1", "uuid-alice")),
+            ("work", login("bob", "secret2", "uuid-bob")),
+        ]);
+        let config_path = write_config(
+            "requests_test_02",
+            &["work"],
+            json!({ "erase_mode": "delete" }),
+        );
+        own(&config_path, &["uuid-alice", "uuid-bob"]);
+        let error = erase(&keepassxc, &config_path, &["erase"], None).unwrap_err();
+        assert!(error.to_string().contains("--force"), "{}", error);
+        assert!(keepassxc.changes().is_empty());
+        assert_eq!(
+            erase(&keepassxc, &config_path, &["erase", "--force"], None).unwrap(),
+            vec!["uuid-alice", "uuid-bob"]
+        );
+        remove_config(config_path);
+    }
+
+    #[test]
+    fn test_03_erase_only_logins_created_by_store() {
+        let _guard = test_guard().lock().unwrap_or_else(PoisonError::into_inner);
+        let keepassxc = MockKeePassXC::new(vec![("work", login("bob", "secret2", "uuid-bob"))]);
+        let config_path = write_config(
+            "requests_test_03",
+            &["work"],
+            json!({ "erase_mode": "delete" }),
+        );
+        let request = git_request(Some("carol"), Some("secret3"));
+        with_args(&["store"], |args| {
+            store_login(&config_path, &None, args, &mut request.as_bytes())
+        })
+        .unwrap();
+        assert_eq!(keepassxc.changes()[0]["login"], "carol");
+        let owned_state_path = state_file_path(&config_path, "owned");
+        assert!(OwnedEntries::read_from(&owned_state_path)
+            .unwrap()
+            .contains("new-1"));
+
+        assert_eq!(
+            erase(&keepassxc, &config_path, &["erase", "--force"], None).unwrap(),
+            vec!["new-1"]
+        );
+        assert!(!OwnedEntries::read_from(&owned_state_path)
+            .unwrap()
+            .contains("new-1"));
+        assert_eq!(
+            erase(
+                &keepassxc,
+                &config_path,
+                &["erase", "--force", "--include-manual"],
+                None
+            )
+            .unwrap(),
+            vec!["uuid-bob"]
+        );
+        remove_config(config_path);
+    }
+
+    #[test]
+    fn test_04_wait_for_locked_databases_together() {
+        let _guard = test_guard().lock().unwrap_or_else(PoisonError::into_inner);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let mut unlocked = false;
+        let _keepassxc = mock_kpxc_serve(&test_host_secret_key(), move |action, request| {
+            let mut recorded = recorded.lock().unwrap();
+            match action {
+                KeePassAction::TestAssociate => {
+                    let id = request["id"].as_str().unwrap();
+                    recorded.push(format!("test-associate {}", id));
+                    if id == "work" || unlocked {
+                        json!({ "id": id, "success": "true" })
+                    } else {
+                        json!({ "error": "Database not opened", "errorCode": "1" })
+                    }
+                }
+                KeePassAction::GetDatabaseHash => {
+                    recorded.push("get-databasehash".to_owned());
+                    unlocked = true;
+                    json!({ "hash": "hash", "success": "true" })
+                }
+                _ => json!({ "success": "false" }),
+            }
+        });
+        let config_path = write_config(
+            "requests_test_04",
+            &["work", "personal", "shared"],
+            json!({}),
+        );
+        let config = Config::read_from(&config_path).unwrap();
+        let (client_id, _, _) = start_session().unwrap();
+        let databases = associated_databases(
+            config.get_databases_in_tier_for(URL, false).unwrap(),
+            &client_id,
+            &Some(UnlockOptions {
+                max_retries: 3,
+                interval: 1,
+            }),
+            HashMismatch::Refuse,
+        )
+        .unwrap();
+        let ids: Vec<_> = databases
+            .iter()
+            .map(|database| database.id.as_str())
+            .collect();
+        assert_eq!(ids, ["work", "personal", "shared"]);
+        // a single wait for both locked databases, which are tested again together
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "test-associate work",
+                "test-associate personal",
+                "test-associate shared",
+                "get-databasehash",
+                "test-associate personal",
+                "test-associate shared",
+            ]
+        );
+        remove_config(config_path);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::utils::{read_state_file, url_host, write_state_file};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// When logins were last stored, persisted next to the configuration file since KeePassXC doesn't
+/// allow setting expiry dates of new logins
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct RotationState {
+    #[serde(default)]
+    stored: HashMap<String, u64>,
+}
+
+impl RotationState {
+    pub fn read_from<T: AsRef<Path>>(state_path: T) -> Result<Self> {
+        read_state_file(state_path)
+    }
+
+    pub fn write_to<T: AsRef<Path>>(&self, state_path: T) -> Result<()> {
+        write_state_file(self, state_path)
+    }
+
+    fn key(username: &str, url: &str) -> Option<String> {
+        url_host(url).map(|host| format!("{}@{}", username, host))
+    }
+
+    pub fn record(&mut self, username: &str, url: &str, now: u64) {
+        if let Some(key) = Self::key(username, url) {
+            self.stored.insert(key, now);
+        }
+    }
+
+    /// Days since the login was stored, if it was stored by us
+    pub fn age_in_days(&self, username: &str, url: &str, now: u64) -> Option<u64> {
+        let stored = self.stored.get(&Self::key(username, url)?)?;
+        Some(now.saturating_sub(*stored) / SECONDS_PER_DAY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_login_age() {
+        let mut state = RotationState::default();
+        state.record("foo", "https://example.com/repo.git", 0);
+        assert_eq!(
+            state.age_in_days("foo", "https://example.com/other.git", 3 * SECONDS_PER_DAY),
+            Some(3)
+        );
+        assert!(state
+            .age_in_days("bar", "https://example.com/repo.git", 3 * SECONDS_PER_DAY)
+            .is_none());
+        assert!(state
+            .age_in_days("foo", "https://example.org/repo.git", 3 * SECONDS_PER_DAY)
+            .is_none());
+    }
+}
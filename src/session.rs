@@ -0,0 +1,152 @@
+use crate::keepassxc::errors::KeePassError;
+use crate::keepassxc::messages::*;
+use crate::keepassxc::version;
+use crate::logging::timed;
+use crate::memlock;
+use crate::transport::{reset_transport, ConnectionLostError};
+use crate::utils::{generate_secret_key, get_client_box, nacl_nonce};
+#[allow(unused_imports)]
+use crate::{debug, error, info, warn};
+use anyhow::{anyhow, Result};
+use crypto_box::{PublicKey, SecretKey};
+use std::cell::RefCell;
+
+type Session = (String, SecretKey, PublicKey);
+
+/// Session of a thread, whose key is unlocked when the thread exits, so that `memlock::wipe_all()`
+/// doesn't write into the freed memory
+struct ThreadSession(Option<Session>);
+
+impl Drop for ThreadSession {
+    fn drop(&mut self) {
+        if let Some((_, session_seckey, _)) = self.0.as_ref() {
+            memlock::unlock_value(session_seckey);
+        }
+    }
+}
+
+thread_local!(static SESSION: RefCell<ThreadSession> = const { RefCell::new(ThreadSession(None)) });
+
+pub fn exchange_keys<T: AsRef<str>>(client_id: T, session_pubkey: &PublicKey) -> Result<PublicKey> {
+    // exchange public keys
+    let cpr_req = ChangePublicKeysRequest::new(client_id.as_ref(), session_pubkey);
+    let cpr_resp = cpr_req.send()?;
+    version::set_version(cpr_resp.version.clone());
+    Ok(cpr_resp
+        .get_public_key()
+        .ok_or_else(|| anyhow!("Failed to retrieve host public key"))?)
+}
+
+pub fn start_session() -> Result<Session> {
+    // reuse the session of the current thread, which saves key exchanges when serving many
+    // requests
+    SESSION.with(|session| {
+        if let Some(session) = session.borrow().0.as_ref() {
+            return Ok(session.clone());
+        }
+        let new = timed("Session setup", new_session)?;
+        let mut session = session.borrow_mut();
+        let (_, session_seckey, _) = session.0.insert(new.clone());
+        memlock::lock_value(session_seckey);
+        Ok(new)
+    })
+}
+
+/// Forgets the session and connection of the current thread, so that the next request starts
+/// over with KeePassXC
+pub fn reset_session() {
+    SESSION.with(|session| {
+        // unlocks the key
+        *session.borrow_mut() = ThreadSession(None);
+    });
+    reset_transport();
+}
+
+/// Whether an error means that the session is gone, i.e. the connection broke or KeePassXC
+/// doesn't know the session key, e.g. as it restarted
+pub fn is_session_lost(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<ConnectionLostError>().is_some()
+        || e.downcast_ref::<KeePassError>()
+            .map(KeePassError::is_session_unknown)
+            .unwrap_or(false)
+}
+
+/// Runs `f`, and once more with a new session if the session was lost meanwhile, e.g. as
+/// KeePassXC restarted between the requests of a long-running process
+pub fn retry_on_lost_session<T, F: FnMut() -> Result<T>>(mut f: F) -> Result<T> {
+    match f() {
+        Err(e) if is_session_lost(&e) => {
+            warn!("{}, starting a new session and retrying", e);
+            reset_session();
+            f()
+        }
+        result => result,
+    }
+}
+
+pub fn new_session() -> Result<Session> {
+    // generate keys for encrypting current session
+    let session_seckey = generate_secret_key();
+    let session_pubkey = session_seckey.public_key();
+
+    // temporary client id
+    let (_, client_id) = nacl_nonce();
+
+    // exchange public keys
+    let host_pubkey = exchange_keys(&client_id, &session_pubkey)?;
+
+    // initialise crypto_box
+    let _ = get_client_box(Some(&host_pubkey), Some(&session_seckey));
+
+    Ok((client_id, session_seckey, host_pubkey))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{mock_kpxc_serve, test_guard, test_host_secret_key};
+    use serde_json::json;
+    use std::sync::PoisonError;
+
+    fn test_associate() -> Result<bool> {
+        let (client_id, _, _) = start_session()?;
+        let taso_resp = TestAssociateRequest::new("mock", "mock").send(client_id, false)?;
+        Ok(taso_resp.success.map(|success| success.0).unwrap_or(false))
+    }
+
+    #[test]
+    fn test_00_retry_once_with_new_session() {
+        let _guard = test_guard().lock().unwrap_or_else(PoisonError::into_inner);
+        let mut restarted = true;
+        let _keepassxc = mock_kpxc_serve(&test_host_secret_key(), move |_, request| {
+            if restarted {
+                // KeePassXC forgot the session key
+                restarted = false;
+                json!({ "error": "Client public key not received", "errorCode": "3" })
+            } else {
+                json!({ "id": request["id"], "success": "true" })
+            }
+        });
+        let (_, old_seckey, _) = start_session().unwrap();
+        let mut attempts = 0;
+        let associated = retry_on_lost_session(|| {
+            attempts += 1;
+            test_associate()
+        })
+        .unwrap();
+        assert!(associated);
+        assert_eq!(attempts, 2);
+        let (_, new_seckey, _) = start_session().unwrap();
+        assert_ne!(old_seckey.to_bytes(), new_seckey.to_bytes());
+
+        // other errors aren't retried
+        assert!(is_session_lost(&ConnectionLostError.into()));
+        let mut attempts = 0;
+        let result: Result<()> = retry_on_lost_session(|| {
+            attempts += 1;
+            Err(anyhow!("mock"))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}
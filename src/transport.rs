@@ -0,0 +1,236 @@
+#[cfg(windows)]
+use crate::utils::NAMED_PIPE_CONNECT_TIMEOUT_MS;
+use crate::utils::{get_socket_path, Failure};
+use anyhow::{anyhow, Context, Result};
+#[cfg(windows)]
+use named_pipe::PipeClient;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{Read, Write};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+/// Connection messages to and from KeePassXC go through
+pub trait Transport {
+    /// Called once before the first message is sent
+    fn connect(&mut self) -> Result<()>;
+    fn send(&mut self, message: &str) -> Result<()>;
+    /// Reads what's been received so far, which may be several messages
+    fn receive(&mut self) -> Result<String>;
+}
+
+/// The connection to KeePassXC broke, e.g. as it quit or restarted
+#[derive(Debug)]
+pub struct ConnectionLostError;
+impl fmt::Display for ConnectionLostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Connection to KeePassXC lost")
+    }
+}
+impl std::error::Error for ConnectionLostError {}
+
+thread_local!(static TRANSPORT: RefCell<Option<Box<dyn Transport>>> = RefCell::new(None));
+
+/// Connects the given transport and uses it for subsequent messages of the current thread,
+/// instead of the KeePassXC socket
+pub fn set_transport(mut transport: Box<dyn Transport>) -> Result<()> {
+    transport.connect()?;
+    TRANSPORT.with(|t| *t.borrow_mut() = Some(transport));
+    Ok(())
+}
+
+/// Runs `f` with the transport of the current thread, connecting to the KeePassXC socket if none
+/// has been set
+pub fn with_transport<R, F: FnOnce(&mut dyn Transport) -> Result<R>>(f: F) -> Result<R> {
+    TRANSPORT.with(|t| {
+        let mut t = t.borrow_mut();
+        if t.is_none() {
+            *t = Some(default_transport()?);
+        }
+        f(t.as_mut().unwrap().as_mut())
+    })
+}
+
+/// Drops the transport of the current thread, so that the next message connects to the KeePassXC
+/// socket again
+pub fn reset_transport() {
+    TRANSPORT.with(|t| *t.borrow_mut() = None);
+}
+
+fn default_transport() -> Result<Box<dyn Transport>> {
+    let path = get_socket_path().context(Failure::SocketNotFound)?;
+    #[cfg(unix)]
+    let mut transport = UnixSocketTransport::new(path);
+    #[cfg(windows)]
+    let mut transport = NamedPipeTransport::new(path);
+    transport.connect().context(Failure::SocketNotFound)?;
+    Ok(Box::new(transport))
+}
+
+fn read_available<T: Read>(stream: &mut T) -> Result<String> {
+    let mut response = String::new();
+    const BUF_SIZE: usize = 128;
+    let mut buf = [0u8; BUF_SIZE];
+    loop {
+        let len = stream.read(&mut buf).context(ConnectionLostError)?;
+        if len == 0 && response.is_empty() {
+            // closed by KeePassXC
+            return Err(ConnectionLostError.into());
+        }
+        response.push_str(std::str::from_utf8(&buf[0..len]).unwrap());
+        if len < BUF_SIZE {
+            break;
+        }
+    }
+    Ok(response)
+}
+
+#[cfg(unix)]
+pub struct UnixSocketTransport {
+    path: PathBuf,
+    stream: Option<UnixStream>,
+}
+
+#[cfg(unix)]
+impl UnixSocketTransport {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            stream: None,
+        }
+    }
+
+    fn stream(&mut self) -> Result<&mut UnixStream> {
+        let path = &self.path;
+        self.stream
+            .as_mut()
+            .ok_or_else(|| anyhow!("Not connected to {}", path.to_string_lossy()))
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixSocketTransport {
+    fn connect(&mut self) -> Result<()> {
+        let stream = UnixStream::connect(&self.path).with_context(|| {
+            format!(
+                "Failed to connect to Unix socket {}",
+                self.path.to_string_lossy()
+            )
+        })?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn send(&mut self, message: &str) -> Result<()> {
+        self.stream()?
+            .write_all(message.as_bytes())
+            .context(ConnectionLostError)
+    }
+
+    fn receive(&mut self) -> Result<String> {
+        read_available(self.stream()?)
+    }
+}
+
+#[cfg(windows)]
+pub struct NamedPipeTransport {
+    path: PathBuf,
+    pipe: Option<PipeClient>,
+}
+
+#[cfg(windows)]
+impl NamedPipeTransport {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            pipe: None,
+        }
+    }
+
+    fn pipe(&mut self) -> Result<&mut PipeClient> {
+        let path = &self.path;
+        self.pipe
+            .as_mut()
+            .ok_or_else(|| anyhow!("Not connected to {}", path.to_string_lossy()))
+    }
+}
+
+#[cfg(windows)]
+impl Transport for NamedPipeTransport {
+    fn connect(&mut self) -> Result<()> {
+        let pipe = PipeClient::connect_ms(&self.path, NAMED_PIPE_CONNECT_TIMEOUT_MS).with_context(
+            || {
+                format!(
+                    "Failed to connect to named pipe {}",
+                    self.path.to_string_lossy()
+                )
+            },
+        )?;
+        self.pipe = Some(pipe);
+        Ok(())
+    }
+
+    fn send(&mut self, message: &str) -> Result<()> {
+        self.pipe()?
+            .write_all(message.as_bytes())
+            .context(ConnectionLostError)
+    }
+
+    fn receive(&mut self) -> Result<String> {
+        read_available(self.pipe()?)
+    }
+}
+
+/// Transport replaying canned responses, recording what's sent
+#[cfg(test)]
+#[derive(Default)]
+pub struct TestTransport {
+    pub sent: std::rc::Rc<RefCell<Vec<String>>>,
+    pub responses: std::collections::VecDeque<String>,
+}
+
+#[cfg(test)]
+impl Transport for TestTransport {
+    fn connect(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn send(&mut self, message: &str) -> Result<()> {
+        self.sent.borrow_mut().push(message.to_owned());
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<String> {
+        self.responses
+            .pop_front()
+            .ok_or_else(|| anyhow!("No more responses"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_00_thread_transport() {
+        let transport = TestTransport {
+            responses: vec!["{}".to_owned()].into(),
+            ..Default::default()
+        };
+        let sent = transport.sent.clone();
+        set_transport(Box::new(transport)).unwrap();
+        with_transport(|t| t.send("{\"action\":\"mock\"}")).unwrap();
+        assert_eq!(*sent.borrow(), vec!["{\"action\":\"mock\"}".to_owned()]);
+        assert_eq!(with_transport(|t| t.receive()).unwrap(), "{}");
+        assert!(with_transport(|t| t.receive()).is_err());
+    }
+
+    #[test]
+    fn test_01_read_from_closed_connection() {
+        // nothing to read, as if KeePassXC quit
+        let error = read_available(&mut std::io::empty()).unwrap_err();
+        assert!(error.downcast_ref::<ConnectionLostError>().is_some());
+        assert_eq!(read_available(&mut "{}".as_bytes()).unwrap(), "{}");
+    }
+}
@@ -0,0 +1,289 @@
+#!/usr/bin/env python3
+"""Generates the KDBX database files the tests of src/kdbx.rs read, independently of it.
+
+Requires the cryptography package (41+ for Argon2). All random values are derived from fixed
+labels, so running it again writes the same files:
+
+    python3 testdata/kdbx/generate.py
+
+The databases have the password "test" (except those opened with the key file only) and these
+entries, with "Root" as root group:
+
+    Root/Example      alice  secret1  https://example.com, with a history entry
+    Root/Expired      bob    secret2  example.com, expired in 1970
+    Root/Git/Other    carol  secret3  https://other.org, with KPH: git = false
+    Root/Git/Alt      erin   secret5  https://nothere.net, KP2A_URL_1 https://example.com
+    Root/Git/Hidden   frank  secret6  https://example.com, hidden from the browser integration
+    Recycle Bin/Old   dave   secret4  https://example.com
+"""
+import base64
+import datetime
+import gzip
+import hashlib
+import hmac
+import os
+import re
+import struct
+
+from cryptography.hazmat.primitives import padding
+from cryptography.hazmat.primitives.ciphers import Cipher, algorithms, modes
+from cryptography.hazmat.primitives.kdf.argon2 import Argon2id
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+PASSWORD = "test"
+KEY = bytes(range(32))
+
+CIPHER_AES256 = bytes.fromhex("31c1f2e6bf714350be5805216afc5aff")
+CIPHER_CHACHA20 = bytes.fromhex("d6038a2b8b6f4cb5a524339a31dbb59a")
+KDF_AES_KDBX4 = bytes.fromhex("7c02bb8279a74ac0927d114a00648238")
+KDF_ARGON2ID = bytes.fromhex("9e298b1956db4773b23dfc3ec6f0a1e6")
+SALSA20_NONCE = bytes([0xE8, 0x30, 0x09, 0x4B, 0x97, 0x20, 0x5D, 0x2A])
+
+
+def u32(x):
+    return struct.pack("<I", x)
+
+
+def u64(x):
+    return struct.pack("<Q", x)
+
+
+def fixed_bytes(label, n):
+    return hashlib.sha512(label.encode()).digest()[:n]
+
+
+def salsa20_stream(key, nonce, n):
+    def rotl(v, c):
+        return ((v << c) & 0xFFFFFFFF) | (v >> (32 - c))
+
+    def quarter_round(x, a, b, c, d):
+        x[b] ^= rotl((x[a] + x[d]) & 0xFFFFFFFF, 7)
+        x[c] ^= rotl((x[b] + x[a]) & 0xFFFFFFFF, 9)
+        x[d] ^= rotl((x[c] + x[b]) & 0xFFFFFFFF, 13)
+        x[a] ^= rotl((x[d] + x[c]) & 0xFFFFFFFF, 18)
+
+    k = struct.unpack("<8I", key)
+    nn = struct.unpack("<2I", nonce)
+    c = struct.unpack("<4I", b"expand 32-byte k")
+    out = b""
+    counter = 0
+    while len(out) < n:
+        state = [c[0], k[0], k[1], k[2], k[3], c[1], nn[0], nn[1],
+                 counter & 0xFFFFFFFF, counter >> 32, c[2], k[4], k[5], k[6], k[7], c[3]]
+        x = state[:]
+        for _ in range(10):
+            quarter_round(x, 0, 4, 8, 12)
+            quarter_round(x, 5, 9, 13, 1)
+            quarter_round(x, 10, 14, 2, 6)
+            quarter_round(x, 15, 3, 7, 11)
+            quarter_round(x, 0, 1, 2, 3)
+            quarter_round(x, 5, 6, 7, 4)
+            quarter_round(x, 10, 11, 8, 9)
+            quarter_round(x, 15, 12, 13, 14)
+        out += struct.pack("<16I", *[(x[i] + state[i]) & 0xFFFFFFFF for i in range(16)])
+        counter += 1
+    return out[:n]
+
+
+def write_key_file():
+    """KeePassXC's XML key file format 2.0"""
+    data = KEY.hex().upper()
+    checksum = hashlib.sha256(KEY).digest()[:4].hex().upper()
+    with open(os.path.join(HERE, "keyfile.keyx"), "w") as f:
+        f.write(
+            '<?xml version="1.0" encoding="utf-8"?>\n<KeyFile>\n'
+            "    <Meta>\n        <Version>2.0</Version>\n    </Meta>\n"
+            '    <Key>\n        <Data Hash="%s">\n            %s\n            %s\n'
+            "        </Data>\n    </Key>\n</KeyFile>\n" % (checksum, data[:32], data[32:])
+        )
+
+
+def database_xml(version, protect):
+    def uuid(i):
+        return base64.b64encode(bytes([i]) * 16).decode()
+
+    def time(unix):
+        if version == 4:
+            return base64.b64encode(struct.pack("<q", unix + 62135596800)).decode()
+        return datetime.datetime.fromtimestamp(unix, datetime.timezone.utc).strftime(
+            "%Y-%m-%dT%H:%M:%SZ"
+        )
+
+    def string(key, value, protected=False):
+        if protected:
+            return '<String><Key>%s</Key><Value Protected="True">%s</Value></String>' % (
+                key,
+                protect(value),
+            )
+        return "<String><Key>%s</Key><Value>%s</Value></String>" % (key, value)
+
+    def entry(i, title, username, password, url, expires=None, extra="", history=""):
+        return (
+            "<Entry><UUID>%s</UUID>" % uuid(i)
+            + "<Times><Expires>%s</Expires><ExpiryTime>%s</ExpiryTime></Times>"
+            % ("True" if expires else "False", time(expires or 0))
+            + string("Title", title)
+            + string("UserName", username)
+            + string("Password", password, protected=True)
+            + string("URL", url)
+            + extra
+            + history
+            + "</Entry>"
+        )
+
+    # protected values are encrypted in document order, so the history comes after the password
+    example = entry(
+        1, "Example", "alice", "secret1", "https://example.com",
+        history="<History>%s</History>" % entry(9, "Example", "alice", "oldpw", "https://example.com"),
+    )
+    expired = entry(2, "Expired", "bob", "secret2", "example.com", expires=60)
+    other = entry(3, "Other", "carol", "secret3", "https://other.org",
+                  extra=string("KPH: git", "false"))
+    alt = entry(5, "Alt", "erin", "secret5", "https://nothere.net",
+                extra=string("KP2A_URL_1", "https://example.com") + string("Notes", "", protected=True))
+    hidden = entry(6, "Hidden", "frank", "secret6", "https://example.com",
+                   extra="<CustomData><Item><Key>BrowserHideEntry</Key><Value>true</Value></Item></CustomData>")
+    recycled = entry(4, "Old", "dave", "secret4", "https://example.com")
+    return (
+        '<?xml version="1.0" encoding="utf-8" standalone="yes"?>\n<KeePassFile>'
+        "<Meta><RecycleBinEnabled>True</RecycleBinEnabled><RecycleBinUUID>%s</RecycleBinUUID></Meta>"
+        "<Root><Group><UUID>%s</UUID><Name>Root</Name>%s%s"
+        "<Group><UUID>%s</UUID><Name>Git</Name>%s%s%s</Group>"
+        "<Group><UUID>%s</UUID><Name>Recycle Bin</Name>%s</Group>"
+        "</Group></Root></KeePassFile>"
+        % (uuid(0x77), uuid(0x10), example, expired, uuid(0x11), other, alt, hidden, uuid(0x77), recycled)
+    )
+
+
+def generate(name, version, kdf, cipher, password, key_file):
+    composite = b""
+    if password is not None:
+        composite += hashlib.sha256(password.encode()).digest()
+    if key_file:
+        composite += KEY
+    composite = hashlib.sha256(composite).digest()
+
+    master_seed = fixed_bytes(name + "master seed", 32)
+    seed = fixed_bytes(name + "seed", 32)
+    iv = fixed_bytes(name + "iv", 16 if cipher == "aes" else 12)
+    inner_key = fixed_bytes(name + "inner key", 64 if version == 4 else 32)
+    rounds = 1000
+
+    if kdf == "aes":
+        encryptor = Cipher(algorithms.AES(seed), modes.ECB()).encryptor()
+        transformed = composite
+        for _ in range(rounds):
+            transformed = encryptor.update(transformed)
+        transformed = hashlib.sha256(transformed).digest()
+    else:
+        transformed = Argon2id(
+            salt=seed, length=32, iterations=2, lanes=2, memory_cost=1024
+        ).derive(composite)
+    master_key = hashlib.sha256(master_seed + transformed).digest()
+
+    if version == 4:
+        digest = hashlib.sha512(inner_key).digest()
+        stream = Cipher(
+            algorithms.ChaCha20(digest[:32], b"\0\0\0\0" + digest[32:44]), mode=None
+        ).encryptor()
+
+        def protect(value):
+            return base64.b64encode(stream.update(value.encode())).decode()
+
+    else:
+        key_stream = salsa20_stream(hashlib.sha256(inner_key).digest(), SALSA20_NONCE, 4096)
+        position = [0]
+
+        def protect(value):
+            data = value.encode()
+            start = position[0]
+            position[0] += len(data)
+            return base64.b64encode(
+                bytes(b ^ k for b, k in zip(data, key_stream[start:position[0]]))
+            ).decode()
+
+    # protect values in document order, as the key stream runs through them
+    placeholders = []
+
+    def placeholder(value):
+        placeholders.append(value)
+        return "@@%d@@" % (len(placeholders) - 1)
+
+    xml = database_xml(version, placeholder)
+    xml = re.sub(r"@@(\d+)@@", lambda m: protect(placeholders[int(m.group(1))]), xml)
+
+    def encrypt(data):
+        if cipher == "chacha20":
+            return Cipher(algorithms.ChaCha20(master_key, b"\0\0\0\0" + iv), mode=None).encryptor().update(data)
+        padder = padding.PKCS7(128).padder()
+        data = padder.update(data) + padder.finalize()
+        encryptor = Cipher(algorithms.AES(master_key), modes.CBC(iv)).encryptor()
+        return encryptor.update(data) + encryptor.finalize()
+
+    cipher_id = CIPHER_AES256 if cipher == "aes" else CIPHER_CHACHA20
+    if version == 4:
+        fields = []
+
+        def field(i, data):
+            fields.append(bytes([i]) + u32(len(data)) + data)
+
+        def item(kind, key, value):
+            return bytes([kind]) + u32(len(key)) + key + u32(len(value)) + value
+
+        if kdf == "aes":
+            parameters = item(0x42, b"$UUID", KDF_AES_KDBX4) + item(0x05, b"R", u64(rounds)) + item(0x42, b"S", seed)
+        else:
+            parameters = (
+                item(0x42, b"$UUID", KDF_ARGON2ID) + item(0x42, b"S", seed) + item(0x04, b"P", u32(2))
+                + item(0x05, b"M", u64(1024 * 1024)) + item(0x05, b"I", u64(2)) + item(0x04, b"V", u32(0x13))
+            )
+        field(2, cipher_id)
+        field(3, u32(1))
+        field(4, master_seed)
+        field(7, iv)
+        field(11, struct.pack("<H", 0x100) + parameters + b"\0")
+        field(0, b"\r\n\r\n")
+        header = u32(0x9AA2D903) + u32(0xB54BFB67) + u32(0x00040001) + b"".join(fields)
+        hmac_key = hashlib.sha512(master_seed + transformed + b"\x01").digest()
+
+        def block_key(index):
+            return hashlib.sha512(u64(index) + hmac_key).digest()
+
+        inner_header = bytes([1]) + u32(4) + u32(3) + bytes([2]) + u32(len(inner_key)) + inner_key + bytes([0]) + u32(0)
+        encrypted = encrypt(gzip.compress(inner_header + xml.encode(), mtime=0))
+        data = header + hashlib.sha256(header).digest()
+        data += hmac.new(block_key(0xFFFFFFFFFFFFFFFF), header, hashlib.sha256).digest()
+        for index, block in enumerate([encrypted, b""]):
+            data += hmac.new(block_key(index), u64(index) + u32(len(block)) + block, hashlib.sha256).digest()
+            data += u32(len(block)) + block
+    else:
+        start_bytes = fixed_bytes(name + "start bytes", 32)
+        fields = []
+
+        def field(i, data):
+            fields.append(bytes([i]) + struct.pack("<H", len(data)) + data)
+
+        field(2, cipher_id)
+        field(3, u32(1))
+        field(4, master_seed)
+        field(5, seed)
+        field(6, u64(rounds))
+        field(7, iv)
+        field(8, inner_key)
+        field(9, start_bytes)
+        field(10, u32(2))
+        field(0, b"\r\n\r\n")
+        header = u32(0x9AA2D903) + u32(0xB54BFB67) + u32(0x00030001) + b"".join(fields)
+        payload = gzip.compress(xml.encode(), mtime=0)
+        blocks = u32(0) + hashlib.sha256(payload).digest() + u32(len(payload)) + payload
+        blocks += u32(1) + b"\0" * 32 + u32(0)
+        data = header + encrypt(start_bytes + blocks)
+    with open(os.path.join(HERE, name), "wb") as f:
+        f.write(data)
+
+
+write_key_file()
+generate("kdbx3-aes.kdbx", 3, "aes", "aes", PASSWORD, False)
+generate("kdbx3-aes-keyfile.kdbx", 3, "aes", "aes", PASSWORD, True)
+generate("kdbx4-argon2-chacha20.kdbx", 4, "argon2", "chacha20", PASSWORD, False)
+generate("kdbx4-argon2-chacha20-keyfile.kdbx", 4, "argon2", "chacha20", None, True)